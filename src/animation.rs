@@ -0,0 +1,109 @@
+//! The animation module takes care of fading channels from one value to
+//! another over time.  A `Fader` holds where a fade starts and ends, how many
+//! ticks it lasts, and the easing curve it follows.  The device keeps one
+//! fader per channel and `PWM5947::tick` advances them all, writing the new
+//! values into the buffer.
+//!
+//! A tick is whatever the caller wants it to be: a pass through the main loop,
+//! a timer interrupt, or a millisecond.  The values are computed from how far
+//! through the fade we are, rather than by adding steps, so a fade always
+//! lands on its target.
+//!
+//! The easing curves live in the `pwm` module, next to
+//! `PWMValue::interpolate`, and are re-exported here.
+
+pub use crate::pwm::Easing;
+use crate::pwm::PWMValue;
+
+/// A fade from a start value to a target value over a number of ticks.
+///
+/// ```
+/// use ledpwm5947::animation::{Easing, Fader};
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// let mut fader = Fader::new(PWMValue::min(), PWMValue::new(100), 4, Easing::Linear);
+///
+/// assert_eq!(PWMValue::new(25), fader.advance(1));
+/// assert_eq!(PWMValue::new(75), fader.advance(2));
+/// assert_eq!(PWMValue::new(100), fader.advance(5));
+/// assert!(fader.is_finished());
+/// ```
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Fader {
+    start: PWMValue,
+    target: PWMValue,
+    duration: u32,
+    elapsed: u32,
+    easing: Easing,
+}
+
+impl Fader {
+    /// Create a fade between the two values lasting `duration` ticks.  A fade
+    /// lasting zero ticks jumps straight to the target.
+    pub const fn new(start: PWMValue, target: PWMValue, duration: u32, easing: Easing) -> Self {
+        Fader {
+            start,
+            target,
+            duration,
+            elapsed: 0,
+            easing,
+        }
+    }
+
+    /// The value the fade ends on.
+    pub fn target(&self) -> PWMValue {
+        self.target
+    }
+
+    /// The value at the current point in the fade.
+    pub fn value(&self) -> PWMValue {
+        PWMValue::interpolate(
+            self.start,
+            self.target,
+            self.elapsed,
+            self.duration,
+            self.easing,
+        )
+    }
+
+    /// Moves the fade on by a number of ticks and returns the new value.
+    pub fn advance(&mut self, ticks: u32) -> PWMValue {
+        self.elapsed = self.elapsed.saturating_add(ticks).min(self.duration);
+        self.value()
+    }
+
+    /// Whether the fade has reached its target.
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_easing_curves() {
+        let start = PWMValue::new(1000);
+        let target = PWMValue::new(0);
+
+        let mut linear = Fader::new(start, target, 10, Easing::Linear);
+        let mut quad_in = Fader::new(start, target, 10, Easing::QuadIn);
+        let mut quad_out = Fader::new(start, target, 10, Easing::QuadOut);
+
+        assert_eq!(PWMValue::new(500), linear.advance(5));
+        assert_eq!(PWMValue::new(750), quad_in.advance(5));
+        assert_eq!(PWMValue::new(250), quad_out.advance(5));
+
+        assert_eq!(target, linear.advance(5));
+        assert_eq!(target, quad_in.advance(5));
+        assert_eq!(target, quad_out.advance(5));
+    }
+
+    #[test]
+    fn test_zero_duration() {
+        let fader = Fader::new(PWMValue::min(), PWMValue::max(), 0, Easing::QuadIn);
+        assert!(fader.is_finished());
+        assert_eq!(PWMValue::max(), fader.value());
+    }
+}
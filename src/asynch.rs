@@ -0,0 +1,374 @@
+//! An async driver for firmware built on an async executor, such as Embassy.
+//! It uses the `embedded-hal-async` SPI bus and delay traits, so the transfer
+//! yields to other tasks instead of blocking the executor while the frame is
+//! shifted out.  The latch and OE lines are `embedded-hal` 1.0 output pins,
+//! which are set immediately and so don't need to be async.
+//!
+//! The frame is the same 36 bytes the blocking SPI backend writes.  The bus
+//! should be configured for mode 0 with the most significant bit first, and
+//! `set_protocol` handles clone boards the same way it does there.
+
+use crate::frame::PackedFrame;
+use crate::protocol::ProtocolConfig;
+use crate::spi::SpiError;
+use crate::{pwm, Channel, PinError, PinRole, PinSource, ALL_CHANNELS};
+use embedded_hal_1::digital::{ErrorType, OutputPin};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiBus;
+
+/// How long the latch is held high.  The TLC5947 needs at least 20ns, which
+/// is less than a single instruction on most microcontrollers, but fast parts
+/// can toggle a pin quicker than that.
+const LATCH_PULSE_NS: u32 = 30;
+
+/// The `PinSource` for a board driven from async code with the pins `L` and
+/// `O`, the same as `spi::SpiSource`.
+pub type AsyncSource<L, O> = PinSource<
+    <L as ErrorType>::Error,
+    <L as ErrorType>::Error,
+    <O as ErrorType>::Error,
+    <L as ErrorType>::Error,
+>;
+
+/// A board driven from async code over SPI.
+#[derive(Debug)]
+pub struct AsyncPWM5947<S, L, O, T>
+where
+    S: SpiBus,
+    L: OutputPin,
+    O: OutputPin,
+    T: DelayNs,
+{
+    buffer: [pwm::PWMValue; 24],
+
+    spi: S,
+    latch: L,
+    oe: O,
+    delay: T,
+    protocol: ProtocolConfig,
+    blank_during_latch: bool,
+}
+
+/// Sets an `embedded-hal` 1.0 pin high or low, turning a failure into the
+/// same `PinError` the blocking drivers return, with `source` tagging the HAL
+/// error.
+fn set_pin<P: OutputPin, E>(
+    pin: &mut P,
+    which: PinRole,
+    high: bool,
+    source: fn(P::Error) -> E,
+) -> Result<(), PinError<E>> {
+    let result = if high { pin.set_high() } else { pin.set_low() };
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if high => Err(PinError::new(&which, "Failed to set high", source(e))),
+        Err(e) => Err(PinError::new(&which, "Failed to set low", source(e))),
+    }
+}
+
+impl<S, L, O, T> AsyncPWM5947<S, L, O, T>
+where
+    S: SpiBus,
+    L: OutputPin,
+    O: OutputPin,
+    T: DelayNs,
+{
+    /// Create a new board on the SPI bus.  Passes in the bus, pins and delay
+    /// that will now be owned by the device.
+    pub fn new(spi: S, latch: L, oe: O, delay: T) -> Self {
+        AsyncPWM5947 {
+            buffer: [pwm::PWMValue::min(); 24],
+            spi,
+            latch,
+            oe,
+            delay,
+            protocol: ProtocolConfig::TLC5947,
+            blank_during_latch: true,
+        }
+    }
+
+    /// Writes and latches the frame the protocol's way, for clone boards, the
+    /// same as `PWM5947Spi::set_protocol`.  Call `begin` afterwards if the
+    /// latch polarity changed, to put the latch at its new idle level.
+    pub fn set_protocol(&mut self, protocol: ProtocolConfig) {
+        self.protocol = protocol;
+    }
+
+    /// The protocol the frame is written and latched in.
+    pub fn protocol(&self) -> &ProtocolConfig {
+        &self.protocol
+    }
+
+    /// Turns OE blanking during the latch on or off, the same as
+    /// `PWM5947::set_blank_during_latch`.  It's on to start with.
+    pub fn set_blank_during_latch(&mut self, blank: bool) {
+        self.blank_during_latch = blank;
+    }
+
+    /// Whether OE is pulled high while the latch is toggled.
+    pub fn is_blank_during_latch(&self) -> bool {
+        self.blank_during_latch
+    }
+
+    /// Drives the latch to its active or idle level, for the protocol's
+    /// latch polarity.
+    fn set_latch(&mut self, active: bool) -> Result<(), PinError<AsyncSource<L, O>>> {
+        let high = active != self.protocol.latch_active_low();
+        set_pin(&mut self.latch, PinRole::Latch, high, PinSource::Latch)
+    }
+
+    /// Sets the latch and OE pins to known, good values and clears the buffer
+    /// to the PWM's `min` value.
+    pub fn begin(&mut self) -> Result<(), PinError<AsyncSource<L, O>>> {
+        set_pin(&mut self.oe, PinRole::OE, false, PinSource::OE)?;
+        self.set_latch(false)?;
+
+        self.buffer = [pwm::PWMValue::min(); 24];
+
+        Ok(())
+    }
+
+    /// Writes a value into the given channel.  It saves the PWM value into the
+    /// buffer for the given channel.
+    pub fn write_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.buffer[channel.0] = *pwm_value;
+    }
+
+    /// This sets the buffer back to all zeros and then flushes to turn off all
+    /// the LEDs.
+    pub async fn all_black(&mut self) -> Result<(), SpiError<S::Error, AsyncSource<L, O>>> {
+        for channel in ALL_CHANNELS {
+            self.buffer[channel.0] = pwm::PWMValue::min();
+        }
+        self.flush().await
+    }
+
+    /// Flushes the buffer to the board.  The frame is written and the bus
+    /// flushed before the latch is toggled, so the board never latches a
+    /// partial frame.  A failed SPI write is returned as the bus error.  OE is
+    /// pulled high while the latch is toggled, unless that's been turned off
+    /// with `set_blank_during_latch`.
+    pub async fn flush(&mut self) -> Result<(), SpiError<S::Error, AsyncSource<L, O>>> {
+        let frame = PackedFrame::pack(&self.protocol.arrange(&self.buffer));
+
+        self.set_latch(false)?;
+        self.spi
+            .write(frame.as_bytes())
+            .await
+            .map_err(SpiError::Bus)?;
+        self.spi.flush().await.map_err(SpiError::Bus)?;
+
+        let blank = self.blank_during_latch;
+        if blank {
+            set_pin(&mut self.oe, PinRole::OE, true, PinSource::OE)?;
+        }
+        self.set_latch(true)?;
+        self.delay.delay_ns(LATCH_PULSE_NS).await;
+        self.set_latch(false)?;
+        if blank {
+            set_pin(&mut self.oe, PinRole::OE, false, PinSource::OE)?;
+        }
+        Ok(())
+    }
+
+    /// Releases the bus, pins and delay, so they can be used for something else.
+    pub fn release(self) -> (S, L, O, T) {
+        (self.spi, self.latch, self.oe, self.delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+    use embedded_hal_1::digital::ErrorType;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    // A waker that does nothing, since nothing is ever woken.  `Waker::noop`
+    // would do, but it's newer than the crate's minimum Rust version.
+    const NOOP: RawWakerVTable = RawWakerVTable::new(|_| RAW, |_| {}, |_| {}, |_| {});
+    const RAW: RawWaker = RawWaker::new(core::ptr::null(), &NOOP);
+
+    // The fakes never return pending, so a single poll runs the future to
+    // completion without needing an executor.
+    fn run<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        // Safety: the vtable's functions never touch the null data pointer.
+        let waker = unsafe { Waker::from_raw(RAW) };
+        let mut context = Context::from_waker(&waker);
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("The fakes should never be pending"),
+        }
+    }
+
+    struct FakePin {
+        high: bool,
+        pulses: usize,
+    }
+
+    impl ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for FakePin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.high = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.high = true;
+            self.pulses += 1;
+            Ok(())
+        }
+    }
+
+    struct FakeSpi {
+        written: [u8; 36],
+    }
+
+    impl embedded_hal_1::spi::ErrorType for FakeSpi {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for FakeSpi {
+        async fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.written.copy_from_slice(words);
+            Ok(())
+        }
+
+        async fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct FakeDelay;
+
+    impl DelayNs for FakeDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn test_flush() {
+        let spi = FakeSpi { written: [0; 36] };
+        let latch = FakePin {
+            high: true,
+            pulses: 0,
+        };
+        let oe = FakePin {
+            high: true,
+            pulses: 0,
+        };
+        let mut device = AsyncPWM5947::new(spi, latch, oe, FakeDelay);
+        assert!(device.begin().is_ok());
+
+        device.write_pwm(&crate::C24, &pwm::PWMValue::new(0xabc));
+        assert!(run(device.flush()).is_ok());
+
+        let (spi, latch, oe, _) = device.release();
+        assert_eq!([0xab, 0xc0, 0x00], spi.written[0..3]);
+        assert_eq!(1, latch.pulses);
+        assert!(!latch.high);
+        assert!(!oe.high);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let spi = FakeSpi { written: [0; 36] };
+        let latch = FakePin {
+            high: false,
+            pulses: 0,
+        };
+        let oe = FakePin {
+            high: true,
+            pulses: 0,
+        };
+        let mut device = AsyncPWM5947::new(spi, latch, oe, FakeDelay);
+        device.set_protocol(ProtocolConfig {
+            bit_order: crate::protocol::BitOrder::LsbFirst,
+            latch: crate::protocol::LatchPolarity::ActiveLow,
+            ..ProtocolConfig::TLC5947
+        });
+        assert!(device.begin().is_ok());
+
+        device.write_pwm(&crate::C24, &pwm::PWMValue::new(0x001));
+        assert!(run(device.flush()).is_ok());
+
+        // The latch idles high and pulses low.
+        let (spi, latch, _, _) = device.release();
+        assert_eq!([0x80, 0x00, 0x00], spi.written[0..3]);
+        assert_eq!(3, latch.pulses);
+        assert!(latch.high);
+    }
+
+    // Records the writes to every pin that shares the log, in order.
+    struct LogPin {
+        role: PinRole,
+        log: Rc<RefCell<Vec<(PinRole, bool)>>>,
+    }
+
+    impl ErrorType for LogPin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for LogPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.log.borrow_mut().push((self.role.clone(), false));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.log.borrow_mut().push((self.role.clone(), true));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_blank_during_latch() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let pin = |role| LogPin {
+            role,
+            log: log.clone(),
+        };
+        let spi = FakeSpi { written: [0; 36] };
+        let mut device = AsyncPWM5947::new(spi, pin(PinRole::Latch), pin(PinRole::OE), FakeDelay);
+
+        assert!(device.is_blank_during_latch());
+        assert!(run(device.flush()).is_ok());
+        assert_eq!(
+            std::vec![
+                (PinRole::Latch, false),
+                (PinRole::OE, true),
+                (PinRole::Latch, true),
+                (PinRole::Latch, false),
+                (PinRole::OE, false),
+            ],
+            *log.borrow()
+        );
+
+        log.borrow_mut().clear();
+        device.set_blank_during_latch(false);
+        assert!(run(device.flush()).is_ok());
+        assert!(log.borrow().iter().all(|(role, _)| *role != PinRole::OE));
+    }
+}
@@ -0,0 +1,358 @@
+//! A bank is several boards that share the data, clock and OE lines, but each
+//! have their own latch.  Unlike a chain, the boards aren't wired one into the
+//! next.  Every board sees the same bits, and only the board whose latch is
+//! toggled takes them.  Each board is flushed with a 288 bit frame instead of
+//! shifting the whole chain, which suits layouts where the boards are far
+//! apart or only some of them change.
+//!
+//! The bank has one flat channel space, so a program can address channel 30
+//! instead of channel 6 of board 1.  Channels 0 to 23 are on the board with
+//! the first latch, 24 to 47 on the second, and so on.  The number of boards
+//! is a const generic parameter, the same as a chain.  So is the protocol:
+//! `set_protocol` sets how every board's frame is shifted and latched.
+
+use crate::frame::PackedFrame;
+use crate::protocol::ProtocolConfig;
+use crate::timing::{Delay, NoDelay, Timing};
+use crate::{pwm, shift_frame, DeviceError, DeviceSource, PWMPin, PinRole, PinSource};
+use embedded_hal::digital::v2::OutputPin;
+
+/// `N` boards with a latch each, sharing the data, OE and clock pins.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::bank::Bank;
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// let mut bank = Bank::new([Pin, Pin, Pin], Pin, Pin, Pin);
+/// assert_eq!(72, bank.channels());
+///
+/// bank.write_pwm(30, &PWMValue::max());
+/// assert_eq!(PWMValue::max(), bank.read_pwm(30));
+/// bank.flush_all().ok();
+/// ```
+///
+/// Like the single board, the T parameter is the delay provider used to meet
+/// the minimum pulse widths, and defaults to `NoDelay`.  See `with_timing`.
+#[derive(Debug)]
+pub struct Bank<L, D, O, C, const N: usize, T = NoDelay>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    buffer: [[pwm::PWMValue; 24]; N],
+
+    latches: [PWMPin<L, DeviceSource<L, D, O, C>>; N],
+    data: PWMPin<D, DeviceSource<L, D, O, C>>,
+    oe: PWMPin<O, DeviceSource<L, D, O, C>>,
+    clock: PWMPin<C, DeviceSource<L, D, O, C>>,
+    delay: T,
+    timing: Timing,
+    protocol: ProtocolConfig,
+    blank_during_latch: bool,
+}
+
+impl<L, D, O, C, const N: usize> Bank<L, D, O, C, N>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+{
+    /// Create a bank of boards, one per latch pin.  Passes in the pins that
+    /// will now be owned by the bank.
+    pub fn new(latches: [L; N], data: D, oe: O, clock: C) -> Self {
+        Bank {
+            buffer: [[pwm::PWMValue::min(); 24]; N],
+            latches: latches.map(|latch| PWMPin::new(latch, PinRole::Latch, PinSource::Latch)),
+            data: PWMPin::new(data, PinRole::Data, PinSource::Data),
+            oe: PWMPin::new(oe, PinRole::OE, PinSource::OE),
+            clock: PWMPin::new(clock, PinRole::Clock, PinSource::Clock),
+            delay: NoDelay,
+            timing: Timing::default(),
+            protocol: ProtocolConfig::TLC5947,
+            blank_during_latch: true,
+        }
+    }
+}
+
+impl<L, D, O, C, const N: usize, T> Bank<L, D, O, C, N, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Slows the protocol down to meet the boards' minimum pulse widths, the
+    /// same as `PWM5947::with_timing`.  The clock is held at each level for
+    /// at least `clock_ns` nanoseconds, and each latch is held high for at
+    /// least `latch_ns` nanoseconds.
+    pub fn with_timing<U>(self, delay: U, clock_ns: u32, latch_ns: u32) -> Bank<L, D, O, C, N, U>
+    where
+        U: Delay,
+    {
+        Bank {
+            buffer: self.buffer,
+            latches: self.latches,
+            data: self.data,
+            oe: self.oe,
+            clock: self.clock,
+            delay,
+            timing: Timing { clock_ns, latch_ns },
+            protocol: self.protocol,
+            blank_during_latch: self.blank_during_latch,
+        }
+    }
+
+    /// Shifts and latches every board's frame the protocol's way, for clone
+    /// boards, the same as `PWM5947::set_protocol`.  The latch polarity
+    /// applies to every latch pin.
+    pub fn set_protocol(&mut self, protocol: ProtocolConfig) {
+        for latch in self.latches.iter_mut() {
+            latch.active_low = protocol.latch_active_low();
+        }
+        self.protocol = protocol;
+    }
+
+    /// The protocol the frames are shifted and latched in.
+    pub fn protocol(&self) -> &ProtocolConfig {
+        &self.protocol
+    }
+
+    /// Turns OE blanking during the latch on or off, the same as
+    /// `PWM5947::set_blank_during_latch`.  It's on to start with.  OE is
+    /// shared, so every board blanks while one of them latches.
+    pub fn set_blank_during_latch(&mut self, blank: bool) {
+        self.blank_during_latch = blank;
+    }
+
+    /// Whether OE is pulled high while a latch is toggled.
+    pub fn is_blank_during_latch(&self) -> bool {
+        self.blank_during_latch
+    }
+
+    /// The number of boards in the bank.
+    pub fn boards(&self) -> usize {
+        N
+    }
+
+    /// The number of channels across all the boards.
+    pub fn channels(&self) -> usize {
+        24 * N
+    }
+
+    /// Sets the pins to known, good values and clears the buffers of every
+    /// board to the PWM's `min` value.
+    pub fn begin(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.oe.set_low()?;
+        for latch in self.latches.iter_mut() {
+            latch.set_low()?;
+        }
+        self.data.set_low()?;
+        self.clock.set_low()?;
+
+        self.buffer = [[pwm::PWMValue::min(); 24]; N];
+
+        Ok(())
+    }
+
+    /// Writes a value into a channel in the flat channel space.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is not less than the number of channels.
+    pub fn write_pwm(&mut self, channel: usize, pwm_value: &pwm::PWMValue) {
+        self.buffer[channel / 24][channel % 24] = *pwm_value;
+    }
+
+    /// Reads the value in a channel in the flat channel space.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is not less than the number of channels.
+    pub fn read_pwm(&self, channel: usize) -> pwm::PWMValue {
+        self.buffer[channel / 24][channel % 24]
+    }
+
+    /// Sets the buffers of every board back to zeros and then flushes them all
+    /// to turn off all the LEDs.
+    pub fn all_black(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.buffer = [[pwm::PWMValue::min(); 24]; N];
+        self.flush_all()
+    }
+
+    /// Flushes one board's buffer and toggles only that board's latch.  OE is
+    /// pulled high while the latch is toggled, unless that's been turned off
+    /// with `set_blank_during_latch`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board number is not less than the number of boards.
+    pub fn flush(&mut self, board: usize) -> Result<(), DeviceError<L, D, O, C>> {
+        self.flush_board(board, self.blank_during_latch)
+    }
+
+    /// Flushes every board in turn, starting with the first latch.
+    pub fn flush_all(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.flush_boards(self.blank_during_latch)
+    }
+
+    /// Flushes every board, optionally pulling OE high while each latch is
+    /// toggled.
+    pub(crate) fn flush_boards(&mut self, blank: bool) -> Result<(), DeviceError<L, D, O, C>> {
+        for board in 0..N {
+            self.flush_board(board, blank)?;
+        }
+        Ok(())
+    }
+
+    fn flush_board(&mut self, board: usize, blank: bool) -> Result<(), DeviceError<L, D, O, C>> {
+        let latch = &mut self.latches[board];
+        latch.set_low()?;
+
+        shift_frame(
+            &mut self.data,
+            &mut self.clock,
+            &mut self.delay,
+            self.timing.clock_ns,
+            &PackedFrame::pack(&self.protocol.arrange(&self.buffer[board])),
+            false,
+        )?;
+
+        if blank {
+            self.oe.set_high()?;
+        }
+        latch.set_high()?;
+        self.delay.delay_ns(self.timing.latch_ns);
+        latch.set_low()?;
+        if blank {
+            self.oe.set_low()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_pins::{self, IdlePin};
+    use core::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    // Records which latch was toggled, and how many bits had been clocked in
+    // at the time.
+    #[derive(Default)]
+    struct Wire {
+        bits: usize,
+        latched: Vec<(usize, usize)>,
+    }
+
+    struct LatchPin(Rc<RefCell<Wire>>, usize);
+    struct ClockPin(Rc<RefCell<Wire>>);
+
+    impl OutputPin for LatchPin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            let mut wire = self.0.borrow_mut();
+            let bits = wire.bits;
+            wire.latched.push((self.1, bits));
+            Ok(())
+        }
+    }
+
+    impl OutputPin for ClockPin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().bits += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_separate_latches() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut bank = Bank::new(
+            [LatchPin(wire.clone(), 0), LatchPin(wire.clone(), 1)],
+            IdlePin,
+            IdlePin,
+            ClockPin(wire.clone()),
+        );
+
+        assert!(bank.flush(1).is_ok());
+        assert!(bank.flush_all().is_ok());
+        assert_eq!(&[(1, 288), (0, 576), (1, 864)], &wire.borrow().latched[..]);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let wire = Rc::new(RefCell::new(test_pins::Wire::default()));
+        let mut bank = Bank::new(
+            [IdlePin, IdlePin],
+            test_pins::DataPin(wire.clone()),
+            IdlePin,
+            test_pins::ClockPin(wire.clone()),
+        );
+        bank.set_protocol(ProtocolConfig {
+            bit_order: crate::protocol::BitOrder::LsbFirst,
+            channel_order: crate::protocol::ChannelOrder::Ascending,
+            latch: crate::protocol::LatchPolarity::ActiveLow,
+        });
+        assert!(bank.latches.iter().all(|latch| latch.active_low));
+
+        bank.write_pwm(24, &pwm::PWMValue::new(0x001));
+        assert!(bank.flush(1).is_ok());
+
+        // The first channel goes first, least significant bit first.
+        let bits = &wire.borrow().bits;
+        assert_eq!(288, bits.len());
+        assert!(bits[0]);
+        assert!(bits[1..].iter().all(|bit| !bit));
+    }
+
+    #[derive(Default)]
+    struct CountingDelay {
+        calls: usize,
+        total_ns: u32,
+    }
+
+    impl Delay for CountingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.calls += 1;
+            self.total_ns += ns;
+        }
+    }
+
+    #[test]
+    fn test_timing() {
+        let mut bank = Bank::new([IdlePin, IdlePin, IdlePin], IdlePin, IdlePin, IdlePin)
+            .with_timing(CountingDelay::default(), 10, 50);
+
+        assert!(bank.flush(2).is_ok());
+        assert_eq!(288 * 2 + 1, bank.delay.calls);
+        assert_eq!(288 * 2 * 10 + 50, bank.delay.total_ns);
+    }
+}
@@ -0,0 +1,359 @@
+//! The builder puts a device together one piece at a time, for the cases the
+//! constructor doesn't cover.  The OE pin is often tied to ground on the board
+//! so the outputs are always on, and then there is no pin to pass in.  The
+//! builder also sets the initial buffer and the delay provider.
+//!
+//! Some boards take the latch or OE through an inverting level shifter, so the
+//! pin on the microcontroller has to be low for the line on the board to be
+//! high.  Marking the pin `active_low` makes the device drive it inverted,
+//! without wrapping the HAL pin.  An open-drain pin with a pull-up behaves
+//! like any other output pin here, so it needs nothing special.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::builder::PWM5947Builder;
+//! use ledpwm5947::pwm::PWMValue;
+//!
+//! let mut device = PWM5947Builder::new(Pin, Pin, Pin)
+//!     .initial_value(PWMValue::new(100))
+//!     .build();
+//!
+//! device.reset_pins().ok();
+//! device.flush().ok();
+//! ```
+
+use crate::protocol::{LatchPolarity, ProtocolConfig};
+use crate::timing::{Delay, NoDelay};
+use crate::{pwm::PWMValue, Channel, PinRole, ALL_CHANNELS, PWM5947};
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use embedded_hal::digital::v2::OutputPin;
+
+/// Stands in for a pin that isn't connected to the microcontroller, such as
+/// an OE pin tied to ground.  Setting it does nothing and never fails, so the
+/// error type can be whatever suits the firmware.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NoPin<E = Infallible> {
+    error: PhantomData<fn() -> E>,
+}
+
+impl<E> NoPin<E> {
+    /// Create a pin that isn't connected.
+    pub fn new() -> Self {
+        NoPin { error: PhantomData }
+    }
+}
+
+impl<E> Default for NoPin<E> {
+    fn default() -> Self {
+        NoPin::new()
+    }
+}
+
+impl<E> OutputPin for NoPin<E> {
+    type Error = E;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Builds a `PWM5947` device.  The latch, data and clock pins are required.
+/// Without an OE pin the device uses `NoPin`, so enabling and disabling the
+/// output does nothing.
+#[derive(Debug)]
+pub struct PWM5947Builder<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    latch: L,
+    data: D,
+    oe: O,
+    clock: C,
+    delay: T,
+    clock_ns: u32,
+    latch_ns: u32,
+    buffer: [PWMValue; 24],
+    inverted: [bool; 24],
+    active_low: [bool; 4],
+    blank_during_latch: Option<bool>,
+    protocol: ProtocolConfig,
+}
+
+impl<L, D, C> PWM5947Builder<L, D, NoPin, C, NoDelay>
+where
+    L: OutputPin,
+    D: OutputPin,
+    C: OutputPin,
+{
+    /// Start building a device with the pins that are always needed.
+    pub fn new(latch: L, data: D, clock: C) -> Self {
+        PWM5947Builder {
+            latch,
+            data,
+            oe: NoPin::new(),
+            clock,
+            delay: NoDelay,
+            clock_ns: 0,
+            latch_ns: 0,
+            buffer: [PWMValue::min(); 24],
+            inverted: [false; 24],
+            active_low: [false; 4],
+            blank_during_latch: None,
+            protocol: ProtocolConfig::TLC5947,
+        }
+    }
+}
+
+impl<L, D, O, C, T> PWM5947Builder<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Use a pin for OE, so the outputs can be blanked.  Blanking during the
+    /// latch turns on with it, unless `blank_during_latch` already set it.
+    pub fn oe<P>(self, oe: P) -> PWM5947Builder<L, D, P, C, T>
+    where
+        P: OutputPin,
+    {
+        PWM5947Builder {
+            latch: self.latch,
+            data: self.data,
+            oe,
+            clock: self.clock,
+            delay: self.delay,
+            clock_ns: self.clock_ns,
+            latch_ns: self.latch_ns,
+            buffer: self.buffer,
+            inverted: self.inverted,
+            active_low: self.active_low,
+            blank_during_latch: self.blank_during_latch.or(Some(true)),
+            protocol: self.protocol,
+        }
+    }
+
+    /// Use a delay provider to meet the minimum pulse widths.  This is the
+    /// same as `PWM5947::with_timing`.
+    pub fn delay<U>(self, delay: U, clock_ns: u32, latch_ns: u32) -> PWM5947Builder<L, D, O, C, U>
+    where
+        U: Delay,
+    {
+        PWM5947Builder {
+            latch: self.latch,
+            data: self.data,
+            oe: self.oe,
+            clock: self.clock,
+            delay,
+            clock_ns,
+            latch_ns,
+            buffer: self.buffer,
+            inverted: self.inverted,
+            active_low: self.active_low,
+            blank_during_latch: self.blank_during_latch,
+            protocol: self.protocol,
+        }
+    }
+
+    /// Start every channel at the same value.
+    pub fn initial_value(mut self, value: PWMValue) -> Self {
+        self.buffer = [value; 24];
+        self
+    }
+
+    /// Start the channels with the given values.
+    pub fn initial_buffer(mut self, buffer: [PWMValue; 24]) -> Self {
+        self.buffer = buffer;
+        self
+    }
+
+    /// Turns OE blanking during the latch on or off, whether it's called
+    /// before or after `oe`.  Left alone, it's on once an OE pin is given, and
+    /// off without one.  See `PWM5947::set_blank_during_latch`.
+    pub fn blank_during_latch(mut self, blank: bool) -> Self {
+        self.blank_during_latch = Some(blank);
+        self
+    }
+
+    /// Inverts the output of the channel.  See `PWM5947::set_inverted`.
+    pub fn inverted(mut self, channel: &Channel) -> Self {
+        self.inverted[channel.0] = true;
+        self
+    }
+
+    /// Drives the pin inverted, so setting it high pulls the HAL pin low.
+    /// Use it for a line that's inverted between the microcontroller and the
+    /// board.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::builder::PWM5947Builder;
+    /// use ledpwm5947::PinRole;
+    ///
+    /// let mut device = PWM5947Builder::new(Pin, Pin, Pin)
+    ///     .oe(Pin)
+    ///     .active_low(PinRole::OE)
+    ///     .active_low(PinRole::Latch)
+    ///     .build();
+    /// device.reset_pins().ok();
+    /// ```
+    pub fn active_low(mut self, pin: PinRole) -> Self {
+        self.active_low[pin_slot(&pin)] = true;
+        self
+    }
+
+    /// Shifts and latches the frames the protocol's way, for clone boards.
+    /// The latch polarity sets whether the latch pin is active low, the same
+    /// as `active_low(PinRole::Latch)`.  See the `protocol` module.
+    pub fn protocol(mut self, protocol: ProtocolConfig) -> Self {
+        self.active_low[pin_slot(&PinRole::Latch)] = protocol.latch == LatchPolarity::ActiveLow;
+        self.protocol = protocol;
+        self
+    }
+
+    /// Builds the device.  The pins aren't touched, so call `reset_pins` and
+    /// then `flush` to show the initial buffer.  Calling `begin` instead would
+    /// clear it.
+    pub fn build(self) -> PWM5947<L, D, O, C, T> {
+        let mut device = PWM5947::new(self.latch, self.data, self.oe, self.clock).with_timing(
+            self.delay,
+            self.clock_ns,
+            self.latch_ns,
+        );
+        device.buffer = self.buffer;
+        for (channel, inverted) in ALL_CHANNELS.iter().zip(self.inverted.iter()) {
+            device.set_inverted(channel, *inverted);
+        }
+        device.latch.active_low = self.active_low[pin_slot(&PinRole::Latch)];
+        device.data.active_low = self.active_low[pin_slot(&PinRole::Data)];
+        device.oe.active_low = self.active_low[pin_slot(&PinRole::OE)];
+        device.clock.active_low = self.active_low[pin_slot(&PinRole::Clock)];
+        device.blank_during_latch = self.blank_during_latch.unwrap_or(false);
+        device.protocol = self.protocol;
+        device.protocol.latch = if device.latch.active_low {
+            LatchPolarity::ActiveLow
+        } else {
+            LatchPolarity::ActiveHigh
+        };
+        device.dirty = true;
+        device
+    }
+}
+
+fn pin_slot(pin: &PinRole) -> usize {
+    match pin {
+        PinRole::Latch => 0,
+        PinRole::Data => 1,
+        PinRole::OE => 2,
+        PinRole::Clock => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::FakePin;
+
+    #[test]
+    fn test_build_without_oe() {
+        let latch = FakePin { value: true };
+        let data = FakePin { value: true };
+        let clock = FakePin { value: true };
+
+        let mut buffer = [PWMValue::min(); 24];
+        buffer[5] = PWMValue::new(55);
+        let mut device = PWM5947Builder::new(latch, data, clock)
+            .initial_buffer(buffer)
+            .build();
+
+        assert!(device.reset_pins().is_ok());
+        assert!(device.disable_output().is_ok());
+        assert!(!device.latch.raw_pin.value);
+        assert_eq!(buffer, device.buffer);
+    }
+
+    #[test]
+    fn test_build_with_oe() {
+        let latch = FakePin { value: true };
+        let data = FakePin { value: true };
+        let clock = FakePin { value: true };
+        let oe = FakePin { value: false };
+
+        let mut device = PWM5947Builder::new(latch, data, clock)
+            .oe(oe)
+            .initial_value(PWMValue::max())
+            .build();
+
+        assert!(device.disable_output().is_ok());
+        assert!(device.oe.raw_pin.value);
+        assert_eq!([PWMValue::max(); 24], device.buffer);
+    }
+
+    #[test]
+    fn test_blank_during_latch() {
+        let pin = || FakePin { value: false };
+
+        let device = PWM5947Builder::new(pin(), pin(), pin()).build();
+        assert!(!device.is_blank_during_latch());
+
+        let device = PWM5947Builder::new(pin(), pin(), pin()).oe(pin()).build();
+        assert!(device.is_blank_during_latch());
+
+        let device = PWM5947Builder::new(pin(), pin(), pin())
+            .blank_during_latch(false)
+            .oe(pin())
+            .build();
+        assert!(!device.is_blank_during_latch());
+
+        let device = PWM5947Builder::new(pin(), pin(), pin())
+            .oe(pin())
+            .blank_during_latch(false)
+            .build();
+        assert!(!device.is_blank_during_latch());
+    }
+
+    #[test]
+    fn test_active_low() {
+        let latch = FakePin { value: false };
+        let data = FakePin { value: false };
+        let clock = FakePin { value: false };
+        let oe = FakePin { value: false };
+
+        let mut device = PWM5947Builder::new(latch, data, clock)
+            .oe(oe)
+            .active_low(PinRole::OE)
+            .build();
+
+        assert!(device.enable_output().is_ok());
+        assert!(device.oe.raw_pin.value);
+        assert!(device.disable_output().is_ok());
+        assert!(!device.oe.raw_pin.value);
+
+        assert!(device.reset_pins().is_ok());
+        assert!(!device.latch.raw_pin.value);
+    }
+}
@@ -0,0 +1,269 @@
+//! Shares the data and clock lines with other shift registers, like a
+//! 74HC595 driving a row of indicator LEDs.  Normally the device owns its data
+//! and clock pins, so every peripheral needs GPIOs of its own.  With a
+//! `ShiftBus` the pins live outside the device, and `flush_on` borrows them
+//! for the length of a flush and hands them back afterwards.  In between, the
+//! firmware is free to clock bits into the other registers.
+//!
+//! Each register only takes the bits clocked into it when its own latch is
+//! toggled, so the devices on the bus need separate latch pins.  The board
+//! sees the bits meant for the other registers go past, but a flush always
+//! shifts a whole 288 bit frame, pushing them out before the latch.  The
+//! device itself is created with `NoPin` for data and clock, and its latch and
+//! OE pins are used as normal.
+//!
+//! The bus is told when a flush takes it and when it gives it back, through a
+//! `BusGuard`.  A bus that has to switch the lines over, or mark them busy for
+//! an interrupt handler, can do that in `acquire` and `release`.  The guard
+//! releases the bus when it's dropped, so a pin error part way through still
+//! gives it back.  The other side of the firmware can take a guard of its own
+//! for its transfers.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::builder::NoPin;
+//! use ledpwm5947::bus::{BusGuard, ShiftBus};
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! struct Lines {
+//!     data: Pin,
+//!     clock: Pin,
+//! }
+//!
+//! impl ShiftBus for Lines {
+//!     type Data = Pin;
+//!     type Clock = Pin;
+//!
+//!     fn pins(&mut self) -> (&mut Pin, &mut Pin) {
+//!         (&mut self.data, &mut self.clock)
+//!     }
+//! }
+//!
+//! let mut lines = Lines { data: Pin, clock: Pin };
+//! let mut device = PWM5947::new(Pin, NoPin::new(), Pin, NoPin::new());
+//!
+//! device.write_pwm(&C1, &PWMValue::max());
+//! device.flush_on(&mut lines).unwrap();
+//!
+//! // Clock a byte out to the 74HC595, which has a latch of its own.
+//! let mut guard = BusGuard::new(&mut lines);
+//! let (data, clock) = guard.pins();
+//! for bit in (0..8).rev().map(|i| 0xa5_u8 & (1 << i) != 0) {
+//!     clock.set_low().unwrap();
+//!     if bit { data.set_high().unwrap() } else { data.set_low().unwrap() }
+//!     clock.set_high().unwrap();
+//! }
+//! ```
+
+use crate::timing::Delay;
+use crate::{shift_frame, DeviceError, PWMPin, PinRole, PinSource, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+/// A data and clock line that the device borrows for each flush.
+pub trait ShiftBus {
+    /// The data pin.  Its error type has to match the device's own data pin,
+    /// which is normally a `NoPin`.
+    type Data: OutputPin;
+    /// The clock pin.  Its error type has to match the device's own clock
+    /// pin.
+    type Clock: OutputPin;
+
+    /// Called when a transfer takes the bus, before any bits are shifted.
+    fn acquire(&mut self) {}
+
+    /// The data and clock pins.
+    fn pins(&mut self) -> (&mut Self::Data, &mut Self::Clock);
+
+    /// Called when a transfer gives the bus back.
+    fn release(&mut self) {}
+}
+
+/// Holds the bus for a transfer.  Creating the guard acquires the bus, and
+/// dropping it releases it.
+#[derive(Debug)]
+pub struct BusGuard<'a, B>
+where
+    B: ShiftBus,
+{
+    bus: &'a mut B,
+}
+
+impl<'a, B> BusGuard<'a, B>
+where
+    B: ShiftBus,
+{
+    /// Acquires the bus.
+    pub fn new(bus: &'a mut B) -> Self {
+        bus.acquire();
+        BusGuard { bus }
+    }
+
+    /// The data and clock pins, while the bus is held.
+    pub fn pins(&mut self) -> (&mut B::Data, &mut B::Clock) {
+        self.bus.pins()
+    }
+}
+
+impl<'a, B> Drop for BusGuard<'a, B>
+where
+    B: ShiftBus,
+{
+    fn drop(&mut self) {
+        self.bus.release();
+    }
+}
+
+/// A borrowed pin, so it can be wrapped in a `PWMPin` for the length of a
+/// transfer.
+struct Borrowed<'a, P>(&'a mut P);
+
+impl<'a, P> OutputPin for Borrowed<'a, P>
+where
+    P: OutputPin,
+{
+    type Error = P::Error;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set_high()
+    }
+}
+
+impl<L, D, O, C, T> PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Flushes the buffer the same as `flush`, but shifts the frame out on the
+    /// bus instead of the device's own data and clock pins.  The bus is held
+    /// while the bits are shifted, and given back before the latch is
+    /// toggled.
+    pub fn flush_on<B>(&mut self, bus: &mut B) -> Result<(), DeviceError<L, D, O, C>>
+    where
+        B: ShiftBus,
+        B::Data: OutputPin<Error = D::Error>,
+        B::Clock: OutputPin<Error = C::Error>,
+    {
+        let packed = self.next_packed();
+        self.shift_and_latch(
+            &packed,
+            self.blank_during_latch,
+            |device, packed, resync| {
+                let mut guard = BusGuard::new(bus);
+                let (data, clock) = guard.pins();
+                shift_frame(
+                    &mut PWMPin::new(Borrowed(data), PinRole::Data, PinSource::Data),
+                    &mut PWMPin::new(Borrowed(clock), PinRole::Clock, PinSource::Clock),
+                    &mut device.delay,
+                    device.timing.clock_ns,
+                    packed,
+                    resync,
+                )
+            },
+            |_| Ok(()),
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::builder::NoPin;
+    use crate::frame;
+    use crate::pwm::PWMValue;
+    use crate::test_pins::{ClockPin, DataPin, Wire};
+    use crate::C1;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Lines {
+        data: DataPin,
+        clock: ClockPin,
+        held: bool,
+        releases: usize,
+    }
+
+    impl ShiftBus for Lines {
+        type Data = DataPin;
+        type Clock = ClockPin;
+
+        fn acquire(&mut self) {
+            assert!(!self.held);
+            self.held = true;
+        }
+
+        fn pins(&mut self) -> (&mut DataPin, &mut ClockPin) {
+            assert!(self.held);
+            (&mut self.data, &mut self.clock)
+        }
+
+        fn release(&mut self) {
+            self.held = false;
+            self.releases += 1;
+        }
+    }
+
+    #[test]
+    fn test_flush_on() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut lines = Lines {
+            data: DataPin(wire.clone()),
+            clock: ClockPin(wire.clone()),
+            held: false,
+            releases: 0,
+        };
+        let mut device: PWM5947<NoPin<()>, NoPin<()>, NoPin<()>, NoPin<()>> =
+            PWM5947::new(NoPin::new(), NoPin::new(), NoPin::new(), NoPin::new());
+
+        device.write_pwm(&C1, &PWMValue::new(1));
+        assert!(device.flush_on(&mut lines).is_ok());
+        assert!(!lines.held);
+        assert_eq!(1, lines.releases);
+        assert!(!device.is_dirty());
+        {
+            let bits = &wire.borrow().bits;
+            assert_eq!(frame::FRAME_BITS, bits.len());
+            assert!(bits[bits.len() - 1]);
+            assert_eq!(1, bits.iter().filter(|bit| **bit).count());
+        }
+
+        wire.borrow_mut().failing = true;
+        let e = device.flush_on(&mut lines).unwrap_err();
+        assert_eq!(PinRole::Clock, e.which);
+        assert!(!lines.held);
+        assert_eq!(2, lines.releases);
+
+        // The failed flush shifts zeros through before the next frame.
+        wire.borrow_mut().failing = false;
+        wire.borrow_mut().bits.clear();
+        assert!(device.flush_on(&mut lines).is_ok());
+        assert_eq!(2 * frame::FRAME_BITS, wire.borrow().bits.len());
+        assert_eq!(3, lines.releases);
+
+        #[cfg(feature = "stats")]
+        {
+            let stats = device.stats();
+            assert_eq!(2, stats.flushes());
+            assert_eq!(3 * frame::FRAME_BITS as u64, stats.bits_shifted());
+            assert_eq!(1, stats.pin_errors(&PinRole::Clock));
+        }
+    }
+}
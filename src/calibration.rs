@@ -0,0 +1,153 @@
+//! Raw RGB LEDs rarely make a neutral white with all three channels at full.
+//! The green and blue dies are usually brighter than the red, so "white"
+//! comes out with a blue or green tint.  A calibration holds a scaling factor
+//! for each color, like red at 100%, green at 80% and blue at 65%, and applies
+//! it whenever a color is turned into channel values, so full white on every
+//! LED in the build looks the same.
+//!
+//! The factors can be given as percentages, or worked out from a white point.
+//! The white point is the 8-bit color that looks white when shown on the raw
+//! LED, found by eye or with a meter.  The brightest component is left at
+//! 100% and the others are scaled down to match.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::calibration::Calibration;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::rgb::RgbLed;
+//! use ledpwm5947::{PWM5947, C1, C2};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let led = RgbLed::contiguous(&C1).expect("C1 to C3 are on the board");
+//!
+//! let calibration = Calibration::new(100, 80, 65);
+//! led.set_color_calibrated(&mut device, &calibration, 0xff, 0xff, 0xff);
+//! assert_eq!(PWMValue::new(3276), device.read_pwm(&C2));
+//! device.flush().ok();
+//! ```
+
+use crate::pwm::PWMValue;
+
+/// The scaling factors for the red, green and blue channels of an RGB LED.
+/// The default leaves every channel at 100%.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    // Thousandths, so a white point can be matched more closely than a
+    // whole percent.
+    red: u16,
+    green: u16,
+    blue: u16,
+}
+
+impl Calibration {
+    /// Create a calibration from a percentage for each color.  Percentages
+    /// over 100 are treated as 100, since a channel can't go past full.
+    pub fn new(red: u8, green: u8, blue: u8) -> Self {
+        Calibration {
+            red: red.min(100) as u16 * 10,
+            green: green.min(100) as u16 * 10,
+            blue: blue.min(100) as u16 * 10,
+        }
+    }
+
+    /// Create a calibration from the 8-bit color that looks white on the raw
+    /// LED.  The brightest component stays at 100%.  A white point of black
+    /// can't be matched, so it gives the default calibration.
+    ///
+    /// ```
+    /// use ledpwm5947::calibration::Calibration;
+    ///
+    /// let calibration = Calibration::from_white_point(255, 204, 166);
+    /// assert_eq!((100, 80, 65), calibration.percentages());
+    /// ```
+    pub fn from_white_point(red: u8, green: u8, blue: u8) -> Self {
+        let brightest = red.max(green).max(blue) as u32;
+        if brightest == 0 {
+            return Calibration::default();
+        }
+
+        let scale = |component: u8| ((component as u32 * 1000 + brightest / 2) / brightest) as u16;
+        Calibration {
+            red: scale(red),
+            green: scale(green),
+            blue: scale(blue),
+        }
+    }
+
+    /// The factors for red, green and blue, rounded to whole percentages.
+    pub fn percentages(&self) -> (u8, u8, u8) {
+        let percent = |factor: u16| ((factor + 5) / 10) as u8;
+        (percent(self.red), percent(self.green), percent(self.blue))
+    }
+
+    /// Scales 12-bit color components by the calibration, rounded to the
+    /// nearest value.
+    pub fn apply(
+        &self,
+        red: PWMValue,
+        green: PWMValue,
+        blue: PWMValue,
+    ) -> (PWMValue, PWMValue, PWMValue) {
+        let scale = |value: PWMValue, factor: u16| {
+            PWMValue::new(((value.value() as u32 * factor as u32 + 500) / 1000) as i32)
+        };
+        (
+            scale(red, self.red),
+            scale(green, self.green),
+            scale(blue, self.blue),
+        )
+    }
+
+    /// Scales 8-bit color components up to 12 bits and then applies the
+    /// calibration.  Doing both at once keeps the precision the 12 bits give.
+    pub fn to_rgb(&self, red: u8, green: u8, blue: u8) -> (PWMValue, PWMValue, PWMValue) {
+        self.apply(
+            PWMValue::from(red),
+            PWMValue::from(green),
+            PWMValue::from(blue),
+        )
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            red: 1000,
+            green: 1000,
+            blue: 1000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_white_point() {
+        let calibration = Calibration::from_white_point(166, 255, 204);
+        let (red, green, blue) = calibration.to_rgb(255, 255, 255);
+        assert_eq!(PWMValue::new(2666), red);
+        assert_eq!(PWMValue::max(), green);
+        assert_eq!(PWMValue::new(3276), blue);
+
+        let (red, green, blue) = calibration.to_rgb(0, 0, 0);
+        assert_eq!(PWMValue::min(), red);
+        assert_eq!(PWMValue::min(), green);
+        assert_eq!(PWMValue::min(), blue);
+
+        assert_eq!(
+            Calibration::default(),
+            Calibration::from_white_point(0, 0, 0)
+        );
+        assert_eq!(Calibration::default(), Calibration::new(100, 200, 255));
+    }
+}
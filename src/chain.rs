@@ -0,0 +1,842 @@
+//! Several boards can be chained together by wiring the data out of one board
+//! into the data in of the next, and sharing the latch, OE and clock lines.
+//! The chain behaves like one long shift register.  The first bits shifted
+//! out end up in the board furthest down the chain, so the flush starts with
+//! the last channel of the last board and works back to the first channel of
+//! the first board.
+//!
+//! The number of boards is a const generic parameter, so the buffer is sized
+//! at compile time and no allocation is needed.
+//!
+//! Firmware that runs on fixtures with different chain lengths can be built
+//! for the longest chain.  The bits for boards that aren't there fall off the
+//! end of the chain, so the boards that are there still get their own
+//! values.  If the data out of the last board is wired back to an input pin,
+//! `detect_chain_length` counts the boards, so the firmware knows how many
+//! LEDs it has.
+//!
+//! The chain keeps its frames packed the way they go out on the wire, and
+//! `write_pwm` repacks only the 12 bits of the channel it changes, so a flush
+//! goes straight to shifting.  Every flush still shifts the whole chain.  The
+//! boards share one latch and have no addresses, so the only way to move new
+//! bits into a board is to push them through every board in front of it, and
+//! the boards behind it have to be shifted again or they'd latch whatever was
+//! pushed into them.  When one board changes much more often than the rest,
+//! wire the boards as a `Bank` instead, so each one is flushed on its own.
+//!
+//! Fixtures are usually wired a board at a time, so `board` gives a view of
+//! one board's buffer, addressed by that board's own channels, with a label
+//! to say what it drives.  The view can clear or load the board's frame, and
+//! each board keeps track of whether it's changed since the last flush, for
+//! firmware that only wants to flush when a particular board needs it.
+//!
+//! Clone boards that shift or latch differently from the TLC5947 are handled
+//! by `set_protocol`, the same as a single board.  Every board in the chain
+//! takes the same protocol, and the packed frames are kept in its order.
+
+use crate::frame::{Frame, PackedFrame, FRAME_BITS};
+use crate::partial::{FlushProgress, PartialFlush};
+use crate::protocol::ProtocolConfig;
+use crate::timing::{Delay, NoDelay, Timing};
+use crate::{
+    pwm, shift_bits, shift_packed, Channel, DeviceError, DeviceSource, PWMPin, PinError, PinRole,
+    PinSource,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+/// The error returned when detecting the chain length.  Either reading the
+/// feedback pin failed, or one of the output pins did.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DetectError<I, P> {
+    Feedback(I),
+    Pin(PinError<P>),
+}
+
+impl<I, P> core::fmt::Display for DetectError<I, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DetectError::Feedback(_) => f.write_str("Failed to read the feedback pin"),
+            DetectError::Pin(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<I, P> From<PinError<P>> for DetectError<I, P> {
+    fn from(error: PinError<P>) -> Self {
+        DetectError::Pin(error)
+    }
+}
+
+/// The error from detecting the length of a chain with the pins `L`, `D`, `O`
+/// and `C`.
+type ChainDetectError<I, L, D, O, C> =
+    DetectError<<I as InputPin>::Error, DeviceSource<L, D, O, C>>;
+
+/// A chain of `N` boards driven from one set of four pins.  Board 0 is the
+/// board wired to the microcontroller.  Each board has its own buffer of 24
+/// values, addressed by the board number and the channel on that board.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::chain::PWM5947Chain;
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::C4;
+///
+/// let mut chain: PWM5947Chain<_, _, _, _, 3> = PWM5947Chain::new(Pin, Pin, Pin, Pin);
+/// chain.write_pwm(2, &C4, &PWMValue::max());
+/// chain.flush().ok();
+/// ```
+///
+/// Like the single board, the T parameter is the delay provider used to meet
+/// the minimum pulse widths, and defaults to `NoDelay`.  See `with_timing`.
+#[derive(Debug)]
+pub struct PWM5947Chain<L, D, O, C, const N: usize, T = NoDelay>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    buffer: [[pwm::PWMValue; 24]; N],
+    packed: [PackedFrame; N],
+
+    latch: PWMPin<L, DeviceSource<L, D, O, C>>,
+    data: PWMPin<D, DeviceSource<L, D, O, C>>,
+    oe: PWMPin<O, DeviceSource<L, D, O, C>>,
+    clock: PWMPin<C, DeviceSource<L, D, O, C>>,
+    delay: T,
+    timing: Timing,
+    protocol: ProtocolConfig,
+
+    partial: Option<PartialFlush<N>>,
+    dirty: [bool; N],
+    labels: [Option<&'static str>; N],
+    blank_during_latch: bool,
+}
+
+impl<L, D, O, C, const N: usize> PWM5947Chain<L, D, O, C, N>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+{
+    /// Create a new chain of boards.  Passes in the pins that will now be
+    /// owned by the chain.
+    pub fn new(latch: L, data: D, oe: O, clock: C) -> Self {
+        PWM5947Chain {
+            buffer: [[pwm::PWMValue::min(); 24]; N],
+            packed: [PackedFrame::default(); N],
+            latch: PWMPin::new(latch, PinRole::Latch, PinSource::Latch),
+            data: PWMPin::new(data, PinRole::Data, PinSource::Data),
+            oe: PWMPin::new(oe, PinRole::OE, PinSource::OE),
+            clock: PWMPin::new(clock, PinRole::Clock, PinSource::Clock),
+            delay: NoDelay,
+            timing: Timing::default(),
+            protocol: ProtocolConfig::TLC5947,
+            partial: None,
+            dirty: [false; N],
+            labels: [None; N],
+            blank_during_latch: true,
+        }
+    }
+}
+
+impl<L, D, O, C, const N: usize, T> PWM5947Chain<L, D, O, C, N, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Slows the protocol down to meet the boards' minimum pulse widths, the
+    /// same as `PWM5947::with_timing`.  The clock is held at each level for
+    /// at least `clock_ns` nanoseconds, and the latch is held high for at
+    /// least `latch_ns` nanoseconds.  A long chain has long clock and latch
+    /// lines, so it's more likely to need it than a single board.
+    pub fn with_timing<U>(
+        self,
+        delay: U,
+        clock_ns: u32,
+        latch_ns: u32,
+    ) -> PWM5947Chain<L, D, O, C, N, U>
+    where
+        U: Delay,
+    {
+        PWM5947Chain {
+            buffer: self.buffer,
+            packed: self.packed,
+            latch: self.latch,
+            data: self.data,
+            oe: self.oe,
+            clock: self.clock,
+            delay,
+            timing: Timing { clock_ns, latch_ns },
+            protocol: self.protocol,
+            partial: self.partial,
+            dirty: self.dirty,
+            labels: self.labels,
+            blank_during_latch: self.blank_during_latch,
+        }
+    }
+
+    /// Shifts and latches every board's frame the protocol's way, for clone
+    /// boards, the same as `PWM5947::set_protocol`.  The packed frames are
+    /// repacked in the new order, and every board is marked dirty.
+    pub fn set_protocol(&mut self, protocol: ProtocolConfig) {
+        self.latch.active_low = protocol.latch_active_low();
+        self.protocol = protocol;
+        for (packed, values) in self.packed.iter_mut().zip(self.buffer.iter()) {
+            *packed = PackedFrame::pack(&protocol.arrange(values));
+        }
+        self.dirty = [true; N];
+    }
+
+    /// The protocol the frames are shifted and latched in.
+    pub fn protocol(&self) -> &ProtocolConfig {
+        &self.protocol
+    }
+
+    /// Turns OE blanking during the latch on or off, the same as
+    /// `PWM5947::set_blank_during_latch`.  It's on to start with.
+    pub fn set_blank_during_latch(&mut self, blank: bool) {
+        self.blank_during_latch = blank;
+    }
+
+    /// Whether OE is pulled high while the latch is toggled.
+    pub fn is_blank_during_latch(&self) -> bool {
+        self.blank_during_latch
+    }
+
+    /// The number of boards in the chain.
+    pub fn boards(&self) -> usize {
+        N
+    }
+
+    /// Sets the pins to known, good values and clears the buffers of every
+    /// board to the PWM's `min` value.  Every board is marked dirty, so the
+    /// cleared frames go out on the next refresh.
+    pub fn begin(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.oe.set_low()?;
+        self.latch.set_low()?;
+        self.data.set_low()?;
+        self.clock.set_low()?;
+
+        self.buffer = [[pwm::PWMValue::min(); 24]; N];
+        self.packed = [PackedFrame::default(); N];
+        self.dirty = [true; N];
+
+        Ok(())
+    }
+
+    /// Writes a value into the given channel of the given board.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board number is not less than the number of boards.
+    pub fn write_pwm(&mut self, board: usize, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.board(board).write_pwm(channel, pwm_value);
+    }
+
+    /// Reads the value in the given channel of the given board.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board number is not less than the number of boards.
+    pub fn read_pwm(&self, board: usize, channel: &Channel) -> pwm::PWMValue {
+        self.buffer[board][channel.0]
+    }
+
+    /// A view of one board's buffer, addressed by its own channels.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::chain::PWM5947Chain;
+    /// use ledpwm5947::frame::Frame;
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::C1;
+    ///
+    /// let mut chain: PWM5947Chain<_, _, _, _, 4> = PWM5947Chain::new(Pin, Pin, Pin, Pin);
+    ///
+    /// let mut shelf = chain.board(2);
+    /// shelf.set_label("top shelf");
+    /// shelf.load(&Frame::new([PWMValue::new(500); 24]));
+    /// shelf.write_pwm(&C1, &PWMValue::max());
+    ///
+    /// assert_eq!(PWMValue::max(), chain.read_pwm(2, &C1));
+    /// assert_eq!(Some("top shelf"), chain.board(2).label());
+    /// assert!(chain.is_board_dirty(2));
+    /// assert!(!chain.is_board_dirty(1));
+    ///
+    /// chain.flush().ok();
+    /// assert!(!chain.is_board_dirty(2));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board number is not less than the number of boards.
+    pub fn board(&mut self, board: usize) -> BoardView<'_> {
+        BoardView {
+            index: board,
+            values: &mut self.buffer[board],
+            packed: &mut self.packed[board],
+            protocol: self.protocol,
+            dirty: &mut self.dirty[board],
+            label: &mut self.labels[board],
+        }
+    }
+
+    /// Whether the board's buffer has changed since the last flush.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board number is not less than the number of boards.
+    pub fn is_board_dirty(&self, board: usize) -> bool {
+        self.dirty[board]
+    }
+
+    /// Whether any board's buffer has changed since the last flush.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.iter().any(|dirty| *dirty)
+    }
+
+    /// Writes a value into one channel and flushes the chain, for a single
+    /// LED that changes often, like a status light.  Only the channel's 12
+    /// bits are repacked, but the whole chain is still shifted, since the
+    /// boards can't be updated on their own.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board number is not less than the number of boards.
+    pub fn flush_channel(
+        &mut self,
+        board: usize,
+        channel: &Channel,
+        pwm_value: &pwm::PWMValue,
+    ) -> Result<(), DeviceError<L, D, O, C>> {
+        self.write_pwm(board, channel, pwm_value);
+        self.flush()
+    }
+
+    /// Sets the buffers of every board back to zeros and then flushes to turn
+    /// off all the LEDs.
+    pub fn all_black(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        for board in 0..N {
+            self.board(board).clear();
+        }
+        self.flush()
+    }
+
+    /// Flushes the buffers to the boards.  The values are shifted out starting
+    /// with the last channel of the last board, so that when the latch is
+    /// toggled every board holds its own values.  Every board is marked
+    /// clean once the latch is toggled.  OE is pulled high while the latch is
+    /// toggled, unless that's been turned off with `set_blank_during_latch`.
+    pub fn flush(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.flush_frame(self.blank_during_latch)
+    }
+
+    /// The flush, optionally pulling OE high while the latch is toggled.
+    pub(crate) fn flush_frame(&mut self, blank: bool) -> Result<(), DeviceError<L, D, O, C>> {
+        self.partial = None;
+        self.latch.set_low()?;
+
+        for board in self.packed.iter().rev() {
+            shift_packed(
+                &mut self.data,
+                &mut self.clock,
+                &mut self.delay,
+                self.timing.clock_ns,
+                board,
+            )?;
+        }
+
+        self.latch_shifted(blank)?;
+        self.dirty = [false; N];
+        Ok(())
+    }
+
+    /// Leaves the clock low and toggles the latch, with OE held high around
+    /// it if `blank` is set.
+    fn latch_shifted(&mut self, blank: bool) -> Result<(), DeviceError<L, D, O, C>> {
+        self.clock.set_low()?;
+        if blank {
+            self.oe.set_high()?;
+        }
+        self.latch.set_high()?;
+        self.delay.delay_ns(self.timing.latch_ns);
+        self.latch.set_low()?;
+        if blank {
+            self.oe.set_low()?;
+        }
+        Ok(())
+    }
+
+    /// Counts the boards in the chain, using an input pin wired to the data
+    /// out of the last board.  It clocks enough zeros through to clear a
+    /// chain one board longer than `N`, then a single one, and counts the
+    /// clocks until the one comes out of the end.  It looks for up to `N`
+    /// boards and returns `None` if the one never arrives, which means the
+    /// chain is longer or the feedback isn't wired.  A chain more than one
+    /// board too long may still hold old bits, which can look like the one.
+    ///
+    /// The latch isn't toggled, so the LEDs keep showing the last frame, but
+    /// the shift registers are left with zeros in them.  Flush afterwards
+    /// before anything else latches.
+    pub fn detect_chain_length<I>(
+        &mut self,
+        feedback: &I,
+    ) -> Result<Option<usize>, ChainDetectError<I, L, D, O, C>>
+    where
+        I: InputPin,
+    {
+        self.partial = None;
+        self.latch.set_low()?;
+
+        let clear = core::iter::repeat_n(false, (N + 1) * FRAME_BITS);
+        shift_bits(
+            &mut self.data,
+            &mut self.clock,
+            &mut self.delay,
+            self.timing.clock_ns,
+            clear,
+        )?;
+
+        let mut marker = core::iter::once(true).chain(core::iter::repeat(false));
+        let mut length = None;
+        for clocks in 1..=N * FRAME_BITS {
+            shift_bits(
+                &mut self.data,
+                &mut self.clock,
+                &mut self.delay,
+                self.timing.clock_ns,
+                marker.by_ref().take(1),
+            )?;
+            if feedback.is_high().map_err(DetectError::Feedback)? {
+                length = Some((clocks + FRAME_BITS / 2) / FRAME_BITS);
+                break;
+            }
+        }
+
+        self.clock.set_low()?;
+        self.data.set_low()?;
+        Ok(length)
+    }
+
+    /// Shifts out at most `budget_bits` bits of the chain's frames, and
+    /// latches them once all `288 * N` bits are out.  The buffers are copied
+    /// when the first bits go, and the next call carries on with the rest.
+    /// See the `partial` module.  If a pin fails, the flush is abandoned and
+    /// every board is marked dirty again.
+    pub fn flush_partial(
+        &mut self,
+        budget_bits: usize,
+    ) -> Result<FlushProgress, DeviceError<L, D, O, C>> {
+        let result = self.shift_partial(budget_bits);
+        if result.is_err() {
+            self.partial = None;
+            self.dirty = [true; N];
+        }
+        result
+    }
+
+    fn shift_partial(
+        &mut self,
+        budget_bits: usize,
+    ) -> Result<FlushProgress, DeviceError<L, D, O, C>> {
+        let mut partial = match self.partial.take() {
+            Some(partial) => partial,
+            None => {
+                self.dirty = [false; N];
+                PartialFlush::new(self.packed)
+            }
+        };
+
+        if partial.is_starting() {
+            self.latch.set_low()?;
+        }
+
+        shift_bits(
+            &mut self.data,
+            &mut self.clock,
+            &mut self.delay,
+            self.timing.clock_ns,
+            partial.take_bits(budget_bits),
+        )?;
+
+        let remaining = partial.remaining();
+        if remaining > 0 {
+            self.partial = Some(partial);
+            return Ok(FlushProgress::Shifting { remaining });
+        }
+
+        self.latch_shifted(self.blank_during_latch)?;
+        Ok(FlushProgress::Latched)
+    }
+}
+
+/// One board's buffer in a chain, from `PWM5947Chain::board`.  Writes through
+/// the view show on the chain's next flush.
+#[derive(Debug)]
+pub struct BoardView<'a> {
+    index: usize,
+    values: &'a mut [pwm::PWMValue; 24],
+    packed: &'a mut PackedFrame,
+    protocol: ProtocolConfig,
+    dirty: &'a mut bool,
+    label: &'a mut Option<&'static str>,
+}
+
+impl BoardView<'_> {
+    /// The board's place in the chain, where 0 is the board wired to the
+    /// microcontroller.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The board's label, if it has one.
+    pub fn label(&self) -> Option<&'static str> {
+        *self.label
+    }
+
+    /// Gives the board a label, naming the fixture it drives.
+    pub fn set_label(&mut self, label: &'static str) {
+        *self.label = Some(label);
+    }
+
+    /// Removes the board's label.
+    pub fn clear_label(&mut self) {
+        *self.label = None;
+    }
+
+    /// Writes a value into the given channel of the board.
+    pub fn write_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.values[channel.0] = *pwm_value;
+        let (slot, value) = self.protocol.place(channel, *pwm_value);
+        self.packed.set(&slot, value);
+        *self.dirty = true;
+    }
+
+    /// Reads the value in the given channel of the board.
+    pub fn read_pwm(&self, channel: &Channel) -> pwm::PWMValue {
+        self.values[channel.0]
+    }
+
+    /// Sets every channel of the board back to zero.
+    pub fn clear(&mut self) {
+        self.load(&Frame::default());
+    }
+
+    /// Writes a whole frame into the board's buffer.
+    pub fn load(&mut self, frame: &Frame) {
+        *self.values = frame.0;
+        *self.packed = PackedFrame::pack(&self.protocol.arrange(&frame.0));
+        *self.dirty = true;
+    }
+
+    /// A copy of the board's buffer.
+    pub fn snapshot(&self) -> Frame {
+        Frame::new(*self.values)
+    }
+
+    /// Whether the board's buffer has changed since the last flush.
+    pub fn is_dirty(&self) -> bool {
+        *self.dirty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::controller::LedController;
+    use crate::test_pins::{ClockPin, DataPin, IdlePin, Wire};
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    // Reads the bit that went into the chain `boards` boards ago, or a high
+    // bit before that many have been shifted, as if the chain held garbage.
+    struct FeedbackPin(Rc<RefCell<Wire>>, usize);
+
+    impl InputPin for FeedbackPin {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            let bits = &self.0.borrow().bits;
+            let delay = self.1 * FRAME_BITS;
+            Ok(bits.len() < delay || bits[bits.len() - delay])
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    #[test]
+    fn test_detect_chain_length() {
+        for boards in 1..=4 {
+            let wire = Rc::new(RefCell::new(Wire::default()));
+            let mut chain: PWM5947Chain<_, _, _, _, 3> = PWM5947Chain::new(
+                IdlePin,
+                DataPin(wire.clone()),
+                IdlePin,
+                ClockPin(wire.clone()),
+            );
+
+            let feedback = FeedbackPin(wire.clone(), boards);
+            let expected = if boards <= 3 { Some(boards) } else { None };
+            assert_eq!(Ok(expected), chain.detect_chain_length(&feedback));
+        }
+    }
+
+    #[test]
+    fn test_flush_order() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut chain: PWM5947Chain<_, _, _, _, 3> = PWM5947Chain::new(
+            IdlePin,
+            DataPin(wire.clone()),
+            IdlePin,
+            ClockPin(wire.clone()),
+        );
+        assert!(chain.begin().is_ok());
+
+        chain.write_pwm(2, &crate::C24, &pwm::PWMValue::new(0x800));
+        chain.write_pwm(1, &crate::C12, &pwm::PWMValue::new(0xa5c));
+        chain.write_pwm(0, &crate::C1, &pwm::PWMValue::new(0x001));
+        assert!(chain.flush().is_ok());
+
+        // The last board's last channel goes first, each value most
+        // significant bit first.
+        let mut expected = Vec::new();
+        for board in (0..3).rev() {
+            for channel in (0..24).rev() {
+                let value = match (board, channel) {
+                    (2, 23) => 0x800,
+                    (1, 11) => 0xa5c,
+                    (0, 0) => 0x001,
+                    _ => 0,
+                };
+                expected.extend((0..12).rev().map(|bit| (value >> bit) & 1 == 1));
+            }
+        }
+        assert_eq!(expected, wire.borrow().bits);
+    }
+
+    #[test]
+    fn test_packed_image() {
+        let mut chain: PWM5947Chain<_, _, _, _, 2> =
+            PWM5947Chain::new(IdlePin, IdlePin, IdlePin, IdlePin);
+
+        for (i, channel) in crate::ALL_CHANNELS.iter().enumerate() {
+            let value = pwm::PWMValue::new(0xfff - i as i32 * 97);
+            chain.write_pwm(0, channel, &value);
+            chain.write_pwm(1, channel, &pwm::PWMValue::max());
+        }
+        chain.write_pwm(1, &crate::C7, &pwm::PWMValue::new(0x5a5));
+        chain.write_pwm(1, &crate::C8, &pwm::PWMValue::new(0x0c3));
+
+        for board in 0..2 {
+            assert_eq!(PackedFrame::pack(&chain.buffer[board]), chain.packed[board]);
+        }
+    }
+
+    #[test]
+    fn test_protocol() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(
+            IdlePin,
+            DataPin(wire.clone()),
+            IdlePin,
+            ClockPin(wire.clone()),
+        );
+        chain.write_pwm(1, &crate::C1, &pwm::PWMValue::new(0x001));
+        chain.set_protocol(ProtocolConfig {
+            bit_order: crate::protocol::BitOrder::LsbFirst,
+            channel_order: crate::protocol::ChannelOrder::Ascending,
+            latch: crate::protocol::LatchPolarity::ActiveLow,
+        });
+        assert!(chain.is_dirty());
+        assert!(chain.latch.active_low);
+        chain
+            .board(0)
+            .write_pwm(&crate::C24, &pwm::PWMValue::new(0x003));
+
+        for board in 0..2 {
+            let arranged = chain.protocol().arrange(&chain.buffer[board]);
+            assert_eq!(PackedFrame::pack(&arranged), chain.packed[board]);
+        }
+
+        // Each board's first channel goes first, least significant bit first.
+        let mut expected = Vec::new();
+        for board in (0..2).rev() {
+            for channel in 0..24 {
+                let value = match (board, channel) {
+                    (1, 0) => 0x001,
+                    (0, 23) => 0x003,
+                    _ => 0,
+                };
+                expected.extend((0..12).map(|bit| (value >> bit) & 1 == 1));
+            }
+        }
+        assert!(chain.flush().is_ok());
+        assert_eq!(expected, wire.borrow().bits);
+
+        wire.borrow_mut().bits.clear();
+        assert_eq!(Ok(FlushProgress::Latched), chain.flush_partial(2 * 288));
+        assert_eq!(expected, wire.borrow().bits);
+    }
+
+    #[test]
+    fn test_board_view() {
+        let mut chain: PWM5947Chain<_, _, _, _, 3> =
+            PWM5947Chain::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let frame = Frame::new([pwm::PWMValue::new(0x123); 24]);
+
+        let mut board = chain.board(1);
+        assert_eq!(1, board.index());
+        assert_eq!(None, board.label());
+        board.set_label("left");
+        board.load(&frame);
+        board.write_pwm(&crate::C2, &pwm::PWMValue::max());
+        assert!(board.is_dirty());
+        assert_eq!(pwm::PWMValue::max(), board.read_pwm(&crate::C2));
+
+        assert!(chain.is_dirty());
+        assert!(!chain.is_board_dirty(0));
+        assert_eq!(frame.0[0], chain.read_pwm(1, &crate::C1));
+        assert_eq!(PackedFrame::pack(&chain.buffer[1]), chain.packed[1]);
+
+        assert!(chain.flush().is_ok());
+        assert!(!chain.is_dirty());
+        chain.board(1).clear();
+        assert_eq!(Frame::default(), chain.board(1).snapshot());
+        assert_eq!(PackedFrame::default(), chain.packed[1]);
+        assert!(chain.is_board_dirty(1));
+        assert_eq!(Some("left"), chain.board(1).label());
+    }
+
+    #[derive(Default)]
+    struct CountingDelay {
+        calls: usize,
+        total_ns: u32,
+    }
+
+    impl Delay for CountingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.calls += 1;
+            self.total_ns += ns;
+        }
+    }
+
+    #[test]
+    fn test_timing() {
+        let mut chain: PWM5947Chain<_, _, _, _, 2> =
+            PWM5947Chain::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        chain.write_pwm(1, &crate::C1, &pwm::PWMValue::max());
+        let mut chain = chain.with_timing(CountingDelay::default(), 10, 50);
+        assert_eq!(pwm::PWMValue::max(), chain.read_pwm(1, &crate::C1));
+
+        assert!(chain.flush().is_ok());
+        assert_eq!(2 * FRAME_BITS * 2 + 1, chain.delay.calls);
+        assert_eq!(2 * FRAME_BITS as u32 * 2 * 10 + 50, chain.delay.total_ns);
+
+        chain.delay = CountingDelay::default();
+        assert_eq!(Ok(FlushProgress::Latched), chain.flush_partial(1000));
+        assert_eq!(2 * FRAME_BITS * 2 + 1, chain.delay.calls);
+    }
+
+    // Fails every write while the shared flag is set.
+    struct FlakyPin(Rc<Cell<bool>>);
+
+    impl OutputPin for FlakyPin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            if self.0.get() {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.set_low()
+        }
+    }
+
+    #[test]
+    fn test_failed_partial_flush() {
+        let failing = Rc::new(Cell::new(false));
+        let pin = || FlakyPin(failing.clone());
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(pin(), pin(), pin(), pin());
+        assert!(chain.flush().is_ok());
+        chain.write_pwm(1, &crate::C3, &pwm::PWMValue::max());
+
+        assert_eq!(
+            Ok(FlushProgress::Shifting { remaining: 476 }),
+            chain.flush_partial(100)
+        );
+        assert!(!chain.is_dirty());
+
+        failing.set(true);
+        assert!(chain.flush_partial(100).is_err());
+        assert!(chain.is_board_dirty(0));
+        assert!(chain.is_board_dirty(1));
+
+        failing.set(false);
+        assert_eq!(Ok(FlushProgress::Latched), chain.flush_partial(1000));
+        assert!(!chain.is_dirty());
+    }
+
+    #[test]
+    fn test_flush_channel() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(
+            IdlePin,
+            DataPin(wire.clone()),
+            IdlePin,
+            ClockPin(wire.clone()),
+        );
+
+        assert!(chain
+            .flush_channel(1, &crate::C24, &pwm::PWMValue::new(0x800))
+            .is_ok());
+
+        let bits = &wire.borrow().bits;
+        assert_eq!(2 * 24 * 12, bits.len());
+        assert!(bits[0]);
+        assert_eq!(1, bits.iter().filter(|bit| **bit).count());
+    }
+
+    #[test]
+    fn test_begin_marks_dirty() {
+        let mut chain: PWM5947Chain<_, _, _, _, 2> =
+            PWM5947Chain::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        assert!(!chain.needs_flush());
+
+        assert!(chain.begin().is_ok());
+        assert!(chain.needs_flush());
+        assert!(chain.flush().is_ok());
+        assert!(!chain.needs_flush());
+    }
+}
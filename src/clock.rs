@@ -0,0 +1,96 @@
+//! The clock module lets animations run on real time rather than ticks.  A
+//! tick only means something if the main loop runs at a steady rate, and it
+//! rarely does once it talks to a radio or waits on a sensor.  Passing the
+//! current time to `tick_at` instead advances the fades and sequences by the
+//! milliseconds that have actually passed, so their durations are in
+//! milliseconds.
+//!
+//! An `Instant` is a millisecond timestamp from any monotonic source, like a
+//! SysTick count or the RTIC monotonic.  It wraps around after about 49 days,
+//! which is fine as long as the gap between two calls is shorter than that.
+//! A `Clock` is anything that can tell the current time, so a timer
+//! peripheral can be handed over once instead of read at every call.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::animation::Easing;
+//! use ledpwm5947::clock::Instant;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//!
+//! // Fade up over 2 seconds.
+//! device.fade_to(&C1, PWMValue::new(2000), 2000, Easing::Linear);
+//! device.tick_at(Instant::from_millis(10_000));
+//! device.tick_at(Instant::from_millis(10_500));
+//! assert_eq!(PWMValue::new(500), device.read_pwm(&C1));
+//!
+//! // A slow pass through the loop still lands in the right place.
+//! device.tick_at(Instant::from_millis(11_900));
+//! assert_eq!(PWMValue::new(1900), device.read_pwm(&C1));
+//! ```
+
+/// A point in time, in milliseconds from whenever the clock started.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Instant {
+    millis: u32,
+}
+
+impl Instant {
+    /// Create an instant from a millisecond count.
+    pub const fn from_millis(millis: u32) -> Self {
+        Instant { millis }
+    }
+
+    /// The millisecond count.
+    pub const fn as_millis(&self) -> u32 {
+        self.millis
+    }
+
+    /// The milliseconds from an earlier instant to this one.  The count is
+    /// allowed to wrap around between the two.
+    ///
+    /// ```
+    /// use ledpwm5947::clock::Instant;
+    ///
+    /// let earlier = Instant::from_millis(u32::MAX - 9);
+    /// assert_eq!(20, Instant::from_millis(10).duration_since(earlier));
+    /// ```
+    pub const fn duration_since(&self, earlier: Instant) -> u32 {
+        self.millis.wrapping_sub(earlier.millis)
+    }
+}
+
+/// A source of the current time.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> Instant;
+}
+
+/// Remembers the last instant it was given, to work out how much time passed
+/// since.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub(crate) struct Elapsed {
+    last: Option<Instant>,
+}
+
+impl Elapsed {
+    /// The milliseconds since the last call, which is zero on the first call.
+    pub(crate) fn since_last(&mut self, now: Instant) -> u32 {
+        let elapsed = match self.last {
+            Some(last) => now.duration_since(last),
+            None => 0,
+        };
+        self.last = Some(now);
+        elapsed
+    }
+}
@@ -0,0 +1,425 @@
+//! Color spaces that are easier to work with than raw red, green and blue.
+//! Hue, saturation and value (HSV) or lightness (HSL) make it simple to
+//! rotate through the colors of the rainbow or wash a color out.  A color
+//! temperature picks a white between the orange of a candle and the blue of
+//! an overcast sky.
+//!
+//! The conversions use integer math only and work at the full 12 bits, so
+//! they return `PWMValue` triples that can go straight to `RgbLed::set_color`.
+//! On parts with a floating point unit, like the Cortex-M4F and M7, the
+//! `float` feature adds `HsvF32`, which takes its hue, saturation and value
+//! as `f32` for code that already works in floating point.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::color::Hsv;
+//! use ledpwm5947::rgb::RgbLed;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let led = RgbLed::contiguous(&C1).expect("C1 to C3 are on the board");
+//!
+//! let mut color = Hsv::new(0, 255, 255);
+//! for _ in 0..36 {
+//!     let (red, green, blue) = color.to_rgb();
+//!     led.set_color(&mut device, red, green, blue);
+//!     device.flush().ok();
+//!     color = color.rotate(10);
+//! }
+//! ```
+
+use crate::pwm::{PWMValue, PWM_MASK};
+
+const FULL: i32 = PWM_MASK as i32;
+
+/// Scales an 8-bit component up to 12 bits, rounded.
+fn to_12_bits(component: u8) -> i32 {
+    (component as i32 * FULL + 127) / 255
+}
+
+/// Builds the RGB triple from a hue, the chroma, and the amount added to all
+/// three components.  This is the part HSV and HSL have in common.
+fn from_hue(hue: u16, chroma: i32, offset: i32) -> (PWMValue, PWMValue, PWMValue) {
+    let hue = (hue % 360) as i32;
+    let second = (chroma * (60 - (hue % 120 - 60).abs()) + 30) / 60;
+
+    let (red, green, blue) = match hue / 60 {
+        0 => (chroma, second, 0),
+        1 => (second, chroma, 0),
+        2 => (0, chroma, second),
+        3 => (0, second, chroma),
+        4 => (second, 0, chroma),
+        _ => (chroma, 0, second),
+    };
+
+    (
+        PWMValue::new(red + offset),
+        PWMValue::new(green + offset),
+        PWMValue::new(blue + offset),
+    )
+}
+
+/// A color as hue, saturation and value.  The hue is in degrees, with red at
+/// 0, green at 120 and blue at 240, and wraps around past 360.  The
+/// saturation and value are 8-bit, so 255 is fully saturated and as bright as
+/// the LED goes.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hsv {
+    pub hue: u16,
+    pub saturation: u8,
+    pub value: u8,
+}
+
+impl Hsv {
+    /// Create a color from its hue, saturation and value.
+    pub const fn new(hue: u16, saturation: u8, value: u8) -> Self {
+        Hsv {
+            hue,
+            saturation,
+            value,
+        }
+    }
+
+    /// The same color with the hue turned by the given number of degrees.
+    /// Negative degrees turn it backwards.
+    ///
+    /// ```
+    /// use ledpwm5947::color::Hsv;
+    ///
+    /// assert_eq!(Hsv::new(10, 255, 255), Hsv::new(350, 255, 255).rotate(20));
+    /// assert_eq!(Hsv::new(340, 255, 255), Hsv::new(10, 255, 255).rotate(-30));
+    /// ```
+    pub fn rotate(&self, degrees: i16) -> Self {
+        let hue = (self.hue as i32 + degrees as i32).rem_euclid(360) as u16;
+        Hsv { hue, ..*self }
+    }
+
+    /// Converts the color to 12-bit red, green and blue.
+    ///
+    /// ```
+    /// use ledpwm5947::color::Hsv;
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let (red, green, blue) = Hsv::new(120, 255, 255).to_rgb();
+    /// assert_eq!(PWMValue::min(), red);
+    /// assert_eq!(PWMValue::max(), green);
+    /// assert_eq!(PWMValue::min(), blue);
+    /// ```
+    pub fn to_rgb(&self) -> (PWMValue, PWMValue, PWMValue) {
+        let value = to_12_bits(self.value);
+        let chroma = (value * self.saturation as i32 + 127) / 255;
+        from_hue(self.hue, chroma, value - chroma)
+    }
+}
+
+/// A color as hue, saturation and value in `f32`.  The hue is in degrees,
+/// like `Hsv`, and wraps around in either direction.  The saturation and
+/// value go from 0.0 to 1.0 and are clamped to that range.  It needs the
+/// `float` feature.
+#[cfg(feature = "float")]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct HsvF32 {
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+#[cfg(feature = "float")]
+impl HsvF32 {
+    /// Create a color from its hue, saturation and value.
+    pub const fn new(hue: f32, saturation: f32, value: f32) -> Self {
+        HsvF32 {
+            hue,
+            saturation,
+            value,
+        }
+    }
+
+    /// Converts the color to 12-bit red, green and blue.  A `NaN` anywhere
+    /// is treated as zero.
+    ///
+    /// ```
+    /// use ledpwm5947::color::HsvF32;
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let (red, green, blue) = HsvF32::new(-120.0, 1.0, 0.5).to_rgb();
+    /// assert_eq!(PWMValue::min(), red);
+    /// assert_eq!(PWMValue::min(), green);
+    /// assert_eq!(PWMValue::new(2048), blue);
+    /// ```
+    pub fn to_rgb(&self) -> (PWMValue, PWMValue, PWMValue) {
+        let unit = |x: f32| if x > 0.0 { x.min(1.0) } else { 0.0 };
+        let value = unit(self.value);
+        let chroma = value * unit(self.saturation);
+
+        let mut hue = self.hue % 360.0;
+        if hue < 0.0 {
+            hue += 360.0;
+        }
+        let hue = if hue.is_nan() { 0.0 } else { hue / 60.0 };
+        let sector = hue as u32;
+        let slope = hue - (sector & !1) as f32 - 1.0;
+        let second = chroma * (1.0 - if slope < 0.0 { -slope } else { slope });
+
+        let (red, green, blue) = match sector {
+            0 => (chroma, second, 0.0),
+            1 => (second, chroma, 0.0),
+            2 => (0.0, chroma, second),
+            3 => (0.0, second, chroma),
+            4 => (second, 0.0, chroma),
+            _ => (chroma, 0.0, second),
+        };
+
+        let offset = value - chroma;
+        (
+            PWMValue::from_f32(red + offset),
+            PWMValue::from_f32(green + offset),
+            PWMValue::from_f32(blue + offset),
+        )
+    }
+}
+
+/// A color as hue, saturation and lightness.  The hue works the same as for
+/// `Hsv`.  A lightness around 128 gives the pure color, while 0 is black and
+/// 255 is white whatever the hue.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hsl {
+    pub hue: u16,
+    pub saturation: u8,
+    pub lightness: u8,
+}
+
+impl Hsl {
+    /// Create a color from its hue, saturation and lightness.
+    pub const fn new(hue: u16, saturation: u8, lightness: u8) -> Self {
+        Hsl {
+            hue,
+            saturation,
+            lightness,
+        }
+    }
+
+    /// The same color with the hue turned by the given number of degrees.
+    pub fn rotate(&self, degrees: i16) -> Self {
+        let hue = (self.hue as i32 + degrees as i32).rem_euclid(360) as u16;
+        Hsl { hue, ..*self }
+    }
+
+    /// Converts the color to 12-bit red, green and blue.
+    ///
+    /// ```
+    /// use ledpwm5947::color::Hsl;
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let (red, green, blue) = Hsl::new(0, 255, 255).to_rgb();
+    /// assert_eq!(PWMValue::max(), red);
+    /// assert_eq!(PWMValue::max(), green);
+    /// assert_eq!(PWMValue::max(), blue);
+    /// ```
+    pub fn to_rgb(&self) -> (PWMValue, PWMValue, PWMValue) {
+        let lightness = to_12_bits(self.lightness);
+        let spread = FULL - (2 * lightness - FULL).abs();
+        let chroma = (spread * self.saturation as i32 + 127) / 255;
+        from_hue(self.hue, chroma, lightness - chroma / 2)
+    }
+}
+
+/// An approximation of a black body's color, in 12-bit red, green and blue,
+/// every 500 kelvin from 1000K to 12000K.
+const BLACK_BODY: [[u16; 3]; 23] = [
+    [4095, 1091, 0],
+    [4095, 1738, 0],
+    [4095, 2198, 223],
+    [4095, 2554, 1125],
+    [4095, 2846, 1765],
+    [4095, 3092, 2262],
+    [4095, 3305, 2667],
+    [4095, 3493, 3010],
+    [4095, 3662, 3307],
+    [4095, 3814, 3569],
+    [4095, 3953, 3803],
+    [4095, 4081, 4015],
+    [3896, 3888, 4095],
+    [3691, 3771, 4095],
+    [3552, 3690, 4095],
+    [3448, 3628, 4095],
+    [3366, 3579, 4095],
+    [3297, 3537, 4095],
+    [3239, 3502, 4095],
+    [3189, 3471, 4095],
+    [3144, 3443, 4095],
+    [3105, 3419, 4095],
+    [3069, 3396, 4095],
+];
+
+/// A white given as its correlated color temperature, in kelvin.  Candles are
+/// around 1900K, incandescent bulbs 2700K, and daylight about 6500K.  Higher
+/// temperatures are cooler, bluer whites.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct ColorTemperature {
+    pub kelvin: u16,
+}
+
+impl ColorTemperature {
+    /// The warmest temperature in the color table.
+    pub const MIN_KELVIN: u16 = 1000;
+    /// The coolest temperature in the color table.
+    pub const MAX_KELVIN: u16 = 12000;
+
+    /// Create a color temperature in kelvin.
+    pub const fn new(kelvin: u16) -> Self {
+        ColorTemperature { kelvin }
+    }
+
+    /// Converts the white to 12-bit red, green and blue for an RGB LED, at
+    /// full brightness.  It interpolates in a table of black body colors, so
+    /// temperatures outside 1000K to 12000K are clamped to the ends.
+    ///
+    /// ```
+    /// use ledpwm5947::color::ColorTemperature;
+    ///
+    /// let (red, _, blue) = ColorTemperature::new(2700).to_rgb();
+    /// assert!(red > blue, "warm whites are mostly red");
+    /// ```
+    pub fn to_rgb(&self) -> (PWMValue, PWMValue, PWMValue) {
+        let kelvin = self.kelvin.clamp(Self::MIN_KELVIN, Self::MAX_KELVIN) as i32;
+        let offset = kelvin - Self::MIN_KELVIN as i32;
+        let index = (offset / 500) as usize;
+        let fraction = offset % 500;
+
+        let low = BLACK_BODY[index];
+        let high = BLACK_BODY[(index + 1).min(BLACK_BODY.len() - 1)];
+        let blend = |i: usize| {
+            PWMValue::new(low[i] as i32 + (high[i] as i32 - low[i] as i32) * fraction / 500)
+        };
+
+        (blend(0), blend(1), blend(2))
+    }
+
+    /// Splits the brightness between a warm and a cool white LED to make this
+    /// temperature, for tunable white strips.  Returns the warm and then the
+    /// cool value, which always add up to the brightness.  The mix is linear
+    /// in mireds, one million over the temperature, which looks more even to
+    /// the eye than mixing linearly in kelvin.  Temperatures outside the two
+    /// LEDs give just the nearer one.
+    ///
+    /// ```
+    /// use ledpwm5947::color::ColorTemperature;
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let warm = ColorTemperature::new(2700);
+    /// let cool = ColorTemperature::new(6500);
+    ///
+    /// let (w, c) = ColorTemperature::new(2700).mix(warm, cool, PWMValue::max());
+    /// assert_eq!((PWMValue::max(), PWMValue::min()), (w, c));
+    ///
+    /// let (w, c) = ColorTemperature::new(4000).mix(warm, cool, PWMValue::max());
+    /// assert!(w < c);
+    /// ```
+    pub fn mix(
+        &self,
+        warm: ColorTemperature,
+        cool: ColorTemperature,
+        brightness: PWMValue,
+    ) -> (PWMValue, PWMValue) {
+        let mired = |t: ColorTemperature| 1_000_000 / t.kelvin.max(1) as i64;
+        let warm_mired = mired(warm);
+        let cool_mired = mired(cool);
+        let span = warm_mired - cool_mired;
+        let total = brightness.value() as i64;
+
+        let cool_part = if span <= 0 {
+            0
+        } else {
+            let distance = (warm_mired - mired(*self)).clamp(0, span);
+            (total * distance + span / 2) / span
+        };
+
+        (
+            PWMValue::new((total - cool_part) as i32),
+            PWMValue::new(cool_part as i32),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(red: i32, green: i32, blue: i32) -> (PWMValue, PWMValue, PWMValue) {
+        (
+            PWMValue::new(red),
+            PWMValue::new(green),
+            PWMValue::new(blue),
+        )
+    }
+
+    #[test]
+    fn test_hsv() {
+        assert_eq!(rgb(4095, 0, 0), Hsv::new(0, 255, 255).to_rgb());
+        assert_eq!(rgb(4095, 4095, 0), Hsv::new(60, 255, 255).to_rgb());
+        assert_eq!(rgb(0, 0, 4095), Hsv::new(240, 255, 255).to_rgb());
+        assert_eq!(rgb(4095, 0, 0), Hsv::new(360, 255, 255).to_rgb());
+        assert_eq!(rgb(4095, 2048, 0), Hsv::new(30, 255, 255).to_rgb());
+        assert_eq!(rgb(4095, 4095, 4095), Hsv::new(200, 0, 255).to_rgb());
+        assert_eq!(rgb(0, 0, 0), Hsv::new(200, 255, 0).to_rgb());
+        assert_eq!(rgb(2056, 1024, 1024), Hsv::new(0, 128, 128).to_rgb());
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn test_hsv_f32() {
+        for hue in (0..360).step_by(15) {
+            for saturation in [0, 64, 200, 255] {
+                let fixed = Hsv::new(hue, saturation, 255).to_rgb();
+                let float = HsvF32::new(hue as f32, saturation as f32 / 255.0, 1.0).to_rgb();
+                for (a, b) in [(fixed.0, float.0), (fixed.1, float.1), (fixed.2, float.2)] {
+                    assert!((a.value() - b.value()).abs() <= 1, "{} {}", hue, saturation);
+                }
+            }
+        }
+
+        assert_eq!(rgb(0, 0, 0), HsvF32::new(f32::NAN, 1.0, f32::NAN).to_rgb());
+        assert_eq!(rgb(4095, 0, 0), HsvF32::new(720.0, 2.0, 1.0).to_rgb());
+    }
+
+    #[test]
+    fn test_hsl() {
+        assert_eq!(rgb(4095, 17, 17), Hsl::new(0, 255, 128).to_rgb());
+        assert_eq!(rgb(4078, 0, 0), Hsl::new(0, 255, 127).to_rgb());
+        assert_eq!(rgb(17, 4095, 4095), Hsl::new(180, 255, 128).to_rgb());
+        assert_eq!(rgb(0, 0, 0), Hsl::new(180, 255, 0).to_rgb());
+        assert_eq!(rgb(2056, 2056, 2056), Hsl::new(90, 0, 128).to_rgb());
+    }
+
+    #[test]
+    fn test_color_temperature() {
+        assert_eq!(rgb(4095, 1091, 0), ColorTemperature::new(0).to_rgb());
+        assert_eq!(rgb(3069, 3396, 4095), ColorTemperature::new(40000).to_rgb());
+        assert_eq!(rgb(4095, 2700, 1445), ColorTemperature::new(2750).to_rgb());
+
+        let warm = ColorTemperature::new(2000);
+        let cool = ColorTemperature::new(5000);
+        let max = PWMValue::max();
+        assert_eq!(
+            (PWMValue::min(), max),
+            ColorTemperature::new(6500).mix(warm, cool, max)
+        );
+        assert_eq!(
+            (max, PWMValue::min()),
+            ColorTemperature::new(1500).mix(warm, cool, max)
+        );
+
+        // 2857K is 350 mireds, half way between 500 and 200.
+        let (w, c) = ColorTemperature::new(2857).mix(warm, cool, max);
+        assert_eq!(4095, w.value() + c.value());
+        assert!((w.value() - c.value()).abs() <= 2);
+    }
+}
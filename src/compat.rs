@@ -0,0 +1,73 @@
+//! Method names mirroring the Adafruit TLC5947 Arduino library, so existing
+//! sketches can be ported line by line.  They use raw numbers for channels and
+//! values, the way the Arduino library does, and map onto the native API.  As
+//! in the Arduino library, writes to channels beyond the board are ignored and
+//! values above 4095 are clamped.
+//!
+//! New code should prefer the native `Channel` and `PWMValue` types.
+#![allow(non_snake_case)]
+
+use crate::timing::Delay;
+use crate::{pwm::PWMValue, Channel, DeviceError, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+impl<L, D, O, C, T> PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Sets a channel, numbered 0 to 23, to a value between 0 and 4095.  This
+    /// is the same as `write_pwm`.
+    pub fn setPWM(&mut self, chan: u16, pwm: u16) {
+        if (chan as usize) < self.buffer.len() {
+            self.write_pwm(&Channel(chan as usize), &PWMValue::new(pwm as i32));
+        }
+    }
+
+    /// Returns the value of a channel, numbered 0 to 23.  Channels beyond the
+    /// board read as zero.
+    pub fn getPWM(&self, chan: u16) -> u16 {
+        self.buffer
+            .get(chan as usize)
+            .map(|value| value.value() as u16)
+            .unwrap_or(0)
+    }
+
+    /// Sets the three channels of an RGB LED.  LED 0 is channels 0, 1 and 2,
+    /// LED 1 is channels 3, 4 and 5, and so on up to LED 7.
+    pub fn setLED(&mut self, lednum: u16, r: u16, g: u16, b: u16) {
+        let first = lednum.saturating_mul(3);
+        self.setPWM(first, r);
+        self.setPWM(first.saturating_add(1), g);
+        self.setPWM(first.saturating_add(2), b);
+    }
+
+    /// Sends the values to the board.  This is the same as `flush`.
+    pub fn write(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::pwm::PWMValue;
+    use crate::test_pins::IdlePin;
+
+    #[test]
+    fn test_set_led() {
+        let mut device = crate::PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        device.setLED(1, 10, 5000, 30);
+        device.setLED(8, 1, 1, 1);
+        device.setPWM(24, 1);
+
+        assert_eq!(PWMValue::new(10), device.buffer[3]);
+        assert_eq!(PWMValue::max(), device.buffer[4]);
+        assert_eq!(30, device.getPWM(5));
+        assert_eq!(0, device.getPWM(24));
+        assert!(device.write().is_ok());
+    }
+}
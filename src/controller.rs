@@ -0,0 +1,244 @@
+//! The `LedController` trait is what every backend has in common: a number of
+//! channels, a way to set each one, and a flush to show them.  It's
+//! implemented for a single board, a chain, a bank and the SPI backend, so an
+//! effect written against the trait runs on any of them.
+//!
+//! The channels are numbered from zero across the whole controller, so on a
+//! chain or a bank channel 24 is the first channel of the second board.  That
+//! flat numbering is what makes loops over LED positions work the same on
+//! every backend.
+//!
+//! A few methods have defaults for backends that don't have the feature, so
+//! the `scheduler` module's refreshers can drive any of them: advancing the
+//! fades does nothing without fades, a backend that doesn't track changes
+//! always needs a flush, and a flush that can't blank OE just flushes.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::chain::PWM5947Chain;
+//! use ledpwm5947::controller::LedController;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::PWM5947;
+//!
+//! // Lights one channel at a time along the whole controller.
+//! fn chase<T: LedController>(leds: &mut T, position: usize) -> Result<(), T::Error> {
+//!     leds.set_all(PWMValue::min());
+//!     leds.set(position % leds.num_channels(), PWMValue::max());
+//!     leds.flush()
+//! }
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut chain: PWM5947Chain<_, _, _, _, 4> = PWM5947Chain::new(Pin, Pin, Pin, Pin);
+//!
+//! for position in 0..96 {
+//!     chase(&mut device, position).ok();
+//!     chase(&mut chain, position).ok();
+//! }
+//! ```
+
+use crate::bank::Bank;
+use crate::chain::PWM5947Chain;
+use crate::clock::Instant;
+use crate::pwm::PWMValue;
+use crate::spi::{PWM5947Spi, SpiError, SpiSource};
+use crate::timing::Delay;
+use crate::{Channel, DeviceError, PWM5947};
+use embedded_hal::blocking::spi::Write;
+use embedded_hal::digital::v2::OutputPin;
+
+/// A set of LED channels that can be written and then flushed.
+pub trait LedController {
+    /// The error a flush can fail with.
+    type Error;
+
+    /// The number of channels.
+    fn num_channels(&self) -> usize;
+
+    /// Writes a value into a channel, counting from zero.  It shows on the
+    /// next flush.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is not less than the number of channels.
+    fn set(&mut self, channel: usize, value: PWMValue);
+
+    /// Sends the values to the LEDs.
+    fn flush(&mut self) -> Result<(), Self::Error>;
+
+    /// Writes the same value into every channel.
+    fn set_all(&mut self, value: PWMValue) {
+        for channel in 0..self.num_channels() {
+            self.set(channel, value);
+        }
+    }
+
+    /// Advances any fades to `now`.  Backends without fades do nothing.
+    fn advance_to(&mut self, _now: Instant) {}
+
+    /// Whether the next flush would change what the LEDs show.  Backends
+    /// that don't keep track always need a flush.
+    fn needs_flush(&self) -> bool {
+        true
+    }
+
+    /// Flushes with OE pulled high while the latch is toggled, if `blank` is
+    /// set.  Backends that can't blank just flush.
+    fn flush_blanked(&mut self, _blank: bool) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+impl<L, D, O, C, T> LedController for PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    type Error = DeviceError<L, D, O, C>;
+
+    fn num_channels(&self) -> usize {
+        24
+    }
+
+    fn set(&mut self, channel: usize, value: PWMValue) {
+        assert!(channel < 24, "the board only has 24 channels");
+        self.write_pwm(&Channel(channel), &value);
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        PWM5947::flush(self)
+    }
+
+    fn advance_to(&mut self, now: Instant) {
+        self.tick_at(now);
+    }
+
+    /// With dithering on, every frame differs from the last.
+    fn needs_flush(&self) -> bool {
+        self.is_dirty() || self.is_dithering()
+    }
+
+    fn flush_blanked(&mut self, blank: bool) -> Result<(), Self::Error> {
+        self.flush_frame(blank)
+    }
+}
+
+impl<L, D, O, C, const N: usize, T> LedController for PWM5947Chain<L, D, O, C, N, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    type Error = DeviceError<L, D, O, C>;
+
+    fn num_channels(&self) -> usize {
+        24 * N
+    }
+
+    fn set(&mut self, channel: usize, value: PWMValue) {
+        self.write_pwm(channel / 24, &Channel(channel % 24), &value);
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        PWM5947Chain::flush(self)
+    }
+
+    fn needs_flush(&self) -> bool {
+        self.is_dirty()
+    }
+
+    fn flush_blanked(&mut self, blank: bool) -> Result<(), Self::Error> {
+        self.flush_frame(blank)
+    }
+}
+
+impl<L, D, O, C, const N: usize, T> LedController for Bank<L, D, O, C, N, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    type Error = DeviceError<L, D, O, C>;
+
+    fn num_channels(&self) -> usize {
+        self.channels()
+    }
+
+    fn set(&mut self, channel: usize, value: PWMValue) {
+        self.write_pwm(channel, &value);
+    }
+
+    /// Flushes every board in the bank.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush_all()
+    }
+
+    fn flush_blanked(&mut self, blank: bool) -> Result<(), Self::Error> {
+        self.flush_boards(blank)
+    }
+}
+
+impl<S, L, O> LedController for PWM5947Spi<S, L, O>
+where
+    S: Write<u8>,
+    L: OutputPin,
+    O: OutputPin,
+{
+    type Error = SpiError<S::Error, SpiSource<L, O>>;
+
+    fn num_channels(&self) -> usize {
+        24
+    }
+
+    fn set(&mut self, channel: usize, value: PWMValue) {
+        assert!(channel < 24, "the board only has 24 channels");
+        self.write_pwm(&Channel(channel), &value);
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        PWM5947Spi::flush(self)
+    }
+
+    fn flush_blanked(&mut self, blank: bool) -> Result<(), Self::Error> {
+        self.flush_frame(blank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+
+    fn fill<T: LedController>(leds: &mut T) {
+        for channel in 0..leds.num_channels() {
+            leds.set(channel, PWMValue::new(channel as i32));
+        }
+    }
+
+    #[test]
+    fn test_flat_channels() {
+        let mut bank = Bank::new([IdlePin, IdlePin], IdlePin, IdlePin, IdlePin);
+        fill(&mut bank);
+        assert_eq!(PWMValue::new(30), bank.read_pwm(30));
+        assert!(LedController::flush(&mut bank).is_ok());
+
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        fill(&mut device);
+        assert_eq!(PWMValue::new(23), device.read_pwm(&crate::C24));
+        device.set_all(PWMValue::max());
+        assert_eq!(PWMValue::max(), device.read_pwm(&crate::C1));
+    }
+}
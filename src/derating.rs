@@ -0,0 +1,138 @@
+//! Thermal derating dims the whole board as the fixture heats up, to protect
+//! high power LEDs.  The firmware feeds in temperature readings from a sensor
+//! on the heat sink, and past a threshold every channel is scaled down along
+//! a straight line, reaching a minimum brightness at a second, hotter
+//! temperature.  Above that it stays at the minimum, which can be zero to
+//! shut the LEDs off.
+//!
+//! Temperatures are in thousandths of a degree Celsius, the unit most
+//! temperature sensor drivers report in.  Until the first reading comes in
+//! nothing is scaled.  Like the power budget, a device with derating applies
+//! it on every flush, after the channel caps and the power budget, and the
+//! buffer keeps the values that were written.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::derating::Derating;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! // Full brightness up to 60°C, down to a quarter at 80°C.
+//! device.set_derating(Some(Derating::new(60_000, 80_000, 25)));
+//! device.write_pwm(&C1, &PWMValue::max());
+//!
+//! device.update_temp_millic(45_000);
+//! assert_eq!(PWMValue::max(), device.output_frame()[0]);
+//!
+//! device.update_temp_millic(70_000);
+//! assert_eq!(PWMValue::new(2553), device.output_frame()[0]);
+//! assert_eq!(PWMValue::max(), device.read_pwm(&C1));
+//! ```
+
+use crate::pwm::PWMValue;
+
+/// The temperatures where the dimming starts and ends, how dim it gets, and
+/// the last reading.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Derating {
+    start_millic: i32,
+    full_millic: i32,
+    minimum: u8,
+    temp_millic: Option<i32>,
+}
+
+impl Derating {
+    /// Create a derating that starts dimming above `start_millic` and reaches
+    /// `minimum_percent` of full brightness at `full_millic`.  Percentages over
+    /// 100 are clamped.  If the two temperatures are the same, or the wrong
+    /// way round, it drops straight to the minimum above `start_millic`.
+    pub fn new(start_millic: i32, full_millic: i32, minimum_percent: u8) -> Self {
+        let percent = minimum_percent.min(100) as u16;
+        Derating {
+            start_millic,
+            full_millic,
+            minimum: ((percent * 255 + 50) / 100) as u8,
+            temp_millic: None,
+        }
+    }
+
+    /// Takes a new temperature reading.
+    pub fn update_temp_millic(&mut self, temp_millic: i32) {
+        self.temp_millic = Some(temp_millic);
+    }
+
+    /// The last temperature reading, if there's been one.
+    pub fn temp_millic(&self) -> Option<i32> {
+        self.temp_millic
+    }
+
+    /// How much the channels are scaled by at the last reading, out of 255.
+    ///
+    /// ```
+    /// use ledpwm5947::derating::Derating;
+    ///
+    /// let mut derating = Derating::new(60_000, 80_000, 0);
+    /// assert_eq!(255, derating.factor());
+    ///
+    /// derating.update_temp_millic(75_000);
+    /// assert_eq!(64, derating.factor());
+    /// derating.update_temp_millic(95_000);
+    /// assert_eq!(0, derating.factor());
+    /// ```
+    pub fn factor(&self) -> u8 {
+        let temp = match self.temp_millic {
+            Some(temp) if temp > self.start_millic => temp as i64,
+            _ => return 255,
+        };
+        let start = self.start_millic as i64;
+        let full = self.full_millic as i64;
+        if temp >= full {
+            return self.minimum;
+        }
+
+        let range = 255 - self.minimum as i64;
+        let drop = (range * (temp - start) + (full - start) / 2) / (full - start);
+        (255 - drop) as u8
+    }
+
+    /// Scales every value in the frame by the factor.
+    pub fn apply(&self, frame: &mut [PWMValue; 24]) {
+        let factor = self.factor();
+        if factor == 255 {
+            return;
+        }
+        for value in frame.iter_mut() {
+            *value = value.scale(factor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curve() {
+        let mut derating = Derating::new(50_000, 50_000, 10);
+        derating.update_temp_millic(50_000);
+        assert_eq!(255, derating.factor());
+        derating.update_temp_millic(50_001);
+        assert_eq!(26, derating.factor());
+
+        let mut derating = Derating::new(-10_000, 10_000, 50);
+        derating.update_temp_millic(0);
+        assert_eq!(191, derating.factor());
+
+        let mut frame = [PWMValue::max(); 24];
+        derating.apply(&mut frame);
+        assert_eq!([PWMValue::new(3067); 24], frame);
+    }
+}
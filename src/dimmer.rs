@@ -0,0 +1,226 @@
+//! Dims the whole board with a hardware PWM channel on the OE pin, instead of
+//! rescaling all 24 values.  A `GlobalDimmer` wraps a timer channel and acts
+//! as the OE pin, so it's handed to `PWM5947::new` in place of a plain GPIO.
+//! The timer switches the outputs off for part of each of its periods, so the
+//! dimming is steady, and doesn't need `service_output` called the way the
+//! software `set_global_brightness` does.
+//!
+//! OE high blanks the board, so the duty cycle on the pin is the fraction of
+//! the time the LEDs are off.  A master brightness of 255 leaves the pin low
+//! all the time, and 0 leaves it high.  The brightness is linear, like
+//! `From<u8>`.  Disabling the output still blanks the board completely, and
+//! enabling it goes back to the master brightness.
+//!
+//! Pick a timer frequency well above what the eye can see, a few kHz or more.
+//! The board's own PWM runs at just under 1kHz, and a dimmer that's close to
+//! a multiple of that beats against it and shimmers.  Leave the software
+//! global brightness at max, so `service_output` doesn't fight the timer.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # use embedded_hal::PwmPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! # struct Timer(u16);
+//! # impl PwmPin for Timer {
+//! #     type Duty = u16;
+//! #     fn disable(&mut self) {}
+//! #     fn enable(&mut self) {}
+//! #     fn get_duty(&self) -> u16 { self.0 }
+//! #     fn get_max_duty(&self) -> u16 { 1000 }
+//! #     fn set_duty(&mut self, duty: u16) { self.0 = duty }
+//! # }
+//! use ledpwm5947::dimmer::GlobalDimmer;
+//! use ledpwm5947::PWM5947;
+//!
+//! let mut device = PWM5947::new(Pin, Pin, GlobalDimmer::new(Timer(0)), Pin);
+//! device.enable_output().ok();
+//!
+//! device.set_master_brightness(64);
+//! assert_eq!(64, device.master_brightness());
+//! ```
+
+use crate::timing::Delay;
+use crate::PWM5947;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::PwmPin;
+
+/// A hardware PWM channel standing in for the OE pin.  Setting a duty cycle
+/// never fails, so `new` gives a dimmer whose error is `Infallible`.  Each pin
+/// keeps its own error type, so it fits in a device with any other pins.
+#[derive(Debug)]
+pub struct GlobalDimmer<P, E = Infallible>
+where
+    P: PwmPin<Duty = u16>,
+{
+    pwm: P,
+    brightness: u8,
+    blanked: bool,
+    error: PhantomData<E>,
+}
+
+impl<P> GlobalDimmer<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    /// Wraps the PWM channel and enables it.  It starts blanked, the same as
+    /// the OE pin on a board that's just powered up, at full brightness.
+    pub fn new(pwm: P) -> Self {
+        let mut dimmer = GlobalDimmer {
+            pwm,
+            brightness: 255,
+            blanked: true,
+            error: PhantomData,
+        };
+        dimmer.update();
+        dimmer.pwm.enable();
+        dimmer
+    }
+}
+
+impl<P, E> GlobalDimmer<P, E>
+where
+    P: PwmPin<Duty = u16>,
+{
+    /// Sets the brightness of the whole board, from 0 for off to 255 for
+    /// full.  While the output is blanked it takes effect once it's enabled.
+    pub fn set_master_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+        self.update();
+    }
+
+    /// The master brightness.
+    pub fn master_brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Whether the output is blanked.
+    pub fn is_blanked(&self) -> bool {
+        self.blanked
+    }
+
+    /// Disables the PWM channel and gives it back.
+    pub fn release(mut self) -> P {
+        self.pwm.disable();
+        self.pwm
+    }
+
+    /// Sets the duty cycle for the off time.
+    fn update(&mut self) {
+        let max = self.pwm.get_max_duty() as u32;
+        let duty = if self.blanked {
+            max
+        } else {
+            (max * (255 - self.brightness as u32) + 127) / 255
+        };
+        self.pwm.set_duty(duty as u16);
+    }
+}
+
+/// OE high blanks the board, and low shows it at the master brightness.
+impl<P, E> OutputPin for GlobalDimmer<P, E>
+where
+    P: PwmPin<Duty = u16>,
+{
+    type Error = E;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.blanked = false;
+        self.update();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.blanked = true;
+        self.update();
+        Ok(())
+    }
+}
+
+impl<L, D, P, E, C, T> PWM5947<L, D, GlobalDimmer<P, E>, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    P: PwmPin<Duty = u16>,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Sets the brightness of the whole board on the dimmer driving the OE
+    /// pin, from 0 for off to 255 for full.  The channel values are left
+    /// alone.
+    pub fn set_master_brightness(&mut self, brightness: u8) {
+        self.oe.raw_pin.set_master_brightness(brightness);
+    }
+
+    /// The master brightness on the dimmer.
+    pub fn master_brightness(&self) -> u8 {
+        self.oe.raw_pin.master_brightness()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+
+    struct Timer {
+        duty: u16,
+        enabled: bool,
+    }
+
+    impl PwmPin for Timer {
+        type Duty = u16;
+
+        fn disable(&mut self) {
+            self.enabled = false;
+        }
+
+        fn enable(&mut self) {
+            self.enabled = true;
+        }
+
+        fn get_duty(&self) -> u16 {
+            self.duty
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            1000
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty = duty;
+        }
+    }
+
+    #[test]
+    fn test_duty() {
+        let timer = Timer {
+            duty: 0,
+            enabled: false,
+        };
+        let mut device = PWM5947::new(IdlePin, IdlePin, GlobalDimmer::new(timer), IdlePin);
+        assert!(device.oe.raw_pin.pwm.enabled);
+        assert_eq!(1000, device.oe.raw_pin.pwm.get_duty());
+
+        assert!(device.enable_output().is_ok());
+        assert_eq!(0, device.oe.raw_pin.pwm.get_duty());
+
+        device.set_master_brightness(51);
+        assert_eq!(800, device.oe.raw_pin.pwm.get_duty());
+        device.set_master_brightness(0);
+        assert_eq!(1000, device.oe.raw_pin.pwm.get_duty());
+
+        device.set_master_brightness(128);
+        assert!(device.disable_output().is_ok());
+        assert_eq!(1000, device.oe.raw_pin.pwm.get_duty());
+        assert!(device.enable_output().is_ok());
+        assert_eq!(498, device.oe.raw_pin.pwm.get_duty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Maps DMX512 slots onto the board's channels, for driving it from a
+//! lighting desk.  A `DmxMapping` takes 24 slots, starting at the DMX address
+//! the fixture is set to, and writes them into the 24 channels in order.
+//! The 8-bit slot values are expanded to 12 bits through a gamma table, so a
+//! fader on the desk dims evenly, or linearly like `From<u8>` if the desk
+//! already corrects them.
+//!
+//! The frame handed over is the whole packet as the receiver read it, with
+//! the start code first, so address 1 is the byte after it.  Only packets
+//! with the null start code carry dimmer levels, so packets with any other
+//! start code, like RDM, are ignored.  A packet too short to reach some of
+//! the slots leaves those channels as they were, since DMX allows short
+//! packets.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::dmx::DmxMapping;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1, C2};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mapping = DmxMapping::new(101).unwrap();
+//!
+//! let mut packet = [0_u8; 513];
+//! packet[101] = 255;
+//! packet[102] = 128;
+//! assert_eq!(24, mapping.apply_dmx_frame(&mut device, &packet));
+//!
+//! assert_eq!(PWMValue::max(), device.read_pwm(&C1));
+//! assert_eq!(PWMValue::from_gamma(128), device.read_pwm(&C2));
+//! device.flush().ok();
+//! ```
+
+use crate::gamma::{GammaTable, GAMMA_2_2};
+use crate::pwm::PWMValue;
+use crate::timing::Delay;
+use crate::{ALL_CHANNELS, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+/// The number of slots in a DMX universe.
+pub const UNIVERSE_SLOTS: u16 = 512;
+
+/// The start code for packets of dimmer levels.
+pub const NULL_START_CODE: u8 = 0x00;
+
+/// Where the board's channels sit in the DMX universe, and how the slot
+/// values become PWM values.
+#[derive(Copy, Clone, Debug)]
+pub struct DmxMapping {
+    start: u16,
+    gamma: Option<&'static GammaTable>,
+}
+
+impl DmxMapping {
+    /// Create a mapping with channel 1 on the DMX address `start`, from 1 to
+    /// 512, and the rest of the channels on the addresses after it.  It
+    /// returns `None` for an address outside the universe.  The values go
+    /// through a gamma of 2.2.
+    pub fn new(start: u16) -> Option<Self> {
+        if start == 0 || start > UNIVERSE_SLOTS {
+            return None;
+        }
+        Some(DmxMapping {
+            start,
+            gamma: Some(&GAMMA_2_2),
+        })
+    }
+
+    /// Uses the gamma table to expand the slot values.
+    pub fn with_gamma(mut self, gamma: &'static GammaTable) -> Self {
+        self.gamma = Some(gamma);
+        self
+    }
+
+    /// Expands the slot values linearly, the same as `From<u8>`.
+    pub fn linear(mut self) -> Self {
+        self.gamma = None;
+        self
+    }
+
+    /// The DMX address of channel 1.
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// The PWM value for a slot value.
+    pub fn expand(&self, slot: u8) -> PWMValue {
+        match self.gamma {
+            Some(gamma) => gamma.correct(slot),
+            None => PWMValue::from(slot),
+        }
+    }
+
+    /// Writes the board's slots from the packet into the buffer, and returns
+    /// the number of channels written.  The packet starts with the start
+    /// code, and nothing is written unless it's the null start code.
+    pub fn apply_dmx_frame<L, D, O, C, T>(
+        &self,
+        device: &mut PWM5947<L, D, O, C, T>,
+        packet: &[u8],
+    ) -> usize
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        if packet.first() != Some(&NULL_START_CODE) {
+            return 0;
+        }
+
+        let slots = packet.iter().skip(self.start as usize);
+        let mut written = 0;
+        for (channel, slot) in ALL_CHANNELS.iter().zip(slots) {
+            device.write_pwm(channel, &self.expand(*slot));
+            written += 1;
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+    use crate::{C1, C3, C4};
+
+    #[test]
+    fn test_short_and_ignored_packets() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mapping = DmxMapping::new(510).unwrap().linear();
+        assert!(DmxMapping::new(0).is_none());
+        assert!(DmxMapping::new(513).is_none());
+
+        let mut packet = [0x7f_u8; 513];
+        packet[0] = 0xcc;
+        assert_eq!(0, mapping.apply_dmx_frame(&mut device, &packet));
+        assert_eq!(PWMValue::min(), device.read_pwm(&C1));
+
+        packet[0] = NULL_START_CODE;
+        assert_eq!(3, mapping.apply_dmx_frame(&mut device, &packet));
+        assert_eq!(PWMValue::from(0x7f_u8), device.read_pwm(&C3));
+        assert_eq!(PWMValue::min(), device.read_pwm(&C4));
+
+        assert_eq!(1, mapping.apply_dmx_frame(&mut device, &packet[..511]));
+    }
+}
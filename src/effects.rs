@@ -0,0 +1,390 @@
+//! Effects are small waveform generators for a single channel.  Each call to
+//! `next` moves the effect on by one tick and returns the value for that tick,
+//! which the caller writes into whichever channels it likes.  Unlike a fade,
+//! an effect never finishes.
+//!
+//! A `Breather` is the slow pulse nearly every demo starts with.  It rises from
+//! its low value to its high value over the first half of the period and falls
+//! back over the second half, either in straight lines or following a sine
+//! curve, which looks more like breathing.
+//!
+//! ```
+//! use ledpwm5947::effects::{Breather, Waveform};
+//! use ledpwm5947::pwm::PWMValue;
+//!
+//! let mut breather = Breather::new(PWMValue::min(), PWMValue::new(100), 4, Waveform::Triangle);
+//!
+//! assert_eq!(PWMValue::new(0), breather.next());
+//! assert_eq!(PWMValue::new(50), breather.next());
+//! assert_eq!(PWMValue::new(100), breather.next());
+//! assert_eq!(PWMValue::new(50), breather.next());
+//! assert_eq!(PWMValue::new(0), breather.next());
+//! ```
+//!
+//! A `Blinker` switches between two values, for heartbeats and error codes on
+//! status LEDs.  It's on for the first `on_ticks` of every period and off for
+//! the rest, so the duty is exact to the tick.  Blinkers with the same period
+//! that tick together stay in step, so a phase offset keeps one channel a set
+//! number of ticks behind another, for alternating or chasing lights.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::effects::Blinker;
+//! use ledpwm5947::group::ChannelGroup;
+//! use ledpwm5947::{PWM5947, C1, C2, C3};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let left = ChannelGroup::new(&[C1, C2]).unwrap();
+//! let mut left_blink = Blinker::new(4, 2);
+//! let mut right_blink = Blinker::new(4, 2).with_phase(2);
+//!
+//! for _ in 0..8 {
+//!     left.set_all(&mut device, left_blink.next());
+//!     device.write_pwm(&C3, &right_blink.next());
+//!     device.flush().ok();
+//! }
+//! ```
+//!
+//! A `Flicker` is candle or fire light: each tick it picks a random level
+//! between its low and high values and moves part of the way there, so the
+//! brightness wanders instead of jumping.  The randomness comes from a small
+//! xorshift generator, so the same seed always gives the same flicker, and
+//! several candles with different seeds don't flicker together.
+//!
+//! ```
+//! use ledpwm5947::effects::Flicker;
+//! use ledpwm5947::pwm::PWMValue;
+//!
+//! let mut candle = Flicker::new(PWMValue::new(1500), PWMValue::new(3500), 7);
+//! for _ in 0..100 {
+//!     let value = candle.next();
+//!     assert!(value >= PWMValue::new(1500) && value <= PWMValue::new(3500));
+//! }
+//! ```
+
+use crate::pwm::{Easing, PWMValue};
+
+/// The shape of a breathing cycle.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Waveform {
+    /// Rises and falls in straight lines.
+    Triangle,
+    /// Rises and falls along a sine curve, lingering at the top and bottom.
+    Sine,
+}
+
+/// Pulses between two values, once every `period` ticks.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Breather {
+    low: PWMValue,
+    high: PWMValue,
+    period: u32,
+    position: u32,
+    waveform: Waveform,
+}
+
+impl Breather {
+    /// Create a breather that starts at `low`, reaches `high` half way through
+    /// the period, and is back at `low` at the end.  With a period below two
+    /// ticks there's no room to pulse, and the value stays at `low`.
+    pub const fn new(low: PWMValue, high: PWMValue, period: u32, waveform: Waveform) -> Self {
+        Breather {
+            low,
+            high,
+            period,
+            position: 0,
+            waveform,
+        }
+    }
+
+    /// The length of one full cycle, in ticks.
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    /// Change the length of the cycle.  The breather starts the new cycle from
+    /// the beginning.
+    pub fn set_period(&mut self, period: u32) {
+        self.period = period;
+        self.position = 0;
+    }
+
+    /// The value at the current tick, without moving on.
+    pub fn value(&self) -> PWMValue {
+        if self.period < 2 {
+            return self.low;
+        }
+
+        let easing = match self.waveform {
+            Waveform::Triangle => Easing::Linear,
+            Waveform::Sine => Easing::Sine,
+        };
+
+        let rise = self.period / 2;
+        if self.position <= rise {
+            PWMValue::interpolate(self.low, self.high, self.position, rise, easing)
+        } else {
+            let fall = self.period - rise;
+            PWMValue::interpolate(self.high, self.low, self.position - rise, fall, easing)
+        }
+    }
+
+    /// Returns the value for this tick and moves on to the next one.  It isn't
+    /// an `Iterator`, since the pulse never ends and there's no `None` to
+    /// unwrap.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> PWMValue {
+        let value = self.value();
+        if self.period > 0 {
+            self.position = (self.position + 1) % self.period;
+        }
+        value
+    }
+
+    /// Goes back to the start of the cycle, at the low value.
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+}
+
+/// Switches between an on and an off value, on for `on_ticks` out of every
+/// `period` ticks.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Blinker {
+    off: PWMValue,
+    on: PWMValue,
+    period: u32,
+    on_ticks: u32,
+    phase: u32,
+    position: u32,
+}
+
+impl Blinker {
+    /// Create a blinker that's fully on for the first `on_ticks` of each
+    /// period and off for the rest.  An `on_ticks` of the period or more
+    /// leaves it on all the time, and a period of zero leaves it off.
+    pub const fn new(period: u32, on_ticks: u32) -> Self {
+        Blinker {
+            off: PWMValue::min(),
+            on: PWMValue::max(),
+            period,
+            on_ticks,
+            phase: 0,
+            position: 0,
+        }
+    }
+
+    /// Uses the two values instead of fully off and fully on, for a dim glow
+    /// between blinks.
+    pub const fn with_levels(mut self, off: PWMValue, on: PWMValue) -> Self {
+        self.off = off;
+        self.on = on;
+        self
+    }
+
+    /// Starts the blinker `phase` ticks into its period, so it runs that many
+    /// ticks ahead of a blinker without an offset.
+    pub const fn with_phase(mut self, phase: u32) -> Self {
+        self.phase = phase;
+        self.position = if self.period > 0 {
+            phase % self.period
+        } else {
+            0
+        };
+        self
+    }
+
+    /// The length of one full cycle, in ticks.
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    /// The number of ticks it's on for in each cycle.
+    pub fn on_ticks(&self) -> u32 {
+        self.on_ticks
+    }
+
+    /// Change the number of ticks it's on for, without restarting the cycle.
+    pub fn set_on_ticks(&mut self, on_ticks: u32) {
+        self.on_ticks = on_ticks;
+    }
+
+    /// Whether it's on at the current tick.
+    pub fn is_on(&self) -> bool {
+        self.period > 0 && self.position < self.on_ticks
+    }
+
+    /// The value at the current tick, without moving on.
+    pub fn value(&self) -> PWMValue {
+        if self.is_on() {
+            self.on
+        } else {
+            self.off
+        }
+    }
+
+    /// Returns the value for this tick and moves on to the next one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> PWMValue {
+        let value = self.value();
+        if self.period > 0 {
+            self.position = (self.position + 1) % self.period;
+        }
+        value
+    }
+
+    /// Goes back to the start of the cycle, keeping the phase offset.
+    pub fn reset(&mut self) {
+        *self = self.with_phase(self.phase);
+    }
+}
+
+/// The seed used in place of zero, which would stick a xorshift generator at
+/// zero for ever.
+const ZERO_SEED: u32 = 0x2545_f491;
+
+/// Wanders randomly between two values, easing towards a new random level
+/// each tick.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Flicker {
+    low: PWMValue,
+    high: PWMValue,
+    level: PWMValue,
+    smoothing: u8,
+    state: u32,
+}
+
+impl Flicker {
+    /// Create a flicker between `low` and `high`, starting half way between
+    /// them.  The seed picks the sequence, and any seed is fine, zero
+    /// included.  Each tick moves a quarter of the way to the new level.
+    pub const fn new(low: PWMValue, high: PWMValue, seed: u32) -> Self {
+        let (low, high) = if low.as_u16() > high.as_u16() {
+            (high, low)
+        } else {
+            (low, high)
+        };
+        Flicker {
+            low,
+            high,
+            level: PWMValue::new((low.as_u16() as i32 + high.as_u16() as i32) / 2),
+            smoothing: 2,
+            state: if seed == 0 { ZERO_SEED } else { seed },
+        }
+    }
+
+    /// Moves `1 / 2^smoothing` of the way to each new level, so higher values
+    /// flicker more gently.  Zero jumps straight to each level, like noise.
+    /// It's capped at 12, past which the value hardly moves.
+    pub const fn with_smoothing(mut self, smoothing: u8) -> Self {
+        self.smoothing = if smoothing > 12 { 12 } else { smoothing };
+        self
+    }
+
+    /// The value at the current tick, without moving on.
+    pub fn value(&self) -> PWMValue {
+        self.level
+    }
+
+    /// Returns the value for this tick and moves on to the next one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> PWMValue {
+        let value = self.level;
+
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        let low = self.low.as_u16() as i32;
+        let span = self.high.as_u16() as i32 - low + 1;
+        let target = low + (self.state % span as u32) as i32;
+        let level = self.level.as_u16() as i32;
+        let moved = level + ((target - level) >> self.smoothing);
+        self.level = PWMValue::new(moved);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_odd_period() {
+        let mut breather = Breather::new(PWMValue::new(10), PWMValue::new(40), 5, Waveform::Sine);
+        let values: [PWMValue; 6] = [
+            breather.next(),
+            breather.next(),
+            breather.next(),
+            breather.next(),
+            breather.next(),
+            breather.next(),
+        ];
+
+        assert_eq!(PWMValue::new(10), values[0]);
+        assert_eq!(PWMValue::new(40), values[2]);
+        assert!(values[3] < values[2] && values[3] > values[4]);
+        assert_eq!(PWMValue::new(10), values[5]);
+    }
+
+    #[test]
+    fn test_short_period() {
+        let mut breather = Breather::new(PWMValue::new(10), PWMValue::max(), 1, Waveform::Triangle);
+        assert_eq!(PWMValue::new(10), breather.next());
+        assert_eq!(PWMValue::new(10), breather.next());
+    }
+
+    #[test]
+    fn test_blinker() {
+        let mut blinker = Blinker::new(5, 2).with_levels(PWMValue::new(10), PWMValue::new(90));
+        let mut late = Blinker::new(5, 2).with_phase(7);
+        let mut pattern = [PWMValue::min(); 6];
+        let mut late_pattern = [false; 6];
+        for (value, on) in pattern.iter_mut().zip(late_pattern.iter_mut()) {
+            *on = late.is_on();
+            late.next();
+            *value = blinker.next();
+        }
+
+        let (off, on) = (PWMValue::new(10), PWMValue::new(90));
+        assert_eq!([on, on, off, off, off, on], pattern);
+        assert_eq!([false, false, false, true, true, false], late_pattern);
+
+        late.reset();
+        assert!(!late.is_on());
+        assert_eq!(PWMValue::max(), Blinker::new(3, 3).next());
+        assert_eq!(PWMValue::min(), Blinker::new(0, 3).next());
+    }
+
+    #[test]
+    fn test_flicker() {
+        let mut a = Flicker::new(PWMValue::new(200), PWMValue::new(100), 0).with_smoothing(0);
+        let mut b = Flicker::new(PWMValue::new(100), PWMValue::new(200), 0).with_smoothing(0);
+        assert_eq!(PWMValue::new(150), a.value());
+
+        let mut changes = 0;
+        let mut last = a.value();
+        for _ in 0..50 {
+            let value = a.next();
+            assert_eq!(value, b.next());
+            assert!(value >= PWMValue::new(100) && value <= PWMValue::new(200));
+            if value != last {
+                changes += 1;
+            }
+            last = value;
+        }
+        assert!(changes > 40);
+
+        let mut other = Flicker::new(PWMValue::new(100), PWMValue::new(200), 99).with_smoothing(0);
+        other.next();
+        assert_ne!(a.next(), other.next());
+    }
+}
@@ -0,0 +1,311 @@
+//! Adapters for `embedded-hal` 1.0.  The drivers are written against the 0.2
+//! traits, which most existing HALs still implement.  HALs that have moved to
+//! 1.0 hand out pins, SPI buses and delays with the newer traits, and these
+//! thin wrappers let them drive the board without duplicating every driver.
+//!
+//! Going the other way, each `ChannelHandle` from `PWM5947::split` is a 1.0
+//! `SetDutyCycle` PWM pin, so driver crates for buzzers, fans and LEDs that
+//! take a PWM pin can each be handed a channel of the board.  The duty cycle
+//! goes from 0 to 4095, and like any other write it only goes into the
+//! buffer.  Nothing reaches the board until the split's `Flusher` commits it
+//! with `flush`, so several drivers can change their channels and have them
+//! all latch together.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal_1::digital::{ErrorType, OutputPin};
+//! # struct Pin;
+//! # impl ErrorType for Pin { type Error = Infallible; }
+//! # impl OutputPin for Pin {
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::eh1::Eh1Pin;
+//! use ledpwm5947::PWM5947;
+//!
+//! let mut device = PWM5947::new(Eh1Pin::new(Pin), Eh1Pin::new(Pin), Eh1Pin::new(Pin), Eh1Pin::new(Pin));
+//! device.flush().ok();
+//! ```
+
+use crate::handles::ChannelHandle;
+use crate::pwm::{PWMValue, PWM_MASK};
+use crate::timing::Delay;
+use core::convert::Infallible;
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::digital;
+use embedded_hal_1::pwm::{self, SetDutyCycle};
+use embedded_hal_1::spi::SpiBus;
+
+/// Wraps an `embedded-hal` 1.0 output pin so it can be used as a 0.2 output
+/// pin.
+#[derive(Debug)]
+pub struct Eh1Pin<P>
+where
+    P: digital::OutputPin,
+{
+    pin: P,
+}
+
+impl<P> Eh1Pin<P>
+where
+    P: digital::OutputPin,
+{
+    /// Wrap the output pin.
+    pub fn new(pin: P) -> Self {
+        Eh1Pin { pin }
+    }
+
+    /// Releases the wrapped pin.
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+impl<P> embedded_hal::digital::v2::OutputPin for Eh1Pin<P>
+where
+    P: digital::OutputPin,
+{
+    type Error = P::Error;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_high()
+    }
+}
+
+/// Wraps an `embedded-hal` 1.0 SPI bus so it can be used as a 0.2 blocking
+/// SPI writer.  Each write also flushes the bus, so the whole frame has left
+/// the bus before the driver toggles the latch.
+#[derive(Debug)]
+pub struct Eh1Spi<S>
+where
+    S: SpiBus,
+{
+    spi: S,
+}
+
+impl<S> Eh1Spi<S>
+where
+    S: SpiBus,
+{
+    /// Wrap the SPI bus.
+    pub fn new(spi: S) -> Self {
+        Eh1Spi { spi }
+    }
+
+    /// Releases the wrapped bus.
+    pub fn release(self) -> S {
+        self.spi
+    }
+}
+
+impl<S> embedded_hal::blocking::spi::Write<u8> for Eh1Spi<S>
+where
+    S: SpiBus,
+{
+    type Error = S::Error;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.spi.write(words)?;
+        self.spi.flush()
+    }
+}
+
+/// Wraps an `embedded-hal` 1.0 delay so it can be used as the driver's delay
+/// provider.
+#[derive(Debug)]
+pub struct Eh1Delay<T>
+where
+    T: DelayNs,
+{
+    delay: T,
+}
+
+impl<T> Eh1Delay<T>
+where
+    T: DelayNs,
+{
+    /// Wrap the delay.
+    pub fn new(delay: T) -> Self {
+        Eh1Delay { delay }
+    }
+
+    /// Releases the wrapped delay.
+    pub fn release(self) -> T {
+        self.delay
+    }
+}
+
+impl<T> Delay for Eh1Delay<T>
+where
+    T: DelayNs,
+{
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay.delay_ns(ns);
+    }
+}
+
+impl<L, D, O, C, T> pwm::ErrorType for ChannelHandle<'_, L, D, O, C, T>
+where
+    L: embedded_hal::digital::v2::OutputPin,
+    D: embedded_hal::digital::v2::OutputPin,
+    O: embedded_hal::digital::v2::OutputPin,
+    C: embedded_hal::digital::v2::OutputPin,
+    T: Delay,
+{
+    type Error = Infallible;
+}
+
+/// The channel as a PWM pin.  Setting the duty cycle writes the buffer, and
+/// it shows once the `Flusher` flushes.  A duty cycle over 4095 is full on.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use core::cell::RefCell;
+/// use embedded_hal_1::pwm::SetDutyCycle;
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::{PWM5947, C2};
+///
+/// let device = RefCell::new(PWM5947::new(Pin, Pin, Pin, Pin));
+/// let parts = PWM5947::split(&device);
+/// let [_, mut fan, ..] = parts.channels;
+///
+/// fan.set_duty_cycle_percent(50).unwrap();
+/// assert!(device.borrow().is_dirty());
+/// parts.flusher.flush().unwrap();
+///
+/// assert_eq!(PWMValue::new(2047), device.borrow().read_pwm(&C2));
+/// ```
+impl<L, D, O, C, T> SetDutyCycle for ChannelHandle<'_, L, D, O, C, T>
+where
+    L: embedded_hal::digital::v2::OutputPin,
+    D: embedded_hal::digital::v2::OutputPin,
+    O: embedded_hal::digital::v2::OutputPin,
+    C: embedded_hal::digital::v2::OutputPin,
+    T: Delay,
+{
+    fn max_duty_cycle(&self) -> u16 {
+        PWM_MASK
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.write(PWMValue::new(duty as i32));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal::blocking::spi::Write;
+    use embedded_hal::digital::v2::OutputPin;
+
+    struct FakePin {
+        high: bool,
+    }
+
+    impl digital::ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl digital::OutputPin for FakePin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.high = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.high = true;
+            Ok(())
+        }
+    }
+
+    struct FakeSpi {
+        written: usize,
+        flushed: bool,
+    }
+
+    impl embedded_hal_1::spi::ErrorType for FakeSpi {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for FakeSpi {
+        fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.written += words.len();
+            self.flushed = false;
+            Ok(())
+        }
+
+        fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut pin = Eh1Pin::new(FakePin { high: false });
+        assert!(pin.set_high().is_ok());
+        assert!(pin.release().high);
+    }
+
+    #[test]
+    fn test_duty_cycle() {
+        let device = core::cell::RefCell::new(crate::PWM5947::new(
+            Eh1Pin::new(FakePin { high: false }),
+            Eh1Pin::new(FakePin { high: false }),
+            Eh1Pin::new(FakePin { high: false }),
+            Eh1Pin::new(FakePin { high: false }),
+        ));
+        let parts = crate::PWM5947::split(&device);
+        let [mut first, _, mut third, ..] = parts.channels;
+
+        assert_eq!(4095, first.max_duty_cycle());
+        assert!(first.set_duty_cycle_fully_on().is_ok());
+        assert!(third.set_duty_cycle(u16::MAX).is_ok());
+        assert_eq!(PWMValue::max(), device.borrow().read_pwm(&crate::C1));
+        assert_eq!(PWMValue::max(), device.borrow().read_pwm(&crate::C3));
+
+        assert!(third.set_duty_cycle_fraction(1, 4).is_ok());
+        assert_eq!(PWMValue::new(1023), third.read());
+        assert_eq!(Ok(true), parts.flusher.flush_if_dirty());
+        assert!(first.set_duty_cycle_fully_off().is_ok());
+        assert!(device.borrow().is_dirty());
+    }
+
+    #[test]
+    fn test_spi() {
+        let mut spi = Eh1Spi::new(FakeSpi {
+            written: 0,
+            flushed: false,
+        });
+        assert!(spi.write(&[0; 36]).is_ok());
+
+        let spi = spi.release();
+        assert_eq!(36, spi.written);
+        assert!(spi.flushed);
+    }
+}
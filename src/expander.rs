@@ -0,0 +1,320 @@
+//! Drives the board from pins on an I2C or SPI GPIO expander, like an
+//! MCP23017 or MCP23S17.  Going through the usual `OutputPin` calls, every
+//! edge on the data and clock lines is a bus transaction of its own, and a
+//! frame takes close to a thousand of them.  This backend keeps a copy of the
+//! expander's output port and works out the port's state for each edge, so
+//! the data and clock lines change together in one register write per edge.
+//!
+//! The writes are handed to the expander driver in batches, through the
+//! `PortWriter` trait.  An MCP23017 with sequential addressing turned off
+//! (`IOCON.SEQOP` set) takes a whole batch of writes to its `OLAT` register
+//! in one I2C transaction, so a frame goes out in ten transactions instead.
+//! A driver that can't do that can loop over the batch and write each state
+//! in turn, which still takes two writes a bit instead of three.
+//!
+//! All four of the board's lines have to be on the same 8 bit port.  The
+//! other pins on the port keep whatever the firmware sets with
+//! `set_spare_bits`, since every write drives the whole port.
+//!
+//! ```
+//! use ledpwm5947::expander::{PWM5947Expander, PortPins, PortWriter};
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::C1;
+//!
+//! struct Mcp23017;
+//!
+//! impl PortWriter for Mcp23017 {
+//!     type Error = ();
+//!     fn write_port(&mut self, states: &[u8]) -> Result<(), ()> {
+//!         // One I2C write: the OLATA address, then every state.
+//!         Ok(())
+//!     }
+//! }
+//!
+//! // GPA0 is latch, GPA1 data, GPA2 OE and GPA3 clock.
+//! let mut device = PWM5947Expander::new(Mcp23017, PortPins::new(0, 1, 2, 3));
+//! device.begin().unwrap();
+//! device.write_pwm(&C1, &PWMValue::max());
+//! device.flush().unwrap();
+//! ```
+
+use crate::frame::{FrameBits, FRAME_BITS};
+use crate::protocol::ProtocolConfig;
+use crate::{pwm, Channel};
+
+/// The number of bits shifted in each batch of port writes.  Each bit takes
+/// two writes, so a batch is 64 bytes.
+const BATCH_BITS: usize = 32;
+
+/// Writes states to an expander's output port.
+pub trait PortWriter {
+    /// The error the expander driver returns.
+    type Error;
+
+    /// Writes each state to the output port in turn, in order.  The states
+    /// should go out in as few bus transactions as the expander allows.
+    fn write_port(&mut self, states: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Which of the port's pins the board's lines are on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortPins {
+    latch: u8,
+    data: u8,
+    oe: u8,
+    clock: u8,
+}
+
+impl PortPins {
+    /// The pin numbers, from 0 to 7, of each line on the port.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a pin number is past 7 or two lines share a pin.
+    pub const fn new(latch: u8, data: u8, oe: u8, clock: u8) -> Self {
+        assert!(latch < 8 && data < 8 && oe < 8 && clock < 8);
+        let pins = PortPins {
+            latch: 1 << latch,
+            data: 1 << data,
+            oe: 1 << oe,
+            clock: 1 << clock,
+        };
+        assert!(pins.mask().count_ones() == 4);
+        pins
+    }
+
+    /// The bits of the port the board uses.
+    pub const fn mask(&self) -> u8 {
+        self.latch | self.data | self.oe | self.clock
+    }
+}
+
+/// A board on an expander port.  It owns the expander driver, so other pins
+/// on the expander need to go through `set_spare_bits`.
+#[derive(Debug)]
+pub struct PWM5947Expander<P>
+where
+    P: PortWriter,
+{
+    buffer: [pwm::PWMValue; 24],
+
+    port: P,
+    pins: PortPins,
+    state: u8,
+    output_enabled: bool,
+    protocol: ProtocolConfig,
+}
+
+impl<P> PWM5947Expander<P>
+where
+    P: PortWriter,
+{
+    /// Create a new board on the expander port.  Nothing is written until
+    /// `begin` or `flush`.
+    pub fn new(port: P, pins: PortPins) -> Self {
+        PWM5947Expander {
+            buffer: [pwm::PWMValue::min(); 24],
+            port,
+            pins,
+            state: 0,
+            output_enabled: true,
+            protocol: ProtocolConfig::TLC5947,
+        }
+    }
+
+    /// Shifts and latches the frame the protocol's way, for clone boards, the
+    /// same as `PWM5947::set_protocol`.  The latch moves to its new idle level
+    /// with the next write to the port.
+    pub fn set_protocol(&mut self, protocol: ProtocolConfig) {
+        self.protocol = protocol;
+        self.state = (self.state & !self.pins.latch) | self.latch_idle();
+    }
+
+    /// The protocol the frame is shifted and latched in.
+    pub fn protocol(&self) -> &ProtocolConfig {
+        &self.protocol
+    }
+
+    /// The latch bit while the latch isn't being pulsed.
+    fn latch_idle(&self) -> u8 {
+        if self.protocol.latch_active_low() {
+            self.pins.latch
+        } else {
+            0
+        }
+    }
+
+    /// Sets the lines to known, good values, with the outputs on, and clears
+    /// the buffer to the PWM's `min` value.
+    pub fn begin(&mut self) -> Result<(), P::Error> {
+        self.buffer = [pwm::PWMValue::min(); 24];
+        self.output_enabled = true;
+        self.state = (self.state & !self.pins.mask()) | self.latch_idle();
+        self.port.write_port(&[self.state])
+    }
+
+    /// Sets the port's other pins, the ones the board doesn't use.  The bits
+    /// for the board's lines are ignored.
+    pub fn set_spare_bits(&mut self, bits: u8) -> Result<(), P::Error> {
+        self.state = (self.state & self.pins.mask()) | (bits & !self.pins.mask());
+        self.port.write_port(&[self.state])
+    }
+
+    /// The state the port was last written with.
+    pub fn port_state(&self) -> u8 {
+        self.state
+    }
+
+    /// Writes a value into the given channel.
+    pub fn write_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.buffer[channel.0] = *pwm_value;
+    }
+
+    /// Reads the value in the given channel from the buffer.
+    pub fn read_pwm(&self, channel: &Channel) -> pwm::PWMValue {
+        self.buffer[channel.0]
+    }
+
+    /// Sets the buffer back to all zeros and then flushes to turn off all the
+    /// LEDs.
+    pub fn all_black(&mut self) -> Result<(), P::Error> {
+        self.buffer = [pwm::PWMValue::min(); 24];
+        self.flush()
+    }
+
+    /// Turns the outputs on by pulling OE low.
+    pub fn enable_output(&mut self) -> Result<(), P::Error> {
+        self.output_enabled = true;
+        self.state &= !self.pins.oe;
+        self.port.write_port(&[self.state])
+    }
+
+    /// Blanks the outputs by pulling OE high.
+    pub fn disable_output(&mut self) -> Result<(), P::Error> {
+        self.output_enabled = false;
+        self.state |= self.pins.oe;
+        self.port.write_port(&[self.state])
+    }
+
+    /// Whether the outputs are on.
+    pub fn is_output_enabled(&self) -> bool {
+        self.output_enabled
+    }
+
+    /// Flushes the buffer to the board.  Each bit is two port states, the
+    /// data with the clock low and then high, and the states go to the
+    /// expander in batches.  The latch is toggled with OE high, so the LEDs
+    /// don't ghost while the new values latch.  The frame is shifted and
+    /// latched the protocol's way.
+    pub fn flush(&mut self) -> Result<(), P::Error> {
+        let mut states = [0; 2 * BATCH_BITS];
+        let frame = self.protocol.arrange(&self.buffer);
+        let mut bits = FrameBits::new(&frame);
+        let mut remaining = FRAME_BITS;
+        let idle = (self.state & !(self.pins.latch | self.pins.clock)) | self.latch_idle();
+
+        while remaining > 0 {
+            let count = remaining.min(BATCH_BITS);
+            for pair in states[..2 * count].chunks_exact_mut(2) {
+                let low = match bits.next() {
+                    Some(true) => idle | self.pins.data,
+                    _ => idle & !self.pins.data,
+                };
+                pair[0] = low;
+                pair[1] = low | self.pins.clock;
+            }
+            self.port.write_port(&states[..2 * count])?;
+            remaining -= count;
+        }
+
+        let settled = idle & !self.pins.data;
+        let blanked = settled | self.pins.oe;
+        let latch = [
+            settled,
+            blanked,
+            blanked ^ self.pins.latch,
+            blanked,
+            settled,
+        ];
+        self.state = settled;
+        self.port.write_port(&latch)
+    }
+
+    /// Releases the expander driver, so it can be used for something else.
+    pub fn release(self) -> P {
+        self.port
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::C1;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct FakePort {
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl PortWriter for FakePort {
+        type Error = ();
+
+        fn write_port(&mut self, states: &[u8]) -> Result<(), ()> {
+            self.writes.push(states.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_batched_flush() {
+        let pins = PortPins::new(0, 1, 2, 3);
+        let mut device = PWM5947Expander::new(FakePort::default(), pins);
+        assert!(device.set_spare_bits(0xff).is_ok());
+        assert_eq!(0xf0, device.port_state());
+
+        device.write_pwm(&crate::C24, &pwm::PWMValue::new(0x800));
+        device.write_pwm(&C1, &pwm::PWMValue::new(1));
+        assert!(device.flush().is_ok());
+
+        let port = device.release();
+        assert_eq!(1 + FRAME_BITS / BATCH_BITS + 1, port.writes.len());
+        let states: Vec<u8> = port.writes[1..10].concat();
+        assert_eq!(2 * FRAME_BITS, states.len());
+        assert_eq!([0xf2, 0xfa, 0xf0, 0xf8], states[..4]);
+        assert_eq!([0xf2, 0xfa], states[states.len() - 2..]);
+        assert_eq!(4, states.iter().filter(|s| *s & 0x02 != 0).count());
+        assert_eq!(std::vec![0xf0, 0xf4, 0xf5, 0xf4, 0xf0], port.writes[10]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_shared_pin() {
+        PortPins::new(0, 1, 1, 3);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let pins = PortPins::new(0, 1, 2, 3);
+        let mut device = PWM5947Expander::new(FakePort::default(), pins);
+        device.set_protocol(crate::protocol::ProtocolConfig {
+            channel_order: crate::protocol::ChannelOrder::Ascending,
+            latch: crate::protocol::LatchPolarity::ActiveLow,
+            ..crate::protocol::ProtocolConfig::TLC5947
+        });
+        assert!(device.begin().is_ok());
+        assert_eq!(0x01, device.port_state());
+
+        device.write_pwm(&C1, &pwm::PWMValue::new(0x800));
+        assert!(device.flush().is_ok());
+        assert_eq!(0x01, device.port_state());
+
+        // C1 goes first, and the latch idles high and pulses low.
+        let port = device.release();
+        assert_eq!([0x03, 0x0b, 0x01, 0x09], port.writes[1][..4]);
+        assert_eq!(std::vec![0x01, 0x05, 0x04, 0x05, 0x01], port.writes[10]);
+    }
+}
@@ -0,0 +1,343 @@
+//! A frame is a copy of all 24 channel values, taken from a device's buffer.
+//! It is a plain value, so a light preset can be kept in a `const`, compared,
+//! or stored and loaded back into a device later.
+//!
+//! Two frames can be crossfaded with `lerp`, which mixes every channel the
+//! same amount.  Loading the mixed frames one after another moves a whole
+//! scene from one preset to another.
+//!
+//! With the `serde` feature a frame can be serialized, for example with
+//! postcard into flash or over a serial link.  Each value is stored as its
+//! 12-bit number, and values are clamped when they're read back.  A
+//! `PackedFrame` holds the same values in the 36 bytes the board takes on the
+//! wire, for storing raw frames or sending them over another transport.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::frame::Frame;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C2};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! device.write_pwm(&C2, &PWMValue::max());
+//!
+//! let evening = device.snapshot();
+//! assert_eq!(PWMValue::max(), evening.values()[1]);
+//!
+//! // Crossfade to black over 2 seconds, flushing every 20ms.
+//! let night = Frame::default();
+//! for step in 0..=100_u32 {
+//!     let t = (step * 255 / 100) as u8;
+//!     device.load_frame(&Frame::lerp(&evening, &night, t));
+//!     device.flush().ok();
+//! }
+//! assert_eq!(PWMValue::min(), device.read_pwm(&C2));
+//! ```
+
+use crate::pwm::{PWMValue, PWM_BITS};
+use crate::{Channel, ALL_CHANNELS};
+
+/// The number of bits in a frame for one board.
+pub const FRAME_BITS: usize = 24 * PWM_BITS as usize;
+
+/// The 24 channel values of one frame, in channel order.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frame(pub [PWMValue; 24]);
+
+/// The earlier name for a `Frame`.
+pub type FrameSnapshot = Frame;
+
+impl Frame {
+    /// Create a frame from the channel values.
+    pub const fn new(values: [PWMValue; 24]) -> Self {
+        Frame(values)
+    }
+
+    /// The channel values, in channel order.
+    pub fn values(&self) -> &[PWMValue; 24] {
+        &self.0
+    }
+
+    /// The frame part way from `a` to `b`.  A `t` of 0 gives `a`, 255 gives
+    /// `b`, and every channel moves the same fraction of its way in between.
+    ///
+    /// ```
+    /// use ledpwm5947::frame::Frame;
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let a = Frame::new([PWMValue::new(1000); 24]);
+    /// let b = Frame::new([PWMValue::new(3000); 24]);
+    ///
+    /// assert_eq!(a, Frame::lerp(&a, &b, 0));
+    /// assert_eq!(PWMValue::new(2004), Frame::lerp(&a, &b, 128).values()[5]);
+    /// assert_eq!(b, Frame::lerp(&a, &b, 255));
+    /// ```
+    pub fn lerp(a: &Frame, b: &Frame, t: u8) -> Frame {
+        let mut values = a.0;
+        for (value, to) in values.iter_mut().zip(b.0.iter()) {
+            *value = value.blend(*to, t);
+        }
+        Frame(values)
+    }
+}
+
+impl From<[PWMValue; 24]> for Frame {
+    fn from(values: [PWMValue; 24]) -> Self {
+        Frame(values)
+    }
+}
+
+/// A frame packed into the 36 bytes the board takes on the wire.  The last
+/// channel comes first and each value is 12 bits, most significant bit first,
+/// so two channels fill three bytes.  This is what the SPI backend writes and
+/// what the bit-bang flush shifts out, and it's a compact way to keep frames
+/// in an EEPROM.
+///
+/// ```
+/// use ledpwm5947::frame::PackedFrame;
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::{C1, C24};
+///
+/// let mut values = [PWMValue::min(); 24];
+/// values[23] = PWMValue::new(0xabc);
+/// values[0] = PWMValue::new(0x123);
+///
+/// let packed = PackedFrame::pack(&values);
+/// assert_eq!([0xab, 0xc0, 0x00], packed.as_bytes()[0..3]);
+/// assert_eq!([0x00, 0x01, 0x23], packed.as_bytes()[33..36]);
+/// assert_eq!(PWMValue::new(0xabc), packed.get(&C24));
+/// assert_eq!(values, packed.unpack());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PackedFrame(pub [u8; 36]);
+
+impl PackedFrame {
+    /// Wraps bytes that are already packed, such as a frame read back from
+    /// storage.
+    pub const fn new(bytes: [u8; 36]) -> Self {
+        PackedFrame(bytes)
+    }
+
+    /// Packs the 24 channel values.
+    pub fn pack(values: &[PWMValue; 24]) -> Self {
+        let mut packed = PackedFrame::default();
+        for (channel, value) in ALL_CHANNELS.iter().zip(values.iter()) {
+            packed.set(channel, *value);
+        }
+        packed
+    }
+
+    /// Unpacks the 24 channel values, in channel order.
+    pub fn unpack(&self) -> [PWMValue; 24] {
+        let mut values = [PWMValue::min(); 24];
+        for (channel, value) in ALL_CHANNELS.iter().zip(values.iter_mut()) {
+            *value = self.get(channel);
+        }
+        values
+    }
+
+    /// The value of one channel.
+    pub fn get(&self, channel: &Channel) -> PWMValue {
+        let bytes = self.bytes_for(channel.0);
+        let raw = if channel.0 % 2 == 1 {
+            (bytes[0] as u16) << 4 | (bytes[1] >> 4) as u16
+        } else {
+            ((bytes[1] & 0x0f) as u16) << 8 | bytes[2] as u16
+        };
+        PWMValue::new(raw as i32)
+    }
+
+    /// Packs one channel's value, touching only that channel's 12 bits.
+    pub fn set(&mut self, channel: &Channel, value: PWMValue) {
+        let value = value.as_u16();
+        let at = (11 - channel.0 / 2) * 3;
+        let bytes = &mut self.0[at..at + 3];
+
+        if channel.0 % 2 == 1 {
+            bytes[0] = (value >> 4) as u8;
+            bytes[1] = (bytes[1] & 0x0f) | ((value << 4) as u8 & 0xf0);
+        } else {
+            bytes[1] = (bytes[1] & 0xf0) | (value >> 8) as u8;
+            bytes[2] = value as u8;
+        }
+    }
+
+    /// The packed bytes, in the order they go on the wire.
+    pub fn as_bytes(&self) -> &[u8; 36] {
+        &self.0
+    }
+
+    /// The bits in the order they go on the wire, most significant bit of
+    /// the first byte first.
+    pub(crate) fn bits(&self) -> impl Iterator<Item = bool> + '_ {
+        self.0
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1 == 1))
+    }
+
+    /// The bit at the index, counting in the order `bits` gives them.
+    pub(crate) fn bit(&self, index: usize) -> bool {
+        self.0[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// The three bytes holding the pair of channels this one is in.
+    fn bytes_for(&self, index: usize) -> &[u8] {
+        let at = (11 - index / 2) * 3;
+        &self.0[at..at + 3]
+    }
+}
+
+/// An all-zero frame, every channel at min.
+impl Default for PackedFrame {
+    fn default() -> Self {
+        PackedFrame([0; 36])
+    }
+}
+
+impl From<[u8; 36]> for PackedFrame {
+    fn from(bytes: [u8; 36]) -> Self {
+        PackedFrame(bytes)
+    }
+}
+
+impl From<PackedFrame> for [u8; 36] {
+    fn from(packed: PackedFrame) -> Self {
+        packed.0
+    }
+}
+
+impl From<&Frame> for PackedFrame {
+    fn from(frame: &Frame) -> Self {
+        PackedFrame::pack(&frame.0)
+    }
+}
+
+impl From<PackedFrame> for Frame {
+    fn from(packed: PackedFrame) -> Self {
+        Frame(packed.unpack())
+    }
+}
+
+/// The 288 bits of a frame, in the order the board takes them.  The last
+/// channel comes first, and each channel's 12 bits are most significant bit
+/// first.  This is the stream `flush` shifts out, for transports the driver
+/// doesn't know about, like a GPIO expander or a UART.
+///
+/// ```
+/// use ledpwm5947::frame::FrameBits;
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// let mut frame = [PWMValue::min(); 24];
+/// frame[23] = PWMValue::new(0x800);
+/// frame[0] = PWMValue::new(0x001);
+///
+/// let bits = FrameBits::new(&frame);
+/// assert_eq!(288, bits.len());
+///
+/// let high: Vec<usize> = bits.enumerate().filter(|(_, bit)| *bit).map(|(i, _)| i).collect();
+/// assert_eq!(vec![0, 287], high);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrameBits<'a> {
+    frame: &'a [PWMValue; 24],
+    position: usize,
+}
+
+impl<'a> FrameBits<'a> {
+    /// The bits of the frame, starting with the most significant bit of the
+    /// last channel.
+    pub fn new(frame: &'a [PWMValue; 24]) -> Self {
+        FrameBits { frame, position: 0 }
+    }
+}
+
+impl<'a> Iterator for FrameBits<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.position >= FRAME_BITS {
+            return None;
+        }
+
+        let bits = PWM_BITS as usize;
+        let channel = 23 - self.position / bits;
+        let shift = bits - 1 - self.position % bits;
+        self.position += 1;
+
+        Some((self.frame[channel].value() >> shift) & 1 == 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = FRAME_BITS - self.position;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for FrameBits<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_round_trip() {
+        let mut values = [PWMValue::min(); 24];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = PWMValue::new(0xfff - i as i32 * 171);
+        }
+
+        let packed = PackedFrame::pack(&values);
+        assert_eq!(values, packed.unpack());
+        assert_eq!(packed, PackedFrame::new(*packed.as_bytes()));
+
+        let bits: [bool; FRAME_BITS] = core::array::from_fn(|i| packed.bits().nth(i).unwrap());
+        let expected: [bool; FRAME_BITS] =
+            core::array::from_fn(|i| FrameBits::new(&values).nth(i).unwrap());
+        assert_eq!(expected, bits);
+
+        let mut changed = packed;
+        changed.set(&crate::C7, PWMValue::new(0x5a5));
+        values[6] = PWMValue::new(0x5a5);
+        assert_eq!(PackedFrame::pack(&values), changed);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_postcard_round_trip() {
+        use crate::pwm::Step;
+
+        let mut values = [PWMValue::min(); 24];
+        values[0] = PWMValue::max();
+        values[23] = PWMValue::new(1234);
+        let snapshot = Frame::new(values);
+
+        let mut buffer = [0_u8; 96];
+        let bytes = postcard::to_slice(&snapshot, &mut buffer).unwrap();
+        let restored: Frame = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(snapshot, restored);
+
+        let mut buffer = [0_u8; 4];
+        let bytes = postcard::to_slice(&Step::new(-300), &mut buffer).unwrap();
+        let step: Step = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(Step::new(-300), step);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_clamps_on_read() {
+        let mut buffer = [0_u8; 4];
+        let bytes = postcard::to_slice(&5000_u16, &mut buffer).unwrap();
+        let value: PWMValue = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(PWMValue::max(), value);
+    }
+}
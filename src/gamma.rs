@@ -0,0 +1,215 @@
+//! LEDs don't look twice as bright when the duty cycle doubles.  The eye is
+//! much more sensitive to changes in dim light than bright light, so a linear
+//! fade seems to jump up from off and then crawl at the top.  Gamma correction
+//! maps a perceived brightness onto the duty cycle, making fades look even.
+//!
+//! A gamma table maps the 256 8-bit brightness levels onto 12-bit PWM values.
+//! The tables are built with a `const fn` using integer math, so a table for
+//! any exponent is computed at build time and lives in flash.  The usual
+//! exponent of 2.2 is provided as `GAMMA_2_2`.
+//!
+//! `CIE_LIGHTNESS` is the other common curve.  It inverts the CIE 1931
+//! lightness formula, which is a cube with a short straight section near
+//! black, and tracks how the eye sees brightness a little more closely than a
+//! plain power law, especially in the dimmest steps.
+
+use crate::pwm::PWMValue;
+
+/// The value of `2^(-1/2^k)` for k from 1 to 16, as fixed-point numbers with 30
+/// fractional bits.  Multiplying together the entries for the bits set in a
+/// fraction raises 2 to the power of minus that fraction.
+const EXP2_NEG_Q30: [u64; 16] = [
+    0x2d41_3ccd,
+    0x35d1_3f33,
+    0x3ab0_31ba,
+    0x3d49_5f45,
+    0x3ea0_ecb7,
+    0x3f4f_8303,
+    0x3fa7_8457,
+    0x3fd3_b2d6,
+    0x3fe9_d595,
+    0x3ff4_e9d4,
+    0x3ffa_74ad,
+    0x3ffd_3a47,
+    0x3ffe_9d20,
+    0x3fff_4e8f,
+    0x3fff_a747,
+    0x3fff_d3a4,
+];
+
+/// The base 2 logarithm of `x / 2^16`, for x from 1 to 2^16, with 16
+/// fractional bits.  The result is never positive.
+const fn log2_q16(x: u64) -> i64 {
+    let mut msb = 0;
+    let mut shifted = x;
+    while shifted > 1 {
+        shifted >>= 1;
+        msb += 1;
+    }
+
+    let mut result = (msb - 16) << 16;
+    let mut y = if msb >= 16 {
+        x >> (msb - 16)
+    } else {
+        x << (16 - msb)
+    };
+
+    let mut bit = 1 << 15;
+    while bit > 0 {
+        y = (y * y) >> 16;
+        if y >= 2 << 16 {
+            y >>= 1;
+            result += bit;
+        }
+        bit >>= 1;
+    }
+
+    result
+}
+
+/// Raises 2 to the power of `-k / 2^16`, for k of zero or more, with 30
+/// fractional bits in the result.
+const fn exp2_neg_q30(k: i64) -> u64 {
+    let whole = k >> 16;
+    if whole >= 31 {
+        return 0;
+    }
+
+    let mut result: u64 = 1 << 30;
+    let mut i = 0;
+    while i < 16 {
+        if k & (1 << (15 - i)) != 0 {
+            result = (result * EXP2_NEG_Q30[i]) >> 30;
+        }
+        i += 1;
+    }
+
+    result >> whole
+}
+
+/// A table mapping 8-bit brightness onto 12-bit PWM values for a given gamma
+/// exponent.
+///
+/// ```
+/// use ledpwm5947::gamma::{GammaTable, GAMMA_2_2};
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// static GAMMA_2_8: GammaTable = GammaTable::new(280);
+///
+/// assert_eq!(PWMValue::max(), GAMMA_2_8.correct(255));
+/// assert!(GAMMA_2_8.correct(128) < GAMMA_2_2.correct(128));
+/// ```
+#[derive(Debug)]
+pub struct GammaTable {
+    table: [u16; 256],
+}
+
+impl GammaTable {
+    /// Builds the table for the gamma exponent, given in hundredths so 2.2 is
+    /// 220.  An exponent of 100 gives a linear table.
+    pub const fn new(exponent_hundredths: u32) -> Self {
+        let mut table = [0_u16; 256];
+
+        let mut i = 1;
+        while i < 256 {
+            let x = ((i as u64) << 16) / 255;
+            let power = -log2_q16(x) * exponent_hundredths as i64 / 100;
+            let scaled = exp2_neg_q30(power);
+            table[i] = ((scaled * 4095 + (1 << 29)) >> 30) as u16;
+            i += 1;
+        }
+
+        GammaTable { table }
+    }
+
+    /// Builds the table for the CIE 1931 lightness curve, taking the 8-bit
+    /// brightness as the lightness from 0 to 100.  Below a lightness of 8 the
+    /// curve is a straight line, and above it a cube.
+    pub const fn cie_lightness() -> Self {
+        let mut table = [0_u16; 256];
+
+        let mut i = 0;
+        while i < 256 {
+            let b = i as u64;
+            // The lightness is 100 * b / 255, and it's 8 at b = 20.4.
+            table[i] = if b * 100 <= 8 * 255 {
+                // Y = L / 903.3
+                ((4095 * 1000 * b + 255 * 9033 / 2) / (255 * 9033)) as u16
+            } else {
+                // Y = ((L + 16) / 116)^3, with L + 16 = (100 * b + 4080) / 255
+                let base = 100 * b + 4080;
+                let full: u64 = 29580 * 29580 * 29580;
+                ((4095 * base * base * base + full / 2) / full) as u16
+            };
+            i += 1;
+        }
+
+        GammaTable { table }
+    }
+
+    /// The gamma corrected PWM value for an 8-bit brightness.
+    pub fn correct(&self, brightness: u8) -> PWMValue {
+        PWMValue::new(self.table[brightness as usize] as i32)
+    }
+}
+
+/// The gamma table for the common exponent of 2.2.
+pub static GAMMA_2_2: GammaTable = GammaTable::new(220);
+
+/// The table for the CIE 1931 lightness curve.
+pub static CIE_LIGHTNESS: GammaTable = GammaTable::cie_lightness();
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn test_matches_float_math() {
+        for exponent in [100_u32, 180, 220, 250, 300].iter() {
+            let table = GammaTable::new(*exponent);
+            for i in 0..256 {
+                let expected = (i as f64 / 255.0).powf(*exponent as f64 / 100.0) * 4095.0;
+                let actual = table.table[i] as f64;
+                assert!(
+                    (expected - actual).abs() <= 1.0,
+                    "gamma {} at {}: {} vs {}",
+                    exponent,
+                    i,
+                    expected,
+                    actual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_cie_matches_float_math() {
+        for i in 0..256 {
+            let lightness = i as f64 * 100.0 / 255.0;
+            let y = if lightness <= 8.0 {
+                lightness / 903.3
+            } else {
+                ((lightness + 16.0) / 116.0).powi(3)
+            };
+            let expected = y * 4095.0;
+            let actual = CIE_LIGHTNESS.table[i] as f64;
+            assert!(
+                (expected - actual).abs() <= 0.5,
+                "lightness at {}: {} vs {}",
+                i,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_end_points() {
+        assert_eq!(PWMValue::min(), GAMMA_2_2.correct(0));
+        assert_eq!(PWMValue::max(), GAMMA_2_2.correct(255));
+        assert_eq!(PWMValue::min(), CIE_LIGHTNESS.correct(0));
+        assert_eq!(PWMValue::max(), CIE_LIGHTNESS.correct(255));
+    }
+}
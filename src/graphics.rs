@@ -0,0 +1,175 @@
+//! Lets a grid of LEDs on one or more boards act as a tiny display for the
+//! `embedded-graphics` crate, so text and shapes can be drawn on it.  A
+//! `GrayMatrix` treats each channel as a grayscale pixel, so one board can be
+//! a 6×4 or 8×3 grid.  An `RgbMatrix` treats each group of three channels as
+//! an RGB pixel, with red first, so one board is eight pixels, a 2×4 or 4×2
+//! grid.
+//!
+//! Pixels are numbered row by row from the top left, so the pixel at `(x, y)`
+//! is pixel `y * width + x`.  Pixel 0 is on channel 1 of the first board, and
+//! on a chain the pixels carry on into the next board.  Pixels outside the
+//! grid are ignored, as a display would.  The 8-bit colors are scaled up to 12
+//! bits linearly, the same as `From<u8>`.
+//!
+//! A matrix draws on anything that implements `LedController`, so a single
+//! board, a chain, a bank or the SPI backend all work.  Drawing only writes
+//! the buffer.  Flush the device afterwards to show it.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use embedded_graphics_core::pixelcolor::Gray8;
+//! use embedded_graphics_core::prelude::*;
+//! use ledpwm5947::graphics::GrayMatrix;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C8};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//!
+//! let mut matrix = GrayMatrix::new(&mut device, 6, 4);
+//! Pixel(Point::new(1, 1), Gray8::WHITE).draw(&mut matrix).unwrap();
+//!
+//! assert_eq!(PWMValue::max(), device.read_pwm(&C8));
+//! device.flush().unwrap();
+//! ```
+
+use crate::controller::LedController;
+use crate::pwm::PWMValue;
+use core::convert::Infallible;
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{OriginDimensions, Size};
+use embedded_graphics_core::pixelcolor::{Gray8, GrayColor, Rgb888, RgbColor};
+use embedded_graphics_core::Pixel;
+
+/// A grid of grayscale pixels, one channel each.
+#[derive(Debug)]
+pub struct GrayMatrix<'a, M: LedController> {
+    target: &'a mut M,
+    width: u32,
+    height: u32,
+}
+
+impl<'a, M: LedController> GrayMatrix<'a, M> {
+    /// Create a grid of `width` by `height` pixels over the target's channels.
+    /// Pixels past the last channel are ignored.
+    pub fn new(target: &'a mut M, width: u32, height: u32) -> Self {
+        GrayMatrix {
+            target,
+            width,
+            height,
+        }
+    }
+}
+
+impl<'a, M: LedController> OriginDimensions for GrayMatrix<'a, M> {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl<'a, M: LedController> DrawTarget for GrayMatrix<'a, M> {
+    type Color = Gray8;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(index) = pixel_index(point.x, point.y, self.width, self.height) {
+                if index < self.target.num_channels() {
+                    self.target.set(index, PWMValue::from(color.luma()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A grid of RGB pixels, three channels each, in red, green, blue order.
+#[derive(Debug)]
+pub struct RgbMatrix<'a, M: LedController> {
+    target: &'a mut M,
+    width: u32,
+    height: u32,
+}
+
+impl<'a, M: LedController> RgbMatrix<'a, M> {
+    /// Create a grid of `width` by `height` pixels over the target's channels.
+    /// Pixels past the last full group of three channels are ignored.
+    pub fn new(target: &'a mut M, width: u32, height: u32) -> Self {
+        RgbMatrix {
+            target,
+            width,
+            height,
+        }
+    }
+}
+
+impl<'a, M: LedController> OriginDimensions for RgbMatrix<'a, M> {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl<'a, M: LedController> DrawTarget for RgbMatrix<'a, M> {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(index) = pixel_index(point.x, point.y, self.width, self.height) {
+                let first = index * 3;
+                if first + 2 < self.target.num_channels() {
+                    self.target.set(first, PWMValue::from(color.r()));
+                    self.target.set(first + 1, PWMValue::from(color.g()));
+                    self.target.set(first + 2, PWMValue::from(color.b()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The row-major index of the pixel, or `None` if it's outside the grid.
+fn pixel_index(x: i32, y: i32, width: u32, height: u32) -> Option<usize> {
+    if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+        return None;
+    }
+    Some(y as usize * width as usize + x as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+    use crate::{C1, C22, C23, C24, PWM5947};
+    use embedded_graphics_core::prelude::*;
+
+    #[test]
+    fn test_rgb_clipping() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut matrix = RgbMatrix::new(&mut device, 3, 3);
+
+        let pixels = [
+            Pixel(Point::new(1, 2), Rgb888::new(255, 0, 128)),
+            Pixel(Point::new(3, 0), Rgb888::WHITE),
+            Pixel(Point::new(0, -1), Rgb888::WHITE),
+        ];
+        matrix.draw_iter(pixels.iter().copied()).unwrap();
+
+        assert_eq!(PWMValue::max(), device.read_pwm(&C22));
+        assert_eq!(PWMValue::min(), device.read_pwm(&C23));
+        assert_eq!(PWMValue::from(128_u8), device.read_pwm(&C24));
+        assert_eq!(PWMValue::min(), device.read_pwm(&C1));
+    }
+}
@@ -0,0 +1,258 @@
+//! A channel group bundles any set of channels so they can be addressed as
+//! one logical thing, like a digit on a 7-segment display or a string of
+//! LEDs along one edge of a sign.  The channels don't have to be next to each
+//! other, and the order they're given in is the order values are written in.
+//!
+//! The writes only touch the buffer, so every channel in the group changes on
+//! the same flush.
+//!
+//! Separately, each channel on the device is in one of `BRIGHTNESS_GROUPS`
+//! brightness groups, which dim their channels at flush time without changing
+//! the buffer.  `assign_brightness_group` puts a whole channel group in one,
+//! so a zone can be written and dimmed as a unit.
+
+use crate::pwm::PWMValue;
+use crate::timing::Delay;
+use crate::{Channel, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+/// The number of brightness groups on a device.  See
+/// `PWM5947::set_group_brightness`.
+pub const BRIGHTNESS_GROUPS: usize = 8;
+
+/// An ordered set of up to 24 channels.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::group::ChannelGroup;
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::{PWM5947, C1, C2, C3, C4, C5, C6, C7};
+///
+/// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+/// let digit = ChannelGroup::new(&[C1, C2, C3, C4, C5, C6, C7]).expect("7 segments");
+///
+/// digit.set_all(&mut device, PWMValue::max());
+/// digit.scale_by(&mut device, 1, 2);
+/// device.flush().ok();
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct ChannelGroup {
+    channels: [u8; 24],
+    len: usize,
+}
+
+impl ChannelGroup {
+    /// Create a group from the channels, in order.  Returns `None` if there
+    /// are more than 24 channels.
+    pub fn new(channels: &[Channel]) -> Option<Self> {
+        if channels.len() > 24 {
+            return None;
+        }
+
+        let mut group = ChannelGroup {
+            channels: [0; 24],
+            len: channels.len(),
+        };
+        for (slot, channel) in group.channels.iter_mut().zip(channels) {
+            *slot = channel.0 as u8;
+        }
+        Some(group)
+    }
+
+    /// Every other channel, starting at the zero-based index `first`.
+    pub(crate) fn every_other(first: usize) -> Self {
+        let mut group = ChannelGroup {
+            channels: [0; 24],
+            len: 12,
+        };
+        for (n, slot) in group.channels[..12].iter_mut().enumerate() {
+            *slot = (first + 2 * n) as u8;
+        }
+        group
+    }
+
+    /// The number of channels in the group.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the group has no channels.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the channel is in the group.
+    pub fn contains(&self, channel: &Channel) -> bool {
+        self.indices().any(|i| i == channel.0)
+    }
+
+    pub(crate) fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.channels[..self.len].iter().map(|i| *i as usize)
+    }
+
+    /// Writes the same value into every channel in the group.
+    pub fn set_all<L, D, O, C, T>(&self, device: &mut PWM5947<L, D, O, C, T>, value: PWMValue)
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        for i in self.indices() {
+            device.buffer[i] = value;
+            device.fine[i] = 0;
+        }
+        device.dirty = true;
+    }
+
+    /// Scales every channel in the group by `numerator / denominator`, rounded
+    /// to the nearest value.  A fraction over one brightens the channels, up
+    /// to the maximum.  A denominator of zero turns the channels off.
+    pub fn scale_by<L, D, O, C, T>(
+        &self,
+        device: &mut PWM5947<L, D, O, C, T>,
+        numerator: u32,
+        denominator: u32,
+    ) where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        for i in self.indices() {
+            device.buffer[i] = if denominator == 0 {
+                PWMValue::min()
+            } else {
+                let scaled = (device.buffer[i].value() as u64 * numerator as u64
+                    + denominator as u64 / 2)
+                    / denominator as u64;
+                PWMValue::new(scaled.min(i32::MAX as u64) as i32)
+            };
+            device.fine[i] = 0;
+        }
+        device.dirty = true;
+    }
+
+    /// Puts every channel in the group into the device's brightness group.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the brightness group is not less than `BRIGHTNESS_GROUPS`.
+    pub fn assign_brightness_group<L, D, O, C, T>(
+        &self,
+        device: &mut PWM5947<L, D, O, C, T>,
+        brightness_group: usize,
+    ) where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        for i in self.indices() {
+            device.set_channel_group(&Channel(i), brightness_group);
+        }
+    }
+
+    /// Turns every channel in the group off.
+    pub fn clear<L, D, O, C, T>(&self, device: &mut PWM5947<L, D, O, C, T>)
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        self.set_all(device, PWMValue::min());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pwm::RangeError;
+    use crate::test_pins::IdlePin;
+    use crate::{C1, C12, C24, C3, C5};
+
+    #[test]
+    fn test_write_group() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let group = ChannelGroup::new(&[C24, C1, C12]).unwrap();
+        assert_eq!(3, group.len());
+        assert!(group.contains(&C12));
+        assert!(!group.contains(&C3));
+
+        let values = [PWMValue::new(1), PWMValue::new(2), PWMValue::new(3)];
+        assert!(device.write_group(&group, &values).is_ok());
+        assert_eq!(PWMValue::new(1), device.buffer[23]);
+        assert_eq!(PWMValue::new(2), device.buffer[0]);
+        assert_eq!(PWMValue::new(3), device.buffer[11]);
+
+        let short = [PWMValue::max(); 2];
+        assert_eq!(
+            Err(RangeError::Length {
+                len: 2,
+                expected: 3
+            }),
+            device.write_group(&group, &short)
+        );
+        let long = [PWMValue::max(); 4];
+        assert_eq!(
+            Err(RangeError::Length {
+                len: 4,
+                expected: 3
+            }),
+            device.write_group(&group, &long)
+        );
+        assert_eq!(PWMValue::new(2), device.buffer[0]);
+    }
+
+    #[test]
+    fn test_group_operations() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let group = ChannelGroup::new(&[C3, C5]).unwrap();
+
+        group.set_all(&mut device, PWMValue::new(1000));
+        assert_eq!(PWMValue::new(1000), device.buffer[2]);
+        assert_eq!(PWMValue::new(1000), device.buffer[4]);
+        assert_eq!(PWMValue::min(), device.buffer[3]);
+
+        group.scale_by(&mut device, 1, 3);
+        assert_eq!(PWMValue::new(333), device.buffer[2]);
+        group.scale_by(&mut device, 100, 1);
+        assert_eq!(PWMValue::max(), device.buffer[4]);
+
+        group.clear(&mut device);
+        assert_eq!(PWMValue::min(), device.buffer[2]);
+        assert_eq!(PWMValue::min(), device.buffer[4]);
+
+        let too_many = [
+            C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1, C1,
+            C1, C1, C1,
+        ];
+        assert!(ChannelGroup::new(&too_many).is_none());
+    }
+
+    #[test]
+    fn test_banks() {
+        let bank_a = Channel::bank_a();
+        let bank_b = Channel::bank_b();
+        assert_eq!(12, bank_a.len());
+        assert_eq!(12, bank_b.len());
+
+        assert!(bank_a.contains(&C1));
+        assert!(bank_a.contains(&crate::C23));
+        assert!(!bank_a.contains(&C24));
+        assert!(bank_b.contains(&C24));
+        assert!(!bank_b.contains(&C3));
+    }
+}
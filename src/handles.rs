@@ -0,0 +1,172 @@
+//! Channel handles let different parts of a program own different channels of
+//! the same board.  The device goes into a `RefCell`, and `PWM5947::split`
+//! hands out one `ChannelHandle` per channel and a single `Flusher`.  A task
+//! that drives the status LED takes channel 1, the task that drives the
+//! headlights takes channels 4 to 6, and the display task flushes.
+//!
+//! Each handle only touches its own channel, and borrows the device just long
+//! enough to read or write the value.  A `RefCell` isn't `Sync`, so the handles
+//! suit tasks on one executor, or an RTIC app where the tasks share a
+//! priority.  Handles can't be used from an interrupt while another borrow is
+//! held; the borrow would panic.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use core::cell::RefCell;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::PWM5947;
+//!
+//! let device = RefCell::new(PWM5947::new(Pin, Pin, Pin, Pin));
+//! let parts = PWM5947::split(&device);
+//! let [status, _, _, headlight, ..] = parts.channels;
+//!
+//! status.write(PWMValue::max());
+//! headlight.write(PWMValue::new(2000));
+//! parts.flusher.flush().unwrap();
+//!
+//! assert_eq!(PWMValue::new(2000), headlight.read());
+//! ```
+
+use crate::pwm::PWMValue;
+use crate::timing::Delay;
+use crate::{Channel, DeviceError, PWM5947};
+use core::cell::RefCell;
+use embedded_hal::digital::v2::OutputPin;
+
+/// The handles returned by `PWM5947::split`, one per channel, in channel order,
+/// and the flusher.
+pub struct Parts<'a, L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    pub channels: [ChannelHandle<'a, L, D, O, C, T>; 24],
+    pub flusher: Flusher<'a, L, D, O, C, T>,
+}
+
+/// Owns one channel of a shared device.
+pub struct ChannelHandle<'a, L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    device: &'a RefCell<PWM5947<L, D, O, C, T>>,
+    index: usize,
+}
+
+impl<'a, L, D, O, C, T> ChannelHandle<'a, L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// The channel the handle owns.
+    pub fn channel(&self) -> Channel {
+        Channel(self.index)
+    }
+
+    /// Writes a value into the channel's slot in the buffer.  Like `write_pwm`,
+    /// it shows on the next flush.
+    pub fn write(&self, value: PWMValue) {
+        self.device
+            .borrow_mut()
+            .write_pwm(&Channel(self.index), &value);
+    }
+
+    /// The value in the channel's slot in the buffer.
+    pub fn read(&self) -> PWMValue {
+        self.device.borrow().read_pwm(&Channel(self.index))
+    }
+}
+
+/// Flushes a shared device.
+pub struct Flusher<'a, L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    device: &'a RefCell<PWM5947<L, D, O, C, T>>,
+}
+
+impl<'a, L, D, O, C, T> Flusher<'a, L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Flushes the buffer to the board.
+    pub fn flush(&self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.device.borrow_mut().flush()
+    }
+
+    /// Flushes the buffer only if a channel changed since the last flush.  It
+    /// returns whether it flushed.
+    pub fn flush_if_dirty(&self) -> Result<bool, DeviceError<L, D, O, C>> {
+        let mut device = self.device.borrow_mut();
+        if !device.is_dirty() {
+            return Ok(false);
+        }
+        device.flush()?;
+        Ok(true)
+    }
+}
+
+impl<L, D, O, C, T> PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Splits a shared device into a handle per channel and a flusher.  The
+    /// device stays in the cell, so it can still be borrowed as a whole for the
+    /// things the handles don't cover, like fades.
+    pub fn split(device: &RefCell<Self>) -> Parts<'_, L, D, O, C, T> {
+        Parts {
+            channels: core::array::from_fn(|index| ChannelHandle { device, index }),
+            flusher: Flusher { device },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pwm::PWMValue;
+    use crate::test_pins::IdlePin;
+
+    #[test]
+    fn test_handles_share_the_buffer() {
+        let device = RefCell::new(PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin));
+        let parts = PWM5947::split(&device);
+
+        assert_eq!(Ok(false), parts.flusher.flush_if_dirty());
+        parts.channels[23].write(PWMValue::max());
+        assert_eq!(Ok(true), parts.flusher.flush_if_dirty());
+        assert_eq!(Ok(false), parts.flusher.flush_if_dirty());
+
+        assert_eq!(PWMValue::max(), device.borrow().read_pwm(&crate::C24));
+        assert_eq!(PWMValue::min(), parts.channels[0].read());
+    }
+}
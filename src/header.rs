@@ -0,0 +1,129 @@
+//! Names the board's connectors, so code can be wired the way the board is.
+//! The outputs are grouped into eight 3-pin headers, J1 to J8, each holding
+//! three channels in order, and an RGB LED on a header has red on its first
+//! pin.  J1 is channels `C1` to `C3`, J2 is `C4` to `C6`, and so on up to J8
+//! with `C22` to `C24`.
+//!
+//! Counting from the header saves working out the channel numbers by hand,
+//! which is where the wiring usually goes wrong.  `Header::of` goes the other
+//! way, for printing which connector a channel is on.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::header::Header;
+//! use ledpwm5947::{PWM5947, C10, C12};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//!
+//! let porch = Header::J4.rgb();
+//! assert_eq!(&C10, porch.red());
+//! porch.set_color_u8(&mut device, 0xff, 0x80, 0x00);
+//! device.flush().ok();
+//!
+//! assert_eq!(Header::J4, Header::of(&C12));
+//! ```
+
+use crate::rgb::RgbLed;
+use crate::Channel;
+
+/// One of the eight 3-channel headers on the board.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Header {
+    /// Channels `C1` to `C3`.
+    J1,
+    /// Channels `C4` to `C6`.
+    J2,
+    /// Channels `C7` to `C9`.
+    J3,
+    /// Channels `C10` to `C12`.
+    J4,
+    /// Channels `C13` to `C15`.
+    J5,
+    /// Channels `C16` to `C18`.
+    J6,
+    /// Channels `C19` to `C21`.
+    J7,
+    /// Channels `C22` to `C24`.
+    J8,
+}
+
+/// Every header, in order.
+pub const ALL_HEADERS: [Header; 8] = [
+    Header::J1,
+    Header::J2,
+    Header::J3,
+    Header::J4,
+    Header::J5,
+    Header::J6,
+    Header::J7,
+    Header::J8,
+];
+
+impl Header {
+    /// The header a channel is on.
+    pub const fn of(channel: &Channel) -> Self {
+        ALL_HEADERS[channel.0 / 3]
+    }
+
+    /// The header's zero-based position, so J1 is 0.
+    pub const fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// The header's three channels, in pin order.
+    pub const fn channels(&self) -> [Channel; 3] {
+        let first = self.index() * 3;
+        [Channel(first), Channel(first + 1), Channel(first + 2)]
+    }
+
+    /// An RGB LED on the header, with red on the first pin.
+    pub const fn rgb(&self) -> RgbLed {
+        let [red, green, blue] = self.channels();
+        RgbLed::new(red, green, blue)
+    }
+}
+
+impl core::fmt::Display for Header {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "J{}", self.index() + 1)
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for Header {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(f, "J{}", self.index() + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{ALL_CHANNELS, C1, C22, C24};
+    use std::string::ToString;
+
+    #[test]
+    fn test_headers() {
+        assert_eq!([C22, crate::C23, C24], Header::J8.channels());
+        assert_eq!(&C1, Header::J1.rgb().red());
+        for (index, channel) in ALL_CHANNELS.iter().enumerate() {
+            let header = Header::of(channel);
+            assert_eq!(index / 3, header.index());
+            assert!(header.channels().contains(channel));
+        }
+        assert_eq!("J8", Header::J8.to_string());
+    }
+}
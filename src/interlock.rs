@@ -0,0 +1,175 @@
+//! A safety interlock limits how long a channel can stay on, as a last line
+//! of defence for IR illuminators, laser diodes and other loads that must not
+//! be left running.  Each guarded channel is timed while its value is above a
+//! threshold, and once it's been above for more than the allowed number of
+//! ticks the interlock trips.  The device then writes the channel down to
+//! zero, cancels any fade on it, and keeps its output at zero until the trip
+//! is cleared, whatever the firmware writes in the meantime.
+//!
+//! The time is counted in the ticks passed to `tick` and `tick_at`, so with
+//! `tick_at` the limit is in milliseconds.  The count is real time, not
+//! animation time, so slowing the animations down with `set_animation_speed`
+//! doesn't stretch the limit.  Nothing is timed unless the firmware keeps
+//! ticking the device.  Dropping to the threshold or below resets a channel's
+//! count.
+//!
+//! The check uses the values in the buffer, before the caps and the other
+//! output shaping.  A channel that trips shows in `tripped_channels`, one bit
+//! per channel, so the firmware can report it.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::interlock::Interlock;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C5};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! // The IR illuminator on C5 can be on for at most 3 ticks at a time.
+//! device.set_interlock(Some(Interlock::new(PWMValue::min(), 3).guard(&C5)));
+//!
+//! device.write_pwm(&C5, &PWMValue::max());
+//! for _ in 0..3 {
+//!     device.tick();
+//! }
+//! assert_eq!(0, device.tripped_channels());
+//!
+//! device.tick();
+//! assert_eq!(1 << 4, device.tripped_channels());
+//! assert_eq!(PWMValue::min(), device.read_pwm(&C5));
+//!
+//! device.write_pwm(&C5, &PWMValue::max());
+//! assert_eq!(PWMValue::min(), device.output_frame()[4]);
+//! device.clear_interlock();
+//! assert_eq!(PWMValue::max(), device.output_frame()[4]);
+//! ```
+
+use crate::pwm::PWMValue;
+use crate::Channel;
+
+/// The threshold and time limit, which channels they apply to, and how long
+/// each channel has been on.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Interlock {
+    threshold: PWMValue,
+    max_ticks: u32,
+    guarded: u32,
+    on_ticks: [u32; 24],
+    tripped: u32,
+}
+
+impl Interlock {
+    /// Create an interlock that trips a channel after it's been above
+    /// `threshold` for more than `max_ticks` ticks.  It doesn't guard any
+    /// channels until they're added with `guard`.
+    pub const fn new(threshold: PWMValue, max_ticks: u32) -> Self {
+        Interlock {
+            threshold,
+            max_ticks,
+            guarded: 0,
+            on_ticks: [0; 24],
+            tripped: 0,
+        }
+    }
+
+    /// Guards the channel as well.
+    pub const fn guard(mut self, channel: &Channel) -> Self {
+        self.guarded |= 1 << channel.0;
+        self
+    }
+
+    /// The value a channel has to be above to be timed.
+    pub fn threshold(&self) -> PWMValue {
+        self.threshold
+    }
+
+    /// The longest a channel can be above the threshold, in ticks.
+    pub fn max_ticks(&self) -> u32 {
+        self.max_ticks
+    }
+
+    /// The guarded channels, one bit per channel with `C1` in bit 0.
+    pub fn guarded(&self) -> u32 {
+        self.guarded
+    }
+
+    /// The channels that have tripped, one bit per channel.
+    pub fn tripped(&self) -> u32 {
+        self.tripped
+    }
+
+    /// Clears the trips and starts every count again.
+    pub fn clear(&mut self) {
+        self.tripped = 0;
+        self.on_ticks = [0; 24];
+    }
+
+    /// Times the guarded channels over `ticks` more ticks at the values, and
+    /// returns the channels that tripped this time.
+    pub fn update(&mut self, values: &[PWMValue; 24], ticks: u32) -> u32 {
+        let mut tripped = 0;
+        for (index, (value, on_ticks)) in values.iter().zip(self.on_ticks.iter_mut()).enumerate() {
+            let bit = 1 << index;
+            if self.guarded & bit == 0 || self.tripped & bit != 0 {
+                continue;
+            }
+
+            if *value > self.threshold {
+                *on_ticks = on_ticks.saturating_add(ticks);
+                if *on_ticks > self.max_ticks {
+                    tripped |= bit;
+                }
+            } else {
+                *on_ticks = 0;
+            }
+        }
+        self.tripped |= tripped;
+        tripped
+    }
+
+    /// Forces the tripped channels in the frame to zero.
+    pub fn apply(&self, frame: &mut [PWMValue; 24]) {
+        for (index, value) in frame.iter_mut().enumerate() {
+            if self.tripped & (1 << index) != 0 {
+                *value = PWMValue::min();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{C1, C2, C3};
+
+    #[test]
+    fn test_counting() {
+        let mut interlock = Interlock::new(PWMValue::new(100), 10).guard(&C1).guard(&C2);
+        let mut values = [PWMValue::new(101); 24];
+        values[1] = PWMValue::new(100);
+
+        assert_eq!(0, interlock.update(&values, 6));
+        values[0] = PWMValue::new(100);
+        assert_eq!(0, interlock.update(&values, 6));
+        values[0] = PWMValue::max();
+        values[1] = PWMValue::max();
+        assert_eq!(0, interlock.update(&values, 10));
+        assert_eq!(0b11, interlock.update(&values, 1));
+        assert_eq!(0, interlock.update(&values, 1));
+        assert_eq!(0b11, interlock.tripped());
+
+        let mut frame = values;
+        interlock.apply(&mut frame);
+        assert_eq!(PWMValue::min(), frame[0]);
+        assert_eq!(values[C3.0], frame[C3.0]);
+
+        interlock.clear();
+        assert_eq!(0, interlock.update(&values, 10));
+    }
+}
@@ -0,0 +1,222 @@
+//! The interpolate module turns a handful of keyframes into a smooth stream
+//! of frames at a fixed refresh rate.  A keyframe is the value of all 24
+//! channels at a point in time.  The interpolator works out where each refresh
+//! falls between two keyframes and blends the channel values accordingly.
+//!
+//! Each frame is computed from the keyframes directly, rather than by adding
+//! a step to the last frame.  The rounding error never builds up, so a long,
+//! slow fade still lands on the keyframe values and moves evenly between them.
+//!
+//! The interpolator runs on a `TimeBase`, so the whole animation can be slowed
+//! down or sped up while it's playing.  At anything but normal speed the frames
+//! no longer land exactly on the keyframes, but they stay evenly spaced.
+
+use crate::pwm::PWMValue;
+use crate::timebase::{Speed, TimeBase};
+
+/// A keyframe is the value of every channel at a given time, in milliseconds
+/// from the start of the animation.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Keyframe {
+    pub time_ms: u32,
+    pub values: [PWMValue; 24],
+}
+
+impl Keyframe {
+    /// Create a new keyframe for the given time.  This is a const function,
+    /// so a whole animation can be a `const` array in flash.
+    pub const fn new(time_ms: u32, values: [PWMValue; 24]) -> Self {
+        Keyframe { time_ms, values }
+    }
+}
+
+/// The interpolator iterates over the frames for a set of keyframes at a fixed
+/// frame rate.  The keyframes are expected to be sorted by time.  Frames start
+/// at time zero, holding the first keyframe until its time comes, and end with
+/// the last keyframe.  A one second animation at 30 frames per second produces
+/// 31 frames.
+///
+/// ```
+/// use ledpwm5947::interpolate::{Interpolator, Keyframe};
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// let keyframes = [
+///     Keyframe::new(0, [PWMValue::min(); 24]),
+///     Keyframe::new(1000, [PWMValue::new(300); 24]),
+/// ];
+///
+/// let mut frames = Interpolator::new(&keyframes, 30);
+/// assert_eq!(Some([PWMValue::min(); 24]), frames.next());
+/// assert_eq!(Some([PWMValue::new(10); 24]), frames.next());
+/// assert_eq!(Some([PWMValue::new(300); 24]), frames.last());
+/// ```
+#[derive(Debug)]
+pub struct Interpolator<'a> {
+    keyframes: &'a [Keyframe],
+    fps: u32,
+    position: u64,
+    segment: usize,
+    time_base: TimeBase,
+}
+
+impl<'a> Interpolator<'a> {
+    /// Create an interpolator over the keyframes at the given frame rate.  A
+    /// frame rate of zero is treated as one frame per second.
+    pub fn new(keyframes: &'a [Keyframe], fps: u32) -> Self {
+        Interpolator {
+            keyframes,
+            fps: fps.max(1),
+            position: 0,
+            segment: 0,
+            time_base: TimeBase::default(),
+        }
+    }
+
+    /// The speed the animation is playing at.
+    pub fn speed(&self) -> Speed {
+        self.time_base.speed()
+    }
+
+    /// Change the speed of the animation, starting with the next frame.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.time_base.set_speed(speed);
+    }
+
+    /// Blends the channel values of the keyframes on either side of the
+    /// time.  The time is in units of `1 / fps` milliseconds, so it's exact
+    /// for every frame.
+    fn blend(&self, from: &Keyframe, to: &Keyframe, time: u64) -> [PWMValue; 24] {
+        let start = from.time_ms as u64 * self.fps as u64;
+        let span = (to.time_ms as u64 * self.fps as u64 - start) as i64;
+        let elapsed = (time - start) as i64;
+
+        let mut result = [PWMValue::min(); 24];
+        for (i, value) in result.iter_mut().enumerate() {
+            let a = from.values[i].value() as i64;
+            let delta = to.values[i].value() as i64 - a;
+            let scaled = delta * elapsed;
+            let rounded = if scaled < 0 {
+                (scaled - span / 2) / span
+            } else {
+                (scaled + span / 2) / span
+            };
+            *value = PWMValue::new((a + rounded) as i32);
+        }
+        result
+    }
+}
+
+impl<'a> Iterator for Interpolator<'a> {
+    type Item = [PWMValue; 24];
+
+    fn next(&mut self) -> Option<[PWMValue; 24]> {
+        let last = self.keyframes.last()?;
+        let time = self.position;
+        if time > last.time_ms as u64 * self.fps as u64 {
+            return None;
+        }
+        self.position += self.time_base.advance(1000) as u64;
+
+        while self.segment + 1 < self.keyframes.len()
+            && self.keyframes[self.segment + 1].time_ms as u64 * self.fps as u64 <= time
+        {
+            self.segment += 1;
+        }
+
+        let from = &self.keyframes[self.segment];
+        if self.segment + 1 == self.keyframes.len() || time < from.time_ms as u64 * self.fps as u64
+        {
+            return Some(from.values);
+        }
+
+        let to = &self.keyframes[self.segment + 1];
+        Some(self.blend(from, to, time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lands_on_keyframes() {
+        let mut middle = [PWMValue::new(4095); 24];
+        middle[3] = PWMValue::new(17);
+        let keyframes = [
+            Keyframe::new(0, [PWMValue::min(); 24]),
+            Keyframe::new(7000, middle),
+            Keyframe::new(9000, [PWMValue::new(1); 24]),
+        ];
+
+        let frames = Interpolator::new(&keyframes, 60);
+        let mut count = 0;
+        let mut previous = PWMValue::min();
+        for (i, frame) in frames.enumerate() {
+            if i == 420 {
+                assert_eq!(middle, frame);
+            }
+            if i <= 420 {
+                assert!(frame[0] >= previous);
+                previous = frame[0];
+            }
+            count += 1;
+        }
+        assert_eq!(541, count);
+    }
+
+    #[test]
+    fn test_late_first_keyframe() {
+        let keyframes = [
+            Keyframe::new(100, [PWMValue::new(10); 24]),
+            Keyframe::new(200, [PWMValue::new(20); 24]),
+        ];
+
+        let mut frames = Interpolator::new(&keyframes, 20);
+        assert_eq!(Some([PWMValue::new(10); 24]), frames.next());
+        assert_eq!(Some([PWMValue::new(10); 24]), frames.next());
+        assert_eq!(Some([PWMValue::new(10); 24]), frames.next());
+        assert_eq!(Some([PWMValue::new(15); 24]), frames.next());
+        assert_eq!(Some([PWMValue::new(20); 24]), frames.next());
+        assert_eq!(None, frames.next());
+    }
+
+    #[test]
+    fn test_speed() {
+        let keyframes = [
+            Keyframe::new(0, [PWMValue::min(); 24]),
+            Keyframe::new(1000, [PWMValue::new(100); 24]),
+        ];
+
+        let mut frames = Interpolator::new(&keyframes, 10);
+        frames.set_speed(Speed::from_ratio(1, 2));
+        assert_eq!(21, frames.by_ref().count());
+
+        let mut frames = Interpolator::new(&keyframes, 10);
+        assert_eq!(Some([PWMValue::min(); 24]), frames.next());
+        frames.set_speed(Speed::from_ratio(2, 1));
+        assert_eq!(Some([PWMValue::new(10); 24]), frames.next());
+        assert_eq!(Some([PWMValue::new(30); 24]), frames.next());
+    }
+
+    #[test]
+    fn test_no_keyframes() {
+        let mut frames = Interpolator::new(&[], 60);
+        assert_eq!(None, frames.next());
+    }
+
+    #[test]
+    fn test_smooth_steps() {
+        let keyframes = [
+            Keyframe::new(0, [PWMValue::min(); 24]),
+            Keyframe::new(60_000, [PWMValue::new(50); 24]),
+        ];
+
+        let mut previous = 0;
+        for frame in Interpolator::new(&keyframes, 50) {
+            let current = frame[23].value();
+            assert!(current - previous <= 1);
+            previous = current;
+        }
+        assert_eq!(50, previous);
+    }
+}
@@ -0,0 +1,135 @@
+//! A big project ends up with dozens of channel constants, and nothing stops
+//! the status LED's channel being passed where the backlight's was meant.  The
+//! `led_layout!` macro names the outputs once and generates a struct with a
+//! setter for each one, so the rest of the firmware says what it means.
+//!
+//! Each output is a single channel, or a range of channels that are always
+//! set together.  Ranges use Rust's syntax, so `C7..=C12` is six channels and
+//! `C7..C12` is five.  The channels have to be constants in scope, like the
+//! ones at the root of the crate.  The struct borrows anything that
+//! implements `LedController`, so the layout works on the SPI backend too.  On
+//! a chain the channels are the ones on the first board.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{led_layout, PWM5947, C1, C12, C2, C7};
+//!
+//! led_layout! {
+//!     /// The front panel.
+//!     pub struct Panel {
+//!         status_red => C1,
+//!         status_green => C2,
+//!         /// The LCD backlight, which has six LEDs.
+//!         backlight => C7..=C12,
+//!     }
+//! }
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//!
+//! let mut panel = Panel::new(&mut device);
+//! panel.status_green(PWMValue::max());
+//! panel.backlight(PWMValue::new(800));
+//! panel.flush().ok();
+//!
+//! assert_eq!(PWMValue::new(800), device.read_pwm(&C12));
+//! ```
+
+/// Declares a struct with a setter for each named output.  See the `layout`
+/// module.
+#[macro_export]
+macro_rules! led_layout {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident => $first:ident $(..= $last:ident)? $(.. $end:ident)?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name<'a, Dev: $crate::controller::LedController> {
+            device: &'a mut Dev,
+        }
+
+        #[allow(dead_code)]
+        impl<'a, Dev: $crate::controller::LedController> $name<'a, Dev> {
+            /// Borrows the device to write the outputs.
+            $vis fn new(device: &'a mut Dev) -> Self {
+                $name { device }
+            }
+
+            $(
+                $(#[$field_meta])*
+                $vis fn $field(&mut self, value: $crate::pwm::PWMValue) {
+                    let channels =
+                        $crate::led_layout!(@channels $first $(..= $last)? $(.. $end)?);
+                    for channel in channels {
+                        self.device.set(channel, value);
+                    }
+                }
+            )*
+
+            /// Flushes the device.
+            $vis fn flush(&mut self) -> Result<(), Dev::Error> {
+                self.device.flush()
+            }
+
+            /// The device the outputs are written to.
+            $vis fn device(&mut self) -> &mut Dev {
+                self.device
+            }
+        }
+    };
+
+    (@channels $first:ident) => {
+        {
+            let first = $first.index() as usize;
+            first..=first
+        }
+    };
+
+    (@channels $first:ident ..= $last:ident) => {
+        ($first.index() as usize)..=($last.index() as usize)
+    };
+
+    (@channels $first:ident .. $end:ident) => {
+        ($first.index() as usize)..($end.index() as usize)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pwm::PWMValue;
+    use crate::test_pins::IdlePin;
+    use crate::{C1, C3, C5, C6, PWM5947};
+
+    led_layout! {
+        struct Sign {
+            power => C1,
+            inclusive => C3..=C5,
+            exclusive => C5..C6,
+        }
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut sign = Sign::new(&mut device);
+
+        sign.inclusive(PWMValue::new(1));
+        sign.exclusive(PWMValue::new(2));
+        sign.power(PWMValue::new(3));
+
+        let expected = [3, 0, 1, 1, 2, 0].map(PWMValue::new);
+        assert_eq!(&expected, &device.buffer()[..6]);
+    }
+}
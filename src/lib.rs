@@ -15,13 +15,79 @@
 
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 use embedded_hal::digital::v2::OutputPin;
 
+pub mod animation;
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod bank;
+pub mod builder;
+pub mod bus;
+pub mod calibration;
+pub mod chain;
+pub mod clock;
+pub mod color;
+#[cfg(feature = "arduino-compat")]
+mod compat;
+pub mod controller;
+pub mod derating;
+pub mod dimmer;
+pub mod dmx;
+pub mod effects;
+#[cfg(feature = "eh1")]
+pub mod eh1;
+pub mod expander;
+pub mod frame;
+pub mod gamma;
+#[cfg(feature = "embedded-graphics")]
+pub mod graphics;
+pub mod group;
+pub mod handles;
+pub mod header;
+pub mod interlock;
+pub mod interpolate;
+pub mod layout;
+pub mod lifecycle;
+pub mod matrix;
+#[cfg(feature = "std")]
+pub mod mock;
+pub mod partial;
+pub mod power;
+pub mod protocol;
 pub mod pwm;
+pub mod queue;
+pub mod remote;
+pub mod report;
+pub mod rgb;
+pub mod scheduler;
+pub mod sequence;
+pub mod servo;
+#[cfg(feature = "critical-section")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(feature = "smart-leds")]
+mod smart_leds;
+pub mod spi;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "storage")]
+pub mod storage;
+pub mod timebase;
+pub mod timing;
+pub mod tlc59711;
+
+use animation::{Easing, Fader};
+use timebase::{Speed, TimeBase};
+use timing::{Delay, NoDelay, Timing};
 
 /// The role a pin occupies in the device.  The values can be the latch pin,
 /// the data pin, the OE pin, or the clock pin.
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PinRole {
     Latch,
     Data,
@@ -30,71 +96,349 @@ pub enum PinRole {
 }
 
 /// The error returned from the configured device.  It indicates which pin
-/// failed and a message to help debug.
-pub struct PinError {
+/// failed and a message to help debug.  The `source` is the error the HAL
+/// returned for the pin, so the actual cause isn't lost.  A pin on an I2C
+/// expander might fail with a NACK, for example.
+///
+/// The device's own errors carry a `PinSource` for the `source`, so each pin
+/// keeps its own error type and the pins can come from different HALs, such
+/// as a latch on an I2C expander and the data and clock on the MCU.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PinError<E> {
     pub which: PinRole,
     pub message: &'static str,
+    pub source: E,
 }
 
-impl PinError {
-    fn new(which: &PinRole, message: &'static str) -> Self {
+impl<E> PinError<E> {
+    fn new(which: &PinRole, message: &'static str, source: E) -> Self {
         PinError {
             which: which.clone(),
             message,
+            source,
+        }
+    }
+}
+
+/// The HAL error from whichever of the device's pins failed.  Each variant
+/// carries that pin's own error type.  When all four pins return the same
+/// error type, as they do when they come from the same HAL, `into_inner` gives
+/// the HAL error back whichever pin it came from.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PinSource<L, D = L, O = L, C = L> {
+    Latch(L),
+    Data(D),
+    OE(O),
+    Clock(C),
+}
+
+impl<E> PinSource<E, E, E, E> {
+    /// The HAL error, from whichever pin it came from.
+    pub fn into_inner(self) -> E {
+        match self {
+            PinSource::Latch(e) | PinSource::Data(e) | PinSource::OE(e) | PinSource::Clock(e) => e,
+        }
+    }
+}
+
+/// The `PinSource` for a device driving the pins `L`, `D`, `O` and `C`.
+pub type DeviceSource<L, D, O, C> = PinSource<
+    <L as OutputPin>::Error,
+    <D as OutputPin>::Error,
+    <O as OutputPin>::Error,
+    <C as OutputPin>::Error,
+>;
+
+/// The error returned by a device driving the pins `L`, `D`, `O` and `C`.
+pub type DeviceError<L, D, O, C> = PinError<DeviceSource<L, D, O, C>>;
+
+impl core::fmt::Display for PinRole {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PinRole::Latch => f.write_str("latch"),
+            PinRole::Data => f.write_str("data"),
+            PinRole::OE => f.write_str("OE"),
+            PinRole::Clock => f.write_str("clock"),
+        }
+    }
+}
+
+/// Shows the message and the pin.  The HAL error is left out, since it may
+/// not implement `Display`, but it's still in `source`.
+impl<E> core::fmt::Display for PinError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} on the {} pin", self.message, self.which)
+    }
+}
+
+/// The HAL error isn't given as the error's `source`, since most HAL errors
+/// don't implement `Error` themselves, but it's still in the `source` field.
+impl<E> core::error::Error for PinError<E> where E: core::fmt::Debug {}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for PinRole {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        match self {
+            PinRole::Latch => f.write_str("latch"),
+            PinRole::Data => f.write_str("data"),
+            PinRole::OE => f.write_str("OE"),
+            PinRole::Clock => f.write_str("clock"),
         }
     }
 }
 
-struct PWMPin<T>
+#[cfg(feature = "ufmt")]
+impl<E> ufmt::uDisplay for PinError<E> {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(f, "{} on the {} pin", self.message, self.which)
+    }
+}
+
+/// Wraps a HAL pin with the role it plays.  An active-low pin is driven
+/// inverted, for a control line that goes through an inverting buffer or
+/// level shifter on the way to the board.  The HAL error is passed through
+/// `source`, which the device uses to tag it with the pin's `PinSource`.
+struct PWMPin<T, S>
 where
     T: OutputPin,
 {
     raw_pin: T,
     which_pin: PinRole,
+    active_low: bool,
+    source: fn(T::Error) -> S,
 }
 
-impl<T> PWMPin<T>
+impl<T, S> PWMPin<T, S>
 where
     T: OutputPin,
 {
-    fn new(raw_pin: T, which_pin: PinRole) -> Self {
-        PWMPin { raw_pin, which_pin }
+    fn new(raw_pin: T, which_pin: PinRole, source: fn(T::Error) -> S) -> Self {
+        PWMPin {
+            raw_pin,
+            which_pin,
+            active_low: false,
+            source,
+        }
+    }
+
+    fn set_raw(&mut self, high: bool) -> Result<(), PinError<S>> {
+        let result = if high {
+            self.raw_pin.set_high()
+        } else {
+            self.raw_pin.set_low()
+        };
+        result.map_err(|e| {
+            let message = if high {
+                "Failed to set high"
+            } else {
+                "Failed to set low"
+            };
+            PinError::new(&self.which_pin, message, (self.source)(e))
+        })
+    }
+}
+
+impl<T, S> core::fmt::Debug for PWMPin<T, S>
+where
+    T: OutputPin + core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PWMPin")
+            .field("raw_pin", &self.raw_pin)
+            .field("which_pin", &self.which_pin)
+            .field("active_low", &self.active_low)
+            .finish()
     }
 }
 
-impl<T> OutputPin for PWMPin<T>
+impl<T, S> OutputPin for PWMPin<T, S>
 where
     T: OutputPin,
 {
-    type Error = PinError;
+    type Error = PinError<S>;
 
     /// Set the pin to low value.  The actual hardware pin should never return
     /// an error, but I chose to return a `PinError` so the
     /// error handling can be similar to functions that may return an error.
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        match self.raw_pin.set_low() {
-            Ok(_) => Ok(()),
-            Err(_) => Err(PinError::new(&self.which_pin, "Failed to set low")),
-        }
+        self.set_raw(self.active_low)
     }
 
     /// Set the pin to a high value.  The actual hardware pin should never
     /// return an error, but I chose to return a `PinError` so the error
     /// handling code can be similar to functions tha may return an error.
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        match self.raw_pin.set_high() {
-            Ok(_) => Ok(()),
-            Err(_) => Err(PinError::new(&self.which_pin, "Failed to set high")),
+        self.set_raw(!self.active_low)
+    }
+}
+
+/// Checks a slice of values is the expected length.
+fn check_len(len: usize, expected: usize) -> Result<(), pwm::RangeError> {
+    if len == expected {
+        Ok(())
+    } else {
+        Err(pwm::RangeError::Length { len, expected })
+    }
+}
+
+/// Shifts a whole frame out to the board, last channel first, and leaves the
+/// clock low, ready for the latch.  The frame comes already packed into its 36
+/// bytes, the same as the SPI backend sends, and the bytes are shifted out
+/// most significant bit first.  Shifting whole bytes is much cheaper than
+/// picking each bit out of its channel, which matters on slow parts where the
+/// shifting limits the frame rate.  With `resync` set, a frame of zeros goes
+/// first, to push out whatever a failed flush left behind.
+fn shift_frame<D, C, S, T>(
+    data: &mut PWMPin<D, S>,
+    clock: &mut PWMPin<C, S>,
+    delay: &mut T,
+    clock_ns: u32,
+    packed: &frame::PackedFrame,
+    resync: bool,
+) -> Result<(), PinError<S>>
+where
+    D: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    let zeros = if resync { frame::FRAME_BITS } else { 0 };
+    let bits = core::iter::repeat_n(false, zeros).chain(packed.bits());
+    shift_bits(data, clock, delay, clock_ns, bits)?;
+    clock.set_low()
+}
+
+/// Shifts a frame that's already packed, most significant bit first.
+fn shift_packed<D, C, S, T>(
+    data: &mut PWMPin<D, S>,
+    clock: &mut PWMPin<C, S>,
+    delay: &mut T,
+    clock_ns: u32,
+    packed: &frame::PackedFrame,
+) -> Result<(), PinError<S>>
+where
+    D: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    shift_bits(data, clock, delay, clock_ns, packed.bits())
+}
+
+/// Shifts bits out to the board.  For each bit it sets the clock low, sets the
+/// data line high or low, and then sets the clock high so the board reads the
+/// bit.  The delay holds the clock at each level for at least `clock_ns`, which
+/// also gives the data line time to settle before the rising edge.
+fn shift_bits<D, C, S, T, I>(
+    data: &mut PWMPin<D, S>,
+    clock: &mut PWMPin<C, S>,
+    delay: &mut T,
+    clock_ns: u32,
+    bits: I,
+) -> Result<(), PinError<S>>
+where
+    D: OutputPin,
+    C: OutputPin,
+    T: Delay,
+    I: Iterator<Item = bool>,
+{
+    for bit in bits {
+        clock.set_low()?;
+
+        if bit {
+            data.set_high()?;
+        } else {
+            data.set_low()?;
         }
+
+        delay.delay_ns(clock_ns);
+        clock.set_high()?;
+        delay.delay_ns(clock_ns);
     }
+
+    Ok(())
 }
 
 /// Channel identifies a legal channel on the board.  There are only 24
 /// legal values for channel.  These constants represent the 24 channels.
-/// It may be necessary to switch to a non-public channel constructor so
-/// only these 24 channels can be instantiated, and the channel number is
-/// opaque.
+/// Channels can also be made from a zero-based index with `new`, which checks
+/// the index at run time, or `const_new`, which checks it at compile time.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Channel(usize);
+
+impl Channel {
+    /// The channel at the zero-based index, so index 0 is `C1`.  Returns
+    /// `None` past the 24th channel.  This lets channels be computed, such as
+    /// `digit * 3 + segment`.
+    ///
+    /// ```
+    /// use ledpwm5947::{Channel, C1, C24};
+    ///
+    /// assert_eq!(Some(C1), Channel::new(0));
+    /// assert_eq!(Some(C24), Channel::new(23));
+    /// assert_eq!(None, Channel::new(24));
+    /// ```
+    pub const fn new(index: u8) -> Option<Self> {
+        if index < 24 {
+            Some(Channel(index as usize))
+        } else {
+            None
+        }
+    }
+
+    /// The channel at the zero-based index, checked when the program is
+    /// compiled.  An index past the 24th channel doesn't compile.
+    ///
+    /// ```
+    /// use ledpwm5947::{Channel, C7};
+    ///
+    /// const STATUS: Channel = Channel::const_new::<6>();
+    /// assert_eq!(C7, STATUS);
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use ledpwm5947::Channel;
+    ///
+    /// const MISSING: Channel = Channel::const_new::<24>();
+    /// ```
+    pub const fn const_new<const N: u8>() -> Self {
+        const { assert!(N < 24, "the board only has 24 channels") };
+        Channel(N as usize)
+    }
+
+    /// The zero-based index of the channel, so `C1` is 0.
+    pub const fn index(&self) -> u8 {
+        self.0 as u8
+    }
+
+    /// The twelve odd-numbered channels, `C1`, `C3` and so on up to `C23`.
+    /// Boards are often wired with two kinds of LED on alternating channels,
+    /// and this pairs with `bank_b` to drive them separately.
+    ///
+    /// ```
+    /// use ledpwm5947::{Channel, C1, C2};
+    ///
+    /// let bank = Channel::bank_a();
+    /// assert_eq!(12, bank.len());
+    /// assert!(bank.contains(&C1));
+    /// assert!(!bank.contains(&C2));
+    /// ```
+    pub fn bank_a() -> group::ChannelGroup {
+        group::ChannelGroup::every_other(0)
+    }
+
+    /// The twelve even-numbered channels, `C2`, `C4` and so on up to `C24`.
+    pub fn bank_b() -> group::ChannelGroup {
+        group::ChannelGroup::every_other(1)
+    }
+}
 pub const C1: Channel = Channel(0);
 pub const C2: Channel = Channel(1);
 pub const C3: Channel = Channel(2);
@@ -120,6 +464,29 @@ pub const C22: Channel = Channel(21);
 pub const C23: Channel = Channel(22);
 pub const C24: Channel = Channel(23);
 
+/// Shows the channel the same way the constants are named, starting at C1.
+///
+/// ```
+/// use ledpwm5947::C12;
+///
+/// assert_eq!("C12", format!("{}", C12));
+/// ```
+impl core::fmt::Display for Channel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "C{}", self.0 + 1)
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for Channel {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(f, "C{}", self.0 + 1)
+    }
+}
+
 /// A slice of all channels to facilitate logic that iterates over the list of
 /// available channels.
 pub const ALL_CHANNELS: &[Channel] = &[
@@ -127,6 +494,9 @@ pub const ALL_CHANNELS: &[Channel] = &[
     C22, C23, C24,
 ];
 
+/// How long `self_test` holds each step of its pattern, in milliseconds.
+pub const SELF_TEST_STEP_MS: u32 = 250;
+
 /// This represents an individual device.  It has four pins that are used, the
 /// L or Latch pin, the D or Data pin, the O or OE pin, and the C or Clock pin.
 /// The reason these are generic parameters is that each pin is it's own data
@@ -137,19 +507,58 @@ pub const ALL_CHANNELS: &[Channel] = &[
 /// It then has members for the four pins.  We need to expor the struct, but not the
 /// individual members.  We don't want someone reaching in and interfering with the
 /// protocol.
-pub struct PWM5947<L, D, O, C>
+///
+/// The T parameter is the delay provider used to meet the board's minimum pulse
+/// widths.  It defaults to `NoDelay`, which toggles the pins as fast as the GPIO
+/// allows.
+#[derive(Debug)]
+pub struct PWM5947<L, D, O, C, T = NoDelay>
 where
     L: OutputPin,
     D: OutputPin,
     O: OutputPin,
     C: OutputPin,
+    T: Delay,
 {
     buffer: [pwm::PWMValue; 24],
 
-    latch: PWMPin<L>,
-    data: PWMPin<D>,
-    oe: PWMPin<O>,
-    clock: PWMPin<C>,
+    latch: PWMPin<L, DeviceSource<L, D, O, C>>,
+    data: PWMPin<D, DeviceSource<L, D, O, C>>,
+    oe: PWMPin<O, DeviceSource<L, D, O, C>>,
+    clock: PWMPin<C, DeviceSource<L, D, O, C>>,
+
+    delay: T,
+    timing: Timing,
+
+    output_enabled: bool,
+    brightness: pwm::PWMValue,
+    brightness_error: u16,
+
+    faders: [Option<Fader>; 24],
+    time_base: TimeBase,
+    elapsed: clock::Elapsed,
+
+    dirty: bool,
+    limits: [pwm::PWMValue; 24],
+    power: Option<power::PowerBudget>,
+    derating: Option<derating::Derating>,
+    groups: [u8; 24],
+    group_brightness: [u8; group::BRIGHTNESS_GROUPS],
+    back: [pwm::PWMValue; 24],
+    inverted: u32,
+    needs_resync: bool,
+    partial: Option<partial::PartialFlush<1>>,
+    fine: [u16; 24],
+    dither_error: [u8; 24],
+    dithering: bool,
+    labels: [Option<&'static str>; 24],
+    blank_during_latch: bool,
+    finished: u32,
+    interlock: Option<interlock::Interlock>,
+    protocol: protocol::ProtocolConfig,
+    packed: Option<frame::PackedFrame>,
+    #[cfg(feature = "stats")]
+    stats: stats::Stats,
 }
 
 impl<L, D, O, C> PWM5947<L, D, O, C>
@@ -164,200 +573,1874 @@ where
     pub fn new(latch: L, data: D, oe: O, clock: C) -> Self {
         PWM5947 {
             buffer: [pwm::PWMValue::min(); 24],
-            latch: PWMPin::new(latch, PinRole::Latch),
-            data: PWMPin::new(data, PinRole::Data),
-            oe: PWMPin::new(oe, PinRole::OE),
-            clock: PWMPin::new(clock, PinRole::Clock),
+            latch: PWMPin::new(latch, PinRole::Latch, PinSource::Latch),
+            data: PWMPin::new(data, PinRole::Data, PinSource::Data),
+            oe: PWMPin::new(oe, PinRole::OE, PinSource::OE),
+            clock: PWMPin::new(clock, PinRole::Clock, PinSource::Clock),
+            delay: NoDelay,
+            timing: Timing::default(),
+            output_enabled: true,
+            brightness: pwm::PWMValue::max(),
+            brightness_error: 0,
+            faders: [None; 24],
+            time_base: TimeBase::default(),
+            elapsed: clock::Elapsed::default(),
+            dirty: false,
+            limits: [pwm::PWMValue::max(); 24],
+            power: None,
+            derating: None,
+            groups: [0; 24],
+            group_brightness: [u8::MAX; group::BRIGHTNESS_GROUPS],
+            back: [pwm::PWMValue::min(); 24],
+            inverted: 0,
+            needs_resync: false,
+            partial: None,
+            fine: [0; 24],
+            dither_error: [0; 24],
+            dithering: false,
+            labels: [None; 24],
+            blank_during_latch: true,
+            finished: 0,
+            interlock: None,
+            protocol: protocol::ProtocolConfig::TLC5947,
+            packed: None,
+            #[cfg(feature = "stats")]
+            stats: stats::Stats::default(),
         }
     }
+}
 
-    /// During debugging I wanted some way to make sure the device was initialized
-    /// to known, good values.  It clears the data in the buffer and sets it to the
-    /// PWM's `min` value.
-    pub fn begin(&mut self) -> Result<(), PinError> {
-        self.oe.set_low()?;
-        self.latch.set_low()?;
-        self.data.set_low()?;
-        self.clock.set_low()?;
-
-        for i in 0..24 {
-            self.buffer[i] = pwm::PWMValue::min();
+impl<L, D, O, C, T> PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Slows the protocol down to meet the board's minimum pulse widths.  The
+    /// clock is held at each level for at least `clock_ns` nanoseconds, and
+    /// the latch is held high for at least `latch_ns` nanoseconds.  The
+    /// datasheet asks for about 20ns, but long wires or level shifters may
+    /// need more.
+    pub fn with_timing<U>(self, delay: U, clock_ns: u32, latch_ns: u32) -> PWM5947<L, D, O, C, U>
+    where
+        U: Delay,
+    {
+        PWM5947 {
+            buffer: self.buffer,
+            latch: self.latch,
+            data: self.data,
+            oe: self.oe,
+            clock: self.clock,
+            delay,
+            timing: Timing { clock_ns, latch_ns },
+            output_enabled: self.output_enabled,
+            brightness: self.brightness,
+            brightness_error: self.brightness_error,
+            faders: self.faders,
+            time_base: self.time_base,
+            elapsed: self.elapsed,
+            dirty: self.dirty,
+            limits: self.limits,
+            power: self.power,
+            derating: self.derating,
+            groups: self.groups,
+            group_brightness: self.group_brightness,
+            back: self.back,
+            inverted: self.inverted,
+            needs_resync: self.needs_resync,
+            partial: self.partial,
+            fine: self.fine,
+            dither_error: self.dither_error,
+            dithering: self.dithering,
+            labels: self.labels,
+            blank_during_latch: self.blank_during_latch,
+            finished: self.finished,
+            interlock: self.interlock,
+            protocol: self.protocol,
+            packed: self.packed,
+            #[cfg(feature = "stats")]
+            stats: self.stats,
         }
+    }
 
-        Ok(())
+    /// Sets the brightness of the whole board, separately from the channel
+    /// values.  It works by switching the OE pin on and off in software, so
+    /// `service_output` has to be called regularly, ideally from a timer
+    /// interrupt.  The faster it's called, the less the LEDs flicker.  At the
+    /// maximum brightness the outputs are simply left on.
+    pub fn set_global_brightness(&mut self, brightness: pwm::PWMValue) {
+        self.brightness = brightness;
     }
 
-    /// Writes a value into the given channel.  It saves the PWM value into the 
+    /// Writes a value into the given channel.  It saves the PWM value into the
     /// buffer for the given channel.
     pub fn write_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
         self.buffer[channel.0] = *pwm_value;
+        self.fine[channel.0] = (pwm_value.value() as u16) << 4;
+        self.dirty = true;
     }
 
-    /// This sets the buffer back to all zeros and then flushes to turn off all the
-    /// LEDs.
-    pub fn all_black(&mut self) -> Result<(), PinError> {
-        for channel in ALL_CHANNELS {
-            self.buffer[channel.0] = pwm::PWMValue::min();
+    /// Writes a batch of values, each into its own channel, in order.  It's
+    /// the same as calling `write_pwm` for each, so a channel that comes up
+    /// twice keeps the last value, but the buffer is only marked dirty once,
+    /// and not at all if the batch is empty.  The updates can come from
+    /// anywhere that yields pairs, like a parser or an effect.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C2, C7, ALL_CHANNELS};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.write_many([(C2, PWMValue::max()), (C7, PWMValue::new(100))]);
+    /// assert_eq!(PWMValue::new(100), device.read_pwm(&C7));
+    ///
+    /// let ramp = ALL_CHANNELS
+    ///     .iter()
+    ///     .map(|channel| (*channel, PWMValue::new(channel.index() as i32 * 100)));
+    /// device.write_many(ramp);
+    /// assert_eq!(PWMValue::new(600), device.read_pwm(&C7));
+    /// ```
+    pub fn write_many<I>(&mut self, updates: I)
+    where
+        I: IntoIterator<Item = (Channel, pwm::PWMValue)>,
+    {
+        let mut written = false;
+        for (channel, value) in updates {
+            self.buffer[channel.0] = value;
+            self.fine[channel.0] = (value.value() as u16) << 4;
+            written = true;
+        }
+        if written {
+            self.dirty = true;
         }
-        self.flush()
     }
 
-    /// Flushes the values from the buffer to the device.  It starts by making
-    /// sure the latch is set to low.  Then, for each channel, it cycles through
-    /// the 12 bits in the PWM value.  It toggles the bit by setting the clock low,
-    /// the data line high or low, and the sets the clock high.  When it's
-    /// finished all 24 channels, it sets the clock log and toggles the latch.
-    pub fn flush(&mut self) -> Result<(), PinError> {
-        self.latch.set_low()?;
+    /// Whether the buffer has changed since the last flush.  Writes through
+    /// any of the methods, or through indexing, mark the buffer dirty, even if
+    /// the value is the same.  A successful flush marks it clean.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Reads back the value in the buffer for the given channel.  This is the
+    /// value that will be sent on the next flush, which may not be showing yet.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    /// use ledpwm5947::{PWM5947, C3};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.write_pwm(&C3, &PWMValue::new(1000));
+    ///
+    /// let step = Step::from_fraction_of_range(1, 10);
+    /// let brighter = (device.read_pwm(&C3) + step).unwrap_or(PWMValue::max());
+    /// device.write_pwm(&C3, &brighter);
+    /// assert_eq!(PWMValue::new(1410), device.read_pwm(&C3));
+    /// ```
+    pub fn read_pwm(&self, channel: &Channel) -> pwm::PWMValue {
+        self.buffer[channel.0]
+    }
 
-        for channel in ALL_CHANNELS.iter().rev() {
-            let channel_value = self.buffer[channel.0];
+    /// The whole buffer, in channel order.
+    pub fn buffer(&self) -> &[pwm::PWMValue; 24] {
+        &self.buffer
+    }
 
-            let bit_values = channel_value.bits();
+    /// A copy of the buffer, to keep as a preset.
+    pub fn snapshot(&self) -> frame::Frame {
+        frame::Frame::new(self.buffer)
+    }
 
-            for i in 0..bit_values.len() {
-                self.clock.set_low()?;
+    /// Writes a whole frame into the buffer, such as a preset taken with
+    /// `snapshot` or a step of a crossfade.  It shows on the next flush.
+    pub fn load_frame(&mut self, frame: &frame::Frame) {
+        self.buffer = frame.0;
+        self.fine = [0; 24];
+        self.dirty = true;
+    }
 
-                if bit_values[i] {
-                    self.data.set_high()?;
-                } else {
-                    self.data.set_low()?;
-                }
+    /// Writes a value into the back buffer instead of the buffer that's shown.
+    /// Staged values don't show until `swap` promotes them, so an animation can
+    /// build a frame over several calls while another task keeps flushing the
+    /// last complete one.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C1, C2};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.stage_pwm(&C1, &PWMValue::max());
+    /// device.stage_pwm(&C2, &PWMValue::max());
+    /// assert_eq!(PWMValue::min(), device.read_pwm(&C1));
+    ///
+    /// device.swap();
+    /// assert_eq!(PWMValue::max(), device.read_pwm(&C1));
+    /// assert_eq!(PWMValue::max(), device.read_pwm(&C2));
+    /// ```
+    pub fn stage_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.back[channel.0] = *pwm_value;
+    }
 
-                self.clock.set_high()?;
-            }
-        }
+    /// The value staged for the channel in the back buffer.
+    pub fn read_staged(&self, channel: &Channel) -> pwm::PWMValue {
+        self.back[channel.0]
+    }
 
-        self.clock.set_low()?;
-        self.latch.set_high()?;
-        self.latch.set_low()
+    /// Promotes the staged frame to the buffer that's shown, all at once.  The
+    /// back buffer keeps the frame, so the next one can be staged as changes to
+    /// it.  Like `write_pwm`, the new frame shows on the next flush.
+    pub fn swap(&mut self) {
+        self.buffer = self.back;
+        self.fine = [0; 24];
+        self.dirty = true;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use core::convert::Infallible;
-    use embedded_hal::digital::v2::OutputPin;
+    /// Packs the buffer into the 36 bytes the board expects on the wire, the
+    /// same frame the SPI backend sends.  This lets the frame go out through
+    /// any transport, like a DMA-driven SPI peripheral.  Shift the bytes out
+    /// most significant bit first on the data and clock lines, then pulse the
+    /// latch.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C1, C24};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.write_pwm(&C24, &PWMValue::new(0xabc));
+    /// device.write_pwm(&C1, &PWMValue::new(0x123));
+    ///
+    /// let mut frame = [0_u8; 36];
+    /// device.serialize_frame(&mut frame);
+    /// assert_eq!([0xab, 0xc0, 0x00], frame[0..3]);
+    /// assert_eq!([0x00, 0x01, 0x23], frame[33..36]);
+    /// ```
+    pub fn serialize_frame(&self, out: &mut [u8; 36]) {
+        *out = self.packed_frame().0;
+    }
 
-    // Fake pin for testing purposes.
-    struct FakePin {
-        value: bool,
+    /// The frame `flush` would send, packed.  This is `serialize_frame` as a
+    /// `PackedFrame`.
+    pub fn packed_frame(&self) -> frame::PackedFrame {
+        frame::PackedFrame::pack(&self.protocol.arrange(&self.output_frame()))
     }
 
-    impl OutputPin for FakePin {
-        type Error = Infallible;
+    /// Shifts and latches the frames the protocol's way, for clone boards.
+    /// The latch polarity sets whether the latch pin is driven active low.
+    /// See the `protocol` module.
+    pub fn set_protocol(&mut self, protocol: protocol::ProtocolConfig) {
+        self.latch.active_low = protocol.latch_active_low();
+        self.protocol = protocol;
+        self.dirty = true;
+    }
 
-        fn set_low(&mut self) -> Result<(), Self::Error> {
-            self.value = false;
-            Ok(())
-        }
+    /// The protocol the frames are shifted and latched in.
+    pub fn protocol(&self) -> &protocol::ProtocolConfig {
+        &self.protocol
+    }
 
-        fn set_high(&mut self) -> Result<(), Self::Error> {
-            self.value = true;
-            Ok(())
-        }
+    /// The flush counters.  See the `stats` module.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> &stats::Stats {
+        &self.stats
     }
 
-    use crate::pwm::PWMValue;
+    /// Sets every flush counter back to zero.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) {
+        self.stats = stats::Stats::default();
+    }
 
-    #[test]
-    fn test_toggle() {
-        let latch = FakePin { value: false };
-        let oe = FakePin { value: false };
-        let data = FakePin { value: false };
-        let clock = FakePin { value: false };
+    /// Caps the channel at the given value, to protect LEDs that can't take
+    /// the full current.  The buffer keeps whatever is written, so `read_pwm`
+    /// still returns it, but the board is never sent more than the cap.
+    /// Setting the cap back to max removes it.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C1};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.set_channel_limit(&C1, PWMValue::new(2000));
+    /// device.write_pwm(&C1, &PWMValue::max());
+    ///
+    /// let mut frame = [0_u8; 36];
+    /// device.serialize_frame(&mut frame);
+    /// assert_eq!([0x07, 0xd0], frame[34..36]);
+    /// assert_eq!(PWMValue::max(), device.read_pwm(&C1));
+    /// ```
+    pub fn set_channel_limit(&mut self, channel: &Channel, limit: pwm::PWMValue) {
+        self.limits[channel.0] = limit;
+        self.dirty = true;
+    }
 
-        let mut device = crate::PWM5947::new(latch, data, oe, clock);
-        let res = device.begin();
-        assert!(res.is_ok());
+    /// The cap on the channel, which is max when there isn't one.
+    pub fn channel_limit(&self, channel: &Channel) -> pwm::PWMValue {
+        self.limits[channel.0]
+    }
 
-        for channel in crate::ALL_CHANNELS {
-            let val = PWMValue::new(channel.0 as i32);
-            device.write_pwm(channel, &val);
-        }
+    /// Puts the channel in one of the brightness groups, taking it out of the
+    /// one it was in.  Every channel starts in group 0.  See
+    /// `set_group_brightness`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the group is not less than `group::BRIGHTNESS_GROUPS`.
+    pub fn set_channel_group(&mut self, channel: &Channel, group: usize) {
+        assert!(group < group::BRIGHTNESS_GROUPS);
+        self.groups[channel.0] = group as u8;
+        self.dirty = true;
+    }
 
-        for i in 0..24 {
-            assert_eq!(device.buffer[i], PWMValue::new(i as i32));
-        }
+    /// The brightness group the channel is in.
+    pub fn channel_group(&self, channel: &Channel) -> usize {
+        self.groups[channel.0] as usize
     }
 
-    #[test]
-    fn test_begin() {
-        let latch = FakePin { value: true };
-        let oe = FakePin { value: true };
-        let data = FakePin { value: true };
-        let clock = FakePin { value: true };
+    /// Dims every channel in the brightness group, from 0 for off to 255 for
+    /// full brightness, which is where every group starts.  Like the channel
+    /// caps, it's applied on the way out, so the buffer and any fades keep
+    /// their values and turning the group back up restores them.  That makes
+    /// it a master dimmer for a zone, like the cabinets in a kitchen.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C1, C2};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.write_pwm(&C1, &PWMValue::max());
+    /// device.write_pwm(&C2, &PWMValue::max());
+    ///
+    /// // C2 is under the cabinets, and C1 stays in the room's group 0.
+    /// device.set_channel_group(&C2, 1);
+    /// device.set_group_brightness(1, 0);
+    /// assert_eq!(PWMValue::max(), device.output_frame()[0]);
+    /// assert_eq!(PWMValue::min(), device.output_frame()[1]);
+    ///
+    /// device.set_group_brightness(1, 255);
+    /// assert_eq!(PWMValue::max(), device.output_frame()[1]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the group is not less than `group::BRIGHTNESS_GROUPS`.
+    pub fn set_group_brightness(&mut self, group: usize, brightness: u8) {
+        self.group_brightness[group] = brightness;
+        self.dirty = true;
+    }
 
-        let mut device = crate::PWM5947::new(latch, data, oe, clock);
-        for i in 0..24 {
-            device.buffer[i] = PWMValue::new(0x10);
-        }
+    /// The brightness of the group, from 0 to 255.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the group is not less than `group::BRIGHTNESS_GROUPS`.
+    pub fn group_brightness(&self, group: usize) -> u8 {
+        self.group_brightness[group]
+    }
 
-        let res = device.begin();
-        assert!(res.is_ok());
+    /// Sets the power budget applied on every flush, or removes it with `None`.
+    /// See the `power` module.
+    pub fn set_power_budget(&mut self, budget: Option<power::PowerBudget>) {
+        self.power = budget;
+        self.dirty = true;
+    }
 
-        for i in 0..24 {
-            assert_eq!(device.buffer[i], PWMValue::min());
-        }
+    /// The power budget applied on every flush, if there is one.
+    pub fn power_budget(&self) -> Option<&power::PowerBudget> {
+        self.power.as_ref()
+    }
 
-        assert!(!device.latch.raw_pin.value);
-        assert!(!device.clock.raw_pin.value);
-        assert!(!device.oe.raw_pin.value);
-        assert!(!device.data.raw_pin.value);
+    /// Sets the thermal derating applied on every flush, or removes it with
+    /// `None`.  See the `derating` module.
+    pub fn set_derating(&mut self, derating: Option<derating::Derating>) {
+        self.derating = derating;
+        self.dirty = true;
     }
 
-    struct FailingPin {
-        will_fail: bool,
-        value: bool,
+    /// The thermal derating applied on every flush, if there is one.
+    pub fn derating(&self) -> Option<&derating::Derating> {
+        self.derating.as_ref()
     }
 
-    impl FailingPin {
-        fn new(will_fail: &bool, value: &bool) -> Self {
-            FailingPin {
-                will_fail: *will_fail,
-                value: *value,
+    /// Passes a temperature reading to the thermal derating.  The buffer is
+    /// marked dirty if it changes how much the output is scaled, so the next
+    /// flush shows it.  Without derating the reading is ignored.
+    pub fn update_temp_millic(&mut self, temp_millic: i32) {
+        if let Some(derating) = &mut self.derating {
+            let before = derating.factor();
+            derating.update_temp_millic(temp_millic);
+            if derating.factor() != before {
+                self.dirty = true;
             }
         }
     }
 
-    // This impl allows me to simulate pin failures.  This allows me to unit
-    // test the error handling without triggering some kind of failure on
-    // physical hardware.
-    impl OutputPin for FailingPin {
-        type Error = &'static str;
+    /// Sets the safety interlock that limits how long channels stay on, or
+    /// removes it with `None`.  See the `interlock` module.
+    pub fn set_interlock(&mut self, interlock: Option<interlock::Interlock>) {
+        self.interlock = interlock;
+        self.dirty = true;
+    }
 
-        fn set_low(&mut self) -> Result<(), Self::Error> {
-            if self.will_fail {
-                Err("Failed")
-            } else {
-                self.value = false;
-                Ok(())
-            }
+    /// The safety interlock, if there is one.
+    pub fn interlock(&self) -> Option<&interlock::Interlock> {
+        self.interlock.as_ref()
+    }
+
+    /// The channels the interlock has tripped and is holding at zero, one bit
+    /// per channel with `C1` in bit 0.
+    pub fn tripped_channels(&self) -> u32 {
+        self.interlock.map_or(0, |interlock| interlock.tripped())
+    }
+
+    /// Clears the interlock's trips, so the channels show their buffer values
+    /// again on the next flush.
+    pub fn clear_interlock(&mut self) {
+        if let Some(interlock) = &mut self.interlock {
+            interlock.clear();
+            self.dirty = true;
         }
+    }
 
-        fn set_high(&mut self) -> Result<(), Self::Error> {
-            if self.will_fail {
-                Err("Failed")
-            } else {
-                self.value = true;
-                Ok(())
+    /// Inverts the channel's output, so the board is sent `max - value`.  This
+    /// is for LEDs wired through a transistor that turns them on when the
+    /// output is off, so the buffer can still hold how bright they are.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C2};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.set_inverted(&C2, true);
+    /// device.write_pwm(&C2, &PWMValue::new(1000));
+    ///
+    /// assert_eq!(PWMValue::new(3095), device.output_frame()[1]);
+    /// # device.set_inverted(&C2, false);
+    /// # assert_eq!(PWMValue::new(1000), device.output_frame()[1]);
+    /// ```
+    pub fn set_inverted(&mut self, channel: &Channel, inverted: bool) {
+        if inverted {
+            self.inverted |= 1 << channel.0;
+        } else {
+            self.inverted &= !(1 << channel.0);
+        }
+        self.dirty = true;
+    }
+
+    /// Whether the channel's output is inverted.
+    pub fn is_inverted(&self, channel: &Channel) -> bool {
+        self.inverted & (1 << channel.0) != 0
+    }
+
+    /// Turns OE blanking during the latch on or off.  It's on to start with,
+    /// so the LEDs don't ghost while new values latch, at the cost of two more
+    /// pin writes a frame.  Turn it off when OE is tied to ground, since the
+    /// pin writes do nothing then.
+    pub fn set_blank_during_latch(&mut self, blank: bool) {
+        self.blank_during_latch = blank;
+    }
+
+    /// Whether OE is pulled high while the latch is toggled.
+    pub fn is_blank_during_latch(&self) -> bool {
+        self.blank_during_latch
+    }
+
+    /// Gives the channel a label, naming the LED it drives.  The label shows
+    /// in the device's `Debug` output and in `dump_state`.  See the `report`
+    /// module.
+    pub fn label_channel(&mut self, channel: &Channel, label: &'static str) {
+        self.labels[channel.0] = Some(label);
+    }
+
+    /// Removes the channel's label.
+    pub fn clear_label(&mut self, channel: &Channel) {
+        self.labels[channel.0] = None;
+    }
+
+    /// The channel's label, if it has one.
+    pub fn channel_label(&self, channel: &Channel) -> Option<&'static str> {
+        self.labels[channel.0]
+    }
+
+    /// A report of every channel's value and label, and whether the buffer
+    /// has changed since the last flush, for printing while debugging.
+    pub fn dump_state(&self) -> report::StateReport {
+        let mut fading = 0;
+        for (index, fader) in self.faders.iter().enumerate() {
+            if fader.is_some() {
+                fading |= 1 << index;
             }
         }
+        report::StateReport::new(
+            self.buffer,
+            self.labels,
+            fading,
+            self.output_enabled,
+            self.dirty,
+        )
     }
 
-    #[test]
-    fn test_failing_pin() {
-        let latch = FakePin { value: true };
-        let oe = FailingPin::new(&true, &true);
-        let data = FakePin { value: true };
-        let clock = FakePin { value: true };
+    /// Writes a 16-bit value into the channel.  The top 12 bits go into the
+    /// buffer like `write_pwm`.  With dithering on, the low 4 bits are kept
+    /// and spread over the following flushes, so the channel shows the value
+    /// in between on average.  Without it they're dropped.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C1};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.set_dithering(true);
+    ///
+    /// // A very slow fade up from off, 1/16 of a step at a time.
+    /// for fine in 0..=64_u16 {
+    ///     device.write_pwm16(&C1, fine);
+    ///     device.flush().ok();
+    /// }
+    /// assert_eq!(PWMValue::new(4), device.read_pwm(&C1));
+    /// assert_eq!(64, device.read_pwm16(&C1));
+    /// ```
+    pub fn write_pwm16(&mut self, channel: &Channel, value: u16) {
+        self.buffer[channel.0] = pwm::PWMValue::new((value >> 4) as i32);
+        self.fine[channel.0] = value;
+        self.dirty = true;
+    }
 
-        let mut device = crate::PWM5947::new(latch, data, oe, clock);
-        let res = device.begin();
-        if let Err(e) = res {
-            assert_eq!(e.which, crate::PinRole::OE);
+    /// Reads the channel as a 16-bit value.  If the channel was last written
+    /// with `write_pwm16` this is the value written, and otherwise it's the
+    /// 12-bit value with four zero bits below it.
+    pub fn read_pwm16(&self, channel: &Channel) -> u16 {
+        let value = self.buffer[channel.0].value() as u16;
+        if self.fine[channel.0] >> 4 == value {
+            self.fine[channel.0]
+        } else {
+            value << 4
+        }
+    }
+
+    /// Turns dithering of the 16-bit values on or off.  Dithering takes one
+    /// extra step up on some flushes, as many out of 16 as the value's low 4
+    /// bits, so the eye sees the value in between the two steps.  It needs
+    /// flushing at a steady rate of a few hundred frames a second or more,
+    /// even when nothing was written, or the extra steps show as flicker.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+        self.dither_error = [0; 24];
+    }
+
+    /// Whether dithering is on.
+    pub fn is_dithering(&self) -> bool {
+        self.dithering
+    }
+
+    /// The buffer for this flush, with the dithering applied.  Every write
+    /// other than `write_pwm16` clears the channel's fraction, and a channel a
+    /// fade has moved no longer matches its 16-bit value, so neither has a
+    /// fraction to spread.
+    fn dithered_buffer(&mut self) -> [pwm::PWMValue; 24] {
+        let mut frame = self.buffer;
+        if !self.dithering {
+            return frame;
+        }
+
+        let channels = frame
+            .iter_mut()
+            .zip(self.fine.iter())
+            .zip(self.dither_error.iter_mut());
+        for ((value, fine), error) in channels {
+            if (fine >> 4) as i32 != value.value() {
+                *error = 0;
+                continue;
+            }
+
+            *error += (fine & 0xf) as u8;
+            if *error >= 16 {
+                *error -= 16;
+                *value = value.saturating_add(pwm::Step::new(1));
+            }
+        }
+        frame
+    }
+
+    /// The values that are sent to the board on a flush.  This is the buffer
+    /// with any channels the interlock tripped at zero and the channel caps
+    /// applied, then scaled by the group brightness, the power budget and the
+    /// thermal derating, and finally with the inverted channels flipped.  Dithering
+    /// isn't included, since it changes from one flush to the next.
+    pub fn output_frame(&self) -> [pwm::PWMValue; 24] {
+        self.shape_frame(self.buffer)
+    }
+
+    /// Applies the interlock, channel caps, group brightness, power budget,
+    /// derating and inversion to a frame.
+    fn shape_frame(&self, mut frame: [pwm::PWMValue; 24]) -> [pwm::PWMValue; 24] {
+        if let Some(interlock) = &self.interlock {
+            interlock.apply(&mut frame);
+        }
+        for (value, limit) in frame.iter_mut().zip(self.limits.iter()) {
+            if *value > *limit {
+                *value = *limit;
+            }
+        }
+        for (value, group) in frame.iter_mut().zip(self.groups.iter()) {
+            let brightness = self.group_brightness[*group as usize];
+            if brightness != u8::MAX {
+                *value = value.scale(brightness);
+            }
+        }
+        if let Some(budget) = &self.power {
+            budget.apply(&mut frame);
+        }
+        if let Some(derating) = &self.derating {
+            derating.apply(&mut frame);
+        }
+        for (i, value) in frame.iter_mut().enumerate() {
+            if self.inverted & (1 << i) != 0 {
+                *value = pwm::PWMValue::new(pwm::PWM_MASK as i32 - value.value());
+            }
+        }
+        frame
+    }
+
+    /// Writes the values into the channels of the group, in the group's order.
+    /// The number of values has to match the size of the group, or it returns
+    /// a `Length` error and nothing is written.
+    pub fn write_group(
+        &mut self,
+        group: &group::ChannelGroup,
+        values: &[pwm::PWMValue],
+    ) -> Result<(), pwm::RangeError> {
+        check_len(values.len(), group.len())?;
+
+        for (i, value) in group.indices().zip(values) {
+            self.buffer[i] = *value;
+            self.fine[i] = 0;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Writes a whole frame into the buffer in one go, starting at channel 1.
+    /// Like `write_group`, the slice has to have exactly 24 values, and if it
+    /// doesn't nothing is written.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::pwm::{PWMValue, RangeError};
+    /// use ledpwm5947::{PWM5947, C24};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// let mut frame = [PWMValue::min(); 24];
+    /// frame[23] = PWMValue::max();
+    ///
+    /// assert_eq!(Ok(()), device.write_frame(&frame));
+    /// assert_eq!(PWMValue::max(), device.read_pwm(&C24));
+    /// assert_eq!(
+    ///     Err(RangeError::Length { len: 23, expected: 24 }),
+    ///     device.write_frame(&frame[1..])
+    /// );
+    /// ```
+    pub fn write_frame(&mut self, values: &[pwm::PWMValue]) -> Result<(), pwm::RangeError> {
+        check_len(values.len(), 24)?;
+
+        self.buffer.copy_from_slice(values);
+        self.fine = [0; 24];
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Writes a whole frame of 8-bit brightnesses, gamma corrected with
+    /// `GAMMA_2_2`.  This suits frames coming from image data or a network,
+    /// which are usually 8-bit.  The slice has to have exactly 24 values.
+    pub fn write_frame_u8(&mut self, values: &[u8]) -> Result<(), pwm::RangeError> {
+        check_len(values.len(), 24)?;
+
+        for (slot, value) in self.buffer.iter_mut().zip(values) {
+            *slot = gamma::GAMMA_2_2.correct(*value);
+        }
+        self.fine = [0; 24];
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Starts a fade on the channel, replacing any fade already running on it.
+    /// The fade moves on each time `tick` is called.
+    pub fn start_fade(&mut self, channel: &Channel, fader: Fader) {
+        self.faders[channel.0] = Some(fader);
+        self.fine[channel.0] = 0;
+        self.finished &= !(1 << channel.0);
+    }
+
+    /// Starts a fade from the channel's current value to the target, lasting
+    /// `duration` ticks.
+    pub fn fade_to(
+        &mut self,
+        channel: &Channel,
+        target: pwm::PWMValue,
+        duration: u32,
+        easing: Easing,
+    ) {
+        let fader = Fader::new(self.buffer[channel.0], target, duration, easing);
+        self.start_fade(channel, fader);
+    }
+
+    /// Starts a fade on every channel to the same target, lasting `duration`
+    /// ticks.  Each channel fades from its own current value, so they all
+    /// arrive together.  Fading to `PWMValue::min()` is the gentle version of
+    /// `all_black`.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::animation::Easing;
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C1, C2};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.write_pwm(&C1, &PWMValue::new(4000));
+    /// device.write_pwm(&C2, &PWMValue::new(400));
+    /// device.fade_all_to(PWMValue::min(), 2, Easing::Linear);
+    ///
+    /// device.tick();
+    /// assert_eq!(PWMValue::new(2000), device.read_pwm(&C1));
+    /// assert_eq!(PWMValue::new(200), device.read_pwm(&C2));
+    /// device.tick();
+    /// assert_eq!(PWMValue::min(), device.read_pwm(&C1));
+    /// ```
+    pub fn fade_all_to(&mut self, target: pwm::PWMValue, duration: u32, easing: Easing) {
+        self.fade_to_frame(&frame::Frame::new([target; 24]), duration, easing);
+    }
+
+    /// Starts a fade on every channel to its value in the frame, lasting
+    /// `duration` ticks, replacing any fades already running.
+    pub fn fade_to_frame(&mut self, frame: &frame::Frame, duration: u32, easing: Easing) {
+        for (channel, target) in ALL_CHANNELS.iter().zip(frame.values().iter()) {
+            self.fade_to(channel, *target, duration, easing);
+        }
+    }
+
+    /// Stops the fade on the channel, leaving it at its current value.
+    pub fn cancel_fade(&mut self, channel: &Channel) {
+        self.faders[channel.0] = None;
+    }
+
+    /// Whether a fade is running on the channel.
+    pub fn is_fading(&self, channel: &Channel) -> bool {
+        self.faders[channel.0].is_some()
+    }
+
+    /// The channels whose fades have reached their targets, one bit per
+    /// channel with `C1` in bit 0.  A channel's bit is set on the tick its fade
+    /// finishes, and stays set until it's taken with `take_finished_fades` or
+    /// a new fade starts on the channel.  Cancelled fades don't count as
+    /// finished.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// use ledpwm5947::animation::Easing;
+    /// use ledpwm5947::pwm::PWMValue;
+    /// use ledpwm5947::{PWM5947, C4};
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.write_pwm(&C4, &PWMValue::max());
+    /// device.fade_to(&C4, PWMValue::min(), 2, Easing::Linear);
+    ///
+    /// device.tick();
+    /// assert_eq!(0, device.finished_fades());
+    /// device.tick();
+    /// if device.take_finished_fades() & (1 << 3) != 0 {
+    ///     // Faded out, so the relay can be switched off.
+    /// }
+    /// assert_eq!(0, device.finished_fades());
+    /// ```
+    pub fn finished_fades(&self) -> u32 {
+        self.finished
+    }
+
+    /// Returns the channels whose fades have finished, the same as
+    /// `finished_fades`, and clears them.
+    pub fn take_finished_fades(&mut self) -> u32 {
+        core::mem::take(&mut self.finished)
+    }
+
+    /// The speed the fades run at.
+    pub fn animation_speed(&self) -> Speed {
+        self.time_base.speed()
+    }
+
+    /// Slows down or speeds up every fade at once, starting with the next tick.
+    pub fn set_animation_speed(&mut self, speed: Speed) {
+        self.time_base.set_speed(speed);
+    }
+
+    /// Advances every running fade by one tick and writes the new values into
+    /// the buffer.  Fades that reach their target are removed.  Like
+    /// `write_pwm`, the new values show on the next flush.
+    pub fn tick(&mut self) {
+        self.advance_fades(1);
+    }
+
+    /// Advances every running fade by the milliseconds since the last call,
+    /// so the fade durations are in milliseconds.  The first call starts the
+    /// clock and doesn't move the fades.  See the `clock` module.
+    pub fn tick_at(&mut self, now: clock::Instant) {
+        let elapsed = self.elapsed.since_last(now);
+        self.advance_fades(elapsed);
+    }
+
+    fn advance_fades(&mut self, ticks: u32) {
+        let elapsed = self.time_base.advance(ticks);
+        let slots = self.buffer.iter_mut().zip(self.faders.iter_mut());
+        for (index, (value, slot)) in slots.enumerate() {
+            if let Some(fader) = slot {
+                *value = fader.advance(elapsed);
+                self.dirty = true;
+                if fader.is_finished() {
+                    *slot = None;
+                    self.finished |= 1 << index;
+                }
+            }
+        }
+
+        if let Some(interlock) = &mut self.interlock {
+            let tripped = interlock.update(&self.buffer, ticks);
+            let slots = self.buffer.iter_mut().zip(self.faders.iter_mut());
+            for (index, (value, slot)) in slots.enumerate() {
+                if tripped & (1 << index) != 0 {
+                    *value = pwm::PWMValue::min();
+                    *slot = None;
+                    self.fine[index] = 0;
+                    self.dirty = true;
+                }
+            }
+        }
+    }
+}
+
+/// The methods that drive the pins.  They return a `DeviceError`, which
+/// carries the HAL error of whichever pin failed.
+impl<L, D, O, C, T> PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// During debugging I wanted some way to make sure the device was initialized
+    /// to known, good values.  It clears the data in the buffer and sets it to the
+    /// PWM's `min` value.
+    pub fn begin(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.reset_pins()?;
+
+        for i in 0..24 {
+            self.buffer[i] = pwm::PWMValue::min();
+        }
+        self.fine = [0; 24];
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    /// Sets the pins to known, good values without touching the buffer.  The
+    /// outputs are enabled and the latch, data and clock lines are low.  This
+    /// is useful for a device built with an initial buffer, which `begin`
+    /// would clear.
+    pub fn reset_pins(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.output_enabled = true;
+        self.oe.set_low()?;
+        self.latch.set_low()?;
+        self.data.set_low()?;
+        self.clock.set_low()
+    }
+
+    /// Turns the outputs on by pulling the OE pin low.  The board shows the
+    /// values from the last flush.
+    pub fn enable_output(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.output_enabled = true;
+        self.oe.set_low()
+    }
+
+    /// Blanks the whole board by pulling the OE pin high.  The buffer and the
+    /// latched values are left alone, so `enable_output` brings the LEDs back
+    /// as they were.
+    pub fn disable_output(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.output_enabled = false;
+        self.oe.set_high()
+    }
+
+    /// Runs one step of the software PWM on the OE pin for the global
+    /// brightness.  The on steps are spread out as evenly as possible, which
+    /// keeps the flicker down at low call rates.  This does nothing to the
+    /// pin while the output is disabled.
+    pub fn service_output(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        if !self.output_enabled {
+            return Ok(());
+        }
+
+        if self.brightness == pwm::PWMValue::max() {
+            return self.oe.set_low();
+        }
+
+        self.brightness_error += self.brightness.value() as u16;
+        if self.brightness_error > pwm::PWM_MASK {
+            self.brightness_error -= pwm::PWM_MASK + 1;
+            self.oe.set_low()
+        } else {
+            self.oe.set_high()
+        }
+    }
+
+    /// This sets the buffer back to all zeros and then flushes to turn off all the
+    /// LEDs.
+    pub fn all_black(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        for channel in ALL_CHANNELS {
+            self.buffer[channel.0] = pwm::PWMValue::min();
+        }
+        self.fine = [0; 24];
+        self.dirty = true;
+        self.flush()
+    }
+
+    /// Runs a test pattern for bringing up new hardware.  Each channel in
+    /// turn, from `C1` to `C24`, goes to full brightness on its own, then
+    /// every channel goes on together, and finally the board is cleared to
+    /// black.  Each step is held for `SELF_TEST_STEP_MS`, so a channel that's
+    /// wired to the wrong LED, or not at all, stands out.  The channel caps
+    /// and the rest of the output shaping still apply, so LEDs that can't
+    /// take the full current stay protected.
+    ///
+    /// The buffer is left all zeros, and the outputs are left as they were,
+    /// so enable them first.  It blocks for about six and a half seconds.
+    ///
+    /// ```
+    /// # use core::convert::Infallible;
+    /// # use embedded_hal::digital::v2::OutputPin;
+    /// # struct Pin;
+    /// # impl OutputPin for Pin {
+    /// #     type Error = Infallible;
+    /// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+    /// # }
+    /// # struct Timer;
+    /// # impl Delay for Timer {
+    /// #     fn delay_ns(&mut self, _ns: u32) {}
+    /// # }
+    /// use ledpwm5947::timing::Delay;
+    /// use ledpwm5947::PWM5947;
+    ///
+    /// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+    /// device.begin().unwrap();
+    /// device.self_test(&mut Timer).unwrap();
+    /// ```
+    pub fn self_test(&mut self, delay: &mut impl Delay) -> Result<(), DeviceError<L, D, O, C>> {
+        for channel in ALL_CHANNELS {
+            self.load_frame(&frame::Frame::default());
+            self.write_pwm(channel, &pwm::PWMValue::max());
+            self.flush()?;
+            delay.delay_ns(SELF_TEST_STEP_MS * 1_000_000);
+        }
+
+        self.load_frame(&frame::Frame::new([pwm::PWMValue::max(); 24]));
+        self.flush()?;
+        delay.delay_ns(SELF_TEST_STEP_MS * 1_000_000);
+
+        self.load_frame(&frame::Frame::default());
+        self.flush()
+    }
+
+    /// Flushes the values from the buffer to the device.  It starts by making
+    /// sure the latch is set to low.  Then, for each channel, it cycles through
+    /// the 12 bits in the PWM value.  It toggles the bit by setting the clock low,
+    /// the data line high or low, and the sets the clock high.  When it's
+    /// finished all 24 channels, it sets the clock log and toggles the latch.
+    /// The channel caps, power budget and inversion are applied on the way
+    /// out.  OE is pulled high while the latch is toggled, unless that's been
+    /// turned off with `set_blank_during_latch`.  The packed frame is kept
+    /// between flushes, so it's only packed again once the buffer or the
+    /// shaping has changed.
+    ///
+    /// If a pin fails part way through, the next flush calls `resync` before
+    /// shifting its own frame, so a failed flush doesn't need any cleaning up.
+    pub fn flush(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.flush_frame(self.blank_during_latch)
+    }
+
+    /// Flushes the buffer like `flush`, and records how long it took from
+    /// the timestamps `now` returns before and after.  The duration is in
+    /// the units of the timestamps, which can wrap around.  See the `stats`
+    /// module.
+    #[cfg(feature = "stats")]
+    pub fn flush_timed<F>(&mut self, mut now: F) -> Result<(), DeviceError<L, D, O, C>>
+    where
+        F: FnMut() -> u32,
+    {
+        let start = now();
+        let result = self.flush();
+        self.stats.set_flush_duration(now().wrapping_sub(start));
+        result
+    }
+
+    /// The flush, optionally pulling OE high while the latch is toggled.  The
+    /// board's outputs glitch for a moment as the new values are latched, and
+    /// blanking hides that.  Outputs that are already disabled stay off.
+    pub(crate) fn flush_frame(&mut self, blank: bool) -> Result<(), DeviceError<L, D, O, C>> {
+        let packed = self.next_packed();
+        self.shift_and_latch(&packed, blank, Self::shift_own, |_| Ok(()))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Shifts a packed frame out on the device's own data and clock pins, for
+    /// `shift_and_latch`.
+    pub(crate) fn shift_own(
+        &mut self,
+        packed: &frame::PackedFrame,
+        resync: bool,
+    ) -> Result<(), DeviceError<L, D, O, C>> {
+        shift_frame(
+            &mut self.data,
+            &mut self.clock,
+            &mut self.delay,
+            self.timing.clock_ns,
+            packed,
+            resync,
+        )
+    }
+
+    /// The frame a flush sends: the buffer with the dithering, the output
+    /// shaping and the protocol's channel order applied.
+    pub(crate) fn next_frame(&mut self) -> [pwm::PWMValue; 24] {
+        let frame = self.dithered_buffer();
+        self.protocol.arrange(&self.shape_frame(frame))
+    }
+
+    /// The packed frame a flush sends.  It's kept from one flush to the next
+    /// and only repacked once something has marked the buffer dirty, or on
+    /// every flush while dithering, since then every frame differs.
+    pub(crate) fn next_packed(&mut self) -> frame::PackedFrame {
+        match self.packed {
+            Some(packed) if !self.dirty && !self.dithering => packed,
+            _ => {
+                let packed = frame::PackedFrame::pack(&self.next_frame());
+                self.packed = Some(packed);
+                packed
+            }
+        }
+    }
+
+    /// Shifts a packed frame out and latches it, the sequence every flush
+    /// shares whichever data and clock lines carry the bits.  `shift` puts the
+    /// frame on the lines with `shift_frame`, clocking zeros through
+    /// first when it's told to resync, and `before_latch` runs once the frame
+    /// is in, with OE held high if `blank` is set and the outputs are on.
+    /// The stats count the frame, or the device pin that failed.
+    pub(crate) fn shift_and_latch<S, F, E>(
+        &mut self,
+        packed: &frame::PackedFrame,
+        blank: bool,
+        shift: S,
+        before_latch: F,
+    ) -> Result<(), E>
+    where
+        S: FnOnce(&mut Self, &frame::PackedFrame, bool) -> Result<(), DeviceError<L, D, O, C>>,
+        F: FnOnce(&mut Self) -> Result<(), E>,
+        E: From<DeviceError<L, D, O, C>>,
+    {
+        self.partial = None;
+        let result = self.shift_out(packed, blank, shift);
+        #[cfg(feature = "stats")]
+        if result.is_err() {
+            self.stats.record(&result);
+        }
+        let blank = result?;
+
+        before_latch(self)?;
+        let result = self.latch_shifted(blank);
+        #[cfg(feature = "stats")]
+        self.stats.record(&result);
+        result.map_err(E::from)
+    }
+
+    fn shift_out<S>(
+        &mut self,
+        packed: &frame::PackedFrame,
+        blank: bool,
+        shift: S,
+    ) -> Result<bool, DeviceError<L, D, O, C>>
+    where
+        S: FnOnce(&mut Self, &frame::PackedFrame, bool) -> Result<(), DeviceError<L, D, O, C>>,
+    {
+        let resync = self.needs_resync;
+        self.needs_resync = true;
+        self.latch.set_low()?;
+
+        shift(self, packed, resync)?;
+        #[cfg(feature = "stats")]
+        {
+            if resync {
+                self.stats.add_frame();
+            }
+            self.stats.add_frame();
+        }
+
+        let blank = blank && self.output_enabled;
+        if blank {
+            self.oe.set_high()?;
+        }
+        Ok(blank)
+    }
+
+    fn latch_shifted(&mut self, blank: bool) -> Result<(), DeviceError<L, D, O, C>> {
+        self.latch.set_high()?;
+        self.delay.delay_ns(self.timing.latch_ns);
+        self.latch.set_low()?;
+        if blank {
+            self.oe.set_low()?;
+        }
+        self.needs_resync = false;
+        Ok(())
+    }
+
+    /// Shifts out at most `budget_bits` bits of the frame, and latches it once
+    /// all 288 bits are out.  The frame is taken from the buffer when its
+    /// first bits go, and the next call carries on with the rest.  See the
+    /// `partial` module.
+    pub fn flush_partial(
+        &mut self,
+        budget_bits: usize,
+    ) -> Result<partial::FlushProgress, DeviceError<L, D, O, C>> {
+        let result = self.shift_partial(budget_bits);
+        #[cfg(feature = "stats")]
+        if !matches!(result, Ok(partial::FlushProgress::Shifting { .. })) {
+            self.stats.record(&result);
+        }
+        if result.is_err() {
+            self.partial = None;
+            self.dirty = true;
+        }
+        result
+    }
+
+    fn shift_partial(
+        &mut self,
+        budget_bits: usize,
+    ) -> Result<partial::FlushProgress, DeviceError<L, D, O, C>> {
+        let mut partial = match self.partial.take() {
+            Some(partial) => partial,
+            None => {
+                if self.needs_resync {
+                    self.resync()?;
+                }
+                let packed = self.next_packed();
+                self.dirty = false;
+                partial::PartialFlush::new([packed])
+            }
+        };
+
+        self.needs_resync = true;
+        if partial.is_starting() {
+            self.latch.set_low()?;
+        }
+
+        #[cfg(feature = "stats")]
+        let bits = budget_bits.min(partial.remaining());
+        shift_bits(
+            &mut self.data,
+            &mut self.clock,
+            &mut self.delay,
+            self.timing.clock_ns,
+            partial.take_bits(budget_bits),
+        )?;
+        #[cfg(feature = "stats")]
+        self.stats.add_bits(bits);
+
+        let remaining = partial.remaining();
+        if remaining > 0 {
+            self.partial = Some(partial);
+            self.needs_resync = false;
+            return Ok(partial::FlushProgress::Shifting { remaining });
+        }
+
+        self.clock.set_low()?;
+        let blank = self.blank_during_latch && self.output_enabled;
+        if blank {
+            self.oe.set_high()?;
+        }
+        self.latch_shifted(blank)?;
+        Ok(partial::FlushProgress::Latched)
+    }
+
+    /// Whether a flush failed part way through, leaving the board's shift
+    /// register half shifted and maybe the latch or clock line high.
+    pub fn needs_resync(&self) -> bool {
+        self.needs_resync
+    }
+
+    /// Puts the board back in a known state after a failed flush.  The latch
+    /// and clock are pulled low, and a whole frame of zeros is clocked in
+    /// without latching it.  That pushes out every bit a failed flush left
+    /// behind, while the LEDs keep showing the last frame that was latched.
+    pub fn resync(&mut self) -> Result<(), DeviceError<L, D, O, C>> {
+        self.needs_resync = true;
+        self.latch.set_low()?;
+
+        shift_bits(
+            &mut self.data,
+            &mut self.clock,
+            &mut self.delay,
+            self.timing.clock_ns,
+            core::iter::repeat_n(false, frame::FRAME_BITS),
+        )?;
+        #[cfg(feature = "stats")]
+        self.stats.add_frame();
+
+        self.clock.set_low()?;
+        self.needs_resync = false;
+        Ok(())
+    }
+}
+
+/// Reads a channel's value from the buffer, the same as `read_pwm`.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::{PWM5947, C3};
+///
+/// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+/// device[C3] = PWMValue::new(100);
+/// assert_eq!(PWMValue::new(100), device[C3]);
+/// assert!(device.is_dirty());
+/// ```
+impl<L, D, O, C, T> core::ops::Index<Channel> for PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    type Output = pwm::PWMValue;
+
+    fn index(&self, channel: Channel) -> &Self::Output {
+        &self.buffer[channel.0]
+    }
+}
+
+/// Writes a channel's value into the buffer, the same as `write_pwm`.  The
+/// buffer is marked dirty as soon as the channel is borrowed, whether or not
+/// the value changes.
+impl<L, D, O, C, T> core::ops::IndexMut<Channel> for PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    fn index_mut(&mut self, channel: Channel) -> &mut Self::Output {
+        self.fine[channel.0] = 0;
+        self.dirty = true;
+        &mut self.buffer[channel.0]
+    }
+}
+
+// Pins for the unit tests, shared by every module's tests.
+#[cfg(test)]
+pub(crate) mod test_pins {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use embedded_hal::digital::v2::OutputPin;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    // Fake pin for testing purposes.
+    pub(crate) struct FakePin {
+        pub(crate) value: bool,
+    }
+
+    impl OutputPin for FakePin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.value = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.value = true;
+            Ok(())
+        }
+    }
+
+    // A device on four fake pins, all of them low.
+    pub(crate) fn fake_device() -> crate::PWM5947<FakePin, FakePin, FakePin, FakePin> {
+        let latch = FakePin { value: false };
+        let oe = FakePin { value: false };
+        let data = FakePin { value: false };
+        let clock = FakePin { value: false };
+        crate::PWM5947::new(latch, data, oe, clock)
+    }
+
+    // A pin that ignores what it's set to, for tests that don't look at it.
+    // It has the same error type as the wire's pins, so they can be mixed.
+    pub(crate) struct IdlePin;
+
+    impl OutputPin for IdlePin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    pub(crate) struct FailingPin {
+        pub(crate) will_fail: bool,
+        pub(crate) value: bool,
+    }
+
+    impl FailingPin {
+        pub(crate) fn new(will_fail: &bool, value: &bool) -> Self {
+            FailingPin {
+                will_fail: *will_fail,
+                value: *value,
+            }
+        }
+    }
+
+    // This impl allows me to simulate pin failures.  This allows me to unit
+    // test the error handling without triggering some kind of failure on
+    // physical hardware.
+    impl OutputPin for FailingPin {
+        type Error = &'static str;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            if self.will_fail {
+                Err("Failed")
+            } else {
+                self.value = false;
+                Ok(())
+            }
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            if self.will_fail {
+                Err("Failed")
+            } else {
+                self.value = true;
+                Ok(())
+            }
+        }
+    }
+
+    // The data line, and every bit read off it when the clock goes high,
+    // which is when the board reads the bit.  While `failing` is set the
+    // clock fails instead.
+    #[derive(Default)]
+    pub(crate) struct Wire {
+        pub(crate) data: bool,
+        pub(crate) bits: Vec<bool>,
+        pub(crate) failing: bool,
+    }
+
+    pub(crate) struct DataPin(pub(crate) Rc<RefCell<Wire>>);
+    pub(crate) struct ClockPin(pub(crate) Rc<RefCell<Wire>>);
+
+    impl OutputPin for DataPin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().data = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().data = true;
+            Ok(())
+        }
+    }
+
+    impl OutputPin for ClockPin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            let mut wire = self.0.borrow_mut();
+            if wire.failing {
+                return Err(());
+            }
+            let bit = wire.data;
+            wire.bits.push(bit);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_pins::{fake_device, FailingPin, FakePin};
+
+    use crate::pwm::PWMValue;
+
+    #[test]
+    fn test_toggle() {
+        let latch = FakePin { value: false };
+        let oe = FakePin { value: false };
+        let data = FakePin { value: false };
+        let clock = FakePin { value: false };
+
+        let mut device = crate::PWM5947::new(latch, data, oe, clock);
+        let res = device.begin();
+        assert!(res.is_ok());
+
+        for channel in crate::ALL_CHANNELS {
+            let val = PWMValue::new(channel.0 as i32);
+            device.write_pwm(channel, &val);
+        }
+
+        for i in 0..24 {
+            assert_eq!(device.buffer[i], PWMValue::new(i as i32));
+        }
+    }
+
+    #[test]
+    fn test_dirty() {
+        let mut device = fake_device();
+        assert!(!device.is_dirty());
+
+        device[crate::C5] = PWMValue::new(5);
+        assert!(device.is_dirty());
+        assert_eq!(PWMValue::new(5), device.buffer[4]);
+        assert!(device.flush().is_ok());
+        assert!(!device.is_dirty());
+
+        assert_eq!(PWMValue::new(5), device[crate::C5]);
+        assert!(!device.is_dirty());
+
+        device.write_many(core::iter::empty());
+        assert!(!device.is_dirty());
+        device.write_many([(crate::C5, PWMValue::new(7)), (crate::C5, PWMValue::new(8))]);
+        assert!(device.is_dirty());
+        assert_eq!(PWMValue::new(8), device[crate::C5]);
+        assert!(device.flush().is_ok());
+
+        device.fade_to(
+            &crate::C1,
+            PWMValue::new(10),
+            2,
+            crate::animation::Easing::Linear,
+        );
+        device.tick();
+        assert!(device.is_dirty());
+    }
+
+    #[test]
+    fn test_packed_between_flushes() {
+        let mut device = fake_device();
+        device.write_pwm(&crate::C1, &PWMValue::new(0xabc));
+        assert!(device.flush().is_ok());
+        assert_eq!(Some(device.packed_frame()), device.packed);
+
+        device.set_channel_limit(&crate::C1, PWMValue::new(0x100));
+        assert!(device.flush().is_ok());
+        assert_eq!(Some(device.packed_frame()), device.packed);
+        assert_eq!(PWMValue::new(0x100), device.packed_frame().get(&crate::C1));
+
+        device.write_pwm(&crate::C2, &PWMValue::new(0x123));
+        assert!(device.flush_partial(10).is_ok());
+        assert_eq!(Some(device.packed_frame()), device.packed);
+    }
+
+    #[test]
+    fn test_write_frame_u8() {
+        let mut device = fake_device();
+        let mut frame = [0_u8; 24];
+        frame[0] = 255;
+        frame[1] = 128;
+
+        assert_eq!(Ok(()), device.write_frame_u8(&frame));
+        assert!(device.is_dirty());
+        assert_eq!(PWMValue::max(), device[crate::C1]);
+        assert_eq!(crate::gamma::GAMMA_2_2.correct(128), device[crate::C2]);
+        assert_eq!(
+            Err(crate::pwm::RangeError::Length {
+                len: 25,
+                expected: 24
+            }),
+            device.write_frame_u8(&[0_u8; 25])
+        );
+    }
+
+    #[test]
+    fn test_begin() {
+        let latch = FakePin { value: true };
+        let oe = FakePin { value: true };
+        let data = FakePin { value: true };
+        let clock = FakePin { value: true };
+
+        let mut device = crate::PWM5947::new(latch, data, oe, clock);
+        for i in 0..24 {
+            device.buffer[i] = PWMValue::new(0x10);
+        }
+
+        let res = device.begin();
+        assert!(res.is_ok());
+
+        for i in 0..24 {
+            assert_eq!(device.buffer[i], PWMValue::min());
+        }
+
+        assert!(!device.latch.raw_pin.value);
+        assert!(!device.clock.raw_pin.value);
+        assert!(!device.oe.raw_pin.value);
+        assert!(!device.data.raw_pin.value);
+    }
+
+    #[test]
+    fn test_output_enable() {
+        let mut device = fake_device();
+        assert!(device.disable_output().is_ok());
+        assert!(device.oe.raw_pin.value);
+        assert!(device.service_output().is_ok());
+        assert!(device.oe.raw_pin.value);
+
+        assert!(device.enable_output().is_ok());
+        assert!(!device.oe.raw_pin.value);
+        assert!(device.service_output().is_ok());
+        assert!(!device.oe.raw_pin.value);
+
+        device.set_global_brightness(PWMValue::new(1024));
+        let mut on = 0;
+        for _ in 0..4096 {
+            assert!(device.service_output().is_ok());
+            if !device.oe.raw_pin.value {
+                on += 1;
+            }
+        }
+        assert_eq!(1024, on);
+
+        device.set_global_brightness(PWMValue::min());
+        assert!(device.service_output().is_ok());
+        assert!(device.oe.raw_pin.value);
+    }
+
+    #[test]
+    fn test_tick_fades() {
+        let mut device = fake_device();
+        device.write_pwm(&crate::C2, &PWMValue::new(400));
+        device.fade_to(
+            &crate::C2,
+            PWMValue::new(0),
+            4,
+            crate::animation::Easing::Linear,
+        );
+        device.fade_to(
+            &crate::C3,
+            PWMValue::new(80),
+            8,
+            crate::animation::Easing::Linear,
+        );
+        device.set_animation_speed(crate::timebase::Speed::from_ratio(2, 1));
+
+        device.tick();
+        assert_eq!(PWMValue::new(200), device.buffer[1]);
+        assert_eq!(PWMValue::new(20), device.buffer[2]);
+
+        device.tick();
+        assert_eq!(PWMValue::new(0), device.buffer[1]);
+        assert!(!device.is_fading(&crate::C2));
+        assert!(device.is_fading(&crate::C3));
+        assert_eq!(0b10, device.finished_fades());
+
+        device.cancel_fade(&crate::C3);
+        device.tick();
+        assert_eq!(PWMValue::new(40), device.buffer[2]);
+        assert_eq!(0b10, device.take_finished_fades());
+        assert_eq!(0, device.finished_fades());
+
+        device.fade_to(
+            &crate::C2,
+            PWMValue::new(2),
+            1,
+            crate::animation::Easing::Linear,
+        );
+        device.tick();
+        device.fade_to(
+            &crate::C2,
+            PWMValue::new(4),
+            1,
+            crate::animation::Easing::Linear,
+        );
+        assert_eq!(0, device.finished_fades());
+    }
+
+    #[test]
+    fn test_fade_to_frame() {
+        let mut device = fake_device();
+        let mut target = crate::frame::Frame::new([PWMValue::new(100); 24]);
+        target.0[23] = PWMValue::max();
+        device.write_pwm(&crate::C1, &PWMValue::new(300));
+        device.fade_to_frame(&target, 4, crate::animation::Easing::Linear);
+
+        device.tick();
+        assert_eq!(PWMValue::new(250), device.buffer[0]);
+        assert_eq!(PWMValue::new(25), device.buffer[1]);
+        for _ in 0..3 {
+            device.tick();
+        }
+        assert_eq!(target, device.snapshot());
+        assert_eq!(0xff_ffff, device.take_finished_fades());
+    }
+
+    #[test]
+    fn test_group_brightness() {
+        let mut device = fake_device();
+        let zone = crate::group::ChannelGroup::new(&[crate::C2, crate::C3]).unwrap();
+        zone.assign_brightness_group(&mut device, 2);
+        assert_eq!(2, device.channel_group(&crate::C3));
+        assert_eq!(0, device.channel_group(&crate::C4));
+
+        device.set_channel_limit(&crate::C3, PWMValue::new(2000));
+        for channel in [crate::C1, crate::C2, crate::C3] {
+            device.write_pwm(&channel, &PWMValue::max());
+        }
+        device.set_group_brightness(2, 128);
+        let frame = device.output_frame();
+        assert_eq!(PWMValue::max(), frame[0]);
+        assert_eq!(PWMValue::new(2056), frame[1]);
+        assert_eq!(PWMValue::new(1004), frame[2]);
+        assert_eq!(PWMValue::max(), device.buffer[1]);
+        assert_eq!(128, device.group_brightness(2));
+    }
+
+    #[test]
+    fn test_latch_polarity() {
+        let mut device = fake_device();
+        device.set_protocol(crate::protocol::ProtocolConfig {
+            latch: crate::protocol::LatchPolarity::ActiveLow,
+            ..crate::protocol::ProtocolConfig::TLC5947
+        });
+        assert!(device.flush().is_ok());
+        assert!(device.latch.raw_pin.value);
+
+        device.set_protocol(crate::protocol::ProtocolConfig::default());
+        assert!(device.flush().is_ok());
+        assert!(!device.latch.raw_pin.value);
+    }
+
+    struct CountingDelay {
+        calls: usize,
+        total_ns: u32,
+    }
+
+    impl crate::timing::Delay for CountingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.calls += 1;
+            self.total_ns += ns;
+        }
+    }
+
+    #[test]
+    fn test_timing() {
+        let delay = CountingDelay {
+            calls: 0,
+            total_ns: 0,
+        };
+        let mut device = fake_device().with_timing(delay, 10, 50);
+        assert!(device.flush().is_ok());
+
+        assert_eq!(24 * 12 * 2 + 1, device.delay.calls);
+        assert_eq!(24 * 12 * 2 * 10 + 50, device.delay.total_ns);
+    }
+
+    #[test]
+    #[allow(clippy::assertions_on_constants)]
+    fn test_failing_pin() {
+        let latch = FakePin { value: true };
+        let oe = FailingPin::new(&true, &true);
+        let data = FakePin { value: true };
+        let clock = FakePin { value: true };
+
+        let mut device = crate::PWM5947::new(latch, data, oe, clock);
+        let res = device.begin();
+        if let Err(e) = res {
+            assert_eq!(e.which, crate::PinRole::OE);
         } else {
             assert!(false);
         }
     }
+
+    #[test]
+    fn test_pin_source() {
+        let latch = FailingPin::new(&false, &true);
+        let oe = FailingPin::new(&true, &true);
+        let data = FailingPin::new(&false, &true);
+        let clock = FailingPin::new(&false, &true);
+
+        let mut device = crate::PWM5947::new(latch, data, oe, clock);
+        let e = device.begin().unwrap_err();
+        assert_eq!(crate::PinRole::OE, e.which);
+        assert_eq!(crate::PinSource::OE("Failed"), e.source);
+        assert_eq!("Failed", e.source.into_inner());
+    }
+
+    #[test]
+    fn test_dithering() {
+        let mut device = fake_device();
+        device.write_pwm16(&crate::C1, 0x0018);
+        device.write_pwm16(&crate::C2, 0xffff);
+        assert_eq!(PWMValue::new(1), device.read_pwm(&crate::C1));
+
+        let frame = device.dithered_buffer();
+        assert_eq!(PWMValue::new(1), frame[0]);
+
+        device.set_dithering(true);
+        let mut high = 0;
+        for _ in 0..16 {
+            let frame = device.dithered_buffer();
+            if frame[0] == PWMValue::new(2) {
+                high += 1;
+            }
+            assert_eq!(PWMValue::max(), frame[1]);
+        }
+        assert_eq!(8, high);
+
+        device.write_pwm(&crate::C1, &PWMValue::new(1));
+        assert_eq!(0x0010, device.read_pwm16(&crate::C1));
+        for _ in 0..16 {
+            assert_eq!(PWMValue::new(1), device.dithered_buffer()[0]);
+        }
+
+        device.write_pwm16(&crate::C3, 0x0128);
+        let mut frame = [PWMValue::min(); 24];
+        frame[2] = PWMValue::new(0x12);
+        assert!(device.write_frame(&frame).is_ok());
+        assert_eq!(0x0120, device.read_pwm16(&crate::C3));
+        for _ in 0..16 {
+            assert_eq!(PWMValue::new(0x12), device.dithered_buffer()[2]);
+        }
+    }
+
+    #[test]
+    fn test_resync_after_failure() {
+        let latch = FailingPin::new(&false, &false);
+        let oe = FailingPin::new(&false, &false);
+        let data = FailingPin::new(&false, &false);
+        let clock = FailingPin::new(&true, &false);
+
+        let mut device = crate::PWM5947::new(latch, data, oe, clock);
+        device.write_pwm(&crate::C1, &PWMValue::max());
+        assert!(device.flush().is_err());
+        assert!(device.needs_resync());
+        assert!(device.is_dirty());
+
+        device.clock.raw_pin.will_fail = false;
+        device.latch.raw_pin.value = true;
+        assert!(device.flush().is_ok());
+        assert!(!device.needs_resync());
+        assert!(!device.is_dirty());
+        assert!(!device.latch.raw_pin.value);
+    }
 }
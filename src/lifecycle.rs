@@ -0,0 +1,218 @@
+//! Fades a fixture up when it starts and down when it stops, instead of
+//! snapping the LEDs on at boot.  A `Lifecycle` holds the scene to show once
+//! the fixture is on.  `begin_with_fade` sets the board up with the outputs
+//! blanked, clears it to black, and then fades every channel up to the scene.
+//! `shutdown` fades every channel back down to black.
+//!
+//! The fades are the device's own, so they move when the device is ticked,
+//! with `tick` or `tick_at`, and show when it's flushed.  Call `update` after
+//! ticking.  It returns `Some` once, when the fade in or out has finished, so
+//! the code that runs after, like cutting the LED supply or going to sleep,
+//! runs at the right time and only once.  The outputs are left enabled after
+//! shutting down.  Disable them as well if the supply stays up.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::frame::Frame;
+//! use ledpwm5947::lifecycle::{Lifecycle, Transition};
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut lifecycle = Lifecycle::new(Frame::new([PWMValue::new(2000); 24]));
+//!
+//! // Fade up over 50 ticks.
+//! lifecycle.begin_with_fade(&mut device, 50).unwrap();
+//! let mut ticks = 0;
+//! while lifecycle.update(&device).is_none() {
+//!     device.tick();
+//!     device.flush().ok();
+//!     ticks += 1;
+//! }
+//! assert_eq!(50, ticks);
+//! assert_eq!(PWMValue::new(2000), device.read_pwm(&C1));
+//!
+//! // And back down, then power off.
+//! lifecycle.shutdown(&mut device, 20);
+//! loop {
+//!     device.tick();
+//!     device.flush().ok();
+//!     if let Some(Transition::Stopped) = lifecycle.update(&device) {
+//!         break;
+//!     }
+//! }
+//! assert_eq!(PWMValue::min(), device.read_pwm(&C1));
+//! ```
+
+use crate::animation::Easing;
+use crate::frame::Frame;
+use crate::pwm::PWMValue;
+use crate::timing::Delay;
+use crate::{DeviceError, ALL_CHANNELS, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+/// Where the fixture is in its lifecycle.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phase {
+    Off,
+    FadingIn,
+    On,
+    FadingOut,
+}
+
+/// A fade in or out that has just finished.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Transition {
+    /// The fade in finished, and the scene is showing.
+    Started,
+    /// The fade out finished, and every channel is off.
+    Stopped,
+}
+
+/// The startup and shutdown fades for a fixture.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Lifecycle {
+    scene: Frame,
+    easing: Easing,
+    phase: Phase,
+}
+
+impl Lifecycle {
+    /// Create a lifecycle that fades up to the scene.  It starts off, with
+    /// linear fades.
+    pub fn new(scene: Frame) -> Self {
+        Lifecycle {
+            scene,
+            easing: Easing::Linear,
+            phase: Phase::Off,
+        }
+    }
+
+    /// Uses the easing curve for the fades in and out.
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// The scene shown once the fixture is on.
+    pub fn scene(&self) -> &Frame {
+        &self.scene
+    }
+
+    /// Changes the scene for the next fade in.
+    pub fn set_scene(&mut self, scene: Frame) {
+        self.scene = scene;
+    }
+
+    /// Where the fixture is.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Sets up the board and starts the fade in, lasting `duration` ticks.
+    /// The pins are reset with the outputs blanked, and a black frame is
+    /// flushed before they're enabled, so whatever the shift registers held
+    /// at power up never shows.
+    pub fn begin_with_fade<L, D, O, C, T>(
+        &mut self,
+        device: &mut PWM5947<L, D, O, C, T>,
+        duration: u32,
+    ) -> Result<(), DeviceError<L, D, O, C>>
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        device.disable_output()?;
+        device.all_black()?;
+        device.reset_pins()?;
+
+        device.fade_to_frame(&self.scene, duration, self.easing);
+        self.phase = Phase::FadingIn;
+        Ok(())
+    }
+
+    /// Starts the fade out, lasting `duration` ticks, from wherever the
+    /// channels are.  Any fades already running are replaced.
+    pub fn shutdown<L, D, O, C, T>(&mut self, device: &mut PWM5947<L, D, O, C, T>, duration: u32)
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        device.fade_all_to(PWMValue::min(), duration, self.easing);
+        self.phase = Phase::FadingOut;
+    }
+
+    /// Checks whether the fade in or out has finished, moving on to `On` or
+    /// `Off` when it has.  It returns the transition on the call where that
+    /// happens, and `None` otherwise.
+    pub fn update<L, D, O, C, T>(&mut self, device: &PWM5947<L, D, O, C, T>) -> Option<Transition>
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        let fading = ALL_CHANNELS.iter().any(|channel| device.is_fading(channel));
+        match self.phase {
+            Phase::FadingIn if !fading => {
+                self.phase = Phase::On;
+                Some(Transition::Started)
+            }
+            Phase::FadingOut if !fading => {
+                self.phase = Phase::Off;
+                Some(Transition::Stopped)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+    use crate::C5;
+
+    #[test]
+    fn test_phases() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut lifecycle = Lifecycle::new(Frame::new([PWMValue::max(); 24]));
+        assert_eq!(None, lifecycle.update(&device));
+
+        assert!(lifecycle.begin_with_fade(&mut device, 10).is_ok());
+        assert_eq!(Phase::FadingIn, lifecycle.phase());
+        device.tick();
+        assert_eq!(PWMValue::new(409), device.read_pwm(&C5));
+
+        // Shutting down half way through the fade in starts from there.
+        for _ in 0..4 {
+            device.tick();
+            assert_eq!(None, lifecycle.update(&device));
+        }
+        lifecycle.shutdown(&mut device, 2);
+        device.tick();
+        assert_eq!(PWMValue::new(1024), device.read_pwm(&C5));
+        assert_eq!(None, lifecycle.update(&device));
+
+        device.tick();
+        assert_eq!(Some(Transition::Stopped), lifecycle.update(&device));
+        assert_eq!(None, lifecycle.update(&device));
+        assert_eq!(Phase::Off, lifecycle.phase());
+    }
+}
@@ -0,0 +1,338 @@
+//! Multiplexes the board across rows of LEDs, so 24 channels drive 24 columns
+//! of as many rows as there are row-select pins.  Each row has its own frame,
+//! and every call to `scan_step` shows the next row: it shifts that row's
+//! frame out, switches the row-select pins over, and latches.  Called from a
+//! timer interrupt, that cycles through the rows fast enough to look steady.
+//!
+//! The frame for the next row is shifted while the current row is still
+//! showing, since the board keeps the latched values until the latch is
+//! toggled.  Only the row switch and the latch happen with OE high, so the
+//! outputs are dark for just a few pin writes and the old row's values never
+//! show on the new row.  The row frames go through the device's caps,
+//! inversion and the other output shaping, but the device's own buffer is
+//! left alone and isn't shown.  The rows only hold 12-bit values, so there's
+//! nothing to dither.  Each step counts as a flush in the device's stats.
+//!
+//! Each row is only lit for one step in every `ROWS`, so the LEDs are that
+//! much dimmer than driven directly, and the scan has to run at `ROWS` times
+//! the refresh rate, say 100Hz per row.  Everything lives in fixed arrays
+//! sized by the const generic, so nothing is allocated.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::matrix::MatrixScanner;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C3};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut matrix = MatrixScanner::new([Pin, Pin, Pin, Pin]);
+//! matrix.begin().unwrap();
+//!
+//! matrix.write_pwm(2, &C3, &PWMValue::max());
+//!
+//! // In the timer interrupt.
+//! assert_eq!(Ok(0), matrix.scan_step(&mut device));
+//! assert_eq!(Ok(1), matrix.scan_step(&mut device));
+//! ```
+
+use crate::frame::PackedFrame;
+use crate::pwm::PWMValue;
+use crate::timing::Delay;
+use crate::{Channel, DeviceSource, PinError, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+/// The error returned when a scan step fails.  Either a row-select pin
+/// failed, or one of the device's pins did.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MatrixError<R, P> {
+    /// The row-select pin for the row failed.
+    Row(usize, R),
+    /// One of the device's pins failed.
+    Pin(PinError<P>),
+}
+
+impl<R, P> core::fmt::Display for MatrixError<R, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MatrixError::Row(row, _) => write!(f, "Failed to switch row {}", row),
+            MatrixError::Pin(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<R, P> From<PinError<P>> for MatrixError<R, P> {
+    fn from(error: PinError<P>) -> Self {
+        MatrixError::Pin(error)
+    }
+}
+
+/// The error from a scan step on a device with the pins `L`, `D`, `O` and `C`.
+type ScanError<R, L, D, O, C> = MatrixError<<R as OutputPin>::Error, DeviceSource<L, D, O, C>>;
+
+/// `ROWS` frames, shown one row at a time through the row-select pins.
+#[derive(Debug)]
+pub struct MatrixScanner<R, const ROWS: usize>
+where
+    R: OutputPin,
+{
+    frames: [[PWMValue; 24]; ROWS],
+    rows: [R; ROWS],
+    active_low: bool,
+    current: usize,
+    started: bool,
+}
+
+impl<R, const ROWS: usize> MatrixScanner<R, ROWS>
+where
+    R: OutputPin,
+{
+    /// Create a scanner with the row-select pins, first row first.  A row is
+    /// selected by setting its pin high.  Every row starts off.
+    pub fn new(rows: [R; ROWS]) -> Self {
+        MatrixScanner {
+            frames: [[PWMValue::min(); 24]; ROWS],
+            rows,
+            active_low: false,
+            current: 0,
+            started: false,
+        }
+    }
+
+    /// Selects a row by setting its pin low instead, for high side switches
+    /// like a P-channel MOSFET.
+    pub fn with_active_low_rows(mut self) -> Self {
+        self.active_low = true;
+        self
+    }
+
+    /// The number of rows.
+    pub fn rows(&self) -> usize {
+        ROWS
+    }
+
+    /// The row that's showing, or `None` before the first step.
+    pub fn current_row(&self) -> Option<usize> {
+        if self.started {
+            Some(self.current)
+        } else {
+            None
+        }
+    }
+
+    /// Writes a value into a channel of the row's frame.  It shows the next
+    /// time the row comes round.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row is not less than the number of rows.
+    pub fn write_pwm(&mut self, row: usize, channel: &Channel, value: &PWMValue) {
+        self.frames[row][channel.0] = *value;
+    }
+
+    /// Reads a channel's value from the row's frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row is not less than the number of rows.
+    pub fn read_pwm(&self, row: usize, channel: &Channel) -> PWMValue {
+        self.frames[row][channel.0]
+    }
+
+    /// Replaces the row's whole frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row is not less than the number of rows.
+    pub fn write_row(&mut self, row: usize, values: &[PWMValue; 24]) {
+        self.frames[row] = *values;
+    }
+
+    /// Sets every row's frame back to zeros.
+    pub fn clear(&mut self) {
+        self.frames = [[PWMValue::min(); 24]; ROWS];
+    }
+
+    /// Turns every row off, and starts the scan again from the first row.
+    pub fn begin(&mut self) -> Result<(), R::Error> {
+        self.started = false;
+        self.current = 0;
+        for row in 0..ROWS {
+            self.select(row, false)?;
+        }
+        Ok(())
+    }
+
+    /// Shows the next row, and returns which one it is.  The rows are shown
+    /// in order, going back to the first after the last.  If a pin fails, OE
+    /// is left high where it can be, so a half switched row stays dark.
+    pub fn scan_step<L, D, O, C, T>(
+        &mut self,
+        device: &mut PWM5947<L, D, O, C, T>,
+    ) -> Result<usize, ScanError<R, L, D, O, C>>
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        if ROWS == 0 {
+            return Ok(0);
+        }
+        let next = if self.started {
+            (self.current + 1) % ROWS
+        } else {
+            0
+        };
+
+        let frame = device
+            .protocol
+            .arrange(&device.shape_frame(self.frames[next]));
+        device.shift_and_latch(
+            &PackedFrame::pack(&frame),
+            true,
+            PWM5947::shift_own,
+            |_| -> Result<(), ScanError<R, L, D, O, C>> {
+                if self.started {
+                    let current = self.current;
+                    self.select(current, false)
+                        .map_err(|e| MatrixError::Row(current, e))?;
+                }
+                self.select(next, true)
+                    .map_err(|e| MatrixError::Row(next, e))?;
+                self.current = next;
+                self.started = true;
+                Ok(())
+            },
+        )?;
+        Ok(next)
+    }
+
+    /// Gives back the row-select pins.
+    pub fn release(self) -> [R; ROWS] {
+        self.rows
+    }
+
+    fn select(&mut self, row: usize, on: bool) -> Result<(), R::Error> {
+        let pin = &mut self.rows[row];
+        if on != self.active_low {
+            pin.set_high()
+        } else {
+            pin.set_low()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{C1, C24};
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    /// The levels of the row pins and OE, logged each time the latch goes
+    /// high, along with the number of data bits that were high.
+    #[derive(Default)]
+    struct Log {
+        rows: [bool; 3],
+        oe: bool,
+        data: bool,
+        ones: usize,
+        latched: Vec<([bool; 3], bool, usize)>,
+    }
+
+    #[derive(Clone, Copy)]
+    enum Role {
+        Row(usize),
+        Latch,
+        Data,
+        Oe,
+        Clock,
+    }
+
+    struct LogPin(Rc<RefCell<Log>>, Role);
+
+    impl OutputPin for LogPin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            let mut log = self.0.borrow_mut();
+            match self.1 {
+                Role::Row(row) => log.rows[row] = false,
+                Role::Data => log.data = false,
+                Role::Oe => log.oe = false,
+                Role::Latch | Role::Clock => {}
+            }
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            let mut log = self.0.borrow_mut();
+            match self.1 {
+                Role::Row(row) => log.rows[row] = true,
+                Role::Data => log.data = true,
+                Role::Oe => log.oe = true,
+                Role::Clock => {
+                    if log.data {
+                        log.ones += 1;
+                    }
+                }
+                Role::Latch => {
+                    let entry = (log.rows, log.oe, log.ones);
+                    log.latched.push(entry);
+                    log.ones = 0;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let pin = |role| LogPin(log.clone(), role);
+        let mut device = PWM5947::new(
+            pin(Role::Latch),
+            pin(Role::Data),
+            pin(Role::Oe),
+            pin(Role::Clock),
+        );
+        let mut matrix =
+            MatrixScanner::new([pin(Role::Row(0)), pin(Role::Row(1)), pin(Role::Row(2))]);
+        assert!(matrix.begin().is_ok());
+        assert_eq!(None, matrix.current_row());
+
+        matrix.write_pwm(1, &C1, &PWMValue::max());
+        matrix.write_pwm(2, &C24, &PWMValue::new(1));
+        for expected in [0, 1, 2, 0] {
+            assert_eq!(Ok(expected), matrix.scan_step(&mut device));
+        }
+
+        let log = log.borrow();
+        assert_eq!(
+            std::vec![
+                ([true, false, false], true, 0),
+                ([false, true, false], true, 12),
+                ([false, false, true], true, 1),
+                ([true, false, false], true, 0),
+            ],
+            log.latched
+        );
+        assert!(!log.oe);
+
+        #[cfg(feature = "stats")]
+        assert_eq!(4, device.stats().flushes());
+    }
+}
@@ -0,0 +1,613 @@
+//! A capture device for testing firmware on the host.  It hands out four
+//! pins that record every transition, and it plays the part of the board's
+//! shift register, so the frames the driver sends can be decoded back into
+//! channel values.  Tests can then check what the LEDs would show without
+//! any hardware attached.
+//!
+//! The pins share the capture through reference counting, so the device can
+//! own the pins while the test keeps the capture to inspect.  A pin can also
+//! be told to fail, to test how the firmware handles pin errors.
+//!
+//! This module needs the `std` feature.
+//!
+//! ```
+//! use ledpwm5947::mock::CaptureDevice;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C2};
+//!
+//! let capture = CaptureDevice::new();
+//! let mut device = PWM5947::new(
+//!     capture.latch_pin(),
+//!     capture.data_pin(),
+//!     capture.oe_pin(),
+//!     capture.clock_pin(),
+//! );
+//!
+//! device.write_pwm(&C2, &PWMValue::new(1234));
+//! device.flush().unwrap();
+//!
+//! let frame = capture.last_frame().expect("one frame was latched");
+//! assert_eq!(PWMValue::new(1234), frame[1]);
+//! ```
+
+use crate::pwm::{PWMValue, PWM_BITS};
+use crate::PinRole;
+use core::cell::RefCell;
+use embedded_hal::digital::v2::OutputPin;
+use std::rc::Rc;
+use std::vec::Vec;
+
+/// The number of bits one board holds in its shift register.
+const FRAME_BITS: usize = 24 * PWM_BITS as usize;
+
+/// A change on one of the pins.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Transition {
+    pub pin: PinRole,
+    pub high: bool,
+}
+
+/// The error a mock pin returns when it has been told to fail.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MockError;
+
+#[derive(Debug, Default)]
+struct Capture {
+    transitions: Vec<Transition>,
+    levels: [bool; 4],
+    failing: [bool; 4],
+    shifted: Vec<bool>,
+    latched: Vec<Vec<bool>>,
+}
+
+fn slot(role: &PinRole) -> usize {
+    match role {
+        PinRole::Latch => 0,
+        PinRole::Data => 1,
+        PinRole::OE => 2,
+        PinRole::Clock => 3,
+    }
+}
+
+impl Capture {
+    fn set(&mut self, role: &PinRole, high: bool) -> Result<(), MockError> {
+        let index = slot(role);
+        if self.failing[index] {
+            return Err(MockError);
+        }
+
+        let rising = high && !self.levels[index];
+        self.levels[index] = high;
+        self.transitions.push(Transition {
+            pin: role.clone(),
+            high,
+        });
+
+        if rising {
+            match role {
+                PinRole::Clock => {
+                    let bit = self.levels[slot(&PinRole::Data)];
+                    self.shifted.push(bit);
+                }
+                PinRole::Latch => {
+                    let register = core::mem::take(&mut self.shifted);
+                    self.latched.push(register);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Records what the driver does to the pins, and decodes the frames it
+/// latches.  Cloning it gives another handle on the same capture.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureDevice {
+    capture: Rc<RefCell<Capture>>,
+}
+
+/// A pin that records its transitions in a `CaptureDevice`.
+#[derive(Debug)]
+pub struct MockPin {
+    role: PinRole,
+    capture: Rc<RefCell<Capture>>,
+}
+
+impl OutputPin for MockPin {
+    type Error = MockError;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.capture.borrow_mut().set(&self.role, false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.capture.borrow_mut().set(&self.role, true)
+    }
+}
+
+impl CaptureDevice {
+    /// Create an empty capture with every pin low.
+    pub fn new() -> Self {
+        CaptureDevice::default()
+    }
+
+    /// A pin for the given role.  Handing out two pins for the same role
+    /// records them both as that role.
+    pub fn pin(&self, role: PinRole) -> MockPin {
+        MockPin {
+            role,
+            capture: self.capture.clone(),
+        }
+    }
+
+    /// The pin to pass to the driver as the latch.
+    pub fn latch_pin(&self) -> MockPin {
+        self.pin(PinRole::Latch)
+    }
+
+    /// The pin to pass to the driver as the data line.
+    pub fn data_pin(&self) -> MockPin {
+        self.pin(PinRole::Data)
+    }
+
+    /// The pin to pass to the driver as OE.
+    pub fn oe_pin(&self) -> MockPin {
+        self.pin(PinRole::OE)
+    }
+
+    /// The pin to pass to the driver as the clock.
+    pub fn clock_pin(&self) -> MockPin {
+        self.pin(PinRole::Clock)
+    }
+
+    /// Makes every later write to the pin fail, or stop failing.
+    pub fn set_failing(&self, role: PinRole, failing: bool) {
+        self.capture.borrow_mut().failing[slot(&role)] = failing;
+    }
+
+    /// Whether the pin is currently high.
+    pub fn is_high(&self, role: PinRole) -> bool {
+        self.capture.borrow().levels[slot(&role)]
+    }
+
+    /// Whether the board's outputs would be on.  OE is active low.
+    pub fn outputs_enabled(&self) -> bool {
+        !self.is_high(PinRole::OE)
+    }
+
+    /// Every transition so far, in order.
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.capture.borrow().transitions.clone()
+    }
+
+    /// The raw bits shifted in before each latch, in the order they were
+    /// shifted.  A chain of boards latches more than one board's worth.
+    pub fn latched_bits(&self) -> Vec<Vec<bool>> {
+        self.capture.borrow().latched.clone()
+    }
+
+    /// Decodes every latched frame into the values of a single board, in
+    /// channel order.  Like the board, only the last 288 bits before each
+    /// latch count.  A frame with fewer bits than that is padded with zeros
+    /// for the channels that never arrived.
+    pub fn frames(&self) -> Vec<[PWMValue; 24]> {
+        self.chain_frames(1)
+            .into_iter()
+            .map(|boards| boards[0])
+            .collect()
+    }
+
+    /// Decodes every latched frame for a chain of boards.  Board 0 is the
+    /// board wired to the microcontroller, the same as in `PWM5947Chain`.
+    pub fn chain_frames(&self, boards: usize) -> Vec<Vec<[PWMValue; 24]>> {
+        self.capture
+            .borrow()
+            .latched
+            .iter()
+            .map(|bits| decode(bits, boards))
+            .collect()
+    }
+
+    /// The values of the last frame latched into a single board.
+    pub fn last_frame(&self) -> Option<[PWMValue; 24]> {
+        self.frames().pop()
+    }
+
+    /// Forgets the recorded transitions and frames, leaving the pin levels
+    /// and failures as they are.
+    pub fn clear(&self) {
+        let mut capture = self.capture.borrow_mut();
+        capture.transitions.clear();
+        capture.shifted.clear();
+        capture.latched.clear();
+    }
+}
+
+/// Works out what each board in a chain holds after the bits were shifted
+/// in.  The last bits shifted in sit in the first board, and within a board
+/// the first bits are the last channel, most significant bit first.
+fn decode(bits: &[bool], boards: usize) -> Vec<[PWMValue; 24]> {
+    let total = FRAME_BITS * boards;
+    let mut register = std::vec![false; total];
+    let kept = bits.len().min(total);
+    register[total - kept..].copy_from_slice(&bits[bits.len() - kept..]);
+
+    let mut result = std::vec![[PWMValue::min(); 24]; boards];
+    for (index, chunk) in register.chunks(PWM_BITS as usize).enumerate() {
+        let value = chunk
+            .iter()
+            .fold(0, |value, bit| (value << 1) | *bit as i32);
+        let board = boards - 1 - index / 24;
+        let channel = 23 - index % 24;
+        result[board][channel] = PWMValue::new(value);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::chain::PWM5947Chain;
+    use crate::clock::Instant;
+    use crate::partial::FlushProgress;
+    use crate::scheduler::DisplayRefresher;
+    use crate::spi::PWM5947Spi;
+    use crate::{PinSource, PWM5947};
+
+    #[test]
+    fn test_capture_frames() {
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+        assert!(device.begin().is_ok());
+
+        device.write_pwm(&crate::C1, &PWMValue::new(1));
+        device.write_pwm(&crate::C24, &PWMValue::max());
+        assert!(device.flush().is_ok());
+        device.write_pwm(&crate::C1, &PWMValue::new(2));
+        assert!(device.flush().is_ok());
+
+        let frames = capture.frames();
+        assert_eq!(2, frames.len());
+        assert_eq!(PWMValue::new(1), frames[0][0]);
+        assert_eq!(PWMValue::max(), frames[0][23]);
+        assert_eq!(PWMValue::new(2), frames[1][0]);
+        assert!(capture.outputs_enabled());
+
+        assert!(device.disable_output().is_ok());
+        assert!(!capture.outputs_enabled());
+        assert_eq!(
+            Some(&Transition {
+                pin: PinRole::OE,
+                high: true
+            }),
+            capture.transitions().last()
+        );
+    }
+
+    #[test]
+    fn test_capture_chain() {
+        let capture = CaptureDevice::new();
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+
+        chain.write_pwm(1, &crate::C3, &PWMValue::new(300));
+        chain.write_pwm(0, &crate::C4, &PWMValue::new(400));
+        assert!(chain.flush().is_ok());
+
+        let frames = capture.chain_frames(2);
+        assert_eq!(PWMValue::new(300), frames[0][1][2]);
+        assert_eq!(PWMValue::new(400), frames[0][0][3]);
+        assert_eq!(PWMValue::new(400), capture.last_frame().unwrap()[3]);
+    }
+
+    #[test]
+    fn test_failing_pin() {
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+
+        capture.set_failing(PinRole::Clock, true);
+        match device.flush() {
+            Err(e) => {
+                assert_eq!(PinRole::Clock, e.which);
+                assert_eq!(PinSource::Clock(MockError), e.source);
+            }
+            Ok(_) => panic!("Should have returned an error"),
+        }
+        assert!(capture.frames().is_empty());
+    }
+
+    #[test]
+    fn test_partial_flush() {
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+
+        device.write_pwm(&crate::C1, &PWMValue::new(1));
+        assert_eq!(
+            Ok(FlushProgress::Shifting { remaining: 88 }),
+            device.flush_partial(200)
+        );
+        // Not part of this frame.
+        device.write_pwm(&crate::C1, &PWMValue::new(2));
+        assert!(capture.frames().is_empty());
+        assert_eq!(Ok(FlushProgress::Latched), device.flush_partial(200));
+        assert_eq!(PWMValue::new(1), capture.last_frame().unwrap()[0]);
+        assert!(device.is_dirty());
+
+        assert!(device.flush_partial(10).is_ok());
+        capture.set_failing(PinRole::Clock, true);
+        assert!(device.flush_partial(10).is_err());
+        capture.set_failing(PinRole::Clock, false);
+        assert_eq!(
+            Ok(FlushProgress::Shifting { remaining: 278 }),
+            device.flush_partial(10)
+        );
+
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+        chain.write_pwm(1, &crate::C3, &PWMValue::new(300));
+        let mut calls = 1;
+        while !chain.flush_partial(100).unwrap().is_latched() {
+            calls += 1;
+        }
+        assert_eq!(6, calls);
+        assert_eq!(
+            PWMValue::new(300),
+            capture.chain_frames(2).last().unwrap()[1][2]
+        );
+    }
+
+    // OE goes high around the latch pulse.
+    fn blanked_latch() -> Vec<(PinRole, bool)> {
+        std::vec![
+            (PinRole::OE, true),
+            (PinRole::Latch, true),
+            (PinRole::Latch, false),
+            (PinRole::OE, false),
+        ]
+    }
+
+    fn latch_tail(capture: &CaptureDevice) -> Vec<(PinRole, bool)> {
+        let transitions = capture.transitions();
+        transitions[transitions.len() - 4..]
+            .iter()
+            .map(|t| (t.pin.clone(), t.high))
+            .collect()
+    }
+
+    #[test]
+    fn test_blank_during_latch() {
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+        let mut refresher = DisplayRefresher::new(20);
+
+        assert_eq!(
+            Ok(false),
+            refresher.refresh(&mut device, Instant::from_millis(0))
+        );
+        device.write_pwm(&crate::C1, &PWMValue::max());
+        assert_eq!(
+            Ok(true),
+            refresher.refresh(&mut device, Instant::from_millis(5))
+        );
+        assert!(capture.outputs_enabled());
+
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+
+        device.write_pwm(&crate::C1, &PWMValue::min());
+        assert_eq!(
+            Ok(false),
+            refresher.refresh(&mut device, Instant::from_millis(10))
+        );
+        assert_eq!(
+            Ok(true),
+            refresher.refresh(&mut device, Instant::from_millis(25))
+        );
+    }
+
+    #[test]
+    fn test_flush_blanks() {
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+        let oe_writes = |capture: &CaptureDevice| {
+            capture
+                .transitions()
+                .iter()
+                .filter(|t| t.pin == PinRole::OE)
+                .count()
+        };
+
+        assert!(device.is_blank_during_latch());
+        assert!(device.flush().is_ok());
+        assert_eq!(2, oe_writes(&capture));
+        assert!(capture.outputs_enabled());
+
+        device.set_blank_during_latch(false);
+        assert!(device.flush().is_ok());
+        assert_eq!(2, oe_writes(&capture));
+        assert_eq!(2, capture.frames().len());
+    }
+
+    #[test]
+    fn test_partial_flush_blanks() {
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+
+        assert_eq!(Ok(FlushProgress::Latched), device.flush_partial(1000));
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+    }
+
+    #[test]
+    fn test_chain_blanks() {
+        let capture = CaptureDevice::new();
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+
+        assert!(chain.is_blank_during_latch());
+        assert!(chain.flush().is_ok());
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+
+        capture.clear();
+        assert_eq!(Ok(FlushProgress::Latched), chain.flush_partial(1000));
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+
+        capture.clear();
+        chain.set_blank_during_latch(false);
+        assert!(chain.flush().is_ok());
+        assert!(capture.transitions().iter().all(|t| t.pin != PinRole::OE));
+    }
+
+    #[test]
+    fn test_refresher_blanks_chain() {
+        let capture = CaptureDevice::new();
+        let mut chain: PWM5947Chain<_, _, _, _, 2> = PWM5947Chain::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+        let mut refresher = DisplayRefresher::new(20);
+
+        refresher.set_blanking(false);
+        chain.write_pwm(1, &crate::C1, &PWMValue::max());
+        assert_eq!(
+            Ok(true),
+            refresher.refresh(&mut chain, Instant::from_millis(0))
+        );
+        assert!(capture.transitions().iter().all(|t| t.pin != PinRole::OE));
+
+        refresher.set_blanking(true);
+        chain.write_pwm(1, &crate::C1, &PWMValue::min());
+        assert_eq!(
+            Ok(true),
+            refresher.refresh(&mut chain, Instant::from_millis(20))
+        );
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+    }
+
+    #[test]
+    fn test_bank_blanks() {
+        let capture = CaptureDevice::new();
+        let mut bank = Bank::new(
+            [capture.latch_pin(), capture.latch_pin()],
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+
+        assert!(bank.is_blank_during_latch());
+        assert!(bank.flush(1).is_ok());
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+
+        capture.clear();
+        bank.set_blank_during_latch(false);
+        assert!(bank.flush_all().is_ok());
+        assert!(capture.transitions().iter().all(|t| t.pin != PinRole::OE));
+        assert_eq!(2, capture.frames().len());
+    }
+
+    #[test]
+    fn test_spi_blanks() {
+        struct Sink;
+
+        impl embedded_hal::blocking::spi::Write<u8> for Sink {
+            type Error = ();
+
+            fn write(&mut self, _words: &[u8]) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947Spi::new(Sink, capture.latch_pin(), capture.oe_pin());
+
+        assert!(device.is_blank_during_latch());
+        assert!(device.flush().is_ok());
+        assert_eq!(blanked_latch(), latch_tail(&capture));
+
+        capture.clear();
+        device.set_blank_during_latch(false);
+        assert!(device.flush().is_ok());
+        assert!(capture.transitions().iter().all(|t| t.pin != PinRole::OE));
+    }
+
+    #[test]
+    fn test_self_test() {
+        struct Timer(u64);
+
+        impl crate::timing::Delay for Timer {
+            fn delay_ns(&mut self, ns: u32) {
+                self.0 += ns as u64;
+            }
+        }
+
+        let capture = CaptureDevice::new();
+        let mut device = PWM5947::new(
+            capture.latch_pin(),
+            capture.data_pin(),
+            capture.oe_pin(),
+            capture.clock_pin(),
+        );
+        device.set_channel_limit(&crate::C2, PWMValue::new(100));
+
+        let mut timer = Timer(0);
+        assert!(device.self_test(&mut timer).is_ok());
+        assert_eq!(25 * crate::SELF_TEST_STEP_MS as u64 * 1_000_000, timer.0);
+
+        let frames = capture.frames();
+        assert_eq!(26, frames.len());
+        for (index, frame) in frames[..24].iter().enumerate() {
+            let lit: Vec<usize> = (0..24).filter(|i| frame[*i] > PWMValue::min()).collect();
+            assert_eq!(std::vec![index], lit);
+        }
+        assert_eq!(PWMValue::new(100), frames[1][1]);
+        assert_eq!(PWMValue::new(100), frames[24][1]);
+        assert_eq!(PWMValue::max(), frames[24][23]);
+        assert_eq!([PWMValue::min(); 24], frames[25]);
+        assert_eq!([PWMValue::min(); 24], *device.buffer());
+    }
+}
@@ -0,0 +1,180 @@
+//! A flush bit-bangs the whole frame in one go, which takes a while on a long
+//! chain or with slow pulse timing.  A cooperative scheduler, or a loop that
+//! has to feed a watchdog, can't always wait that long.  `flush_partial`
+//! shifts out at most a given number of bits and returns, picking up where it
+//! left off on the next call.  The latch is only toggled once the last bit is
+//! out, so the LEDs keep showing the previous frame until the new one is
+//! complete.
+//!
+//! The frame is copied when its first bits go out, so writes made between the
+//! calls don't tear it.  They show on the frame after.  A full `flush` in the
+//! middle drops the partial frame and sends a whole new one, which pushes out
+//! the bits already shifted.  If a pin fails, the partial frame is dropped and
+//! the next call starts again from the first bit.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::partial::FlushProgress;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! device.write_pwm(&C1, &PWMValue::max());
+//!
+//! assert_eq!(
+//!     Ok(FlushProgress::Shifting { remaining: 188 }),
+//!     device.flush_partial(100)
+//! );
+//! assert_eq!(
+//!     Ok(FlushProgress::Shifting { remaining: 88 }),
+//!     device.flush_partial(100)
+//! );
+//! assert_eq!(Ok(FlushProgress::Latched), device.flush_partial(100));
+//! ```
+
+use crate::frame::{PackedFrame, FRAME_BITS};
+
+/// How far a partial flush has got.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlushProgress {
+    /// The frame is part way out, with this many bits still to shift.
+    Shifting { remaining: usize },
+    /// The last bits went out and the frame was latched.
+    Latched,
+}
+
+impl FlushProgress {
+    /// Whether the frame was latched.
+    pub fn is_latched(&self) -> bool {
+        *self == FlushProgress::Latched
+    }
+}
+
+/// The packed frames for `N` boards being shifted out, and how many bits
+/// have gone.  The frames are kept packed so the next bit can be picked out
+/// directly, however far the flush has got.
+#[derive(Debug)]
+pub(crate) struct PartialFlush<const N: usize> {
+    frames: [PackedFrame; N],
+    sent: usize,
+}
+
+impl<const N: usize> PartialFlush<N> {
+    pub(crate) fn new(frames: [PackedFrame; N]) -> Self {
+        PartialFlush { frames, sent: 0 }
+    }
+
+    /// Whether none of the bits have gone out yet.
+    pub(crate) fn is_starting(&self) -> bool {
+        self.sent == 0
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        N * FRAME_BITS - self.sent
+    }
+
+    /// The next `count` bits, starting with the last board the same as a
+    /// full flush.  They're counted as sent straight away, since a failure
+    /// part way through drops the partial frame.
+    pub(crate) fn take_bits(&mut self, count: usize) -> impl Iterator<Item = bool> + '_ {
+        let start = self.sent;
+        let count = count.min(self.remaining());
+        self.sent += count;
+
+        let frames = &self.frames;
+        (start..start + count).map(move |index| {
+            let board = N - 1 - index / FRAME_BITS;
+            frames[board].bit(index % FRAME_BITS)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::pwm::PWMValue;
+    use crate::test_pins::FakePin;
+    use crate::PWM5947;
+    use core::convert::Infallible;
+    use embedded_hal::digital::v2::OutputPin;
+    use std::vec::Vec;
+
+    // Counts the times the latch goes high.
+    struct LatchPin(usize);
+
+    impl OutputPin for LatchPin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    fn frames() -> [PackedFrame; 2] {
+        let mut first = [PWMValue::min(); 24];
+        first[0] = PWMValue::new(0xabc);
+        let mut second = [PWMValue::min(); 24];
+        second[23] = PWMValue::max();
+        [PackedFrame::pack(&first), PackedFrame::pack(&second)]
+    }
+
+    #[test]
+    fn test_chunk_boundaries() {
+        let frames = frames();
+        let whole: Vec<bool> = frames.iter().rev().flat_map(PackedFrame::bits).collect();
+
+        for chunk in [1, 7, 8, 100, 288, 289, 1000] {
+            let mut partial = PartialFlush::new(frames);
+            let mut bits = Vec::new();
+            while partial.remaining() > 0 {
+                bits.extend(partial.take_bits(chunk));
+            }
+            assert_eq!(whole, bits);
+        }
+    }
+
+    #[test]
+    fn test_frame_ends_mid_chunk() {
+        let mut partial = PartialFlush::new([PackedFrame::default()]);
+        assert!(partial.is_starting());
+
+        assert_eq!(100, partial.take_bits(100).count());
+        assert!(!partial.is_starting());
+        assert_eq!(188, partial.remaining());
+        assert_eq!(100, partial.take_bits(100).count());
+        assert_eq!(88, partial.take_bits(100).count());
+        assert_eq!(0, partial.remaining());
+        assert_eq!(0, partial.take_bits(100).count());
+    }
+
+    #[test]
+    fn test_latched_at_the_end() {
+        let pin = || FakePin { value: false };
+        let mut device = PWM5947::new(LatchPin(0), pin(), pin(), pin());
+        device.write_pwm(&crate::C1, &PWMValue::max());
+
+        let progress = device.flush_partial(100).unwrap();
+        assert!(!progress.is_latched());
+        let progress = device.flush_partial(100).unwrap();
+        assert_eq!(FlushProgress::Shifting { remaining: 88 }, progress);
+        assert_eq!(0, device.latch.raw_pin.0);
+
+        assert!(device.flush_partial(100).unwrap().is_latched());
+        assert_eq!(1, device.latch.raw_pin.0);
+    }
+}
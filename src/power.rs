@@ -0,0 +1,130 @@
+//! A power budget keeps the board from drawing more current than the supply
+//! can give.  Each channel has a cost, the current in milliamps its LEDs draw at
+//! full scale, and the budget caps the total.  A panel fed from a USB port
+//! might have a 500mA budget, even though all 24 channels at full brightness
+//! would draw far more.
+//!
+//! The current a channel draws is taken to be proportional to its PWM value,
+//! which is how the TLC5947's constant current outputs behave.  When the frame
+//! would draw more than the budget, every channel is scaled down by the same
+//! proportion, so colors keep their balance and the frame just gets dimmer.
+//!
+//! A device with a budget applies it on every flush, after the channel caps.
+//! The buffer keeps the values that were written, so the frame comes back to
+//! full brightness once the other channels go dark.
+//!
+//! ```
+//! use ledpwm5947::power::PowerBudget;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::C1;
+//!
+//! let mut budget = PowerBudget::new(60, 20);
+//! budget.set_channel_current(&C1, 40);
+//!
+//! let mut frame = [PWMValue::min(); 24];
+//! frame[0] = PWMValue::max();
+//! frame[1] = PWMValue::max();
+//! frame[2] = PWMValue::max();
+//! assert_eq!(80, budget.draw(&frame));
+//!
+//! budget.apply(&mut frame);
+//! assert!(budget.draw(&frame) <= 60);
+//! assert_eq!(PWMValue::new(3071), frame[0]);
+//! ```
+
+use crate::pwm::{PWMValue, PWM_BITS, PWM_MASK};
+use crate::Channel;
+
+/// The current each channel draws at full scale, and the most all of them may
+/// draw together.  Currents are in milliamps.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PowerBudget {
+    budget_ma: u32,
+    full_scale_ma: [u16; 24],
+}
+
+impl PowerBudget {
+    /// Create a budget of `budget_ma` where every channel draws `channel_ma` at
+    /// full scale.  Channels that draw a different current, or nothing at all
+    /// because nothing is connected, can be set afterwards.
+    pub fn new(budget_ma: u32, channel_ma: u16) -> Self {
+        PowerBudget {
+            budget_ma,
+            full_scale_ma: [channel_ma; 24],
+        }
+    }
+
+    /// The most current the channels may draw together.
+    pub fn budget(&self) -> u32 {
+        self.budget_ma
+    }
+
+    /// Change the most current the channels may draw together.
+    pub fn set_budget(&mut self, budget_ma: u32) {
+        self.budget_ma = budget_ma;
+    }
+
+    /// Sets the current the channel draws at full scale.
+    pub fn set_channel_current(&mut self, channel: &Channel, channel_ma: u16) {
+        self.full_scale_ma[channel.0] = channel_ma;
+    }
+
+    /// The current the channel draws at full scale.
+    pub fn channel_current(&self, channel: &Channel) -> u16 {
+        self.full_scale_ma[channel.0]
+    }
+
+    /// The current the frame would draw, rounded down to the milliamp.
+    pub fn draw(&self, frame: &[PWMValue; 24]) -> u32 {
+        (self.draw_scaled(frame) / PWM_MASK as u64) as u32
+    }
+
+    /// Scales the frame down, if it has to be, so it draws no more than the
+    /// budget.  Every channel is scaled by the same proportion, rounding down.
+    /// A frame within the budget is left alone.
+    pub fn apply(&self, frame: &mut [PWMValue; 24]) {
+        let draw = self.draw_scaled(frame);
+        let budget = self.budget_ma as u64 * PWM_MASK as u64;
+        if draw <= budget {
+            return;
+        }
+
+        for value in frame.iter_mut() {
+            let raw = value.to_resolution(PWM_BITS) as u64;
+            *value = PWMValue::new((raw * budget / draw) as i32);
+        }
+    }
+
+    /// The draw in units of a milliamp over 4095, so nothing is lost to
+    /// rounding before the comparison.
+    fn draw_scaled(&self, frame: &[PWMValue; 24]) -> u64 {
+        frame
+            .iter()
+            .zip(self.full_scale_ma.iter())
+            .map(|(value, ma)| value.to_resolution(PWM_BITS) as u64 * *ma as u64)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_within_budget() {
+        let budget = PowerBudget::new(480, 20);
+        let mut frame = [PWMValue::max(); 24];
+
+        budget.apply(&mut frame);
+        assert_eq!([PWMValue::max(); 24], frame);
+    }
+
+    #[test]
+    fn test_zero_budget() {
+        let budget = PowerBudget::new(0, 20);
+        let mut frame = [PWMValue::new(100); 24];
+
+        budget.apply(&mut frame);
+        assert_eq!([PWMValue::min(); 24], frame);
+    }
+}
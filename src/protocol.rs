@@ -0,0 +1,157 @@
+//! The details of the shift protocol, for clone boards and other 12-bit
+//! shift-PWM chips that differ from the TLC5947 in small ways.  The TLC5947
+//! takes the last channel first, each value with its most significant bit
+//! first, and latches on a high pulse.  A `ProtocolConfig` can flip any of
+//! those, and `PWM5947::set_protocol` or the builder's `protocol` uses it for
+//! every flush.  Chains, banks, the port expander and the SPI and async
+//! drivers have a `set_protocol` of their own.
+//!
+//! The bit and channel orders are applied to the frame just before it's
+//! shifted, so the buffer, the channel caps and everything else still work in
+//! the usual channel numbers, and `packed_frame` shows the bytes as they go
+//! out.  The latch polarity is the same setting as driving the latch pin
+//! active low, so setting the protocol replaces it.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::protocol::{ChannelOrder, ProtocolConfig};
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! device.set_protocol(ProtocolConfig {
+//!     channel_order: ChannelOrder::Ascending,
+//!     ..ProtocolConfig::TLC5947
+//! });
+//!
+//! device.write_pwm(&C1, &PWMValue::new(0xabc));
+//! assert_eq!([0xab, 0xc0], device.packed_frame().as_bytes()[..2]);
+//! ```
+
+use crate::pwm::PWMValue;
+use crate::{Channel, ALL_CHANNELS};
+
+/// The order of the bits in each value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BitOrder {
+    /// The most significant bit goes first, like the TLC5947.
+    MsbFirst,
+    /// The least significant bit goes first.
+    LsbFirst,
+}
+
+/// The order the channels are shifted in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelOrder {
+    /// The last channel goes first, so the first channel ends up nearest the
+    /// data input, like the TLC5947.
+    Descending,
+    /// The first channel goes first.
+    Ascending,
+}
+
+/// The level of the latch pulse.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LatchPolarity {
+    /// The latch idles low and pulses high, like the TLC5947.
+    ActiveHigh,
+    /// The latch idles high and pulses low.
+    ActiveLow,
+}
+
+/// How the frame is shifted out and latched.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProtocolConfig {
+    /// The order of the bits in each value.
+    pub bit_order: BitOrder,
+    /// The order the channels are shifted in.
+    pub channel_order: ChannelOrder,
+    /// The level of the latch pulse.
+    pub latch: LatchPolarity,
+}
+
+impl ProtocolConfig {
+    /// The TLC5947's own protocol.
+    pub const TLC5947: ProtocolConfig = ProtocolConfig {
+        bit_order: BitOrder::MsbFirst,
+        channel_order: ChannelOrder::Descending,
+        latch: LatchPolarity::ActiveHigh,
+    };
+
+    /// Rearranges the frame so that shifting it the TLC5947's way sends it in
+    /// this protocol's order.
+    pub fn arrange(&self, frame: &[PWMValue; 24]) -> [PWMValue; 24] {
+        let mut arranged = *frame;
+        for (channel, value) in ALL_CHANNELS.iter().zip(frame.iter()) {
+            let (slot, value) = self.place(channel, *value);
+            arranged[slot.0] = value;
+        }
+        arranged
+    }
+
+    /// Where one channel's value goes in the arranged frame, and what it
+    /// becomes, so a packed frame can be kept arranged a channel at a time.
+    pub(crate) fn place(&self, channel: &Channel, value: PWMValue) -> (Channel, PWMValue) {
+        let slot = match self.channel_order {
+            ChannelOrder::Descending => Channel(channel.0),
+            ChannelOrder::Ascending => Channel(23 - channel.0),
+        };
+        let value = match self.bit_order {
+            BitOrder::MsbFirst => value,
+            BitOrder::LsbFirst => PWMValue::new((value.as_u16().reverse_bits() >> 4) as i32),
+        };
+        (slot, value)
+    }
+
+    /// Whether the latch pin is driven active low.
+    pub(crate) fn latch_active_low(&self) -> bool {
+        self.latch == LatchPolarity::ActiveLow
+    }
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        ProtocolConfig::TLC5947
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrange() {
+        let mut frame = [PWMValue::min(); 24];
+        frame[0] = PWMValue::new(0x001);
+        frame[23] = PWMValue::new(0xc00);
+
+        assert_eq!(frame, ProtocolConfig::TLC5947.arrange(&frame));
+
+        let lsb = ProtocolConfig {
+            bit_order: BitOrder::LsbFirst,
+            ..ProtocolConfig::TLC5947
+        };
+        let arranged = lsb.arrange(&frame);
+        assert_eq!(PWMValue::new(0x800), arranged[0]);
+        assert_eq!(PWMValue::new(0x003), arranged[23]);
+
+        let both = ProtocolConfig {
+            channel_order: ChannelOrder::Ascending,
+            ..lsb
+        };
+        let arranged = both.arrange(&frame);
+        assert_eq!(PWMValue::new(0x003), arranged[0]);
+        assert_eq!(PWMValue::new(0x800), arranged[23]);
+    }
+}
@@ -5,49 +5,185 @@
 //! The are changed by stepping them up and down, using a Step.  it is possible
 //! that the stepped value exceeds the PWM limits (usually 0 and some 8, 10,
 //! or 12 bit value).  In this case an `Overflow` or `Underflow` error is
-//! returned.
+//! returned, carrying the value that was out of range and the limit it went
+//! past.
 //!
 //! New PWM values are clamped between min and max.  Step values are clamped
 //! between -min and max.  Creating a new PWM value does not cause an error,
 //! but operations are intended to highlight code issues that might indifinitely
 //! loop on stepping up or down a PWM, thinking the limit has not been reached.
+//!
+//! The constructors are `const fn`, clamping included, so tables of values and
+//! steps can be `const` or `static` arrays that stay in flash instead of being
+//! built in RAM at start up.
+//!
+//! ```
+//! use ledpwm5947::pwm::{PWMValue, Step};
+//!
+//! static LEVELS: [PWMValue; 4] = [
+//!     PWMValue::min(),
+//!     PWMValue::from_percent(25),
+//!     PWMValue::new(3000),
+//!     PWMValue::new(9000),
+//! ];
+//! const NUDGE: Step = Step::new(-5000).half_step();
+//!
+//! assert_eq!(PWMValue::max(), LEVELS[3]);
+//! assert_eq!(Step::new(-2047), NUDGE);
+//! ```
+
+/// The resolution of a PWM value in bits.  The TLC5947 takes 12 bits per
+/// channel.
+pub const PWM_BITS: u8 = 12;
 
 /// The PWM_MASK allows us to "mask" the extra bits in a 16 bit integer, which
 /// is what we're using to store the PWM state.  Since this is used internally,
 /// to clamp values to a valid 12-bit number, we don't need to export it.
-pub const PWM_MASK: u16 = 0x0fff;
+pub const PWM_MASK: u16 = (1 << PWM_BITS) - 1;
 
 /// The PWM value is a number between 0 and the maximum 12-bit value.  As an
-/// invariant, the PWM value can never be below 0 or above 4095.
-#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+/// invariant, the PWM value can never be below 0 or above 4095.  Values are
+/// ordered by brightness and can be hashed, so tables of them can be sorted,
+/// searched and deduplicated.
+///
+/// ```
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// let mut levels = [PWMValue::new(900), PWMValue::max(), PWMValue::new(40)];
+/// levels.sort();
+/// assert_eq!(Ok(1), levels.binary_search(&PWMValue::new(900)));
+/// assert_eq!(PWMValue::max(), levels[2]);
+/// ```
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PWMValue {
     raw: i16,
 }
 
 /// The range error is returned when the math around PWM values either falls
 /// below zero or above the max 12-bit value.  It also applies to steps, where
-/// the resulting step is below -4095 or above 4095.
-#[derive(PartialOrd, PartialEq, Debug)]
+/// the resulting step is below -4095 or above 4095, and to slices of values
+/// that are the wrong length.  Each variant carries the value that was worked
+/// out and the limit it went past, so a clamping bug can be tracked down from
+/// the error alone.
+///
+/// ```
+/// use ledpwm5947::pwm::{PWMValue, RangeError, Step};
+///
+/// let error = (PWMValue::new(4000) + Step::new(200)).unwrap_err();
+/// assert_eq!(RangeError::Overflow { value: 4200, max: 4095 }, error);
+/// assert_eq!("4200 is above the maximum of 4095", format!("{}", error));
+/// ```
+#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RangeError {
-    Underflow,
-    Overflow,
+    /// The value was below the minimum.
+    Underflow { value: i32, min: i32 },
+    /// The value was above the maximum.
+    Overflow { value: i32, max: i32 },
+    /// A slice had `len` values where `expected` were needed.
+    Length { len: usize, expected: usize },
+    /// A scale had a denominator of zero.
+    DivideByZero,
 }
 
 /// A step is a fixed amount that can be added to a PWM value to change its value.
 /// The specific invariant is that the step can never be less than -4095 or above
 /// 4095.
-#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Step {
     amount: i16,
 }
 
-/// This is the set of masks we'll use to check if the bit on a 12-bit number is
-/// 1 or 0.  Since this is internal to our implementation, we don't need to export
-/// it.  Preferred to have the masks in an array for easier iteration.
-const PWM_BIT_MASKS: [u16; 12] = [
-    0x0800_u16, 0x0400_u16, 0x0200_u16, 0x0100_u16, 0x0080_u16, 0x0040_u16, 0x0020_u16, 0x0010_u16,
-    0x0008_u16, 0x0004_u16, 0x0002_u16, 0x0001_u16,
-];
+/// The steps for a fade from one PWM value to another over a fixed number of
+/// frames.  Each frame gets the whole part of the per-frame step, and the
+/// remainder is spread across the frames so the fade lands exactly on the
+/// target value on the last frame.
+#[derive(Clone, Debug)]
+pub struct FadeSteps {
+    delta: i32,
+    frames: u32,
+    frame: u32,
+}
+
+/// A run of PWM values with a fixed step between them, from `range_to` or
+/// `sweep`.  It can be walked from either end, and it never changes the
+/// value it was made from.
+#[derive(Clone, Debug)]
+pub struct PWMRange {
+    start: i32,
+    step: i32,
+    front: u32,
+    back: u32,
+}
+
+/// An 8-bit PWM value, for storing values compactly or for chips with 8-bit
+/// channels.  Converting to a 12-bit `PWMValue` and back gives the same value,
+/// and 255 converts to the 12-bit maximum.
+///
+/// ```
+/// use ledpwm5947::pwm::{PWMValue, PWMValue8};
+///
+/// for raw in 0..=255 {
+///     let compact = PWMValue8::new(raw);
+///     assert_eq!(compact, PWMValue8::from(PWMValue::from(compact)));
+/// }
+/// assert_eq!(PWMValue::max(), PWMValue::from(PWMValue8::max()));
+/// ```
+#[derive(Copy, Clone, PartialOrd, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PWMValue8 {
+    raw: u8,
+}
+
+/// The shape of a change from one value to another.  A linear change moves by
+/// the same amount each step, which tends to look mechanical.  The quadratic
+/// curves start slow and speed up, or start fast and slow down.  The sine
+/// curve starts slow, speeds up through the middle, and slows down again at
+/// the end, which looks the most natural for breathing and pulsing lights.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    Sine,
+}
+
+/// The number of fractional bits used for the progress through a change.
+const PROGRESS_SHIFT: u32 = 16;
+
+impl Easing {
+    /// Applies the curve to the progress through a change, where both the
+    /// progress and the result have 16 fractional bits.
+    fn apply(&self, progress: u64) -> u64 {
+        let one = 1 << PROGRESS_SHIFT;
+        match self {
+            Easing::Linear => progress,
+            Easing::QuadIn => (progress * progress) >> PROGRESS_SHIFT,
+            Easing::QuadOut => {
+                let remaining = one - progress;
+                one - ((remaining * remaining) >> PROGRESS_SHIFT)
+            }
+            Easing::Sine => {
+                // The curve is (1 - cos(πt)) / 2.  Bhaskara's approximation
+                // gives the cosine for the first half within 0.1%, and the
+                // second half mirrors it, so the middle lands exactly halfway.
+                let ease_in = |t: u64| {
+                    let squared = (t * t) >> PROGRESS_SHIFT;
+                    let cosine = ((one - 4 * squared) << PROGRESS_SHIFT) / (one + squared);
+                    (one - cosine) / 2
+                };
+                if progress <= one / 2 {
+                    ease_in(progress)
+                } else {
+                    one - ease_in(one - progress)
+                }
+            }
+        }
+    }
+}
 
 impl Step {
     /// Create a new Step from a raw numeric value.  The step will be clamped to
@@ -67,7 +203,7 @@ impl Step {
     ///
     /// assert_eq!(too_large_step, max_step);
     /// ```
-    pub fn new(amount: i32) -> Self {
+    pub const fn new(amount: i32) -> Self {
         if amount > PWM_MASK as i32 {
             Step {
                 amount: PWM_MASK as i16,
@@ -94,7 +230,7 @@ impl Step {
     ///
     /// assert_eq!(Step::new(-10), reversed);
     /// ```
-    pub fn reverse(&self) -> Self {
+    pub const fn reverse(&self) -> Self {
         Step {
             amount: -self.amount,
         }
@@ -113,24 +249,39 @@ impl Step {
     ///
     /// let normal_step = Step::checked_new(100).expect("The value indicates a valid step");
     /// if let Err(v) = Step::checked_new(5000) {
-    ///     assert_eq!(v, RangeError::Overflow);
+    ///     assert_eq!(v, RangeError::Overflow { value: 5000, max: 4095 });
     /// } else {
     ///     assert!(false, "Should have returned an error");
     /// }
     ///
     /// if let Err(v) = Step::checked_new(-5000) {
-    ///     assert_eq!(v, RangeError::Underflow)
+    ///     assert_eq!(v, RangeError::Underflow { value: -5000, min: -4095 })
     /// } else {
     ///     assert!(false, "Should have returned an error");
     /// }
     /// ```
-    pub fn checked_new(amount: i16) -> Result<Self, RangeError> {
-        if amount < -4095 {
-            Err(RangeError::Underflow)
-        } else if amount > 4095 as i16 {
-            Err(RangeError::Overflow)
+    pub const fn checked_new(amount: i16) -> Result<Self, RangeError> {
+        Step::checked_from_i32(amount as i32)
+    }
+
+    /// The checked constructor the arithmetic goes through.  The sums and
+    /// products are worked out in `i32`, so they can't overflow on the way,
+    /// and only a result that's in range is narrowed back down.
+    const fn checked_from_i32(amount: i32) -> Result<Self, RangeError> {
+        if amount < -(PWM_MASK as i32) {
+            Err(RangeError::Underflow {
+                value: amount,
+                min: -(PWM_MASK as i32),
+            })
+        } else if amount > PWM_MASK as i32 {
+            Err(RangeError::Overflow {
+                value: amount,
+                max: PWM_MASK as i32,
+            })
         } else {
-            Ok(Step { amount })
+            Ok(Step {
+                amount: amount as i16,
+            })
         }
     }
 
@@ -144,7 +295,7 @@ impl Step {
     ///
     /// let big_step = Step::new(3000);
     /// if let Err(v) = big_step.double() {
-    ///     assert_eq!(RangeError::Overflow, v);
+    ///     assert_eq!(RangeError::Overflow { value: 6000, max: 4095 }, v);
     /// } else {
     ///     assert!(false, "Should have returned an error");
     /// }
@@ -165,7 +316,7 @@ impl Step {
     ///
     /// assert_eq!(half_step, step.half_step())
     /// ```
-    pub fn half_step(&self) -> Self {
+    pub const fn half_step(&self) -> Self {
         Step {
             amount: self.amount / 2,
         }
@@ -183,7 +334,7 @@ impl Step {
     /// let step = Step::new(22);
     /// assert_eq!(Step::new(5), step.quarter_step());
     /// ```
-    pub fn quarter_step(&self) -> Self {
+    pub const fn quarter_step(&self) -> Self {
         Step {
             amount: self.amount / 4,
         }
@@ -201,7 +352,7 @@ impl Step {
     /// let step = Step::new(255);
     /// assert_eq!(Step::new(31), step.eighth_step());
     /// ```
-    pub fn eighth_step(&self) -> Self {
+    pub const fn eighth_step(&self) -> Self {
         Step {
             amount: self.amount / 8,
         }
@@ -219,13 +370,242 @@ impl Step {
     /// let step = Step::new(255);
     /// assert_eq!(Step::new(15), step.sixteenth_step());
     /// ```
-    pub fn sixteenth_step(&self) -> Self {
+    pub const fn sixteenth_step(&self) -> Self {
         Step {
             amount: self.amount / 16,
         }
     }
+
+    /// Scales the step by `numerator / denominator`.  It uses integer
+    /// arithmetic, so like the fixed fractions the remainder is truncated.
+    /// Returns a range error if the scaled step is out of range, or
+    /// `DivideByZero` for a denominator of zero.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{RangeError, Step};
+    ///
+    /// let step = Step::new(100);
+    /// assert_eq!(Ok(Step::new(150)), step.scale(3, 2));
+    /// assert_eq!(Ok(Step::new(-33)), step.scale(-1, 3));
+    /// let over = |value| Err(RangeError::Overflow { value, max: 4095 });
+    /// assert_eq!(over(5000), step.scale(50, 1));
+    /// assert_eq!(Err(RangeError::DivideByZero), step.scale(1, 0));
+    /// assert_eq!(
+    ///     Err(RangeError::Underflow { value: -5000, min: -4095 }),
+    ///     step.scale(50, -1)
+    /// );
+    /// ```
+    pub fn scale(&self, numerator: i16, denominator: i16) -> Result<Self, RangeError> {
+        if denominator == 0 {
+            return Err(RangeError::DivideByZero);
+        }
+
+        Step::checked_from_i32(self.amount as i32 * numerator as i32 / denominator as i32)
+    }
+
+    /// Creates a step that is a fraction of the full PWM range.  The result is
+    /// rounded to the nearest whole step and clamped like `new`.  Since the
+    /// fraction doesn't depend on the number of bits, fade code written this
+    /// way doesn't need to change if the PWM resolution does.  A denominator
+    /// of zero is treated as the full range.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::Step;
+    ///
+    /// assert_eq!(Step::new(16), Step::from_fraction_of_range(1, 256));
+    /// assert_eq!(Step::new(2048), Step::from_fraction_of_range(1, 2));
+    /// assert_eq!(Step::new(4095), Step::from_fraction_of_range(3, 2));
+    /// ```
+    pub fn from_fraction_of_range(numerator: u32, denominator: u32) -> Self {
+        if denominator == 0 {
+            return Step::new(PWM_MASK as i32);
+        }
+
+        let scaled =
+            (PWM_MASK as u64 * numerator as u64 + denominator as u64 / 2) / denominator as u64;
+        if scaled > PWM_MASK as u64 {
+            Step::new(PWM_MASK as i32)
+        } else {
+            Step::new(scaled as i32)
+        }
+    }
+
+    /// Creates a step that is a fraction of the span between two PWM values.
+    /// The step is negative if `to` is below `from`, so adding it moves the
+    /// value from `from` toward `to`.  The result is rounded to the nearest
+    /// whole step, away from zero on a tie.  A denominator of zero is treated
+    /// as the whole span.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// let from = PWMValue::new(100);
+    /// let to = PWMValue::new(200);
+    ///
+    /// assert_eq!(Step::new(25), Step::from_fraction_of_span(from, to, 1, 4));
+    /// assert_eq!(Step::new(-25), Step::from_fraction_of_span(to, from, 1, 4));
+    /// ```
+    pub fn from_fraction_of_span(
+        from: PWMValue,
+        to: PWMValue,
+        numerator: u32,
+        denominator: u32,
+    ) -> Self {
+        let span = to.raw as i64 - from.raw as i64;
+        if denominator == 0 {
+            return Step::new(span as i32);
+        }
+
+        let scaled = span * numerator as i64;
+        let half = denominator as i64 / 2;
+        let rounded = if scaled < 0 {
+            (scaled - half) / denominator as i64
+        } else {
+            (scaled + half) / denominator as i64
+        };
+        Step::new(rounded.clamp(-(PWM_MASK as i64), PWM_MASK as i64) as i32)
+    }
+
+    /// Creates a step that is `percent` percent of the value, for fades that
+    /// look even to the eye.  The eye notices the ratio between brightnesses
+    /// rather than the difference, so steps of a fixed size crawl at the dim
+    /// end and jump at the bright end.  Stepping by the same percentage of
+    /// the current value each frame gives an exponential fade instead, with
+    /// small steps when the LED is dim and large ones when it's bright.
+    ///
+    /// The step is rounded to the nearest whole step, but is never less than
+    /// one unless `percent` is zero, so a fade up can start from off.  It's
+    /// always positive, so subtract it or `reverse` it to fade down.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// assert_eq!(Step::new(1), Step::proportional_of(PWMValue::new(4), 10));
+    /// assert_eq!(Step::new(400), Step::proportional_of(PWMValue::new(4000), 10));
+    ///
+    /// let mut value = PWMValue::min();
+    /// let mut frames = 0;
+    /// while value < PWMValue::max() {
+    ///     value += Step::proportional_of(value, 10);
+    ///     frames += 1;
+    /// }
+    /// assert!(frames < 100);
+    /// ```
+    pub fn proportional_of(value: PWMValue, percent: u8) -> Self {
+        if percent == 0 {
+            return Step::new(0);
+        }
+
+        let scaled = (value.raw as i32 * percent as i32 + 50) / 100;
+        Step::new(scaled.max(1))
+    }
+
+    /// Computes the steps for a fade from `from` to `to` lasting `duration_ms`
+    /// milliseconds at a refresh rate of `fps` frames per second.  Rather than
+    /// a single step, which would drift because of integer truncation, it
+    /// returns the steps for each frame.  There is always at least one frame,
+    /// so a zero duration or frame rate jumps straight to the target.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// let from = PWMValue::new(0);
+    /// let to = PWMValue::new(100);
+    ///
+    /// let steps = Step::for_fade(from, to, 1000, 30);
+    /// assert_eq!(30, steps.len());
+    ///
+    /// let mut value = from;
+    /// for step in steps {
+    ///     value = (value + step).expect("It should stay in range");
+    /// }
+    /// assert_eq!(to, value);
+    /// ```
+    pub fn for_fade(from: PWMValue, to: PWMValue, duration_ms: u32, fps: u32) -> FadeSteps {
+        let frames = (duration_ms as u64 * fps as u64 / 1000).clamp(1, u32::MAX as u64) as u32;
+        FadeSteps {
+            delta: to.raw as i32 - from.raw as i32,
+            frames,
+            frame: 0,
+        }
+    }
+}
+
+impl FadeSteps {
+    /// The total number of frames in the fade.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// How far the fade has travelled after `frame` frames.  Working from the
+    /// total distance each time, instead of summing steps, is what keeps the
+    /// truncation error from accumulating.
+    fn travelled(&self, frame: u32) -> i32 {
+        (self.delta as i64 * frame as i64 / self.frames as i64) as i32
+    }
+}
+
+impl Iterator for FadeSteps {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        if self.frame >= self.frames {
+            return None;
+        }
+
+        let amount = self.travelled(self.frame + 1) - self.travelled(self.frame);
+        self.frame += 1;
+        Some(Step::new(amount))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.frames - self.frame) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for FadeSteps {}
+
+impl PWMRange {
+    fn value(&self, index: u32) -> PWMValue {
+        PWMValue {
+            raw: (self.start + index as i32 * self.step) as i16,
+        }
+    }
+}
+
+impl Iterator for PWMRange {
+    type Item = PWMValue;
+
+    fn next(&mut self) -> Option<PWMValue> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        let value = self.value(self.front);
+        self.front += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.back - self.front) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl DoubleEndedIterator for PWMRange {
+    fn next_back(&mut self) -> Option<PWMValue> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        Some(self.value(self.back))
+    }
 }
 
+impl ExactSizeIterator for PWMRange {}
+
 impl core::ops::Add for Step {
     type Output = Result<Self, RangeError>;
 
@@ -245,30 +625,13 @@ impl core::ops::Add for Step {
     /// let step2 = Step::new(2000);
     ///
     /// if let Err(v) = step1 + step2 {
-    ///     assert_eq!(RangeError::Overflow, v);
+    ///     assert_eq!(RangeError::Overflow { value: 6000, max: 4095 }, v);
     /// } else {
     ///     assert!(false, "Should have returned an error");
     /// }
     /// ```
     fn add(self, rhs: Step) -> Self::Output {
-        let computed_value = self.amount + rhs.amount;
-        if computed_value < 0 {
-            if -computed_value > PWM_MASK as i16 {
-                Err(RangeError::Underflow)
-            } else {
-                Ok(Step {
-                    amount: computed_value,
-                })
-            }
-        } else {
-            if computed_value > PWM_MASK as i16 {
-                Err(RangeError::Overflow)
-            } else {
-                Ok(Step {
-                    amount: computed_value,
-                })
-            }
-        }
+        Step::checked_from_i32(self.amount as i32 + rhs.amount as i32)
     }
 }
 
@@ -291,22 +654,33 @@ impl core::ops::Sub for Step {
     /// let step3 = step1 - step2;
     ///
     /// if let Err(v) = step1 - step2 {
-    ///     assert_eq!(v, RangeError::Underflow);
+    ///     assert_eq!(v, RangeError::Underflow { value: -5000, min: -4095 });
     /// } else {
     ///     assert!(false, "It should have raised an error");
     /// }
     /// ```
     fn sub(self, rhs: Step) -> Self::Output {
-        let computed_value = self.amount - rhs.amount;
-        if computed_value < -4095 {
-            Err(RangeError::Underflow)
-        } else if computed_value > 4095 {
-            Err(RangeError::Overflow)
-        } else {
-            Ok(Step {
-                amount: computed_value,
-            })
-        }
+        Step::checked_from_i32(self.amount as i32 - rhs.amount as i32)
+    }
+}
+
+impl core::ops::Mul<i16> for Step {
+    type Output = Result<Self, RangeError>;
+
+    /// Multiplies a step by a whole factor.  The product can overflow or
+    /// underflow the same as a sum.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{RangeError, Step};
+    ///
+    /// assert_eq!(Ok(Step::new(-30)), Step::new(10) * -3);
+    /// assert_eq!(
+    ///     Err(RangeError::Overflow { value: 6000, max: 4095 }),
+    ///     Step::new(2000) * 3
+    /// );
+    /// ```
+    fn mul(self, rhs: i16) -> Self::Output {
+        self.scale(rhs, 1)
     }
 }
 
@@ -326,7 +700,7 @@ impl PWMValue {
     /// let p1 = PWMValue::new(-5000);
     /// assert_eq!(PWMValue::min(), p1);
     /// ```
-    pub fn new(v: i32) -> Self {
+    pub const fn new(v: i32) -> Self {
         if v > PWM_MASK as i32 {
             PWMValue::max()
         } else if v < 0 {
@@ -350,7 +724,7 @@ impl PWMValue {
     /// assert!(p2 == min, "Zero should be also min");
     /// assert!(p3 == min, "-1 should also clamp to min");
     /// ```
-    pub fn min() -> Self {
+    pub const fn min() -> Self {
         PWMValue { raw: 0 }
     }
 
@@ -368,18 +742,695 @@ impl PWMValue {
     /// assert!(p2 == max, "4095 should be the max for 12 bit");
     /// assert!(p3 == max, "4096 should clamp to max");
     /// ```
-    pub fn max() -> Self {
+    pub const fn max() -> Self {
         PWMValue { raw: 0x0FFF }
     }
 
-    pub(crate) fn bits(&self) -> [bool; 12] {
-        let mut result: [bool; 12] = [false; 12];
+    /// Returns the PWM value for a percentage of full scale, rounded to the
+    /// nearest value.  Percentages over 100 are clamped to max.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::new(2048), PWMValue::from_percent(50));
+    /// assert_eq!(PWMValue::max(), PWMValue::from_percent(100));
+    /// assert_eq!(PWMValue::max(), PWMValue::from_percent(150));
+    /// ```
+    pub const fn from_percent(percent: u8) -> Self {
+        PWMValue::from_fraction(percent as u16, 100)
+    }
+
+    /// Returns the PWM value for a fraction of full scale, rounded to the
+    /// nearest value.  Fractions over one are clamped to max, and like
+    /// `Step::from_fraction_of_range`, a denominator of zero gives max.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::new(1365), PWMValue::from_fraction(1, 3));
+    /// assert_eq!(PWMValue::min(), PWMValue::from_fraction(0, 7));
+    /// assert_eq!(PWMValue::max(), PWMValue::from_fraction(1, 0));
+    /// ```
+    pub const fn from_fraction(numerator: u16, denominator: u16) -> Self {
+        if denominator == 0 || numerator >= denominator {
+            return PWMValue::max();
+        }
+
+        let scaled =
+            (PWM_MASK as u32 * numerator as u32 + denominator as u32 / 2) / denominator as u32;
+        PWMValue::new(scaled as i32)
+    }
+
+    /// The value as a whole percentage of full scale, rounded to the nearest
+    /// percent.  Use `percent` for tenths of a percent.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(50, PWMValue::from_percent(50).as_percent());
+    /// assert_eq!(0, PWMValue::new(20).as_percent());
+    /// assert_eq!(1, PWMValue::new(21).as_percent());
+    /// ```
+    pub const fn as_percent(&self) -> u8 {
+        ((self.raw as u32 * 100 + PWM_MASK as u32 / 2) / PWM_MASK as u32) as u8
+    }
+
+    /// Returns the PWM value for an 8-bit brightness, corrected with the usual
+    /// gamma of 2.2 so that equal steps in brightness look equal.  Unlike the
+    /// `From<u8>` conversion, which scales linearly, half brightness is much
+    /// less than half the maximum PWM value.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::min(), PWMValue::from_gamma(0));
+    /// assert_eq!(PWMValue::max(), PWMValue::from_gamma(255));
+    /// assert!(PWMValue::from_gamma(128) < PWMValue::from(128));
+    /// ```
+    pub fn from_gamma(brightness: u8) -> Self {
+        crate::gamma::GAMMA_2_2.correct(brightness)
+    }
+
+    /// Converts an 8-bit brightness into a PWM value along the CIE 1931
+    /// lightness curve, an alternative to `from_gamma` that's a little
+    /// brighter in the dimmest steps.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::min(), PWMValue::from_lightness(0));
+    /// assert_eq!(PWMValue::max(), PWMValue::from_lightness(255));
+    /// assert_eq!(PWMValue::new(2), PWMValue::from_lightness(1));
+    /// assert!(PWMValue::from_lightness(128) < PWMValue::from(128));
+    /// ```
+    pub fn from_lightness(brightness: u8) -> Self {
+        crate::gamma::CIE_LIGHTNESS.correct(brightness)
+    }
 
-        for i in 0..12 {
-            result[i] = PWM_BIT_MASKS[i] & (self.raw as u16) > 0;
+    /// Returns the value part way from one value to another, following the
+    /// easing curve.  How far along is given as the fraction `t_num / t_den`,
+    /// which is clamped to between zero and one, so a denominator of zero
+    /// gives the end value.  Each value is worked out from the two ends, so
+    /// the rounding errors don't add up over a fade.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{Easing, PWMValue};
+    ///
+    /// let from = PWMValue::min();
+    /// let to = PWMValue::new(1000);
+    ///
+    /// assert_eq!(PWMValue::new(500), PWMValue::interpolate(from, to, 1, 2, Easing::Linear));
+    /// assert_eq!(PWMValue::new(250), PWMValue::interpolate(from, to, 1, 2, Easing::QuadIn));
+    /// assert_eq!(PWMValue::new(500), PWMValue::interpolate(from, to, 1, 2, Easing::Sine));
+    /// assert_eq!(to, PWMValue::interpolate(from, to, 3, 2, Easing::Sine));
+    /// ```
+    pub fn interpolate(
+        from: PWMValue,
+        to: PWMValue,
+        t_num: u32,
+        t_den: u32,
+        easing: Easing,
+    ) -> PWMValue {
+        if t_num >= t_den {
+            return to;
         }
 
-        result
+        let progress = ((t_num as u64) << PROGRESS_SHIFT) / t_den as u64;
+        let eased = easing.apply(progress) as i64;
+
+        let start = from.raw as i64;
+        let delta = to.raw as i64 - start;
+        let half = 1 << (PROGRESS_SHIFT - 1);
+        let offset = if delta < 0 {
+            -((-delta * eased + half) >> PROGRESS_SHIFT)
+        } else {
+            (delta * eased + half) >> PROGRESS_SHIFT
+        };
+
+        PWMValue::new((start + offset) as i32)
+    }
+
+    /// Moves the value toward `target` by however far it should have gone in
+    /// `elapsed_ticks`, at `rate_per_1024_ticks` PWM units every 1024 ticks,
+    /// and stops at the target.  A fixed step each time round the main loop
+    /// runs fast when the loop does and slow when it doesn't, but passing the
+    /// ticks since the last call keeps the fade at the same speed however
+    /// long each pass takes.
+    ///
+    /// The part of a unit left over is kept in `carry`, in 1024ths of a unit,
+    /// and added in on the next call, so many short calls move exactly as far
+    /// as one long one, even at rates too slow to move a whole unit a call.
+    /// Keep one carry for each fading value, starting at zero.  It goes back
+    /// to zero when the value reaches the target.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// // 4096 units a second, with the ticks in milliseconds.
+    /// let rate = 4096 * 1024 / 1000;
+    /// let target = PWMValue::max();
+    ///
+    /// let mut value = PWMValue::min();
+    /// let mut carry = 0;
+    /// for elapsed_ms in [10, 250, 3, 100] {
+    ///     value = value.advance_toward(target, rate, elapsed_ms, &mut carry);
+    /// }
+    /// assert_eq!(PWMValue::new(1486), value);
+    ///
+    /// value = value.advance_toward(target, rate, 5000, &mut carry);
+    /// assert_eq!(target, value);
+    /// assert_eq!(0, carry);
+    /// ```
+    pub fn advance_toward(
+        self,
+        target: PWMValue,
+        rate_per_1024_ticks: u32,
+        elapsed_ticks: u32,
+        carry: &mut u32,
+    ) -> PWMValue {
+        let total = rate_per_1024_ticks as u64 * elapsed_ticks as u64 + *carry as u64;
+        let distance = total / 1024;
+        let gap = (target.raw as i32 - self.raw as i32).unsigned_abs() as u64;
+        if distance >= gap {
+            *carry = 0;
+            return target;
+        }
+
+        *carry = (total % 1024) as u32;
+        if target.raw > self.raw {
+            PWMValue::new(self.raw as i32 + distance as i32)
+        } else {
+            PWMValue::new(self.raw as i32 - distance as i32)
+        }
+    }
+
+    /// Steps the value, stopping at min or max instead of failing.  This is
+    /// the easy choice for loops that run a light up to full and hold it.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// assert_eq!(PWMValue::max(), PWMValue::new(4000).saturating_add(Step::new(200)));
+    /// assert_eq!(PWMValue::min(), PWMValue::new(100).saturating_add(Step::new(-200)));
+    /// ```
+    pub fn saturating_add(self, step: Step) -> PWMValue {
+        PWMValue::new(self.raw as i32 + step.amount as i32)
+    }
+
+    /// Steps the value, wrapping around past max back to min, and past min
+    /// to max.  The value wraps at 4096, the same as the lowest 12 bits of a
+    /// plain integer.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// assert_eq!(PWMValue::new(104), PWMValue::new(4000).wrapping_add(Step::new(200)));
+    /// assert_eq!(PWMValue::new(3996), PWMValue::new(100).wrapping_add(Step::new(-200)));
+    /// ```
+    pub fn wrapping_add(self, step: Step) -> PWMValue {
+        let wrapped = (self.raw as i32 + step.amount as i32).rem_euclid(PWM_MASK as i32 + 1);
+        PWMValue {
+            raw: wrapped as i16,
+        }
+    }
+
+    /// Steps the value, returning `None` if it would go past min or max.  This
+    /// is the same as adding the step, without saying which way it failed.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// assert_eq!(Some(PWMValue::new(110)), PWMValue::new(100).checked_add(Step::new(10)));
+    /// assert_eq!(None, PWMValue::max().checked_add(Step::new(1)));
+    /// ```
+    pub fn checked_add(self, step: Step) -> Option<PWMValue> {
+        (self + step).ok()
+    }
+
+    pub(crate) const fn value(&self) -> i32 {
+        self.raw as i32
+    }
+
+    /// The raw 12-bit value, from 0 to 4095, for handing to code that works in
+    /// plain numbers.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(4095, PWMValue::max().as_u16());
+    /// assert_eq!(1234_u16, PWMValue::new(1234).into());
+    /// ```
+    pub const fn as_u16(&self) -> u16 {
+        self.raw as u16
+    }
+
+    /// Creates a PWM value from a raw number, or a range error if it's above
+    /// 4095.  `new` clamps instead.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, RangeError};
+    ///
+    /// assert_eq!(Ok(PWMValue::max()), PWMValue::checked_new(4095));
+    /// assert_eq!(
+    ///     Err(RangeError::Overflow { value: 4096, max: 4095 }),
+    ///     PWMValue::checked_new(4096)
+    /// );
+    /// ```
+    pub const fn checked_new(v: u16) -> Result<Self, RangeError> {
+        if v > PWM_MASK {
+            Err(RangeError::Overflow {
+                value: v as i32,
+                max: PWM_MASK as i32,
+            })
+        } else {
+            Ok(PWMValue { raw: v as i16 })
+        }
+    }
+
+    /// Converts a value from another resolution, such as a 10-bit ADC reading
+    /// or a 16-bit channel.  Lower resolutions are scaled up by repeating the
+    /// high bits in the new low bits, so the maximum maps to the maximum.
+    /// Higher resolutions drop the extra low bits.  Any bits above the given
+    /// resolution are ignored, and a resolution of zero or above 16 gives min.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::max(), PWMValue::from_resolution(0x3ff, 10));
+    /// assert_eq!(PWMValue::new(0x802), PWMValue::from_resolution(0x200, 10));
+    /// assert_eq!(PWMValue::new(0xabc), PWMValue::from_resolution(0xabcd, 16));
+    /// ```
+    pub fn from_resolution(value: u16, bits: u8) -> Self {
+        if bits == 0 || bits > 16 {
+            return PWMValue::min();
+        }
+
+        let value = (value as u32) & ((1_u32 << bits) - 1);
+        PWMValue {
+            raw: rescale(value, bits, PWM_BITS) as i16,
+        }
+    }
+
+    /// Converts the value to another resolution, the reverse of
+    /// `from_resolution`.  The TLC59711 takes 16 bits per channel, for
+    /// example.  A resolution of zero or above 16 gives zero.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(0xffff, PWMValue::max().to_resolution(16));
+    /// assert_eq!(0xabca, PWMValue::new(0xabc).to_resolution(16));
+    /// assert_eq!(0xab, PWMValue::new(0xabc).to_resolution(8));
+    /// ```
+    pub fn to_resolution(&self, bits: u8) -> u16 {
+        if bits == 0 || bits > 16 {
+            return 0;
+        }
+
+        rescale(self.raw as u32, PWM_BITS, bits) as u16
+    }
+
+    /// The value as a percentage of full scale, for showing over a console.
+    pub fn percent(&self) -> Percent {
+        Percent {
+            tenths: ((self.raw as u32 * 1000 + PWM_MASK as u32 / 2) / PWM_MASK as u32) as u16,
+        }
+    }
+
+    /// Every value from this one to `end`, including both.  It counts down if
+    /// `end` is below this value.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let up = PWMValue::new(10).range_to(PWMValue::new(13));
+    /// assert_eq!(4, up.len());
+    ///
+    /// let mut down = PWMValue::new(13).range_to(PWMValue::new(10));
+    /// assert_eq!(Some(PWMValue::new(13)), down.next());
+    /// assert_eq!(Some(PWMValue::new(10)), down.next_back());
+    /// assert_eq!(Some(PWMValue::new(12)), down.next());
+    /// assert_eq!(Some(PWMValue::new(11)), down.next());
+    /// assert_eq!(None, down.next());
+    /// ```
+    pub fn range_to(&self, end: PWMValue) -> PWMRange {
+        let start = self.raw as i32;
+        let distance = end.raw as i32 - start;
+        PWMRange {
+            start,
+            step: distance.signum(),
+            front: 0,
+            back: distance.unsigned_abs() + 1,
+        }
+    }
+
+    /// This value and then every `step` after it, for as long as the values
+    /// stay in range.  A negative step sweeps down towards zero, and a zero
+    /// step gives just this value.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::{PWMValue, Step};
+    ///
+    /// let mut sweep = PWMValue::new(4000).sweep(Step::new(-1500));
+    /// assert_eq!(Some(PWMValue::new(4000)), sweep.next());
+    /// assert_eq!(Some(PWMValue::new(2500)), sweep.next());
+    /// assert_eq!(Some(PWMValue::new(1000)), sweep.next());
+    /// assert_eq!(None, sweep.next());
+    ///
+    /// let last = PWMValue::new(100).sweep(Step::new(1000)).next_back();
+    /// assert_eq!(Some(PWMValue::new(3100)), last);
+    /// ```
+    pub fn sweep(&self, step: Step) -> PWMRange {
+        let start = self.raw as i32;
+        let step = step.amount as i32;
+        let room = match step.signum() {
+            1 => PWM_MASK as i32 - start,
+            -1 => start,
+            _ => 0,
+        };
+        let count = if step == 0 { 1 } else { room / step.abs() + 1 };
+        PWMRange {
+            start,
+            step,
+            front: 0,
+            back: count as u32,
+        }
+    }
+
+    /// Scales the value by `factor / 255`, rounded to the nearest value.  This
+    /// is the usual master brightness, where 255 leaves the value alone and 0
+    /// turns it off.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::new(1000), PWMValue::new(1000).scale(255));
+    /// assert_eq!(PWMValue::new(502), PWMValue::new(1000).scale(128));
+    /// assert_eq!(PWMValue::min(), PWMValue::max().scale(0));
+    /// ```
+    pub fn scale(&self, factor: u8) -> Self {
+        PWMValue {
+            raw: ((self.raw as u32 * factor as u32 + 127) / 255) as i16,
+        }
+    }
+
+    /// Mixes this value with another for a crossfade.  An `alpha` of 0 gives
+    /// this value, 255 gives the other one, and the steps in between move
+    /// evenly from one to the other.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let from = PWMValue::new(1000);
+    /// let to = PWMValue::new(3000);
+    ///
+    /// assert_eq!(from, from.blend(to, 0));
+    /// assert_eq!(PWMValue::new(2004), from.blend(to, 128));
+    /// assert_eq!(to, from.blend(to, 255));
+    /// assert_eq!(PWMValue::new(1996), to.blend(from, 128));
+    /// ```
+    pub fn blend(&self, other: PWMValue, alpha: u8) -> Self {
+        let from = self.raw as i32;
+        let span = other.raw as i32 - from;
+        let offset = span * alpha as i32;
+        let rounded = if offset < 0 {
+            (offset - 127) / 255
+        } else {
+            (offset + 127) / 255
+        };
+        PWMValue {
+            raw: (from + rounded) as i16,
+        }
+    }
+}
+
+impl core::ops::Mul for PWMValue {
+    type Output = PWMValue;
+
+    /// Multiplies two values as fractions of full scale, so a channel value
+    /// times a master brightness gives the dimmed value.  Full scale times any
+    /// value leaves it alone, and the product is rounded to the nearest value.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// let half = PWMValue::new(2048);
+    ///
+    /// assert_eq!(PWMValue::new(1000), PWMValue::new(1000) * PWMValue::max());
+    /// assert_eq!(PWMValue::new(500), PWMValue::new(1000) * half);
+    /// assert_eq!(PWMValue::min(), half * PWMValue::min());
+    /// ```
+    fn mul(self, rhs: PWMValue) -> PWMValue {
+        let product = self.raw as u32 * rhs.raw as u32;
+        PWMValue {
+            raw: ((product + PWM_MASK as u32 / 2) / PWM_MASK as u32) as i16,
+        }
+    }
+}
+
+impl core::ops::MulAssign for PWMValue {
+    fn mul_assign(&mut self, rhs: PWMValue) {
+        *self = *self * rhs;
+    }
+}
+
+/// Shows the raw 12-bit value.
+///
+/// ```
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// assert_eq!("2048", format!("{}", PWMValue::new(2048)));
+/// ```
+impl core::fmt::Display for PWMValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// Shows the step with its sign, so a step up is easy to tell from a value.
+///
+/// ```
+/// use ledpwm5947::pwm::Step;
+///
+/// assert_eq!("+16", format!("{}", Step::new(16)));
+/// assert_eq!("-16", format!("{}", Step::new(-16)));
+/// ```
+impl core::fmt::Display for Step {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:+}", self.amount)
+    }
+}
+
+/// A PWM value as a percentage of full scale, for showing to people.  It's
+/// kept to a tenth of a percent.
+///
+/// ```
+/// use ledpwm5947::pwm::PWMValue;
+///
+/// assert_eq!("50.0%", format!("{}", PWMValue::new(2048).percent()));
+/// assert_eq!("100.0%", format!("{}", PWMValue::max().percent()));
+/// assert_eq!(1, PWMValue::new(3).percent().tenths());
+/// ```
+#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Percent {
+    tenths: u16,
+}
+
+impl Percent {
+    /// The percentage in tenths of a percent, so 1000 is full scale.
+    pub fn tenths(&self) -> u16 {
+        self.tenths
+    }
+}
+
+impl core::fmt::Display for Percent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}%", self.tenths / 10, self.tenths % 10)
+    }
+}
+
+impl core::fmt::Display for RangeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RangeError::Underflow { value, min } => {
+                write!(f, "{} is below the minimum of {}", value, min)
+            }
+            RangeError::Overflow { value, max } => {
+                write!(f, "{} is above the maximum of {}", value, max)
+            }
+            RangeError::Length { len, expected } => {
+                write!(f, "{} values where {} were expected", len, expected)
+            }
+            RangeError::DivideByZero => f.write_str("Divided by zero"),
+        }
+    }
+}
+
+impl core::error::Error for RangeError {}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for PWMValue {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(f, "{}", self.raw)
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for Percent {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(f, "{}.{}%", self.tenths / 10, self.tenths % 10)
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for Step {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        if self.amount >= 0 {
+            f.write_str("+")?;
+        }
+        ufmt::uwrite!(f, "{}", self.amount)
+    }
+}
+
+/// Changes the resolution of a value by dropping low bits, or by repeating the
+/// high bits in the new low bits.
+fn rescale(value: u32, from: u8, to: u8) -> u32 {
+    if to <= from {
+        return value >> (from - to);
+    }
+
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        let shift = to as i32 - filled as i32 - from as i32;
+        result |= if shift >= 0 {
+            value << shift
+        } else {
+            value >> -shift
+        };
+        filled += from;
+    }
+    result
+}
+
+impl PWMValue8 {
+    /// Create an 8-bit PWM value.
+    pub const fn new(v: u8) -> Self {
+        PWMValue8 { raw: v }
+    }
+
+    /// Returns the minimum 8-bit setting, zero.
+    pub const fn min() -> Self {
+        PWMValue8 { raw: 0 }
+    }
+
+    /// Returns the maximum 8-bit setting, 255.
+    pub const fn max() -> Self {
+        PWMValue8 { raw: 0xff }
+    }
+
+    /// The raw 8-bit value.
+    pub const fn as_u8(&self) -> u8 {
+        self.raw
+    }
+}
+
+impl From<PWMValue8> for PWMValue {
+    fn from(val: PWMValue8) -> Self {
+        PWMValue::from(val.raw)
+    }
+}
+
+/// Drops the low four bits.  This undoes the conversion from 8 bits, but a
+/// 12-bit value between two 8-bit steps rounds down.
+impl From<PWMValue> for PWMValue8 {
+    fn from(val: PWMValue) -> Self {
+        PWMValue8 {
+            raw: (val.raw >> 4) as u8,
+        }
+    }
+}
+
+impl core::fmt::Display for PWMValue8 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// A PWM value is stored as its raw 12-bit number.  Like `new`, reading a value
+/// back clamps it, so a corrupt preset can't break the invariant.
+#[cfg(feature = "serde")]
+impl serde::Serialize for PWMValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.raw as u16)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PWMValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = <u16 as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(PWMValue::new(raw as i32))
+    }
+}
+
+/// Conversions to and from `f32`, for parts with a floating point unit.  The
+/// integer versions, like `from_percent` and `from_fraction`, work the same
+/// everywhere, so these are only built with the `float` feature.
+#[cfg(feature = "float")]
+impl PWMValue {
+    /// Creates a PWM value from a fraction of full scale, from 0.0 for off to
+    /// 1.0 for full, rounded to the nearest value.  A fraction outside that
+    /// range is clamped, and `NaN` is off.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(PWMValue::new(2048), PWMValue::from_f32(0.5));
+    /// assert_eq!(PWMValue::max(), PWMValue::from_f32(1.5));
+    /// assert_eq!(PWMValue::min(), PWMValue::from_f32(f32::NAN));
+    /// ```
+    pub fn from_f32(fraction: f32) -> Self {
+        // The cast saturates, and turns NaN into 0.
+        PWMValue::new((fraction * PWM_MASK as f32 + 0.5) as i32)
+    }
+
+    /// The value as a fraction of full scale, from 0.0 to 1.0.
+    ///
+    /// ```
+    /// use ledpwm5947::pwm::PWMValue;
+    ///
+    /// assert_eq!(1.0, PWMValue::max().as_f32());
+    /// assert_eq!(PWMValue::new(1234), PWMValue::from_f32(PWMValue::new(1234).as_f32()));
+    /// ```
+    pub fn as_f32(&self) -> f32 {
+        self.raw as f32 / PWM_MASK as f32
+    }
+}
+
+/// A step is stored as its signed amount, and clamped when it's read back.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Step {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i16(self.amount)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Step {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let amount = <i16 as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(Step::new(amount as i32))
     }
 }
 
@@ -438,7 +1489,7 @@ impl core::ops::Add<Step> for PWMValue {
     /// let s1 = Step::new(500);
     ///
     /// if let Err(v) = p1 + s1 {
-    ///     assert_eq!(RangeError::Overflow, v);
+    ///     assert_eq!(RangeError::Overflow { value: 4595, max: 4095 }, v);
     /// } else {
     ///     assert!(false, "It should have raised an error");
     /// }
@@ -447,25 +1498,61 @@ impl core::ops::Add<Step> for PWMValue {
     /// let s1 = Step::new(-2500);
     ///
     /// if let Err(v) = p1 + s1 {
-    ///     assert_eq!(RangeError::Underflow, v);
+    ///     assert_eq!(RangeError::Underflow { value: -2500, min: 0 }, v);
     /// } else {
     ///     assert!(false, "It should have raised an error");
     /// }
     /// ```
     fn add(self, rhs: Step) -> Self::Output {
-        let computed_value = self.raw + rhs.amount;
+        let computed_value = self.raw as i32 + rhs.amount as i32;
         if computed_value < 0 {
-            Err(RangeError::Underflow)
-        } else if computed_value > PWM_MASK as i16 {
-            Err(RangeError::Overflow)
+            Err(RangeError::Underflow {
+                value: computed_value,
+                min: 0,
+            })
+        } else if computed_value > PWM_MASK as i32 {
+            Err(RangeError::Overflow {
+                value: computed_value,
+                max: PWM_MASK as i32,
+            })
         } else {
             Ok(PWMValue {
-                raw: computed_value,
+                raw: computed_value as i16,
             })
         }
     }
 }
 
+/// Steps the value in place, stopping at min or max the same as
+/// `saturating_add`.  It never fails, so it suits animation loops that don't
+/// want to handle a `Result` every frame.
+///
+/// ```
+/// use ledpwm5947::pwm::{PWMValue, Step};
+///
+/// let mut value = PWMValue::new(4000);
+/// value += Step::new(200);
+/// assert_eq!(PWMValue::max(), value);
+///
+/// value -= Step::new(95);
+/// assert_eq!(PWMValue::new(4000), value);
+/// ```
+impl core::ops::AddAssign<Step> for PWMValue {
+    fn add_assign(&mut self, rhs: Step) {
+        *self = self.saturating_add(rhs);
+    }
+}
+
+/// Steps the value down in place, stopping at min or max.
+impl core::ops::SubAssign<Step> for PWMValue {
+    fn sub_assign(&mut self, rhs: Step) {
+        *self = self.saturating_add(rhs.reverse());
+    }
+}
+
+/// Steps the value itself up by one each time, stopping at the maximum.
+/// Iterating a value with `for` consumes a copy and can only count up, so
+/// `range_to` and `sweep` are usually clearer.
 impl Iterator for PWMValue {
     type Item = PWMValue;
 
@@ -479,6 +1566,32 @@ impl Iterator for PWMValue {
     }
 }
 
+impl From<PWMValue> for u16 {
+    fn from(val: PWMValue) -> Self {
+        val.as_u16()
+    }
+}
+
+/// Converts a raw number the same as `checked_new`, failing above 4095.
+///
+/// ```
+/// use core::convert::TryFrom;
+/// use ledpwm5947::pwm::{PWMValue, RangeError};
+///
+/// assert_eq!(Ok(PWMValue::max()), PWMValue::try_from(4095_u16));
+/// assert_eq!(
+///     Err(RangeError::Overflow { value: 4096, max: 4095 }),
+///     PWMValue::try_from(4096_u16)
+/// );
+/// ```
+impl core::convert::TryFrom<u16> for PWMValue {
+    type Error = RangeError;
+
+    fn try_from(val: u16) -> Result<Self, Self::Error> {
+        PWMValue::checked_new(val)
+    }
+}
+
 impl From<u8> for PWMValue {
     fn from(val: u8) -> Self {
         let shifted = (val as i16) << 4;
@@ -537,6 +1650,107 @@ impl From<u8> for PWMValue {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "ufmt")]
+    #[test]
+    fn test_ufmt() {
+        struct Line {
+            bytes: [u8; 16],
+            len: usize,
+        }
+
+        impl ufmt::uWrite for Line {
+            type Error = ();
+
+            fn write_str(&mut self, s: &str) -> Result<(), ()> {
+                self.bytes[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+                self.len += s.len();
+                Ok(())
+            }
+        }
+
+        let mut line = Line {
+            bytes: [0; 16],
+            len: 0,
+        };
+        ufmt::uwrite!(
+            &mut line,
+            "{} {} {}",
+            PWMValue::new(2048),
+            PWMValue::new(2048).percent(),
+            Step::new(0)
+        )
+        .unwrap();
+        assert_eq!(b"2048 50.0% +0", &line.bytes[..line.len]);
+    }
+
+    #[test]
+    fn test_breathing_loop() {
+        let mut value = PWMValue::min();
+        let mut step = Step::new(1000);
+        let mut peaks = 0;
+        for _ in 0..20 {
+            value = value.saturating_add(step);
+            if value == PWMValue::max() || value == PWMValue::min() {
+                step = step.reverse();
+                peaks += 1;
+            }
+        }
+        assert_eq!(4, peaks);
+        assert_eq!(PWMValue::min(), value);
+    }
+
+    #[test]
+    fn test_sine_easing() {
+        let from = PWMValue::min();
+        let to = PWMValue::max();
+        let mut previous = from;
+        for t in 0..=100 {
+            let value = PWMValue::interpolate(from, to, t, 100, Easing::Sine);
+            let exact = (1.0 - cos_pi(t as f64 / 100.0)) / 2.0 * 4095.0;
+            assert!((value.raw as f64 - exact).abs() <= 5.0, "t = {}", t);
+            assert!(value >= previous);
+            previous = value;
+        }
+        assert_eq!(to, previous);
+    }
+
+    // The cosine of pi times x, by its Taylor series, since core has no
+    // floating point functions.
+    fn cos_pi(x: f64) -> f64 {
+        let angle = core::f64::consts::PI * x;
+        let mut term = 1.0;
+        let mut sum = 1.0;
+        for n in 1..30 {
+            term *= -angle * angle / ((2 * n - 1) as f64 * (2 * n) as f64);
+            sum += term;
+        }
+        sum
+    }
+
+    #[test]
+    fn test_resolution() {
+        for raw in 0..=4095 {
+            let value = PWMValue::new(raw);
+            assert_eq!(
+                value,
+                PWMValue::from_resolution(value.to_resolution(16), 16)
+            );
+            assert_eq!(
+                value,
+                PWMValue::from_resolution(value.to_resolution(12), 12)
+            );
+        }
+
+        assert_eq!(PWMValue::max(), PWMValue::from_resolution(1, 1));
+        assert_eq!(PWMValue::new(0xaaa), PWMValue::from_resolution(2, 2));
+        assert_eq!(PWMValue::new(0x924), PWMValue::from_resolution(4, 3));
+        assert_eq!(PWMValue::max(), PWMValue::from_resolution(0xff, 8));
+        assert_eq!(PWMValue::min(), PWMValue::from_resolution(0xff, 0));
+        assert_eq!(PWMValue::min(), PWMValue::from_resolution(0xff, 17));
+        assert_eq!(0, PWMValue::max().to_resolution(17));
+        assert_eq!(1, PWMValue::max().to_resolution(1));
+    }
+
     #[test]
     fn test_make_pwm() {
         let v1 = PWMValue::new(30);
@@ -587,17 +1801,219 @@ mod tests {
     }
 
     #[test]
+    fn test_extreme_steps() {
+        let top = Step::new(4095);
+        let bottom = top.reverse();
+        let over = |value| RangeError::Overflow { value, max: 4095 };
+        assert_eq!(Err(over(8190)), top + top);
+        assert_eq!(
+            Err(RangeError::Underflow {
+                value: -8190,
+                min: -4095
+            }),
+            bottom - top
+        );
+        assert_eq!(Ok(PWMValue::min()), PWMValue::max() + bottom);
+        assert_eq!(
+            Err(RangeError::Underflow {
+                value: -4095,
+                min: 0
+            }),
+            PWMValue::min() + bottom
+        );
+        assert_eq!(Err(over(4095 * i16::MAX as i32)), top.scale(i16::MAX, 1));
+
+        let mut value = PWMValue::min();
+        value -= bottom;
+        value += top;
+        assert_eq!(PWMValue::max(), value);
+        value -= top;
+        value -= top;
+        assert_eq!(PWMValue::min(), value);
+    }
+
+    #[test]
+    fn test_total_order() {
+        extern crate std;
+        use std::collections::{BTreeSet, HashSet};
+
+        let values = [
+            PWMValue::new(5),
+            PWMValue::max(),
+            PWMValue::new(5),
+            PWMValue::min(),
+        ];
+        let sorted: BTreeSet<PWMValue> = values.iter().copied().collect();
+        let hashed: HashSet<PWMValue> = values.iter().copied().collect();
+        assert_eq!(3, sorted.len());
+        assert_eq!(3, hashed.len());
+        assert_eq!(Some(&PWMValue::min()), sorted.iter().next());
+
+        let steps = [Step::new(3), Step::new(-4095), Step::new(0)];
+        assert_eq!(Some(&Step::new(-4095)), steps.iter().min());
+        assert_eq!(
+            Step::new(3).cmp(&Step::new(2)),
+            core::cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_range_error() {
+        extern crate std;
+        use std::string::ToString;
+
+        let error = PWMValue::min() + Step::new(-7);
+        let error: &dyn core::error::Error = &error.unwrap_err();
+        assert_eq!("-7 is below the minimum of 0", error.to_string());
+        assert!(error.source().is_none());
+
+        let error = RangeError::Length {
+            len: 3,
+            expected: 24,
+        };
+        assert_eq!("3 values where 24 were expected", error.to_string());
+        assert_eq!("Divided by zero", RangeError::DivideByZero.to_string());
+
+        let error = crate::PinError::new(&crate::PinRole::Clock, "Failed to set high", ());
+        let error: &dyn core::error::Error = &error;
+        assert_eq!("Failed to set high on the clock pin", error.to_string());
+    }
+
+    #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_step_subtraction() {
         let step1 = Step::new(-2500);
         let step2 = Step::new(2500);
 
         let step3 = step1 - step2;
         match step3 {
-            Err(v) => assert_eq!(RangeError::Underflow, v),
+            Err(v) => assert_eq!(
+                RangeError::Underflow {
+                    value: -5000,
+                    min: -4095
+                },
+                v
+            ),
             Ok(_) => assert!(false, "should have returned an error"),
         }
     }
 
+    #[test]
+    fn test_fraction_steps() {
+        assert_eq!(4095, Step::from_fraction_of_range(1, 1).amount);
+        assert_eq!(4095, Step::from_fraction_of_range(1, 0).amount);
+        assert_eq!(0, Step::from_fraction_of_range(0, 256).amount);
+        assert_eq!(1024, Step::from_fraction_of_range(1, 4).amount);
+
+        let from = PWMValue::new(0);
+        let to = PWMValue::new(4095);
+        assert_eq!(-4095, Step::from_fraction_of_span(to, from, 1, 1).amount);
+        assert_eq!(-2048, Step::from_fraction_of_span(to, from, 1, 2).amount);
+        assert_eq!(0, Step::from_fraction_of_span(from, from, 1, 2).amount);
+    }
+
+    #[test]
+    fn test_proportional_steps() {
+        assert_eq!(0, Step::proportional_of(PWMValue::max(), 0).amount);
+        assert_eq!(1, Step::proportional_of(PWMValue::min(), 5).amount);
+        assert_eq!(1, Step::proportional_of(PWMValue::new(10), 5).amount);
+        assert_eq!(205, Step::proportional_of(PWMValue::max(), 5).amount);
+        assert_eq!(4095, Step::proportional_of(PWMValue::max(), 255).amount);
+
+        let mut value = PWMValue::max();
+        let mut steps = 0;
+        while value > PWMValue::min() {
+            value -= Step::proportional_of(value, 20);
+            steps += 1;
+        }
+        assert!(steps < 60);
+    }
+
+    #[test]
+    fn test_advance_toward() {
+        let from = PWMValue::new(1000);
+        let up = PWMValue::new(2000);
+        let down = PWMValue::new(10);
+        let step = |value: PWMValue, target, rate, elapsed| {
+            let mut carry = 0;
+            value.advance_toward(target, rate, elapsed, &mut carry)
+        };
+
+        assert_eq!(PWMValue::new(1100), step(from, up, 1024, 100));
+        assert_eq!(PWMValue::new(900), step(from, down, 1024, 100));
+        assert_eq!(from, step(from, up, 1023, 1));
+        assert_eq!(from, step(from, up, 1024, 0));
+        assert_eq!(up, step(from, up, u32::MAX, u32::MAX));
+        assert_eq!(down, step(from, down, 1024, 990));
+        assert_eq!(up, step(up, up, 1024, 100));
+
+        let mut carry = 0;
+        let mut slow = from;
+        for _ in 0..3 {
+            slow = slow.advance_toward(up, 512, 1, &mut carry);
+        }
+        assert_eq!(PWMValue::new(1001), slow);
+        assert_eq!(512, carry);
+
+        // 1536 is one and a half units a tick, so ten single ticks have to
+        // add up to the same 15 units as one call of ten ticks.
+        let mut carry = 0;
+        let mut ticked = from;
+        for _ in 0..10 {
+            ticked = ticked.advance_toward(up, 1536, 1, &mut carry);
+        }
+        assert_eq!(PWMValue::new(1015), ticked);
+        assert_eq!(ticked, step(from, up, 1536, 10));
+
+        let mut carry = 0;
+        let mut uneven = from;
+        for elapsed in [5, 100, 45, 80, 70, 3, 7] {
+            uneven = uneven.advance_toward(down, 700, elapsed, &mut carry);
+        }
+        assert_eq!(step(from, down, 700, 310), uneven);
+
+        let mut carry = 1000;
+        assert_eq!(up, from.advance_toward(up, 1024, 1000, &mut carry));
+        assert_eq!(0, carry);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn test_f32() {
+        assert_eq!(PWMValue::min(), PWMValue::from_f32(-0.2));
+        assert_eq!(PWMValue::min(), PWMValue::from_f32(f32::NEG_INFINITY));
+        assert_eq!(PWMValue::max(), PWMValue::from_f32(f32::INFINITY));
+        assert_eq!(PWMValue::new(1), PWMValue::from_f32(1.0 / 4095.0));
+        assert_eq!(0.0, PWMValue::min().as_f32());
+        for raw in 0..=4095 {
+            let value = PWMValue::new(raw);
+            assert_eq!(value, PWMValue::from_f32(value.as_f32()));
+        }
+    }
+
+    #[test]
+    fn test_fade_steps() {
+        let from = PWMValue::new(4000);
+        let to = PWMValue::new(7);
+        let steps = Step::for_fade(from, to, 2500, 60);
+        assert_eq!(150, steps.frames());
+
+        let mut value = from;
+        let mut smallest = i16::MAX;
+        let mut largest = i16::MIN;
+        for step in steps {
+            smallest = smallest.min(step.amount);
+            largest = largest.max(step.amount);
+            value = (value + step).unwrap();
+        }
+        assert_eq!(to, value);
+        assert!(largest - smallest <= 1);
+
+        let mut instant = Step::for_fade(from, to, 0, 60);
+        assert_eq!(Some(Step::new(-3993)), instant.next());
+        assert_eq!(None, instant.next());
+    }
+
     #[test]
     fn test_simple_iteration() {
         let mut last_value = PWMValue::default();
@@ -0,0 +1,258 @@
+//! A command queue lets an interrupt handler decide what the LEDs should do
+//! while the main loop does the work.  The handler pushes commands, like set
+//! a channel or start a fade, and the main loop drains them into the device
+//! before it flushes.  The handler never touches the device, so it never has
+//! to wait for a flush to finish.
+//!
+//! The queue is a lock-free ring for one producer and one consumer.  It's split
+//! into a `Producer` for the interrupt side and a `Consumer` for the main loop,
+//! and each side only writes its own end of the ring, so neither needs a
+//! critical section.  It only uses atomic loads and stores, which every
+//! target with atomics has, including the Cortex-M0.
+//!
+//! The ring has `N` slots and holds up to `N - 1` commands.  Pushing into a
+//! full queue hands the command back, so the handler can decide whether to
+//! drop it.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::queue::{Command, CommandQueue};
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut queue: CommandQueue<8> = CommandQueue::new();
+//! let (mut producer, mut consumer) = queue.split();
+//!
+//! // In the interrupt handler.
+//! producer
+//!     .push(Command::SetChannel(C1, PWMValue::max()))
+//!     .ok();
+//!
+//! // In the main loop.
+//! assert_eq!(1, consumer.apply(&mut device));
+//! assert_eq!(PWMValue::max(), device.read_pwm(&C1));
+//! device.flush().ok();
+//! ```
+
+use crate::animation::Easing;
+use crate::frame::Frame;
+use crate::pwm::PWMValue;
+use crate::timing::Delay;
+use crate::{Channel, PWM5947};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use embedded_hal::digital::v2::OutputPin;
+
+/// A change to make to the device later.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    /// Writes a value into a channel, the same as `write_pwm`.
+    SetChannel(Channel, PWMValue),
+    /// Starts a fade, the same as `fade_to`.
+    FadeChannel {
+        channel: Channel,
+        target: PWMValue,
+        duration: u32,
+        easing: Easing,
+    },
+    /// Writes a whole frame into the buffer, the same as `load_frame`.
+    LoadFrame(Frame),
+}
+
+impl Command {
+    /// Makes the change to the device's buffer or fades.
+    pub fn apply<L, D, O, C, T>(&self, device: &mut PWM5947<L, D, O, C, T>)
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        match self {
+            Command::SetChannel(channel, value) => device.write_pwm(channel, value),
+            Command::FadeChannel {
+                channel,
+                target,
+                duration,
+                easing,
+            } => device.fade_to(channel, *target, *duration, *easing),
+            Command::LoadFrame(frame) => device.load_frame(frame),
+        }
+    }
+}
+
+/// A ring of `N` slots for commands, holding up to `N - 1` of them.
+pub struct CommandQueue<const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<Command>>; N],
+    // The next slot to pop, only written by the consumer.
+    head: AtomicUsize,
+    // The next slot to push, only written by the producer.
+    tail: AtomicUsize,
+}
+
+// SAFETY: a slot is only written by the producer while it's outside the
+// range from head to tail, and only read by the consumer while it's inside.
+// The release stores and acquire loads of the indices order those accesses.
+unsafe impl<const N: usize> Sync for CommandQueue<N> {}
+
+impl<const N: usize> CommandQueue<N> {
+    /// Create an empty queue.  It can be kept in a `static`.  A queue needs at
+    /// least two slots to hold anything, so fewer doesn't compile.
+    pub const fn new() -> Self {
+        const { assert!(N >= 2, "a command queue needs at least two slots") };
+        CommandQueue {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// The most commands the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+
+    /// The number of commands waiting.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + N - head) % N
+    }
+
+    /// Whether no commands are waiting.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the queue into the end that pushes and the end that pops.
+    /// Borrowing the queue mutably makes sure there's only one of each.
+    pub fn split(&mut self) -> (Producer<'_, N>, Consumer<'_, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+}
+
+impl<const N: usize> Default for CommandQueue<N> {
+    fn default() -> Self {
+        CommandQueue::new()
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for CommandQueue<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CommandQueue")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// The pushing end of a queue, for the interrupt handler.
+#[derive(Debug)]
+pub struct Producer<'a, const N: usize> {
+    queue: &'a CommandQueue<N>,
+}
+
+impl<'a, const N: usize> Producer<'a, N> {
+    /// Adds a command to the back of the queue.  If the queue is full, the
+    /// command is handed back.
+    pub fn push(&mut self, command: Command) -> Result<(), Command> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+        if next == self.queue.head.load(Ordering::Acquire) {
+            return Err(command);
+        }
+
+        // SAFETY: the slot at tail isn't between head and tail, so the
+        // consumer won't read it until the store below publishes it.
+        unsafe { (*self.queue.slots[tail].get()).write(command) };
+        self.queue.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Whether a push would fail.
+    pub fn is_full(&self) -> bool {
+        self.queue.len() == self.queue.capacity()
+    }
+}
+
+/// The popping end of a queue, for the main loop.
+#[derive(Debug)]
+pub struct Consumer<'a, const N: usize> {
+    queue: &'a CommandQueue<N>,
+}
+
+impl<'a, const N: usize> Consumer<'a, N> {
+    /// Takes the command at the front of the queue, if there is one.
+    pub fn pop(&mut self) -> Option<Command> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // SAFETY: the slot at head is between head and tail, so the producer
+        // wrote it before publishing tail and won't touch it again until the
+        // store below hands it back.  Commands are `Copy`, so reading one out
+        // leaves nothing to drop.
+        let command = unsafe { (*self.queue.slots[head].get()).assume_init_read() };
+        self.queue.head.store((head + 1) % N, Ordering::Release);
+        Some(command)
+    }
+
+    /// Applies every waiting command to the device, oldest first, and returns
+    /// how many there were.  Commands pushed while it runs are applied too.
+    pub fn apply<L, D, O, C, T>(&mut self, device: &mut PWM5947<L, D, O, C, T>) -> usize
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        let mut applied = 0;
+        while let Some(command) = self.pop() {
+            command.apply(device);
+            applied += 1;
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{C1, C2};
+
+    #[test]
+    fn test_ring_wraps() {
+        let mut queue: CommandQueue<3> = CommandQueue::new();
+        assert_eq!(2, queue.capacity());
+        let (mut producer, mut consumer) = queue.split();
+
+        for round in 0..5 {
+            let value = PWMValue::new(round);
+            assert!(producer.push(Command::SetChannel(C1, value)).is_ok());
+            assert!(producer.push(Command::SetChannel(C2, value)).is_ok());
+            assert!(producer.is_full());
+            assert_eq!(
+                Err(Command::SetChannel(C1, PWMValue::max())),
+                producer.push(Command::SetChannel(C1, PWMValue::max()))
+            );
+
+            assert_eq!(Some(Command::SetChannel(C1, value)), consumer.pop());
+            assert_eq!(Some(Command::SetChannel(C2, value)), consumer.pop());
+            assert_eq!(None, consumer.pop());
+        }
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+//! A small binary protocol for driving the board from a host over a serial
+//! link.  Each packet starts with the sync byte `0xA5`, then an opcode, the
+//! payload for that opcode, and a checksum.  The checksum makes the bytes
+//! from the opcode to the checksum add up to zero, ignoring overflow.
+//!
+//! | Opcode | Packet  | Payload                                               |
+//! |--------|---------|-------------------------------------------------------|
+//! | `0x01` | set     | channel index, value as two bytes                     |
+//! | `0x02` | fade    | channel index, target as two bytes, duration as four bytes, easing |
+//! | `0x03` | frame   | 24 values of two bytes each, in channel order         |
+//! | `0x04` | flush   | nothing                                               |
+//!
+//! Numbers are big endian, channel indexes start from zero, and the easing is
+//! 0 for linear, 1 for quad in, 2 for quad out and 3 for sine.  Values above
+//! 4095 are clamped, the same as `PWMValue::new`.
+//!
+//! `RemoteIngest` decodes the packets a byte at a time, so it can be fed from
+//! a UART interrupt or a polling loop, and it needs no allocation.  Bytes
+//! before a sync byte are skipped, and a bad packet is reported and dropped,
+//! so the parser finds the next packet after line noise.  Set, fade and frame
+//! packets change the buffer or the fades, and a flush packet flushes the
+//! device.  `encode` builds packets, for a host that uses this crate too.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::remote::{Message, RemoteIngest};
+//! use ledpwm5947::{PWM5947, C3};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut ingest = RemoteIngest::new();
+//!
+//! // Set channel 3 to 0x800, then flush.
+//! let bytes = [0xa5, 0x01, 0x02, 0x08, 0x00, 0xf5, 0xa5, 0x04, 0xfc];
+//! for byte in bytes.iter() {
+//!     ingest.feed(&mut device, *byte).unwrap();
+//! }
+//! assert_eq!(PWMValue::new(0x800), device.read_pwm(&C3));
+//! assert!(!device.is_dirty());
+//! ```
+
+use crate::animation::Easing;
+use crate::frame::Frame;
+use crate::pwm::PWMValue;
+use crate::queue::Command;
+use crate::timing::Delay;
+use crate::{Channel, DeviceSource, PinError, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+
+/// The byte every packet starts with.
+pub const SYNC: u8 = 0xa5;
+
+/// The longest packet, a frame: sync, opcode, 48 bytes of values and the
+/// checksum.
+pub const MAX_PACKET: usize = 51;
+
+const SET: u8 = 0x01;
+const FADE: u8 = 0x02;
+const FRAME: u8 = 0x03;
+const FLUSH: u8 = 0x04;
+
+/// A decoded packet.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Message {
+    /// A change to the buffer or the fades.
+    Command(Command),
+    /// Flush the device.
+    Flush,
+}
+
+/// The error returned when a packet can't be used, or the flush fails.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RemoteError<P> {
+    /// The opcode after the sync byte isn't one the protocol has.
+    UnknownOpcode(u8),
+    /// The packet's bytes don't add up to zero.
+    Checksum,
+    /// The packet names a channel past the 24th.
+    BadChannel(u8),
+    /// The packet names an easing the protocol doesn't have.
+    BadEasing(u8),
+    /// Flushing the device failed.
+    Pin(PinError<P>),
+}
+
+impl<P> core::fmt::Display for RemoteError<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RemoteError::UnknownOpcode(opcode) => write!(f, "Unknown opcode {:#04x}", opcode),
+            RemoteError::Checksum => f.write_str("Bad packet checksum"),
+            RemoteError::BadChannel(index) => write!(f, "No channel at index {}", index),
+            RemoteError::BadEasing(easing) => write!(f, "No easing numbered {}", easing),
+            RemoteError::Pin(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<P> From<PinError<P>> for RemoteError<P> {
+    fn from(error: PinError<P>) -> Self {
+        RemoteError::Pin(error)
+    }
+}
+
+/// The error from feeding a device with the pins `L`, `D`, `O` and `C`.
+type FeedError<L, D, O, C> = RemoteError<DeviceSource<L, D, O, C>>;
+
+/// Where the parser is in a packet.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum State {
+    /// Waiting for a sync byte.
+    Idle,
+    /// The sync byte is in, waiting for the opcode.
+    Synced,
+    /// Reading the payload and then the checksum.
+    Payload(u8),
+}
+
+/// Decodes packets one byte at a time.
+#[derive(Clone, Debug)]
+pub struct RemoteIngest {
+    state: State,
+    payload: [u8; 48],
+    len: usize,
+    sum: u8,
+}
+
+impl RemoteIngest {
+    /// Create a parser waiting for a sync byte.
+    pub const fn new() -> Self {
+        RemoteIngest {
+            state: State::Idle,
+            payload: [0; 48],
+            len: 0,
+            sum: 0,
+        }
+    }
+
+    /// Drops any packet part way through and waits for the next sync byte.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.len = 0;
+        self.sum = 0;
+    }
+
+    /// Takes the next byte from the link and applies the packet to the
+    /// device if the byte finishes one.  It returns the message that was
+    /// applied, or `None` if the packet isn't finished yet.
+    pub fn feed<L, D, O, C, T>(
+        &mut self,
+        device: &mut PWM5947<L, D, O, C, T>,
+        byte: u8,
+    ) -> Result<Option<Message>, FeedError<L, D, O, C>>
+    where
+        L: OutputPin,
+        D: OutputPin,
+        O: OutputPin,
+        C: OutputPin,
+        T: Delay,
+    {
+        let message = self.decode(byte)?;
+        match message {
+            Some(Message::Command(command)) => command.apply(device),
+            Some(Message::Flush) => device.flush()?,
+            None => (),
+        }
+        Ok(message)
+    }
+
+    /// Takes the next byte from the link, and returns the message if the
+    /// byte finishes a packet, without applying it.
+    pub fn decode<P>(&mut self, byte: u8) -> Result<Option<Message>, RemoteError<P>> {
+        let opcode = match self.state {
+            State::Idle => {
+                if byte == SYNC {
+                    self.state = State::Synced;
+                }
+                return Ok(None);
+            }
+            State::Synced => {
+                if payload_len(byte).is_none() {
+                    self.reset();
+                    return Err(RemoteError::UnknownOpcode(byte));
+                }
+                self.state = State::Payload(byte);
+                self.sum = byte;
+                return Ok(None);
+            }
+            State::Payload(opcode) => opcode,
+        };
+
+        let needed = payload_len(opcode).unwrap_or(0);
+        self.sum = self.sum.wrapping_add(byte);
+        if self.len < needed {
+            self.payload[self.len] = byte;
+            self.len += 1;
+            return Ok(None);
+        }
+
+        let sum = self.sum;
+        self.reset();
+        if sum != 0 {
+            return Err(RemoteError::Checksum);
+        }
+        parse(opcode, &self.payload[..needed]).map(Some)
+    }
+}
+
+impl Default for RemoteIngest {
+    fn default() -> Self {
+        RemoteIngest::new()
+    }
+}
+
+/// Encodes the message as a packet into the buffer, and returns the number
+/// of bytes written.
+///
+/// ```
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::queue::Command;
+/// use ledpwm5947::remote::{encode, Message, MAX_PACKET};
+/// use ledpwm5947::C3;
+///
+/// let mut packet = [0_u8; MAX_PACKET];
+/// let message = Message::Command(Command::SetChannel(C3, PWMValue::new(0x800)));
+/// let len = encode(&message, &mut packet);
+/// assert_eq!(&[0xa5, 0x01, 0x02, 0x08, 0x00, 0xf5], &packet[..len]);
+/// ```
+pub fn encode(message: &Message, out: &mut [u8; MAX_PACKET]) -> usize {
+    let mut len = 2;
+    let mut push = |out: &mut [u8; MAX_PACKET], bytes: &[u8]| {
+        out[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    };
+
+    out[0] = SYNC;
+    match message {
+        Message::Command(Command::SetChannel(channel, value)) => {
+            out[1] = SET;
+            push(out, &[channel.index()]);
+            push(out, &value.as_u16().to_be_bytes());
+        }
+        Message::Command(Command::FadeChannel {
+            channel,
+            target,
+            duration,
+            easing,
+        }) => {
+            out[1] = FADE;
+            push(out, &[channel.index()]);
+            push(out, &target.as_u16().to_be_bytes());
+            push(out, &duration.to_be_bytes());
+            push(out, &[easing_number(*easing)]);
+        }
+        Message::Command(Command::LoadFrame(frame)) => {
+            out[1] = FRAME;
+            for value in frame.values().iter() {
+                push(out, &value.as_u16().to_be_bytes());
+            }
+        }
+        Message::Flush => out[1] = FLUSH,
+    }
+
+    let sum = out[1..len]
+        .iter()
+        .fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+    out[len] = sum.wrapping_neg();
+    len + 1
+}
+
+/// The payload length for the opcode, or `None` if there's no such opcode.
+fn payload_len(opcode: u8) -> Option<usize> {
+    match opcode {
+        SET => Some(3),
+        FADE => Some(8),
+        FRAME => Some(48),
+        FLUSH => Some(0),
+        _ => None,
+    }
+}
+
+fn parse<P>(opcode: u8, payload: &[u8]) -> Result<Message, RemoteError<P>> {
+    let value =
+        |at: usize| PWMValue::new(u16::from_be_bytes([payload[at], payload[at + 1]]) as i32);
+    let channel = || Channel::new(payload[0]).ok_or(RemoteError::BadChannel(payload[0]));
+
+    let message = match opcode {
+        SET => Message::Command(Command::SetChannel(channel()?, value(1))),
+        FADE => Message::Command(Command::FadeChannel {
+            channel: channel()?,
+            target: value(1),
+            duration: u32::from_be_bytes([payload[3], payload[4], payload[5], payload[6]]),
+            easing: easing(payload[7]).ok_or(RemoteError::BadEasing(payload[7]))?,
+        }),
+        FRAME => {
+            let mut values = [PWMValue::min(); 24];
+            for (index, slot) in values.iter_mut().enumerate() {
+                *slot = value(index * 2);
+            }
+            Message::Command(Command::LoadFrame(Frame::new(values)))
+        }
+        _ => Message::Flush,
+    };
+    Ok(message)
+}
+
+fn easing(number: u8) -> Option<Easing> {
+    match number {
+        0 => Some(Easing::Linear),
+        1 => Some(Easing::QuadIn),
+        2 => Some(Easing::QuadOut),
+        3 => Some(Easing::Sine),
+        _ => None,
+    }
+}
+
+fn easing_number(easing: Easing) -> u8 {
+    match easing {
+        Easing::Linear => 0,
+        Easing::QuadIn => 1,
+        Easing::QuadOut => 2,
+        Easing::Sine => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{C1, C24};
+    use core::convert::Infallible;
+
+    fn decode_all(ingest: &mut RemoteIngest, bytes: &[u8]) -> Option<Message> {
+        let mut last = None;
+        for byte in bytes {
+            if let Some(message) = ingest.decode::<Infallible>(*byte).unwrap() {
+                last = Some(message);
+            }
+        }
+        last
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut values = [PWMValue::min(); 24];
+        values[0] = PWMValue::max();
+        values[23] = PWMValue::new(1234);
+        let messages = [
+            Message::Command(Command::SetChannel(C24, PWMValue::new(7))),
+            Message::Command(Command::FadeChannel {
+                channel: C1,
+                target: PWMValue::new(3000),
+                duration: 70_000,
+                easing: Easing::Sine,
+            }),
+            Message::Command(Command::LoadFrame(Frame::new(values))),
+            Message::Flush,
+        ];
+
+        let mut ingest = RemoteIngest::new();
+        let mut packet = [0_u8; MAX_PACKET];
+        for message in messages.iter() {
+            let len = encode(message, &mut packet);
+            // Noise before the sync byte is skipped.
+            assert_eq!(None, decode_all(&mut ingest, &[0x00, 0x13]));
+            assert_eq!(Some(*message), decode_all(&mut ingest, &packet[..len]));
+        }
+    }
+
+    #[test]
+    fn test_bad_packets() {
+        let mut ingest = RemoteIngest::new();
+        let mut packet = [0_u8; MAX_PACKET];
+        let len = encode(&Message::Flush, &mut packet);
+
+        assert_eq!(Ok(None), ingest.decode::<Infallible>(SYNC));
+        assert_eq!(
+            Err(RemoteError::UnknownOpcode(0x7f)),
+            ingest.decode::<Infallible>(0x7f)
+        );
+        assert_eq!(
+            Some(Message::Flush),
+            decode_all(&mut ingest, &packet[..len])
+        );
+
+        for byte in [SYNC, SET, 24, 0x00, 0x00].iter() {
+            assert_eq!(Ok(None), ingest.decode::<Infallible>(*byte));
+        }
+        assert_eq!(
+            Err(RemoteError::BadChannel(24)),
+            ingest.decode::<Infallible>(0xe7)
+        );
+
+        for byte in [SYNC, SET, 0, 0x00, 0x00].iter() {
+            assert_eq!(Ok(None), ingest.decode::<Infallible>(*byte));
+        }
+        assert_eq!(
+            Err(RemoteError::Checksum),
+            ingest.decode::<Infallible>(0x00)
+        );
+        assert_eq!(
+            Some(Message::Flush),
+            decode_all(&mut ingest, &packet[..len])
+        );
+    }
+}
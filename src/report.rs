@@ -0,0 +1,167 @@
+//! Labels and state reports for debugging a fixture.  A channel can be given a
+//! label with `label_channel`, naming the LED it drives, and `dump_state`
+//! takes a report of every channel's value along with its label.  The report
+//! prints one line per channel, so it can go straight to a serial console, and
+//! the labels show in the device's `Debug` output as well.
+//!
+//! The report is a copy of the state when it was taken, so it can be printed
+//! after the device has moved on.  It implements `Display`, `ufmt::uDisplay`
+//! with the `ufmt` feature, and `defmt::Format` with the `defmt` feature.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::{PWM5947, C1, C2};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! device.label_channel(&C1, "status_led");
+//! device.write_pwm(&C1, &PWMValue::new(2048));
+//!
+//! let report = format!("{}", device.dump_state());
+//! let mut lines = report.lines();
+//! assert_eq!(Some("output on, changed since the last flush"), lines.next());
+//! assert_eq!(Some("C1 status_led: 2048 (50.0%)"), lines.next());
+//! assert_eq!(Some("C2: 0 (0.0%)"), lines.next());
+//! assert_eq!(Some("status_led"), device.channel_label(&C1));
+//! assert_eq!(None, device.channel_label(&C2));
+//! ```
+
+use crate::pwm::PWMValue;
+use crate::ALL_CHANNELS;
+
+/// A copy of a device's channels and labels, for printing.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StateReport {
+    values: [PWMValue; 24],
+    labels: [Option<&'static str>; 24],
+    fading: u32,
+    output_enabled: bool,
+    dirty: bool,
+}
+
+impl StateReport {
+    pub(crate) fn new(
+        values: [PWMValue; 24],
+        labels: [Option<&'static str>; 24],
+        fading: u32,
+        output_enabled: bool,
+        dirty: bool,
+    ) -> Self {
+        StateReport {
+            values,
+            labels,
+            fading,
+            output_enabled,
+            dirty,
+        }
+    }
+
+    /// The channel values in the buffer, in channel order.
+    pub fn values(&self) -> &[PWMValue; 24] {
+        &self.values
+    }
+
+    /// The channel labels, in channel order.
+    pub fn labels(&self) -> &[Option<&'static str>; 24] {
+        &self.labels
+    }
+
+    /// Whether the channel at the index was fading.
+    fn is_fading(&self, index: usize) -> bool {
+        self.fading & (1 << index) != 0
+    }
+
+    fn output(&self) -> &'static str {
+        if self.output_enabled {
+            "on"
+        } else {
+            "off"
+        }
+    }
+
+    fn flushed(&self) -> &'static str {
+        if self.dirty {
+            "changed since the last flush"
+        } else {
+            "flushed"
+        }
+    }
+}
+
+/// A line for the output and flush state, then a line per channel with its
+/// label, its value and its percentage.  Fading channels are marked.
+impl core::fmt::Display for StateReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "output {}, {}", self.output(), self.flushed())?;
+        for (index, channel) in ALL_CHANNELS.iter().enumerate() {
+            let value = self.values[index];
+            write!(f, "{}", channel)?;
+            if let Some(label) = self.labels[index] {
+                write!(f, " {}", label)?;
+            }
+            write!(f, ": {} ({})", value, value.percent())?;
+            if self.is_fading(index) {
+                f.write_str(", fading")?;
+            }
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for StateReport {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uwrite!(f, "output {}, {}\n", self.output(), self.flushed())?;
+        for (index, channel) in ALL_CHANNELS.iter().enumerate() {
+            let value = self.values[index];
+            ufmt::uwrite!(f, "{}", channel)?;
+            if let Some(label) = self.labels[index] {
+                ufmt::uwrite!(f, " {}", label)?;
+            }
+            ufmt::uwrite!(f, ": {} ({})", value, value.percent())?;
+            if self.is_fading(index) {
+                f.write_str(", fading")?;
+            }
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use crate::animation::Easing;
+    use crate::test_pins::IdlePin;
+    use crate::{C24, C3, PWM5947};
+    use std::string::ToString;
+
+    #[test]
+    fn test_report() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        device.label_channel(&C24, "porch");
+        device.fade_to(&C3, crate::pwm::PWMValue::max(), 10, Easing::Linear);
+        assert!(device.flush().is_ok());
+        assert!(device.disable_output().is_ok());
+
+        let report = device.dump_state().to_string();
+        let lines: std::vec::Vec<&str> = report.lines().collect();
+        assert_eq!(25, lines.len());
+        assert_eq!("output off, flushed", lines[0]);
+        assert_eq!("C3: 0 (0.0%), fading", lines[3]);
+        assert_eq!("C24 porch: 0 (0.0%)", lines[24]);
+    }
+}
@@ -0,0 +1,401 @@
+//! Most people wire common-anode RGB LEDs to this board, using three channels
+//! per LED.  An `RgbLed` remembers which three channels belong to an LED so
+//! the color can be set in one call, instead of managing triples of channels
+//! by hand.  Colors can be given as 12-bit PWM values or as 8-bit components,
+//! like the colors used on the web.
+//!
+//! When the LEDs are wired on consecutive channels, `rgb_triples` walks a
+//! slice of channels three at a time, so a loop can go over the LEDs instead
+//! of the channel numbers.  A slice that isn't a multiple of three leaves the
+//! last one or two channels out.
+//!
+//! `RgbChannels` is the checked version, for LEDs set up in `const` items.  It
+//! won't take the same channel for two of the colors, and in a `const` the
+//! mistake doesn't compile.  `PWM5947::write_rgb` writes an `Rgb8` color to
+//! it.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::rgb::RgbTriples;
+//! use ledpwm5947::{PWM5947, ALL_CHANNELS};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//!
+//! assert_eq!(8, ALL_CHANNELS.rgb_triples().count());
+//! for (n, led) in ALL_CHANNELS.rgb_triples().enumerate() {
+//!     led.set_color_u8(&mut device, 32 * n as u8, 0, 0xff);
+//! }
+//! device.flush().ok();
+//! ```
+
+use crate::calibration::Calibration;
+use crate::controller::LedController;
+use crate::timing::Delay;
+use crate::{pwm::PWMValue, Channel, PWM5947};
+use core::slice::ChunksExact;
+use embedded_hal::digital::v2::OutputPin;
+
+/// The three channels driving the red, green and blue parts of an RGB LED.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::rgb::RgbLed;
+/// use ledpwm5947::{PWM5947, C1};
+///
+/// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+/// let led = RgbLed::contiguous(&C1).expect("C1 to C3 are on the board");
+///
+/// led.set_color_u8(&mut device, 0xff, 0xff, 0x00);
+/// device.flush().ok();
+/// ```
+#[derive(Debug)]
+pub struct RgbLed {
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+}
+
+impl RgbLed {
+    /// Create an RGB LED from any three channels.
+    pub const fn new(red: Channel, green: Channel, blue: Channel) -> Self {
+        RgbLed { red, green, blue }
+    }
+
+    /// Create an RGB LED on three consecutive channels, with red on the first
+    /// channel.  Returns `None` if the last channel would be past the end of
+    /// the board.
+    ///
+    /// ```
+    /// use ledpwm5947::rgb::RgbLed;
+    /// use ledpwm5947::{C22, C23};
+    ///
+    /// assert!(RgbLed::contiguous(&C22).is_some());
+    /// assert!(RgbLed::contiguous(&C23).is_none());
+    /// ```
+    pub fn contiguous(first: &Channel) -> Option<Self> {
+        if first.0 + 2 < 24 {
+            Some(RgbLed {
+                red: Channel(first.0),
+                green: Channel(first.0 + 1),
+                blue: Channel(first.0 + 2),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The channel driving red.
+    pub fn red(&self) -> &Channel {
+        &self.red
+    }
+
+    /// The channel driving green.
+    pub fn green(&self) -> &Channel {
+        &self.green
+    }
+
+    /// The channel driving blue.
+    pub fn blue(&self) -> &Channel {
+        &self.blue
+    }
+
+    /// Writes the 12-bit color components into the buffer of any
+    /// `LedController`.  Like `write_pwm`, the color shows on the next flush.
+    /// On a chain or a bank the channels are on the first board.
+    pub fn set_color<T: LedController>(
+        &self,
+        device: &mut T,
+        red: PWMValue,
+        green: PWMValue,
+        blue: PWMValue,
+    ) {
+        device.set(self.red.0, red);
+        device.set(self.green.0, green);
+        device.set(self.blue.0, blue);
+    }
+
+    /// Writes 8-bit color components into the device buffer, scaling them up
+    /// to 12 bits.
+    pub fn set_color_u8<T: LedController>(&self, device: &mut T, red: u8, green: u8, blue: u8) {
+        self.set_color(
+            device,
+            PWMValue::from(red),
+            PWMValue::from(green),
+            PWMValue::from(blue),
+        );
+    }
+
+    /// Writes 8-bit color components into the device buffer, scaled by the
+    /// white-point calibration for this LED.
+    pub fn set_color_calibrated<T: LedController>(
+        &self,
+        device: &mut T,
+        calibration: &Calibration,
+        red: u8,
+        green: u8,
+        blue: u8,
+    ) {
+        let (red, green, blue) = calibration.to_rgb(red, green, blue);
+        self.set_color(device, red, green, blue);
+    }
+}
+
+/// A color with 8-bit components, like the colors used on the web.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rgb8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb8 {
+    /// Create a color from its components.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb8 { r, g, b }
+    }
+}
+
+/// Three different channels for the red, green and blue parts of an RGB
+/// LED.  The constructors are `const fn` and panic on a channel that's used
+/// twice or past the end of the board, so in a `const` item the mistake is a
+/// compile error.  `try_custom` checks at run time instead.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::rgb::{Rgb8, RgbChannels};
+/// use ledpwm5947::{PWM5947, C1, C17, C5, C9};
+///
+/// const STRIP: RgbChannels = RgbChannels::contiguous(C1);
+/// const STATUS: RgbChannels = RgbChannels::custom(C5, C9, C17);
+///
+/// let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+/// device.write_rgb(&STATUS, Rgb8::new(0xff, 0, 0x80));
+/// device.write_rgb(&STRIP, Rgb8::default());
+///
+/// assert_eq!(PWMValue::max(), device.read_pwm(&C5));
+/// assert_eq!(PWMValue::min(), device.read_pwm(&C9));
+/// ```
+///
+/// ```compile_fail
+/// use ledpwm5947::rgb::RgbChannels;
+/// use ledpwm5947::{C17, C5};
+///
+/// const TWICE: RgbChannels = RgbChannels::custom(C5, C5, C17);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RgbChannels {
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+}
+
+impl RgbChannels {
+    /// Three consecutive channels, with red on the first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last channel would be past the end of the board.
+    pub const fn contiguous(first: Channel) -> Self {
+        assert!(first.0 + 2 < 24, "the LED runs past the end of the board");
+        RgbChannels {
+            red: first,
+            green: Channel(first.0 + 1),
+            blue: Channel(first.0 + 2),
+        }
+    }
+
+    /// Any three channels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two of the colors are on the same channel.
+    pub const fn custom(red: Channel, green: Channel, blue: Channel) -> Self {
+        match RgbChannels::try_custom(red, green, blue) {
+            Some(channels) => channels,
+            None => panic!("each color needs its own channel"),
+        }
+    }
+
+    /// Any three channels, or `None` if two of the colors are on the same
+    /// channel.
+    pub const fn try_custom(red: Channel, green: Channel, blue: Channel) -> Option<Self> {
+        if red.0 == green.0 || red.0 == blue.0 || green.0 == blue.0 {
+            None
+        } else {
+            Some(RgbChannels { red, green, blue })
+        }
+    }
+
+    /// The channel driving red.
+    pub const fn red(&self) -> Channel {
+        self.red
+    }
+
+    /// The channel driving green.
+    pub const fn green(&self) -> Channel {
+        self.green
+    }
+
+    /// The channel driving blue.
+    pub const fn blue(&self) -> Channel {
+        self.blue
+    }
+}
+
+impl From<RgbChannels> for RgbLed {
+    fn from(channels: RgbChannels) -> Self {
+        RgbLed::new(channels.red, channels.green, channels.blue)
+    }
+}
+
+impl<L, D, O, C, T> PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Writes the color into the LED's three channels, scaling the components
+    /// up to 12 bits.  Like `write_pwm`, it shows on the next flush.
+    pub fn write_rgb(&mut self, led: &RgbChannels, color: Rgb8) {
+        self.write_pwm(&led.red, &PWMValue::from(color.r));
+        self.write_pwm(&led.green, &PWMValue::from(color.g));
+        self.write_pwm(&led.blue, &PWMValue::from(color.b));
+    }
+}
+
+/// Splits a run of channels into RGB LEDs, three channels at a time.
+pub trait RgbTriples {
+    /// The LEDs on the channels, with red on the first channel of each three.
+    fn rgb_triples(&self) -> Triples<'_>;
+}
+
+impl RgbTriples for [Channel] {
+    fn rgb_triples(&self) -> Triples<'_> {
+        Triples {
+            chunks: self.chunks_exact(3),
+        }
+    }
+}
+
+/// The iterator returned by `rgb_triples`.
+#[derive(Clone, Debug)]
+pub struct Triples<'a> {
+    chunks: ChunksExact<'a, Channel>,
+}
+
+impl<'a> Iterator for Triples<'a> {
+    type Item = RgbLed;
+
+    fn next(&mut self) -> Option<RgbLed> {
+        self.chunks
+            .next()
+            .map(|three| RgbLed::new(three[0], three[1], three[2]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Triples<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+
+    #[test]
+    fn test_set_color() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+
+        let led = RgbLed::new(crate::C9, crate::C2, crate::C17);
+        led.set_color(
+            &mut device,
+            PWMValue::new(1),
+            PWMValue::new(2),
+            PWMValue::new(3),
+        );
+        assert_eq!(PWMValue::new(1), device.buffer[8]);
+        assert_eq!(PWMValue::new(2), device.buffer[1]);
+        assert_eq!(PWMValue::new(3), device.buffer[16]);
+
+        let led = RgbLed::contiguous(&crate::C4).unwrap();
+        led.set_color_u8(&mut device, 0, 255, 0);
+        assert_eq!(PWMValue::min(), device.buffer[3]);
+        assert_eq!(PWMValue::max(), device.buffer[4]);
+        assert_eq!(PWMValue::min(), device.buffer[5]);
+    }
+
+    #[test]
+    fn test_set_color_on_bank() {
+        let mut bank = crate::bank::Bank::new([IdlePin, IdlePin], IdlePin, IdlePin, IdlePin);
+
+        let led = RgbLed::contiguous(&crate::C1).unwrap();
+        led.set_color_u8(&mut bank, 255, 0, 0);
+        assert_eq!(PWMValue::max(), bank.read_pwm(0));
+        assert_eq!(PWMValue::min(), bank.read_pwm(1));
+        assert_eq!(PWMValue::min(), bank.read_pwm(24));
+    }
+
+    #[test]
+    fn test_rgb_channels() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let led = RgbChannels::contiguous(crate::C22);
+        assert_eq!(crate::C24, led.blue());
+
+        device.write_rgb(&led, Rgb8::new(255, 0, 128));
+        assert_eq!(PWMValue::max(), device.buffer[21]);
+        assert_eq!(PWMValue::min(), device.buffer[22]);
+        assert_eq!(PWMValue::from(128_u8), device.buffer[23]);
+
+        assert!(RgbChannels::try_custom(crate::C1, crate::C2, crate::C1).is_none());
+        let custom = RgbChannels::try_custom(crate::C3, crate::C1, crate::C2).unwrap();
+        assert_eq!(&crate::C3, RgbLed::from(custom).red());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rgb_channels_past_the_end() {
+        RgbChannels::contiguous(crate::C23);
+    }
+
+    #[test]
+    fn test_rgb_triples() {
+        let channels = [crate::C5, crate::C6, crate::C7, crate::C8, crate::C9];
+        assert_eq!(1, channels.rgb_triples().len());
+        let led = channels.rgb_triples().next().unwrap();
+        assert_eq!(&crate::C5, led.red());
+        assert_eq!(&crate::C7, led.blue());
+
+        let last = crate::ALL_CHANNELS.rgb_triples().last().unwrap();
+        assert_eq!(&crate::C22, last.red());
+        assert_eq!(&crate::C23, last.green());
+        assert_eq!(&crate::C24, last.blue());
+    }
+}
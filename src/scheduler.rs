@@ -0,0 +1,235 @@
+//! Flushing bit-bangs 288 bits through the pins, which takes most of a
+//! millisecond on a slow part.  A main loop that flushes on every pass spends
+//! nearly all of its time shifting frames the LEDs can't show any faster.  A
+//! `FrameScheduler` caps the frame rate: it's called unconditionally with the
+//! current tick count and only flushes when the minimum frame interval has
+//! passed since the last flush.
+//!
+//! The ticks come from whatever monotonic counter the board has, such as a
+//! SysTick or timer count.  The count is allowed to wrap around, as long as
+//! less than `u32::MAX` ticks pass between calls.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::scheduler::FrameScheduler;
+//! use ledpwm5947::PWM5947;
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut scheduler = FrameScheduler::new(20);
+//!
+//! assert_eq!(Ok(true), scheduler.flush(&mut device, 0));
+//! assert_eq!(Ok(false), scheduler.flush(&mut device, 5));
+//! assert_eq!(Ok(true), scheduler.flush(&mut device, 20));
+//! ```
+//!
+//! A `DisplayRefresher` does the whole job of keeping the LEDs up to date from
+//! a periodic timer interrupt, such as an RTIC task or an Embassy `Ticker`
+//! loop.  Each call advances the fades to the current time, and flushes if
+//! something changed and the frame is due.  It also pulls OE high while the
+//! latch is toggled, which hides the short glitch the board's outputs make as
+//! new values are latched.  Without it, fast refreshes show up as flicker.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::animation::Easing;
+//! use ledpwm5947::clock::Instant;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::scheduler::DisplayRefresher;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut refresher = DisplayRefresher::new(20);
+//! device.fade_to(&C1, PWMValue::max(), 1000, Easing::Linear);
+//!
+//! // Called from a 10ms timer interrupt.
+//! assert_eq!(Ok(true), refresher.refresh(&mut device, Instant::from_millis(0)));
+//! assert_eq!(Ok(false), refresher.refresh(&mut device, Instant::from_millis(10)));
+//! assert_eq!(Ok(true), refresher.refresh(&mut device, Instant::from_millis(20)));
+//! ```
+
+use crate::clock::Instant;
+use crate::controller::LedController;
+
+/// Limits how often a device is flushed.  The first call always flushes, after
+/// that a flush happens once at least the minimum interval has passed.
+#[derive(Debug)]
+pub struct FrameScheduler {
+    interval: u32,
+    last: Option<u32>,
+}
+
+impl FrameScheduler {
+    /// Create a scheduler that flushes at most once every `interval` ticks.
+    /// An interval of zero flushes on every call.
+    pub fn new(interval: u32) -> Self {
+        FrameScheduler {
+            interval,
+            last: None,
+        }
+    }
+
+    /// The minimum number of ticks between flushes.
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Change the minimum number of ticks between flushes.  It takes effect on
+    /// the next call.
+    pub fn set_interval(&mut self, interval: u32) {
+        self.interval = interval;
+    }
+
+    /// Whether a flush at tick `now` is due.
+    pub fn is_due(&self, now: u32) -> bool {
+        match self.last {
+            Some(last) => now.wrapping_sub(last) >= self.interval,
+            None => true,
+        }
+    }
+
+    /// Forgets the last flush, so the next call flushes right away.  Useful
+    /// when the buffer changes in a way that has to show at once.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Flushes the device if the frame is due at tick `now`.  It returns
+    /// whether it flushed.  If the flush fails the frame stays due, so the next
+    /// call tries again.
+    pub fn flush<T: LedController>(&mut self, device: &mut T, now: u32) -> Result<bool, T::Error> {
+        if !self.is_due(now) {
+            return Ok(false);
+        }
+
+        device.flush()?;
+        self.last = Some(now);
+        Ok(true)
+    }
+}
+
+/// Advances the animations and flushes, at a limited frame rate, from one
+/// call in a periodic interrupt.  OE blanking during the latch is on unless
+/// it's turned off.
+#[derive(Debug)]
+pub struct DisplayRefresher {
+    scheduler: FrameScheduler,
+    blanking: bool,
+}
+
+impl DisplayRefresher {
+    /// Create a refresher that flushes at most once every `interval`
+    /// milliseconds.
+    pub fn new(interval: u32) -> Self {
+        DisplayRefresher {
+            scheduler: FrameScheduler::new(interval),
+            blanking: true,
+        }
+    }
+
+    /// The minimum number of milliseconds between flushes.
+    pub fn interval(&self) -> u32 {
+        self.scheduler.interval()
+    }
+
+    /// Change the minimum number of milliseconds between flushes.
+    pub fn set_interval(&mut self, interval: u32) {
+        self.scheduler.set_interval(interval);
+    }
+
+    /// Whether OE is pulled high while the latch is toggled.
+    pub fn is_blanking(&self) -> bool {
+        self.blanking
+    }
+
+    /// Turn OE blanking during the latch on or off.  It costs two more pin
+    /// writes a frame.  This is used in place of the device's own
+    /// `set_blank_during_latch` for the flushes the refresher makes.  The
+    /// boards, chains, banks and SPI boards all blank, but a controller
+    /// without an OE pin, like the `sim` module's `Simulator`, ignores it.
+    pub fn set_blanking(&mut self, blanking: bool) {
+        self.blanking = blanking;
+    }
+
+    /// Advances the device's fades to `now`, and flushes if the buffer has
+    /// changed and the frame is due.  With dithering on, every due frame is
+    /// flushed.  Any `LedController` can be refreshed, and a backend that
+    /// doesn't keep track of changes is flushed on every due frame.  It
+    /// returns whether it flushed.  A failed flush leaves the buffer dirty, so
+    /// the next call tries again.
+    pub fn refresh<T: LedController>(
+        &mut self,
+        device: &mut T,
+        now: Instant,
+    ) -> Result<bool, T::Error> {
+        device.advance_to(now);
+
+        let now = now.as_millis();
+        if !device.needs_flush() || !self.scheduler.is_due(now) {
+            return Ok(false);
+        }
+
+        device.flush_blanked(self.blanking)?;
+        self.scheduler.last = Some(now);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::PWM5947Chain;
+    use crate::pwm::PWMValue;
+    use crate::test_pins::IdlePin;
+
+    #[test]
+    fn test_wrapping_ticks() {
+        let mut scheduler = FrameScheduler::new(10);
+        scheduler.last = Some(u32::MAX - 4);
+
+        assert!(!scheduler.is_due(u32::MAX));
+        assert!(!scheduler.is_due(4));
+        assert!(scheduler.is_due(5));
+    }
+
+    #[test]
+    fn test_refresh_chain() {
+        let mut chain: PWM5947Chain<_, _, _, _, 2> =
+            PWM5947Chain::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut refresher = DisplayRefresher::new(10);
+
+        assert_eq!(
+            Ok(false),
+            refresher.refresh(&mut chain, Instant::from_millis(0))
+        );
+        chain.write_pwm(1, &crate::C1, &PWMValue::max());
+        assert_eq!(
+            Ok(true),
+            refresher.refresh(&mut chain, Instant::from_millis(0))
+        );
+        assert!(!chain.is_dirty());
+
+        chain.write_pwm(1, &crate::C1, &PWMValue::min());
+        assert_eq!(
+            Ok(false),
+            refresher.refresh(&mut chain, Instant::from_millis(5))
+        );
+        assert_eq!(
+            Ok(true),
+            refresher.refresh(&mut chain, Instant::from_millis(10))
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! A sequence is a scripted light show: a list of cues, each setting one
+//! channel to a value at a given tick.  Unlike the interpolator's keyframes,
+//! a cue changes a single channel and the value is set at once, with no
+//! blending.  Fades can still be scripted by starting them from the cues'
+//! values with `fade_to`, or by spelling out the steps.
+//!
+//! The cues live in a slice the caller provides, so a whole show can be a
+//! `const` array in flash.  A `Player` steps through the sequence each time
+//! `tick` is called, writing the cues into the buffer of any `LedController`.
+//! On a chain or a bank the cues play on the first board.  Like the fades,
+//! the player runs on a `TimeBase`, so the show can be slowed down or sped up.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::sequence::{Cue, Player, Sequence};
+//! use ledpwm5947::{PWM5947, C1, C2};
+//!
+//! const SHOW: &[Cue] = &[
+//!     Cue::new(0, C1, PWMValue::max()),
+//!     Cue::new(10, C1, PWMValue::min()),
+//!     Cue::new(10, C2, PWMValue::max()),
+//!     Cue::new(20, C2, PWMValue::min()),
+//! ];
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let mut player = Player::new(Sequence::new(SHOW), true);
+//! for _ in 0..100 {
+//!     player.tick(&mut device);
+//!     device.flush().ok();
+//! }
+//! ```
+
+use crate::clock::{Elapsed, Instant};
+use crate::controller::LedController;
+use crate::pwm::PWMValue;
+use crate::timebase::{Speed, TimeBase};
+use crate::Channel;
+
+/// Sets the channel to the value when the sequence reaches the tick.
+#[derive(Debug)]
+pub struct Cue {
+    pub tick: u32,
+    pub channel: Channel,
+    pub value: PWMValue,
+}
+
+impl Cue {
+    /// Create a cue.  This is a const function, so cues can be put in a
+    /// `const` array.
+    pub const fn new(tick: u32, channel: Channel, value: PWMValue) -> Self {
+        Cue {
+            tick,
+            channel,
+            value,
+        }
+    }
+}
+
+/// A list of cues, sorted by tick, and the length of the sequence in ticks.
+/// The length matters when looping, since the sequence starts again once it
+/// has run that many ticks.
+#[derive(Copy, Clone, Debug)]
+pub struct Sequence<'a> {
+    cues: &'a [Cue],
+    length: u32,
+}
+
+impl<'a> Sequence<'a> {
+    /// Create a sequence from the cues, which are expected to be sorted by
+    /// tick.  The sequence ends on the tick after the last cue, so a loop
+    /// starts again straight after it.
+    pub const fn new(cues: &'a [Cue]) -> Self {
+        let length = if cues.is_empty() {
+            0
+        } else {
+            cues[cues.len() - 1].tick + 1
+        };
+        Sequence { cues, length }
+    }
+
+    /// Create a sequence with an explicit length in ticks, for a pause after
+    /// the last cue before it loops.  A length shorter than the cues is
+    /// stretched to fit them.
+    pub const fn with_length(cues: &'a [Cue], length: u32) -> Self {
+        let fitted = Sequence::new(cues);
+        if length > fitted.length {
+            Sequence { cues, length }
+        } else {
+            fitted
+        }
+    }
+
+    /// The cues in the sequence.
+    pub fn cues(&self) -> &'a [Cue] {
+        self.cues
+    }
+
+    /// The length of the sequence in ticks.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+/// Plays a sequence into a device, one tick at a time.
+#[derive(Debug)]
+pub struct Player<'a> {
+    sequence: Sequence<'a>,
+    looping: bool,
+    position: u32,
+    next: usize,
+    time_base: TimeBase,
+    elapsed: Elapsed,
+}
+
+impl<'a> Player<'a> {
+    /// Create a player at the start of the sequence.  A looping player starts
+    /// the sequence again when it reaches the end, otherwise it stops.
+    pub fn new(sequence: Sequence<'a>, looping: bool) -> Self {
+        Player {
+            sequence,
+            looping,
+            position: 0,
+            next: 0,
+            time_base: TimeBase::default(),
+            elapsed: Elapsed::default(),
+        }
+    }
+
+    /// Whether the player starts the sequence again at the end.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Turns looping on or off.  Turning it off lets the sequence finish.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// The tick the player has reached in the sequence.
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Whether a sequence that doesn't loop has played all of its ticks.
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.position >= self.sequence.length
+    }
+
+    /// Goes back to the start of the sequence.
+    pub fn rewind(&mut self) {
+        self.position = 0;
+        self.next = 0;
+    }
+
+    /// The speed the sequence plays at.
+    pub fn speed(&self) -> Speed {
+        self.time_base.speed()
+    }
+
+    /// Change the speed of the sequence, starting with the next tick.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.time_base.set_speed(speed);
+    }
+
+    /// Moves the sequence on by a tick and writes every cue that's come due
+    /// into the device buffer.  At more than normal speed several ticks pass
+    /// at once, and every cue in between is still written, in order, even
+    /// past the end of a sequence that doesn't loop.  The cues show on the
+    /// next flush.
+    pub fn tick<T: LedController>(&mut self, device: &mut T) {
+        self.advance(device, 1);
+    }
+
+    /// Plays the sequence on by the milliseconds since the last call, so the
+    /// cue ticks are in milliseconds.  The first call starts the clock and
+    /// writes the cues at the current position.  Like `tick`, every cue that
+    /// has come due is written.
+    pub fn tick_at<T: LedController>(&mut self, device: &mut T, now: Instant) {
+        let elapsed = self.elapsed.since_last(now);
+        self.advance(device, elapsed);
+    }
+
+    fn advance<T: LedController>(&mut self, device: &mut T, ticks: u32) {
+        if self.is_finished() {
+            return;
+        }
+
+        self.position = self.position.saturating_add(self.time_base.advance(ticks));
+        if self.position >= self.sequence.length {
+            self.play_until(device, u32::MAX);
+            if self.looping && self.sequence.length > 0 {
+                self.position %= self.sequence.length;
+                self.next = 0;
+            }
+        }
+        self.play_until(device, self.position);
+    }
+
+    fn play_until<T: LedController>(&mut self, device: &mut T, tick: u32) {
+        while let Some(cue) = self.sequence.cues.get(self.next) {
+            if cue.tick > tick {
+                break;
+            }
+            device.set(cue.channel.0, cue.value);
+            self.next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::PWM5947Chain;
+    use crate::test_pins::IdlePin;
+    use crate::{C1, C2, PWM5947};
+
+    const SHOW: &[Cue] = &[
+        Cue::new(0, C1, PWMValue::new(1)),
+        Cue::new(2, C2, PWMValue::new(2)),
+        Cue::new(3, C1, PWMValue::new(3)),
+    ];
+
+    #[test]
+    fn test_play_once() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::new(SHOW), false);
+        assert_eq!(4, Sequence::new(SHOW).length());
+
+        player.tick(&mut device);
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        assert_eq!(PWMValue::min(), device.read_pwm(&C2));
+        player.tick(&mut device);
+        assert_eq!(PWMValue::new(2), device.read_pwm(&C2));
+        player.tick(&mut device);
+        assert_eq!(PWMValue::new(3), device.read_pwm(&C1));
+        assert!(!player.is_finished());
+        player.tick(&mut device);
+        assert!(player.is_finished());
+
+        device.write_pwm(&C1, &PWMValue::max());
+        player.tick(&mut device);
+        assert_eq!(PWMValue::max(), device.read_pwm(&C1));
+    }
+
+    #[test]
+    fn test_tick_at() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::new(SHOW), false);
+
+        player.tick_at(&mut device, Instant::from_millis(100));
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        assert_eq!(0, player.position());
+
+        player.tick_at(&mut device, Instant::from_millis(102));
+        assert_eq!(2, player.position());
+        assert_eq!(PWMValue::new(2), device.read_pwm(&C2));
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        player.tick_at(&mut device, Instant::from_millis(103));
+        assert_eq!(PWMValue::new(3), device.read_pwm(&C1));
+    }
+
+    #[test]
+    fn test_tick_at_past_the_end() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::new(SHOW), false);
+
+        player.tick_at(&mut device, Instant::from_millis(0));
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        assert_eq!(PWMValue::min(), device.read_pwm(&C2));
+
+        player.tick_at(&mut device, Instant::from_millis(10));
+        assert_eq!(PWMValue::new(2), device.read_pwm(&C2));
+        assert_eq!(PWMValue::new(3), device.read_pwm(&C1));
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_looping() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::with_length(SHOW, 6), true);
+
+        for _ in 0..5 {
+            player.tick(&mut device);
+        }
+        assert_eq!(5, player.position());
+        assert_eq!(PWMValue::new(3), device.read_pwm(&C1));
+
+        player.tick(&mut device);
+        assert_eq!(0, player.position());
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn test_sped_up_plays_last_cue() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::new(SHOW), false);
+        player.set_speed(Speed::from_ratio(2, 1));
+
+        player.tick(&mut device);
+        assert_eq!(2, player.position());
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        assert_eq!(PWMValue::new(2), device.read_pwm(&C2));
+
+        player.tick(&mut device);
+        assert_eq!(PWMValue::new(3), device.read_pwm(&C1));
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_fast_wrap_plays_every_cue() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::new(SHOW), true);
+        player.set_speed(Speed::from_ratio(3, 1));
+
+        player.tick(&mut device);
+        assert_eq!(3, player.position());
+        assert_eq!(PWMValue::new(3), device.read_pwm(&C1));
+        device.write_pwm(&C2, &PWMValue::min());
+
+        player.tick(&mut device);
+        assert_eq!(2, player.position());
+        assert_eq!(PWMValue::new(1), device.read_pwm(&C1));
+        assert_eq!(PWMValue::new(2), device.read_pwm(&C2));
+    }
+
+    #[test]
+    fn test_play_on_chain() {
+        let mut chain: PWM5947Chain<_, _, _, _, 2> =
+            PWM5947Chain::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut player = Player::new(Sequence::new(SHOW), false);
+
+        player.tick(&mut chain);
+        player.tick(&mut chain);
+        assert_eq!(PWMValue::new(1), chain.read_pwm(0, &C1));
+        assert_eq!(PWMValue::new(2), chain.read_pwm(0, &C2));
+        assert_eq!(PWMValue::min(), chain.read_pwm(1, &C1));
+    }
+}
@@ -0,0 +1,140 @@
+//! The servo module turns servo pulse widths and angles into PWM values.  The
+//! TLC5947 runs its PWM from a fixed internal 4MHz clock, so each of the 4096
+//! steps is 250ns and a period is 1.024ms, just under 1kHz.  A pulse of `us`
+//! microseconds is `us * 4` steps.
+//!
+//! That period is shorter than the 1000-2000µs pulse a standard hobby servo
+//! expects, and there's no way to slow the board's clock.  Standard pulses
+//! past 1023µs come out as full scale, which most servos treat as a lost
+//! signal.  Narrow pulse servos, the 760µs kind used for helicopter tails, take
+//! pulses of roughly 560µs to 960µs and do fit in the period.  There's no
+//! default range that would work, so a servo is always created with the pulse
+//! range from its datasheet.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::servo::Servo;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! let tail = Servo::new(C1, 560, 960);
+//!
+//! tail.set_angle(&mut device, 90);
+//! assert_eq!(760, tail.pulse_for(90));
+//! assert_eq!(PWMValue::new(3040), device.read_pwm(&C1));
+//! ```
+
+use crate::controller::LedController;
+use crate::pwm::PWMValue;
+use crate::Channel;
+
+/// The length of one PWM period on the TLC5947, in nanoseconds.
+pub const PWM_PERIOD_NS: u32 = 1_024_000;
+
+/// The length of one of the 4096 PWM steps, in nanoseconds.
+pub const PWM_STEP_NS: u32 = 250;
+
+/// The PWM value for a pulse of `pulse_us` microseconds.  Pulses as long as
+/// the period, or longer, give full scale.
+///
+/// ```
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::servo::pulse_to_pwm;
+///
+/// assert_eq!(PWMValue::new(2000), pulse_to_pwm(500));
+/// assert_eq!(PWMValue::max(), pulse_to_pwm(1500));
+/// ```
+pub fn pulse_to_pwm(pulse_us: u32) -> PWMValue {
+    let steps = pulse_us.saturating_mul(1000) / PWM_STEP_NS;
+    PWMValue::new(steps.min(i32::MAX as u32) as i32)
+}
+
+/// A servo on one channel.  The angle is spread evenly over the pulse range,
+/// so the smallest pulse is 0° and the largest is the full travel.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Servo {
+    channel: Channel,
+    min_pulse_us: u16,
+    max_pulse_us: u16,
+    travel_deg: u16,
+}
+
+impl Servo {
+    /// Create a servo on the channel with pulse widths, in microseconds, for
+    /// the two ends of travel, and 180° of travel.  See the module notes on
+    /// which servos have a range that fits the board's period.
+    pub const fn new(channel: Channel, min_pulse_us: u16, max_pulse_us: u16) -> Self {
+        Servo {
+            channel,
+            min_pulse_us,
+            max_pulse_us,
+            travel_deg: 180,
+        }
+    }
+
+    /// Sets the pulse widths, in microseconds, for the two ends of travel.
+    pub fn with_pulse_range(self, min_pulse_us: u16, max_pulse_us: u16) -> Self {
+        Servo {
+            min_pulse_us,
+            max_pulse_us,
+            ..self
+        }
+    }
+
+    /// Sets how far the servo turns, in degrees, across the pulse range.
+    pub fn with_travel(self, travel_deg: u16) -> Self {
+        Servo { travel_deg, ..self }
+    }
+
+    /// The channel the servo is on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// The pulse width, in microseconds, for an angle.  Angles past the end of
+    /// travel are clamped to it.
+    pub fn pulse_for(&self, angle_deg: u16) -> u32 {
+        if self.travel_deg == 0 {
+            return self.min_pulse_us as u32;
+        }
+
+        let angle = angle_deg.min(self.travel_deg) as i32;
+        let min = self.min_pulse_us as i32;
+        let span = self.max_pulse_us as i32 - min;
+        (min + span * angle / self.travel_deg as i32) as u32
+    }
+
+    /// Writes the pulse for the angle into the servo's channel, on any
+    /// `LedController`.  Like `write_pwm`, it takes effect on the next flush.
+    pub fn set_angle<T: LedController>(&self, device: &mut T, angle_deg: u16) {
+        self.set_pulse(device, self.pulse_for(angle_deg));
+    }
+
+    /// Writes a pulse width, in microseconds, into the servo's channel.
+    pub fn set_pulse<T: LedController>(&self, device: &mut T, pulse_us: u32) {
+        device.set(self.channel.0, pulse_to_pwm(pulse_us));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::C3;
+
+    #[test]
+    fn test_reversed_range() {
+        let servo = Servo::new(C3, 900, 600).with_travel(90);
+
+        assert_eq!(900, servo.pulse_for(0));
+        assert_eq!(750, servo.pulse_for(45));
+        assert_eq!(600, servo.pulse_for(200));
+    }
+}
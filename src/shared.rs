@@ -0,0 +1,165 @@
+//! A shared device can be written from interrupt handlers and flushed from the
+//! main loop.  It keeps the device in a `critical_section::Mutex`, so each
+//! access runs with interrupts held off and the two sides never see the buffer
+//! half written.  The wrapper can live in a `static`, since it starts out
+//! empty and the device is put in once the pins are set up.
+//!
+//! Everything happens inside a critical section, including the flush.  Shifting
+//! a frame out holds interrupts off for as long as the bit-bang takes, which
+//! can be most of a millisecond on a slow part.  Handlers that can't wait that
+//! long should only touch the buffer when the main loop isn't flushing, or use
+//! the SPI backend, which is quicker.
+//!
+//! This needs the `critical-section` feature, and an implementation of the
+//! `critical-section` crate for the target, which the HAL or runtime normally
+//! provides.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::shared::SharedPWM5947;
+//! use ledpwm5947::{PWM5947, C1};
+//!
+//! static DEVICE: SharedPWM5947<Pin, Pin, Pin, Pin> = SharedPWM5947::new();
+//!
+//! DEVICE.init(PWM5947::new(Pin, Pin, Pin, Pin));
+//!
+//! // In the interrupt handler.
+//! DEVICE.write_pwm(&C1, &PWMValue::max());
+//!
+//! // In the main loop.
+//! assert_eq!(Ok(true), DEVICE.flush());
+//! assert_eq!(Some(PWMValue::max()), DEVICE.read_pwm(&C1));
+//! ```
+
+use crate::pwm::PWMValue;
+use crate::timing::{Delay, NoDelay};
+use crate::{Channel, DeviceError, PWM5947};
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embedded_hal::digital::v2::OutputPin;
+
+/// The slot the device sits in, empty until `init`.
+type Slot<L, D, O, C, T> = RefCell<Option<PWM5947<L, D, O, C, T>>>;
+
+/// A device that can be shared between interrupt handlers and the main loop.
+pub struct SharedPWM5947<L, D, O, C, T = NoDelay>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    device: Mutex<Slot<L, D, O, C, T>>,
+}
+
+impl<L, D, O, C, T> SharedPWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Create an empty wrapper.  It does nothing until a device is put in with
+    /// `init`.
+    pub const fn new() -> Self {
+        SharedPWM5947 {
+            device: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Puts the device in the wrapper, returning the one that was there
+    /// before, if any.
+    pub fn init(&self, device: PWM5947<L, D, O, C, T>) -> Option<PWM5947<L, D, O, C, T>> {
+        critical_section::with(|cs| self.device.borrow(cs).replace(Some(device)))
+    }
+
+    /// Takes the device back out of the wrapper.
+    pub fn release(&self) -> Option<PWM5947<L, D, O, C, T>> {
+        critical_section::with(|cs| self.device.borrow(cs).take())
+    }
+
+    /// Runs the closure on the device inside a critical section.  It returns
+    /// the closure's result, or `None` if there's no device yet.
+    pub fn with<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut PWM5947<L, D, O, C, T>) -> R,
+    {
+        critical_section::with(|cs| self.device.borrow(cs).borrow_mut().as_mut().map(f))
+    }
+
+    /// Writes a value into the channel.  It returns false, and writes nothing,
+    /// if there's no device yet.
+    pub fn write_pwm(&self, channel: &Channel, pwm_value: &PWMValue) -> bool {
+        self.with(|device| device.write_pwm(channel, pwm_value))
+            .is_some()
+    }
+
+    /// Reads the value in the channel, or `None` if there's no device yet.
+    pub fn read_pwm(&self, channel: &Channel) -> Option<PWMValue> {
+        self.with(|device| device.read_pwm(channel))
+    }
+}
+
+impl<L, D, O, C, T> SharedPWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    /// Flushes the device, holding the critical section for the whole frame.
+    /// It returns whether there was a device to flush.
+    pub fn flush(&self) -> Result<bool, DeviceError<L, D, O, C>> {
+        match self.with(|device| device.flush()) {
+            Some(result) => result.map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+impl<L, D, O, C, T> Default for SharedPWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    fn default() -> Self {
+        SharedPWM5947::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+    use crate::C2;
+
+    #[test]
+    fn test_empty() {
+        let shared: SharedPWM5947<IdlePin, IdlePin, IdlePin, IdlePin> = SharedPWM5947::new();
+
+        assert!(!shared.write_pwm(&C2, &PWMValue::max()));
+        assert_eq!(None, shared.read_pwm(&C2));
+        assert_eq!(Ok(false), shared.flush());
+
+        assert!(shared
+            .init(PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin))
+            .is_none());
+        assert!(shared.write_pwm(&C2, &PWMValue::max()));
+        assert!(shared.release().is_some());
+        assert_eq!(None, shared.read_pwm(&C2));
+    }
+}
@@ -0,0 +1,164 @@
+//! A simulator that draws the LEDs in a terminal, for trying out animations on
+//! a desktop before flashing the hardware.  It implements `LedController`,
+//! so an effect written against the trait runs on it unchanged.  Every flush
+//! draws each channel as a colored block, one row per 24 channels, and moves
+//! the cursor back up so the next flush draws over it.
+//!
+//! The blocks use the 24-bit ANSI color codes, which most terminals support.
+//! Each 12-bit value is scaled down to the 8 bits of a terminal color.  In the
+//! `Rgb` style each group of three channels is one block, red first, the same
+//! as the `RgbMatrix`.
+//!
+//! This module needs the `std` feature.
+//!
+//! ```
+//! use ledpwm5947::controller::LedController;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::sim::{Simulator, Style};
+//!
+//! let mut sim = Simulator::new(Vec::new(), 24).with_style(Style::Rgb);
+//! for frame in 0..8 {
+//!     sim.set_all(PWMValue::min());
+//!     sim.set(frame * 3, PWMValue::max());
+//!     sim.flush().unwrap();
+//! }
+//! assert_eq!(8, sim.frames());
+//! ```
+
+use crate::controller::LedController;
+use crate::pwm::PWMValue;
+use std::io::{self, Stdout, Write};
+use std::vec::Vec;
+
+/// How the channels are drawn.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Style {
+    /// Each channel is a gray block.
+    Gray,
+    /// Each three channels are one colored block, in red, green, blue order.
+    Rgb,
+}
+
+/// A set of channels drawn to a terminal, or anything else that can be
+/// written to, on every flush.
+#[derive(Debug)]
+pub struct Simulator<W: Write> {
+    out: W,
+    values: Vec<PWMValue>,
+    style: Style,
+    frames: usize,
+}
+
+impl Simulator<Stdout> {
+    /// A simulator that draws on standard out.
+    pub fn stdout(channels: usize) -> Self {
+        Simulator::new(io::stdout(), channels)
+    }
+}
+
+impl<W: Write> Simulator<W> {
+    /// Create a simulator with the number of channels, all off, drawing gray
+    /// blocks to the writer.
+    pub fn new(out: W, channels: usize) -> Self {
+        Simulator {
+            out,
+            values: std::vec![PWMValue::min(); channels],
+            style: Style::Gray,
+            frames: 0,
+        }
+    }
+
+    /// Draws in the style.
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// The channel values.
+    pub fn values(&self) -> &[PWMValue] {
+        &self.values
+    }
+
+    /// The number of frames drawn so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Gives back the writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Draws the channels as they are now.
+    fn draw(&mut self) -> io::Result<()> {
+        let rows = self.values.len().div_ceil(24);
+        if self.frames > 0 && rows > 0 {
+            write!(self.out, "\x1b[{}A", rows)?;
+        }
+
+        for row in self.values.chunks(24) {
+            match self.style {
+                Style::Gray => {
+                    for value in row {
+                        let level = value.to_resolution(8);
+                        block(&mut self.out, level, level, level)?;
+                    }
+                }
+                Style::Rgb => {
+                    for pixel in row.chunks_exact(3) {
+                        block(
+                            &mut self.out,
+                            pixel[0].to_resolution(8),
+                            pixel[1].to_resolution(8),
+                            pixel[2].to_resolution(8),
+                        )?;
+                    }
+                }
+            }
+            writeln!(self.out, "\x1b[0m")?;
+        }
+
+        self.frames += 1;
+        self.out.flush()
+    }
+}
+
+/// Writes one block, two spaces wide with the color as the background.
+fn block<W: Write>(out: &mut W, red: u16, green: u16, blue: u16) -> io::Result<()> {
+    write!(out, "\x1b[48;2;{};{};{}m  ", red, green, blue)
+}
+
+impl<W: Write> LedController for Simulator<W> {
+    type Error = io::Error;
+
+    fn num_channels(&self) -> usize {
+        self.values.len()
+    }
+
+    fn set(&mut self, channel: usize, value: PWMValue) {
+        self.values[channel] = value;
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.draw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    #[test]
+    fn test_draw() {
+        let mut sim = Simulator::new(Vec::new(), 6).with_style(Style::Rgb);
+        sim.set(0, PWMValue::max());
+        sim.set(4, PWMValue::new(0x800));
+        assert!(sim.flush().is_ok());
+        assert!(sim.flush().is_ok());
+
+        let out = String::from_utf8(sim.into_inner()).unwrap();
+        let frame = "\x1b[48;2;255;0;0m  \x1b[48;2;0;128;0m  \x1b[0m\n";
+        assert_eq!(std::format!("{}\x1b[1A{}", frame, frame), out);
+    }
+}
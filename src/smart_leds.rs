@@ -0,0 +1,96 @@
+//! Lets the board stand in for a strip of smart LEDs, so effects written for
+//! the `smart-leds` crates, like rainbows and gradients, can drive it.  Each
+//! group of three channels is an RGB pixel, so one board is eight pixels.
+//! Pixel 0 is channels 1 to 3, with red on channel 1, and so on.  Writing the
+//! pixels flushes them to the board, the same as writing to a strip.
+//!
+//! The 8-bit color components are scaled up to 12 bits linearly.  The
+//! `smart-leds` crate has its own gamma correction that can be applied to the
+//! pixels first.
+//!
+//! With the `arduino-compat` feature the device also has an inherent `write`
+//! method, which takes precedence, so call this one as
+//! `SmartLedsWrite::write(&mut device, pixels)`.
+
+use crate::chain::PWM5947Chain;
+use crate::timing::Delay;
+use crate::{pwm::PWMValue, Channel, DeviceError, PWM5947};
+use embedded_hal::digital::v2::OutputPin;
+use smart_leds_trait::{SmartLedsWrite, RGB8};
+
+impl<L, D, O, C, T> SmartLedsWrite for PWM5947<L, D, O, C, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    type Error = DeviceError<L, D, O, C>;
+    type Color = RGB8;
+
+    /// Writes up to eight pixels and flushes.  Any further pixels are ignored,
+    /// and channels without a pixel keep their values.
+    fn write<I, P>(&mut self, iterator: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<Self::Color>,
+    {
+        for (pixel, color) in iterator.into_iter().take(8).enumerate() {
+            let color = color.into();
+            self.write_pwm(&Channel(pixel * 3), &PWMValue::from(color.r));
+            self.write_pwm(&Channel(pixel * 3 + 1), &PWMValue::from(color.g));
+            self.write_pwm(&Channel(pixel * 3 + 2), &PWMValue::from(color.b));
+        }
+        self.flush()
+    }
+}
+
+impl<L, D, O, C, const N: usize, T> SmartLedsWrite for PWM5947Chain<L, D, O, C, N, T>
+where
+    L: OutputPin,
+    D: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+    T: Delay,
+{
+    type Error = DeviceError<L, D, O, C>;
+    type Color = RGB8;
+
+    /// Writes up to eight pixels per board and flushes.  Pixel 8 is the first
+    /// pixel on board 1, and so on down the chain.
+    fn write<I, P>(&mut self, iterator: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<Self::Color>,
+    {
+        for (pixel, color) in iterator.into_iter().take(8 * N).enumerate() {
+            let color = color.into();
+            let board = pixel / 8;
+            let first = (pixel % 8) * 3;
+            self.write_pwm(board, &Channel(first), &PWMValue::from(color.r));
+            self.write_pwm(board, &Channel(first + 1), &PWMValue::from(color.g));
+            self.write_pwm(board, &Channel(first + 2), &PWMValue::from(color.b));
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::IdlePin;
+
+    #[test]
+    fn test_write_pixels() {
+        let mut device = PWM5947::new(IdlePin, IdlePin, IdlePin, IdlePin);
+        let mut colors = [RGB8::new(255, 0, 0); 9];
+        colors[1] = RGB8::new(0, 0, 255);
+
+        assert!(SmartLedsWrite::write(&mut device, colors.iter().cloned()).is_ok());
+        assert_eq!(PWMValue::max(), device.buffer[0]);
+        assert_eq!(PWMValue::min(), device.buffer[1]);
+        assert_eq!(PWMValue::max(), device.buffer[5]);
+        assert_eq!(PWMValue::max(), device.buffer[21]);
+    }
+}
@@ -0,0 +1,263 @@
+//! The data and clock lines of the board map directly onto the MOSI and SCK
+//! lines of an SPI peripheral.  Instead of bit-banging two GPIOs, this backend
+//! packs the 24 channels into 36 bytes and writes them in one SPI transaction,
+//! which is much faster on most microcontrollers.  The latch and OE lines are
+//! still plain output pins.
+//!
+//! The SPI bus should be configured for mode 0 with the most significant bit
+//! first.  The board reads data on the rising edge of the clock.  Clone
+//! boards that want the bits the other way round are handled by
+//! `set_protocol`, which rearranges the frame before it's written, so the bus
+//! configuration stays the same.
+
+use crate::frame::PackedFrame;
+use crate::protocol::ProtocolConfig;
+use crate::{pwm, Channel, PWMPin, PinError, PinRole, PinSource, ALL_CHANNELS};
+use embedded_hal::blocking::spi::Write;
+use embedded_hal::digital::v2::OutputPin;
+
+/// The error returned when flushing over SPI.  Either the bus failed, or one
+/// of the pins did.  Both carry the error the HAL returned.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiError<S, P> {
+    Bus(S),
+    Pin(PinError<P>),
+}
+
+impl<S, P> core::fmt::Display for SpiError<S, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SpiError::Bus(_) => f.write_str("Failed to write SPI frame"),
+            SpiError::Pin(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<S, P> From<PinError<P>> for SpiError<S, P> {
+    fn from(error: PinError<P>) -> Self {
+        SpiError::Pin(error)
+    }
+}
+
+/// The `PinSource` for a board on SPI with the pins `L` and `O`.  The data
+/// and clock lines are the bus, so only `Latch` and `OE` occur, and the others
+/// take the latch pin's error type.
+pub type SpiSource<L, O> = PinSource<
+    <L as OutputPin>::Error,
+    <L as OutputPin>::Error,
+    <O as OutputPin>::Error,
+    <L as OutputPin>::Error,
+>;
+
+/// A board driven over SPI.  It owns the SPI bus, so a shared bus needs to be
+/// wrapped by the caller.
+#[derive(Debug)]
+pub struct PWM5947Spi<S, L, O>
+where
+    S: Write<u8>,
+    L: OutputPin,
+    O: OutputPin,
+{
+    buffer: [pwm::PWMValue; 24],
+
+    spi: S,
+    latch: PWMPin<L, SpiSource<L, O>>,
+    oe: PWMPin<O, SpiSource<L, O>>,
+    protocol: ProtocolConfig,
+    blank_during_latch: bool,
+}
+
+impl<S, L, O> PWM5947Spi<S, L, O>
+where
+    S: Write<u8>,
+    L: OutputPin,
+    O: OutputPin,
+{
+    /// Create a new board on the SPI bus.  Passes in the bus and the pins that
+    /// will now be owned by the device.
+    pub fn new(spi: S, latch: L, oe: O) -> Self {
+        PWM5947Spi {
+            buffer: [pwm::PWMValue::min(); 24],
+            spi,
+            latch: PWMPin::new(latch, PinRole::Latch, PinSource::Latch),
+            oe: PWMPin::new(oe, PinRole::OE, PinSource::OE),
+            protocol: ProtocolConfig::TLC5947,
+            blank_during_latch: true,
+        }
+    }
+
+    /// Writes and latches the frame the protocol's way, for clone boards, the
+    /// same as `PWM5947::set_protocol`.
+    pub fn set_protocol(&mut self, protocol: ProtocolConfig) {
+        self.latch.active_low = protocol.latch_active_low();
+        self.protocol = protocol;
+    }
+
+    /// The protocol the frame is written and latched in.
+    pub fn protocol(&self) -> &ProtocolConfig {
+        &self.protocol
+    }
+
+    /// Turns OE blanking during the latch on or off, the same as
+    /// `PWM5947::set_blank_during_latch`.  It's on to start with.
+    pub fn set_blank_during_latch(&mut self, blank: bool) {
+        self.blank_during_latch = blank;
+    }
+
+    /// Whether OE is pulled high while the latch is toggled.
+    pub fn is_blank_during_latch(&self) -> bool {
+        self.blank_during_latch
+    }
+
+    /// Sets the latch and OE pins to known, good values and clears the buffer
+    /// to the PWM's `min` value.
+    pub fn begin(&mut self) -> Result<(), PinError<SpiSource<L, O>>> {
+        self.oe.set_low()?;
+        self.latch.set_low()?;
+
+        self.buffer = [pwm::PWMValue::min(); 24];
+
+        Ok(())
+    }
+
+    /// Writes a value into the given channel.  It saves the PWM value into the
+    /// buffer for the given channel.
+    pub fn write_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.buffer[channel.0] = *pwm_value;
+    }
+
+    /// This sets the buffer back to all zeros and then flushes to turn off all
+    /// the LEDs.
+    pub fn all_black(&mut self) -> Result<(), SpiError<S::Error, SpiSource<L, O>>> {
+        for channel in ALL_CHANNELS {
+            self.buffer[channel.0] = pwm::PWMValue::min();
+        }
+        self.flush()
+    }
+
+    /// Flushes the buffer to the board.  The channels are packed into a 36
+    /// byte frame, written in a single transaction, and then the latch is
+    /// toggled.  A failed SPI write is returned as the bus error, and the
+    /// latch is left low.  OE is pulled high while the latch is toggled,
+    /// unless that's been turned off with `set_blank_during_latch`.
+    pub fn flush(&mut self) -> Result<(), SpiError<S::Error, SpiSource<L, O>>> {
+        self.flush_frame(self.blank_during_latch)
+    }
+
+    /// The flush, optionally pulling OE high while the latch is toggled.
+    pub(crate) fn flush_frame(
+        &mut self,
+        blank: bool,
+    ) -> Result<(), SpiError<S::Error, SpiSource<L, O>>> {
+        let frame = PackedFrame::pack(&self.protocol.arrange(&self.buffer));
+
+        self.latch.set_low()?;
+        self.spi.write(frame.as_bytes()).map_err(SpiError::Bus)?;
+        if blank {
+            self.oe.set_high()?;
+        }
+        self.latch.set_high()?;
+        self.latch.set_low()?;
+        if blank {
+            self.oe.set_low()?;
+        }
+        Ok(())
+    }
+
+    /// Releases the bus and pins, so they can be used for something else.
+    pub fn release(self) -> (S, L, O) {
+        (self.spi, self.latch.raw_pin, self.oe.raw_pin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pins::{FailingPin, IdlePin};
+
+    struct FakeSpi {
+        written: [u8; 36],
+        fail: bool,
+    }
+
+    impl Write<u8> for FakeSpi {
+        type Error = ();
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            if self.fail {
+                return Err(());
+            }
+            self.written.copy_from_slice(words);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_packed_frame() {
+        let spi = FakeSpi {
+            written: [0; 36],
+            fail: false,
+        };
+        let mut device = PWM5947Spi::new(spi, IdlePin, IdlePin);
+
+        device.write_pwm(&crate::C24, &pwm::PWMValue::new(0xabc));
+        device.write_pwm(&crate::C23, &pwm::PWMValue::new(0x123));
+        device.write_pwm(&crate::C1, &pwm::PWMValue::new(0xfff));
+        assert!(device.flush().is_ok());
+
+        let (spi, _, _) = device.release();
+        assert_eq!([0xab, 0xc1, 0x23], spi.written[0..3]);
+        assert_eq!([0x00, 0x0f, 0xff], spi.written[33..36]);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let spi = FakeSpi {
+            written: [0; 36],
+            fail: false,
+        };
+        let mut device = PWM5947Spi::new(spi, IdlePin, IdlePin);
+        device.set_protocol(ProtocolConfig {
+            channel_order: crate::protocol::ChannelOrder::Ascending,
+            latch: crate::protocol::LatchPolarity::ActiveLow,
+            ..ProtocolConfig::TLC5947
+        });
+        assert!(device.latch.active_low);
+
+        device.write_pwm(&crate::C1, &pwm::PWMValue::new(0xabc));
+        assert!(device.flush().is_ok());
+
+        let (spi, _, _) = device.release();
+        assert_eq!([0xab, 0xc0, 0x00], spi.written[0..3]);
+        assert_eq!([0x00, 0x00, 0x00], spi.written[33..36]);
+    }
+
+    #[test]
+    fn test_spi_failure() {
+        let spi = FakeSpi {
+            written: [0; 36],
+            fail: true,
+        };
+        let mut device = PWM5947Spi::new(spi, IdlePin, IdlePin);
+
+        match device.flush() {
+            Err(e) => assert_eq!(SpiError::Bus(()), e),
+            Ok(_) => panic!("Should have returned an error"),
+        }
+    }
+
+    #[test]
+    fn test_pin_source() {
+        let spi = FakeSpi {
+            written: [0; 36],
+            fail: false,
+        };
+        let oe = FailingPin::new(&true, &true);
+        let mut device = PWM5947Spi::new(spi, IdlePin, oe);
+
+        let e = device.begin().unwrap_err();
+        assert_eq!(PinRole::OE, e.which);
+        assert_eq!(PinSource::OE("Failed"), e.source);
+    }
+}
@@ -0,0 +1,162 @@
+//! Counters for health telemetry, kept by the device as it flushes.  They
+//! count the completed flushes, the bits clocked out to the board, and the
+//! pin failures by which pin failed, so firmware can report how the link to
+//! the board is doing without wrapping every call to the driver.
+//!
+//! The counters cover `flush`, `flush_partial` and everything built on them,
+//! like `all_black`, `flush_on`, the matrix scan and the refreshers in the
+//! `scheduler` module, along with the zeros `resync` clocks in.  Bits are
+//! counted once a whole shift has gone out, so a flush that fails part way
+//! only counts its pin failure.  The driver has no clock of its own, so the
+//! flush time is only measured by `flush_timed`, in whatever units its
+//! timestamps are in.
+//!
+//! The counters need the `stats` feature, since they add a little to every
+//! flush.  They wrap around rather than overflow.
+//!
+//! ```
+//! # use core::convert::Infallible;
+//! # use embedded_hal::digital::v2::OutputPin;
+//! # struct Pin;
+//! # impl OutputPin for Pin {
+//! #     type Error = Infallible;
+//! #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! use ledpwm5947::{PinRole, PWM5947};
+//!
+//! let mut device = PWM5947::new(Pin, Pin, Pin, Pin);
+//! device.flush().unwrap();
+//!
+//! let mut now = 100_u32;
+//! device
+//!     .flush_timed(|| {
+//!         now += 40;
+//!         now
+//!     })
+//!     .unwrap();
+//!
+//! let stats = device.stats();
+//! assert_eq!(2, stats.flushes());
+//! assert_eq!(576, stats.bits_shifted());
+//! assert_eq!(0, stats.pin_errors(&PinRole::Data));
+//! assert_eq!(Some(40), stats.last_flush_duration());
+//! ```
+
+use crate::frame::FRAME_BITS;
+use crate::{PinError, PinRole};
+
+/// The device's counters.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stats {
+    flushes: u32,
+    bits_shifted: u64,
+    pin_errors: [u32; 4],
+    last_flush_duration: Option<u32>,
+}
+
+impl Stats {
+    /// The number of frames flushed and latched.
+    pub fn flushes(&self) -> u32 {
+        self.flushes
+    }
+
+    /// The number of bits clocked out to the board, including the zeros
+    /// clocked in by `resync`.
+    pub fn bits_shifted(&self) -> u64 {
+        self.bits_shifted
+    }
+
+    /// The number of times the pin failed.
+    pub fn pin_errors(&self, which: &PinRole) -> u32 {
+        self.pin_errors[slot(which)]
+    }
+
+    /// The number of pin failures on any pin.
+    pub fn total_pin_errors(&self) -> u32 {
+        self.pin_errors
+            .iter()
+            .fold(0, |total, errors| total.wrapping_add(*errors))
+    }
+
+    /// How long the last `flush_timed` took, in the units of its timestamps,
+    /// or `None` if it hasn't been called.
+    pub fn last_flush_duration(&self) -> Option<u32> {
+        self.last_flush_duration
+    }
+
+    pub(crate) fn add_bits(&mut self, bits: usize) {
+        self.bits_shifted = self.bits_shifted.wrapping_add(bits as u64);
+    }
+
+    pub(crate) fn add_frame(&mut self) {
+        self.add_bits(FRAME_BITS);
+    }
+
+    pub(crate) fn set_flush_duration(&mut self, duration: u32) {
+        self.last_flush_duration = Some(duration);
+    }
+
+    /// Counts a flush, or the pin that failed it.
+    pub(crate) fn record<T, E>(&mut self, result: &Result<T, PinError<E>>) {
+        match result {
+            Ok(_) => self.flushes = self.flushes.wrapping_add(1),
+            Err(error) => {
+                let errors = &mut self.pin_errors[slot(&error.which)];
+                *errors = errors.wrapping_add(1);
+            }
+        }
+    }
+}
+
+fn slot(which: &PinRole) -> usize {
+    match which {
+        PinRole::Latch => 0,
+        PinRole::Data => 1,
+        PinRole::OE => 2,
+        PinRole::Clock => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partial::FlushProgress;
+    use crate::test_pins::FailingPin;
+    use crate::PWM5947;
+
+    #[test]
+    fn test_counters() {
+        let pin = || FailingPin::new(&false, &false);
+        let mut device = PWM5947::new(pin(), pin(), pin(), pin());
+
+        assert!(device.flush().is_ok());
+        assert_eq!(
+            Ok(FlushProgress::Shifting { remaining: 188 }),
+            device.flush_partial(100)
+        );
+        assert_eq!(1, device.stats().flushes());
+        assert_eq!(388, device.stats().bits_shifted());
+        assert_eq!(Ok(FlushProgress::Latched), device.flush_partial(1000));
+        assert_eq!(2, device.stats().flushes());
+        assert_eq!(576, device.stats().bits_shifted());
+
+        device.data.raw_pin.will_fail = true;
+        assert!(device.flush().is_err());
+        assert!(device.flush().is_err());
+        device.data.raw_pin.will_fail = false;
+        assert!(device.flush().is_ok());
+
+        let stats = device.stats();
+        assert_eq!(3, stats.flushes());
+        assert_eq!(576 + 2 * FRAME_BITS as u64, stats.bits_shifted());
+        assert_eq!(2, stats.pin_errors(&PinRole::Data));
+        assert_eq!(0, stats.pin_errors(&PinRole::Clock));
+        assert_eq!(2, stats.total_pin_errors());
+        assert_eq!(None, stats.last_flush_duration());
+
+        device.reset_stats();
+        assert_eq!(Stats::default(), *device.stats());
+    }
+}
@@ -0,0 +1,340 @@
+//! Saves named scenes to EEPROM or flash, so presets and the last brightness
+//! survive a power cycle.  A `SceneStore` splits the storage into fixed size
+//! slots, one scene each, and finds scenes by name.  A name is up to 16 bytes,
+//! and saving under a name that's already there overwrites that slot.
+//!
+//! The store works over the `ReadStorage` and `Storage` traits from the
+//! `embedded-storage` crate, so any EEPROM or flash driver for that crate can
+//! be handed straight to it.  The traits are re-exported here.  `write` is
+//! expected to take care of erasing flash pages first.  Erased flash reads as
+//! all ones, and a slot of all ones or all zeros counts as empty, so a blank
+//! part needs no formatting.
+//!
+//! Each slot starts with a tag and a format version, and ends with a
+//! checksum.  A slot written by a later version of the format reads back as a
+//! `StoreError::Version` error rather than garbage, and a slot that was only
+//! half written when the power went reads back as `Corrupt`.  Version 1 holds
+//! the name and the frame in the same 36 byte layout as `PackedFrame`.
+//!
+//! This module needs the `storage` feature.
+//!
+//! ```
+//! use ledpwm5947::frame::Frame;
+//! use ledpwm5947::pwm::PWMValue;
+//! use ledpwm5947::storage::{ReadStorage, SceneStore, Storage, SLOT_SIZE};
+//!
+//! struct Eeprom([u8; 4 * SLOT_SIZE]);
+//!
+//! impl ReadStorage for Eeprom {
+//!     type Error = ();
+//!     fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+//!         let start = offset as usize;
+//!         bytes.copy_from_slice(&self.0[start..start + bytes.len()]);
+//!         Ok(())
+//!     }
+//!     fn capacity(&self) -> usize {
+//!         self.0.len()
+//!     }
+//! }
+//!
+//! impl Storage for Eeprom {
+//!     fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+//!         let start = offset as usize;
+//!         self.0[start..start + bytes.len()].copy_from_slice(bytes);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut store = SceneStore::new(Eeprom([0xff; 4 * SLOT_SIZE]));
+//! let evening = Frame::new([PWMValue::new(600); 24]);
+//! store.save("evening", &evening).unwrap();
+//!
+//! assert_eq!(Some(evening), store.load("evening").unwrap());
+//! assert_eq!(None, store.load("morning").unwrap());
+//! ```
+
+use crate::frame::{Frame, PackedFrame};
+pub use embedded_storage::{ReadStorage, Storage};
+
+/// The bytes each scene takes in storage.
+pub const SLOT_SIZE: usize = 64;
+
+/// The longest name a scene can have, in bytes.
+pub const NAME_LEN: usize = 16;
+
+/// The version of the slot format this crate writes.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// The first two bytes of every used slot.
+const TAG: [u8; 2] = *b"L5";
+
+const VERSION_AT: usize = 2;
+const NAME_AT: usize = 3;
+const FRAME_AT: usize = NAME_AT + NAME_LEN;
+const CHECKSUM_AT: usize = FRAME_AT + 36;
+
+/// The error returned when a scene can't be saved or loaded.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StoreError<E> {
+    /// The name is longer than `NAME_LEN` bytes, or empty.
+    BadName,
+    /// Every slot holds a scene with another name.
+    Full,
+    /// The slot's checksum doesn't match its contents.
+    Corrupt,
+    /// The slot was written in a format version this crate can't read.
+    Version(u8),
+    /// The storage driver failed.
+    Storage(E),
+}
+
+impl<E> core::fmt::Display for StoreError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StoreError::BadName => write!(f, "Scene names are 1 to {} bytes", NAME_LEN),
+            StoreError::Full => f.write_str("No free scene slots"),
+            StoreError::Corrupt => f.write_str("Bad scene checksum"),
+            StoreError::Version(version) => write!(f, "Unknown scene format {}", version),
+            StoreError::Storage(_) => f.write_str("Storage failed"),
+        }
+    }
+}
+
+/// What a slot holds.
+enum Slot {
+    Empty,
+    Scene([u8; NAME_LEN], Frame),
+}
+
+/// Named scenes kept in storage, one per slot.
+#[derive(Debug)]
+pub struct SceneStore<S>
+where
+    S: Storage,
+{
+    storage: S,
+}
+
+impl<S> SceneStore<S>
+where
+    S: Storage,
+{
+    /// Create a store over the storage, using all of it.
+    pub fn new(storage: S) -> Self {
+        SceneStore { storage }
+    }
+
+    /// The number of scenes that fit.
+    pub fn slots(&self) -> usize {
+        self.storage.capacity() / SLOT_SIZE
+    }
+
+    /// Gives back the storage.
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Saves the frame under the name, replacing a scene with the same name or
+    /// taking the first empty slot.  A slot that can't be read, because it's
+    /// corrupt or from a later format, is never reused.
+    pub fn save(&mut self, name: &str, frame: &Frame) -> Result<(), StoreError<S::Error>> {
+        let key = encode_name(name)?;
+        let mut free = None;
+        let mut found = None;
+        for index in 0..self.slots() {
+            match self.read_slot(index) {
+                Ok(Slot::Scene(slot_name, _)) if slot_name == key => {
+                    found = Some(index);
+                    break;
+                }
+                Ok(Slot::Empty) if free.is_none() => free = Some(index),
+                Err(StoreError::Storage(e)) => return Err(StoreError::Storage(e)),
+                _ => {}
+            }
+        }
+
+        let index = found.or(free).ok_or(StoreError::Full)?;
+        let mut bytes = [0; SLOT_SIZE];
+        encode_slot(&key, frame, &mut bytes);
+        self.write_slot(index, &bytes)
+    }
+
+    /// Loads the scene saved under the name, or `None` if there isn't one.
+    pub fn load(&mut self, name: &str) -> Result<Option<Frame>, StoreError<S::Error>> {
+        let key = encode_name(name)?;
+        Ok(self.find(&key)?.map(|(_, frame)| frame))
+    }
+
+    /// Removes the scene saved under the name, and returns whether there was
+    /// one.
+    pub fn remove(&mut self, name: &str) -> Result<bool, StoreError<S::Error>> {
+        let key = encode_name(name)?;
+        match self.find(&key)? {
+            Some((index, _)) => {
+                self.write_slot(index, &[0xff; SLOT_SIZE])?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The slot and frame of the scene with the name.  Slots that can't be
+    /// read are skipped, so one bad slot doesn't hide the others.
+    fn find(
+        &mut self,
+        key: &[u8; NAME_LEN],
+    ) -> Result<Option<(usize, Frame)>, StoreError<S::Error>> {
+        for index in 0..self.slots() {
+            match self.read_slot(index) {
+                Ok(Slot::Scene(name, frame)) if name == *key => return Ok(Some((index, frame))),
+                Err(StoreError::Storage(e)) => return Err(StoreError::Storage(e)),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_slot(&mut self, index: usize) -> Result<Slot, StoreError<S::Error>> {
+        let mut bytes = [0; SLOT_SIZE];
+        self.storage
+            .read((index * SLOT_SIZE) as u32, &mut bytes)
+            .map_err(StoreError::Storage)?;
+        decode_slot(&bytes)
+    }
+
+    fn write_slot(
+        &mut self,
+        index: usize,
+        bytes: &[u8; SLOT_SIZE],
+    ) -> Result<(), StoreError<S::Error>> {
+        self.storage
+            .write((index * SLOT_SIZE) as u32, bytes)
+            .map_err(StoreError::Storage)
+    }
+}
+
+/// The name padded out with zeros.
+fn encode_name<E>(name: &str) -> Result<[u8; NAME_LEN], StoreError<E>> {
+    if name.is_empty() || name.len() > NAME_LEN {
+        return Err(StoreError::BadName);
+    }
+    let mut key = [0; NAME_LEN];
+    key[..name.len()].copy_from_slice(name.as_bytes());
+    Ok(key)
+}
+
+/// The byte that makes everything before the checksum add up to zero.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0_u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg()
+}
+
+fn encode_slot(name: &[u8; NAME_LEN], frame: &Frame, bytes: &mut [u8; SLOT_SIZE]) {
+    bytes[..VERSION_AT].copy_from_slice(&TAG);
+    bytes[VERSION_AT] = FORMAT_VERSION;
+    bytes[NAME_AT..FRAME_AT].copy_from_slice(name);
+    bytes[FRAME_AT..CHECKSUM_AT].copy_from_slice(PackedFrame::from(frame).as_bytes());
+    bytes[CHECKSUM_AT] = checksum(&bytes[..CHECKSUM_AT]);
+}
+
+fn decode_slot<E>(bytes: &[u8; SLOT_SIZE]) -> Result<Slot, StoreError<E>> {
+    if bytes.iter().all(|byte| *byte == 0xff) || bytes.iter().all(|byte| *byte == 0) {
+        return Ok(Slot::Empty);
+    }
+    if bytes[..VERSION_AT] != TAG {
+        return Err(StoreError::Corrupt);
+    }
+    if bytes[VERSION_AT] != FORMAT_VERSION {
+        return Err(StoreError::Version(bytes[VERSION_AT]));
+    }
+    if checksum(&bytes[..CHECKSUM_AT]) != bytes[CHECKSUM_AT] {
+        return Err(StoreError::Corrupt);
+    }
+
+    let mut name = [0; NAME_LEN];
+    name.copy_from_slice(&bytes[NAME_AT..FRAME_AT]);
+    let mut packed = [0; 36];
+    packed.copy_from_slice(&bytes[FRAME_AT..CHECKSUM_AT]);
+    Ok(Slot::Scene(name, PackedFrame::new(packed).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pwm::PWMValue;
+
+    struct Eeprom {
+        bytes: [u8; 3 * SLOT_SIZE],
+        failing: bool,
+    }
+
+    impl ReadStorage for Eeprom {
+        type Error = ();
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+            if self.failing {
+                return Err(());
+            }
+            let start = offset as usize;
+            bytes.copy_from_slice(&self.bytes[start..start + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.bytes.len() + 10
+        }
+    }
+
+    impl Storage for Eeprom {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+            let start = offset as usize;
+            self.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_slots() {
+        let mut store = SceneStore::new(Eeprom {
+            bytes: [0; 3 * SLOT_SIZE],
+            failing: false,
+        });
+        assert_eq!(3, store.slots());
+
+        let dim = Frame::new([PWMValue::new(100); 24]);
+        let bright = Frame::new([PWMValue::max(); 24]);
+        assert_eq!(Ok(()), store.save("a", &dim));
+        assert_eq!(Ok(()), store.save("b", &dim));
+        assert_eq!(Ok(()), store.save("a", &bright));
+        assert_eq!(Ok(()), store.save("sixteen_bytes_ok", &dim));
+        assert_eq!(Err(StoreError::Full), store.save("c", &dim));
+        assert_eq!(
+            Err(StoreError::BadName),
+            store.save("seventeen_bytes!!", &dim)
+        );
+        assert_eq!(Ok(Some(bright)), store.load("a"));
+
+        assert_eq!(Ok(true), store.remove("b"));
+        assert_eq!(Ok(false), store.remove("b"));
+        assert_eq!(Ok(()), store.save("c", &bright));
+        assert_eq!(Ok(Some(bright)), store.load("c"));
+
+        let mut eeprom = store.into_inner();
+        eeprom.bytes[10] ^= 1;
+        eeprom.bytes[SLOT_SIZE + VERSION_AT] = 2;
+        let mut store = SceneStore::new(eeprom);
+        assert_eq!(Err(StoreError::Corrupt), store.read_slot(0).map(|_| ()));
+        assert_eq!(Err(StoreError::Version(2)), store.read_slot(1).map(|_| ()));
+        assert_eq!(Ok(None), store.load("a"));
+        assert_eq!(Ok(Some(dim)), store.load("sixteen_bytes_ok"));
+        assert_eq!(Err(StoreError::Full), store.save("a", &dim));
+
+        let mut eeprom = store.into_inner();
+        eeprom.failing = true;
+        let mut store = SceneStore::new(eeprom);
+        assert_eq!(Err(StoreError::Storage(())), store.load("a"));
+    }
+}
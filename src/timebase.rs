@@ -0,0 +1,153 @@
+//! The timebase module lets animations run faster or slower than real time.
+//! The speed is a fixed-point multiplier with 8 fractional bits, so 256 is
+//! normal speed, 128 is half speed and 512 is double speed.  A `TimeBase`
+//! applies the speed to elapsed time, carrying the fractional part over to the
+//! next call so slowed down animations don't lose time to truncation.
+//!
+//! The device's fades, the `Interpolator` and the sequence `Player` each keep
+//! a `TimeBase`.  Changing its speed takes effect on the next frame, so a show
+//! can be slowed down or sped up while it's running.  The effects in the
+//! `effects` module step once per call to `next`, so they run at whatever
+//! rate they're called.
+
+/// The number of fractional bits in a `Speed`.
+const SPEED_SHIFT: u32 = 8;
+
+/// A speed multiplier applied to elapsed time.  It is a fixed-point number with
+/// 8 fractional bits, between 0 (paused) and just under 256 times normal speed.
+#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+pub struct Speed {
+    raw: u16,
+}
+
+impl Speed {
+    /// Create a speed from the raw fixed-point value, where 256 is normal speed.
+    ///
+    /// ```
+    /// use ledpwm5947::timebase::Speed;
+    ///
+    /// assert_eq!(Speed::normal(), Speed::from_raw(256));
+    /// ```
+    pub fn from_raw(raw: u16) -> Self {
+        Speed { raw }
+    }
+
+    /// Create a speed from a ratio, such as 3/4 for three-quarter speed.  The
+    /// speed is truncated to the nearest 1/256th and clamped to the largest
+    /// speed.  A denominator of zero gives the largest speed.
+    ///
+    /// ```
+    /// use ledpwm5947::timebase::Speed;
+    ///
+    /// assert_eq!(Speed::from_raw(192), Speed::from_ratio(3, 4));
+    /// assert_eq!(Speed::from_raw(512), Speed::from_ratio(2, 1));
+    /// ```
+    pub fn from_ratio(numerator: u32, denominator: u32) -> Self {
+        if denominator == 0 {
+            return Speed { raw: u16::MAX };
+        }
+
+        let raw = ((numerator as u64) << SPEED_SHIFT) / denominator as u64;
+        Speed {
+            raw: raw.min(u16::MAX as u64) as u16,
+        }
+    }
+
+    /// Normal, real-time speed.
+    pub fn normal() -> Self {
+        Speed {
+            raw: 1 << SPEED_SHIFT,
+        }
+    }
+
+    /// A speed of zero, which pauses the animation.
+    pub fn paused() -> Self {
+        Speed { raw: 0 }
+    }
+
+    /// The raw fixed-point value, where 256 is normal speed.
+    pub fn raw(&self) -> u16 {
+        self.raw
+    }
+}
+
+impl core::default::Default for Speed {
+    /// The default speed is normal speed.
+    fn default() -> Self {
+        Speed::normal()
+    }
+}
+
+/// A time base converts real elapsed time into animation time at the current
+/// speed.  The part of a tick lost to integer division is kept and added to
+/// the next conversion, so over many calls the animation time is exact.
+///
+/// ```
+/// use ledpwm5947::timebase::{Speed, TimeBase};
+///
+/// let mut time_base = TimeBase::new(Speed::from_ratio(1, 4));
+/// let total: u32 = (0..10).map(|_| time_base.advance(1)).sum();
+///
+/// assert_eq!(2, total);
+/// ```
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct TimeBase {
+    speed: Speed,
+    remainder: u32,
+}
+
+impl TimeBase {
+    /// Create a time base running at the given speed.
+    pub fn new(speed: Speed) -> Self {
+        TimeBase {
+            speed,
+            remainder: 0,
+        }
+    }
+
+    /// The current speed.
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Change the speed.  It takes effect on the next call to `advance`.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    /// Converts the real time elapsed since the last call into animation time.
+    /// The units are whatever the caller uses, such as ticks or milliseconds.
+    pub fn advance(&mut self, elapsed: u32) -> u32 {
+        let scaled = elapsed as u64 * self.speed.raw as u64 + self.remainder as u64;
+        self.remainder = (scaled & ((1 << SPEED_SHIFT) - 1)) as u32;
+        (scaled >> SPEED_SHIFT).min(u32::MAX as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normal_speed() {
+        let mut time_base = TimeBase::default();
+        assert_eq!(17, time_base.advance(17));
+        assert_eq!(0, time_base.advance(0));
+    }
+
+    #[test]
+    fn test_paused() {
+        let mut time_base = TimeBase::new(Speed::paused());
+        assert_eq!(0, time_base.advance(1000));
+
+        time_base.set_speed(Speed::from_ratio(5, 2));
+        assert_eq!(2500, time_base.advance(1000));
+    }
+
+    #[test]
+    fn test_carries_remainder() {
+        let mut time_base = TimeBase::new(Speed::from_raw(100));
+        let total: u32 = (0..256).map(|_| time_base.advance(1)).sum();
+        assert_eq!(100, total);
+    }
+}
@@ -0,0 +1,95 @@
+//! The TLC5947 has minimum widths for the clock and latch pulses.  On slow
+//! microcontrollers toggling a GPIO takes longer than that anyway, but a fast
+//! part can toggle a pin in a few nanoseconds and the board misses bits.  The
+//! driver can hold each edge for a minimum time using a delay provider.
+//!
+//! The `Delay` trait is the delay provider the driver uses.  `NoDelay` is the
+//! default and doesn't wait at all, which is right for most boards.
+//! `DelayUsAdapter` wraps an `embedded-hal` microsecond delay, rounding every
+//! wait up to a whole microsecond, which is slow but always safe.
+
+use embedded_hal::blocking::delay::DelayUs;
+
+/// A delay provider with nanosecond resolution.
+pub trait Delay {
+    /// Waits for at least `ns` nanoseconds.
+    fn delay_ns(&mut self, ns: u32);
+}
+
+/// A delay provider that doesn't wait.  The driver runs the pins as fast as
+/// the GPIO allows.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct NoDelay;
+
+impl Delay for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Wraps an `embedded-hal` microsecond delay.  Since it can only wait in whole
+/// microseconds, each wait is rounded up, and waits of zero don't wait at all.
+#[derive(Debug)]
+pub struct DelayUsAdapter<T>
+where
+    T: DelayUs<u32>,
+{
+    delay: T,
+}
+
+impl<T> DelayUsAdapter<T>
+where
+    T: DelayUs<u32>,
+{
+    /// Wrap the microsecond delay.
+    pub fn new(delay: T) -> Self {
+        DelayUsAdapter { delay }
+    }
+
+    /// Releases the wrapped delay.
+    pub fn release(self) -> T {
+        self.delay
+    }
+}
+
+impl<T> Delay for DelayUsAdapter<T>
+where
+    T: DelayUs<u32>,
+{
+    fn delay_ns(&mut self, ns: u32) {
+        if ns > 0 {
+            self.delay.delay_us(ns.div_ceil(1000));
+        }
+    }
+}
+
+/// The minimum time, in nanoseconds, to hold the clock and latch lines at
+/// each level.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Timing {
+    pub clock_ns: u32,
+    pub latch_ns: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingDelay {
+        total_us: u32,
+    }
+
+    impl DelayUs<u32> for CountingDelay {
+        fn delay_us(&mut self, us: u32) {
+            self.total_us += us;
+        }
+    }
+
+    #[test]
+    fn test_rounds_up() {
+        let mut delay = DelayUsAdapter::new(CountingDelay { total_us: 0 });
+        delay.delay_ns(0);
+        delay.delay_ns(1);
+        delay.delay_ns(1000);
+        delay.delay_ns(1001);
+        assert_eq!(4, delay.release().total_us);
+    }
+}
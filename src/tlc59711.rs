@@ -0,0 +1,236 @@
+//! A driver for the Adafruit TLC59711 breakout, the smaller sibling of the
+//! TLC5947.  It has 12 channels instead of 24, with 16 bits per channel, and
+//! only needs a data and a clock line.  There is no latch pin; the chip
+//! latches the data on its own once the clock has been idle for a short while.
+//!
+//! The driver takes the same `PWMValue` and `Channel` types as the TLC5947
+//! driver, so code can move between the two boards.  The 12-bit values are
+//! scaled up to 16 bits on the way out, with the maximum staying the maximum.
+//!
+//! The chip also has a 7-bit brightness control for each color group, which
+//! scales the red, green and blue outputs in hardware.  Channels 1, 4, 7 and
+//! 10 are red, channels 2, 5, 8 and 11 green, and the rest blue.
+
+use crate::{pwm, Channel, PWMPin, PinError, PinRole, PinSource};
+use embedded_hal::digital::v2::OutputPin;
+
+/// The write command that starts every frame.
+const WRITE_COMMAND: u32 = 0x25;
+
+/// The function bits after the command.  The outputs change on the rising
+/// edge of the grayscale clock, the internal clock is used, the grayscale
+/// counter resets on each latch, the display repeats, and the outputs aren't
+/// blanked.
+const FUNCTION_BITS: u32 = 0b10110;
+
+/// The maximum of the 7-bit brightness control.
+pub const MAX_BRIGHTNESS: u8 = 0x7f;
+
+/// The number of channels on the board.
+pub const CHANNELS: usize = 12;
+
+/// The `PinSource` for a board with the pins `D` and `C`.  The board has no
+/// latch or OE pin, so only `Data` and `Clock` occur, and the others take the
+/// data pin's error type.
+pub type BoardSource<D, C> = PinSource<
+    <D as OutputPin>::Error,
+    <D as OutputPin>::Error,
+    <D as OutputPin>::Error,
+    <C as OutputPin>::Error,
+>;
+
+/// The error returned by a board with the pins `D` and `C`.
+pub type BoardError<D, C> = PinError<BoardSource<D, C>>;
+
+/// Shifts the low `bits` of the word out to the board, most significant bit
+/// first.  The board reads each bit on the rising edge of the clock.
+fn shift_word<D, C, S>(
+    data: &mut PWMPin<D, S>,
+    clock: &mut PWMPin<C, S>,
+    word: u32,
+    bits: u8,
+) -> Result<(), PinError<S>>
+where
+    D: OutputPin,
+    C: OutputPin,
+{
+    for bit in (0..bits).rev() {
+        clock.set_low()?;
+
+        if word & (1 << bit) != 0 {
+            data.set_high()?;
+        } else {
+            data.set_low()?;
+        }
+
+        clock.set_high()?;
+    }
+
+    Ok(())
+}
+
+/// A TLC59711 board driven by bit-banging the data and clock lines.
+///
+/// ```
+/// # use core::convert::Infallible;
+/// # use embedded_hal::digital::v2::OutputPin;
+/// # struct Pin;
+/// # impl OutputPin for Pin {
+/// #     type Error = Infallible;
+/// #     fn set_low(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), Self::Error> { Ok(()) }
+/// # }
+/// use ledpwm5947::pwm::PWMValue;
+/// use ledpwm5947::tlc59711::PWM59711;
+/// use ledpwm5947::C4;
+///
+/// let mut device = PWM59711::new(Pin, Pin);
+/// device.write_pwm(&C4, &PWMValue::max());
+/// device.set_brightness(0x7f, 0x40, 0x7f);
+/// device.flush().ok();
+/// ```
+#[derive(Debug)]
+pub struct PWM59711<D, C>
+where
+    D: OutputPin,
+    C: OutputPin,
+{
+    buffer: [pwm::PWMValue; CHANNELS],
+    brightness: [u8; 3],
+
+    data: PWMPin<D, BoardSource<D, C>>,
+    clock: PWMPin<C, BoardSource<D, C>>,
+}
+
+impl<D, C> PWM59711<D, C>
+where
+    D: OutputPin,
+    C: OutputPin,
+{
+    /// Create a new board.  Passes in the pins that will now be owned by the
+    /// device.  The brightness starts at the maximum.
+    pub fn new(data: D, clock: C) -> Self {
+        PWM59711 {
+            buffer: [pwm::PWMValue::min(); CHANNELS],
+            brightness: [MAX_BRIGHTNESS; 3],
+            data: PWMPin::new(data, PinRole::Data, PinSource::Data),
+            clock: PWMPin::new(clock, PinRole::Clock, PinSource::Clock),
+        }
+    }
+
+    /// Sets the pins to known, good values and clears the buffer to the PWM's
+    /// `min` value.
+    pub fn begin(&mut self) -> Result<(), BoardError<D, C>> {
+        self.data.set_low()?;
+        self.clock.set_low()?;
+
+        self.buffer = [pwm::PWMValue::min(); CHANNELS];
+
+        Ok(())
+    }
+
+    /// Writes a value into the given channel.  It saves the PWM value into the
+    /// buffer for the given channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is past C12, the last channel on this board.
+    pub fn write_pwm(&mut self, channel: &Channel, pwm_value: &pwm::PWMValue) {
+        self.buffer[channel.0] = *pwm_value;
+    }
+
+    /// Reads back the value in the buffer for the given channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is past C12, the last channel on this board.
+    pub fn read_pwm(&self, channel: &Channel) -> pwm::PWMValue {
+        self.buffer[channel.0]
+    }
+
+    /// Sets the hardware brightness of the red, green and blue outputs.  Each
+    /// is 7 bits, and values above `MAX_BRIGHTNESS` are clamped.  Like the
+    /// channel values, the brightness is sent on the next flush.
+    pub fn set_brightness(&mut self, red: u8, green: u8, blue: u8) {
+        self.brightness = [
+            red.min(MAX_BRIGHTNESS),
+            green.min(MAX_BRIGHTNESS),
+            blue.min(MAX_BRIGHTNESS),
+        ];
+    }
+
+    /// The red, green and blue brightness.
+    pub fn brightness(&self) -> (u8, u8, u8) {
+        (self.brightness[0], self.brightness[1], self.brightness[2])
+    }
+
+    /// This sets the buffer back to all zeros and then flushes to turn off all
+    /// the LEDs.
+    pub fn all_black(&mut self) -> Result<(), BoardError<D, C>> {
+        self.buffer = [pwm::PWMValue::min(); CHANNELS];
+        self.flush()
+    }
+
+    /// Flushes the buffer to the board.  It shifts out the 32 bits of command,
+    /// function and brightness bits, then the channels starting with the last,
+    /// 16 bits each, most significant bit first.  The board latches the data
+    /// once the clock stays low for about eight clock periods, so don't start
+    /// another flush straight away on a fast microcontroller.
+    pub fn flush(&mut self) -> Result<(), BoardError<D, C>> {
+        let mut header = WRITE_COMMAND;
+        header = (header << 5) | FUNCTION_BITS;
+        header = (header << 7) | self.brightness[2] as u32;
+        header = (header << 7) | self.brightness[1] as u32;
+        header = (header << 7) | self.brightness[0] as u32;
+        shift_word(&mut self.data, &mut self.clock, header, 32)?;
+
+        for value in self.buffer.iter().rev() {
+            let wide = value.to_resolution(16);
+            shift_word(&mut self.data, &mut self.clock, wide as u32, 16)?;
+        }
+
+        self.clock.set_low()
+    }
+
+    /// Releases the pins, so they can be used for something else.
+    pub fn release(self) -> (D, C) {
+        (self.data.raw_pin, self.clock.raw_pin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_pins::{ClockPin, DataPin, Wire};
+    use core::cell::RefCell;
+    use std::rc::Rc;
+
+    fn word(bits: &[bool]) -> u32 {
+        bits.iter().fold(0, |word, bit| (word << 1) | *bit as u32)
+    }
+
+    #[test]
+    fn test_frame() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut device = PWM59711::new(DataPin(wire.clone()), ClockPin(wire.clone()));
+        assert!(device.begin().is_ok());
+
+        device.write_pwm(&crate::C12, &pwm::PWMValue::max());
+        device.write_pwm(&crate::C1, &pwm::PWMValue::new(0x800));
+        device.set_brightness(0x01, 0x02, 0xff);
+        assert_eq!((0x01, 0x02, 0x7f), device.brightness());
+        assert!(device.flush().is_ok());
+
+        let bits = &wire.borrow().bits;
+        assert_eq!(32 + 12 * 16, bits.len());
+        assert_eq!(0x25, word(&bits[0..6]));
+        assert_eq!(0b10110, word(&bits[6..11]));
+        assert_eq!(0x7f, word(&bits[11..18]));
+        assert_eq!(0x02, word(&bits[18..25]));
+        assert_eq!(0x01, word(&bits[25..32]));
+        assert_eq!(0xffff, word(&bits[32..48]));
+        assert_eq!(0x8008, word(&bits[bits.len() - 16..]));
+    }
+}